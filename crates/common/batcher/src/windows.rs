@@ -0,0 +1,181 @@
+use crate::batchable::Batchable;
+use crate::config::BatchConfig;
+use std::collections::BTreeMap;
+use time::Duration;
+use time::OffsetDateTime;
+
+/// Group events into fixed-size time windows, aligned on the epoch.
+///
+/// Contrary to a [Batcher](crate::Batcher), that groups together events close in time,
+/// the windows are contiguous and of the same size, e.g. one window per minute.
+/// The events are assigned to a window according to their event time,
+/// and a window is closed once the delivery jitter has elapsed after the window end.
+///
+/// The batch configuration is interpreted as follows:
+/// - the event jitter is the size of the windows,
+/// - the delivery jitter is how long to wait for late events once a window is over,
+/// - the message leap limit is how far in the future an event can be.
+#[derive(Debug)]
+pub struct TimeWindows<B: Batchable> {
+    config: BatchConfig,
+    windows: BTreeMap<OffsetDateTime, Vec<B>>,
+}
+
+/// The events of a closed time window
+#[derive(Debug, Eq, PartialEq)]
+pub struct TimeWindow<B> {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub events: Vec<B>,
+}
+
+impl<B: Batchable> TimeWindows<B> {
+    /// Create time windows with the specified config.
+    pub fn new(config: BatchConfig) -> TimeWindows<B> {
+        TimeWindows {
+            config,
+            windows: BTreeMap::new(),
+        }
+    }
+
+    /// The size of the windows
+    pub fn window_size(&self) -> Duration {
+        self.config.event_jitter()
+    }
+
+    /// Add an event to its window, returning the time at which this window will be closed.
+    ///
+    /// Return `None` if the event has been discarded,
+    /// because its window has already been closed or because the event is too futuristic.
+    pub fn event(&mut self, processing_time: OffsetDateTime, event: B) -> Option<OffsetDateTime> {
+        let event_time = event.event_time();
+        if event_time > processing_time + self.config.message_leap_limit() {
+            return None;
+        }
+
+        let window_start = self.window_start(event_time);
+        let window_timeout = window_start + self.window_size() + self.config.delivery_jitter();
+        if window_timeout <= processing_time {
+            return None;
+        }
+
+        self.windows.entry(window_start).or_default().push(event);
+        Some(window_timeout)
+    }
+
+    /// Close all the windows which delivery jitter has elapsed at the given time
+    pub fn time(&mut self, time: OffsetDateTime) -> Vec<TimeWindow<B>> {
+        let closing_time = time - self.window_size() - self.config.delivery_jitter();
+        let open_windows = self
+            .windows
+            .split_off(&(closing_time + Duration::nanoseconds(1)));
+        let closed_windows = std::mem::replace(&mut self.windows, open_windows);
+        self.time_windows(closed_windows)
+    }
+
+    /// Close all the windows
+    pub fn flush(&mut self) -> Vec<TimeWindow<B>> {
+        let closed_windows = std::mem::take(&mut self.windows);
+        self.time_windows(closed_windows)
+    }
+
+    fn window_start(&self, event_time: OffsetDateTime) -> OffsetDateTime {
+        let size = self.window_size().whole_nanoseconds().max(1);
+        let time = event_time.unix_timestamp_nanos();
+        let start = time - time.rem_euclid(size);
+        OffsetDateTime::from_unix_timestamp_nanos(start).unwrap_or(event_time)
+    }
+
+    fn time_windows(&self, windows: BTreeMap<OffsetDateTime, Vec<B>>) -> Vec<TimeWindow<B>> {
+        windows
+            .into_iter()
+            .map(|(start, events)| TimeWindow {
+                start,
+                end: start + self.window_size(),
+                events,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BatchConfigBuilder;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    struct TestEvent {
+        time: i64,
+        value: u32,
+    }
+
+    impl Batchable for TestEvent {
+        type Key = u32;
+
+        fn key(&self) -> Self::Key {
+            self.value
+        }
+
+        fn event_time(&self) -> OffsetDateTime {
+            at(self.time)
+        }
+    }
+
+    fn at(millis: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::milliseconds(millis)
+    }
+
+    fn event(time: i64, value: u32) -> TestEvent {
+        TestEvent { time, value }
+    }
+
+    fn windows(size: u32, delivery_jitter: u32) -> TimeWindows<TestEvent> {
+        let config = BatchConfigBuilder::new()
+            .event_jitter(size)
+            .delivery_jitter(delivery_jitter)
+            .message_leap_limit(0)
+            .build();
+        TimeWindows::new(config)
+    }
+
+    #[test]
+    fn events_are_grouped_into_aligned_windows() {
+        let mut windows = windows(100, 10);
+
+        assert_eq!(windows.event(at(120), event(120, 1)), Some(at(210)));
+        assert_eq!(windows.event(at(150), event(150, 2)), Some(at(210)));
+        assert_eq!(windows.event(at(205), event(199, 3)), Some(at(210)));
+        assert_eq!(windows.event(at(205), event(201, 4)), Some(at(310)));
+
+        assert_eq!(windows.time(at(209)), vec![]);
+        assert_eq!(
+            windows.time(at(210)),
+            vec![TimeWindow {
+                start: at(100),
+                end: at(200),
+                events: vec![event(120, 1), event(150, 2), event(199, 3)],
+            }]
+        );
+        assert_eq!(
+            windows.flush(),
+            vec![TimeWindow {
+                start: at(200),
+                end: at(300),
+                events: vec![event(201, 4)],
+            }]
+        );
+    }
+
+    #[test]
+    fn late_and_futuristic_events_are_discarded() {
+        let mut windows = windows(100, 10);
+
+        // The window [100, 200) is closed at 210
+        assert_eq!(windows.event(at(210), event(199, 1)), None);
+
+        // No leap in the future is accepted
+        assert_eq!(windows.event(at(210), event(211, 2)), None);
+
+        assert_eq!(windows.flush(), vec![]);
+    }
+}
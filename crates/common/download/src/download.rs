@@ -437,8 +437,10 @@ async fn save_chunks_to_file_at(
 ) -> Result<(), SaveChunksError> {
     writer.seek(SeekFrom::Start(offset))?;
 
+    let downloaded_bytes = tedge_metrics::downloaded_bytes();
     while let Some(bytes) = response.chunk().await? {
         writer.write_all(&bytes)?;
+        downloaded_bytes.inc_by(bytes.len() as u64);
     }
     Ok(())
 }
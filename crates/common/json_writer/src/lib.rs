@@ -58,6 +58,20 @@ impl JsonWriter {
         }
     }
 
+    pub fn write_i64(&mut self, value: i64) -> Result<(), JsonWriterError> {
+        self.maybe_separate();
+        serde_json::to_writer(&mut self.buffer, &value)?;
+        self.needs_separator = true;
+        Ok(())
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> Result<(), JsonWriterError> {
+        self.maybe_separate();
+        serde_json::to_writer(&mut self.buffer, &value)?;
+        self.needs_separator = true;
+        Ok(())
+    }
+
     pub fn write_open_obj(&mut self) {
         self.maybe_separate();
         self.buffer.push(b'{');
@@ -140,6 +154,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn write_integer_and_boolean_values() -> anyhow::Result<()> {
+        let mut jw = JsonWriter::with_capacity(128);
+        jw.write_open_obj();
+        jw.write_key("counter")?;
+        jw.write_i64(12345678901)?;
+        jw.write_key("door_open")?;
+        jw.write_bool(true)?;
+        jw.write_close_obj();
+        assert_eq!(
+            jw.into_string()?,
+            r#"{"counter":12345678901,"door_open":true}"#
+        );
+        Ok(())
+    }
+
     #[test]
     fn write_multivalue_message() -> anyhow::Result<()> {
         let mut jw = JsonWriter::with_capacity(128);
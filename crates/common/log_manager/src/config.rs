@@ -4,17 +4,106 @@ use serde::Deserialize;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
+use tedge_api::messages::LogCompression;
+use time::UtcOffset;
 
 #[derive(Clone, Deserialize, Debug, Eq, PartialEq, Default)]
 pub struct LogPluginConfig {
+    /// The UTC offset of the log timestamps given without offset, e.g. `+02:00` (UTC if not set)
+    #[serde(default, deserialize_with = "deserialize_utc_offset")]
+    pub utc_offset: Option<UtcOffset>,
     pub files: Vec<FileEntry>,
 }
 
+fn deserialize_utc_offset<'de, D>(deserializer: D) -> Result<Option<UtcOffset>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let offset = String::deserialize(deserializer)?;
+    parse_utc_offset(&offset)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid UTC offset: {offset:?}")))
+}
+
+/// Parse a UTC offset given as `Z`, `+HH:MM` or `-HH:MM`
+fn parse_utc_offset(offset: &str) -> Option<UtcOffset> {
+    if offset == "Z" {
+        return Some(UtcOffset::UTC);
+    }
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = offset.get(1..)?.split_once(':')?;
+    let is_two_digits = |s: &str| s.len() == 2 && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_two_digits(hours) || !is_two_digits(minutes) {
+        return None;
+    }
+    let hours: i8 = hours.parse().ok()?;
+    let minutes: i8 = minutes.parse().ok()?;
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
 #[derive(Deserialize, Debug, Eq, Default, Clone)]
 pub struct FileEntry {
+    #[serde(default)]
     pub(crate) path: String,
     #[serde(rename = "type")]
     pub config_type: String,
+    /// Read the logs from the systemd journal, instead of files
+    pub(crate) journal: Option<JournalSource>,
+    /// Read the logs from the output of a command, instead of files
+    pub(crate) command: Option<String>,
+    /// Compress the logs of this type, when no compression is given by the log upload command
+    #[serde(default)]
+    pub(crate) compression: Option<LogCompression>,
+}
+
+/// The selection of systemd journal entries retrieved for a log type
+#[derive(Deserialize, Debug, Eq, PartialEq, Default, Clone)]
+pub struct JournalSource {
+    /// Only the entries of this systemd unit
+    pub(crate) unit: Option<String>,
+    /// Only the entries with this priority or a higher priority, e.g. `warning`
+    pub(crate) priority: Option<String>,
+}
+
+impl std::fmt::Display for JournalSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "journal: {}",
+            self.unit.as_deref().unwrap_or("all units")
+        )?;
+        if let Some(priority) = &self.priority {
+            write!(f, " (priority: {priority})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the logs of a log type are read from
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LogSource<'a> {
+    /// Files matching a glob pattern
+    Files(&'a str),
+    /// The systemd journal
+    Journal(&'a JournalSource),
+    /// The output of a command
+    Command(&'a str),
+}
+
+impl FileEntry {
+    pub fn source(&self) -> LogSource<'_> {
+        if let Some(journal) = &self.journal {
+            LogSource::Journal(journal)
+        } else if let Some(command) = &self.command {
+            LogSource::Command(command)
+        } else {
+            LogSource::Files(&self.path)
+        }
+    }
 }
 
 impl PartialEq for FileEntry {
@@ -49,6 +138,14 @@ impl LogPluginConfig {
         }
     }
 
+    /// The compression configured for a log type, if any
+    pub fn compression(&self, log_type: &str) -> Option<LogCompression> {
+        self.files
+            .iter()
+            .filter(|file| file.config_type == log_type)
+            .find_map(|file| file.compression)
+    }
+
     pub fn get_all_file_types(&self) -> Vec<String> {
         self.files
             .iter()
@@ -66,15 +163,97 @@ fn test_no_duplicated_file_types() {
         FileEntry {
             path: "a/path".to_string(),
             config_type: "type_one".to_string(),
+            ..Default::default()
         },
         FileEntry {
             path: "some/path".to_string(),
             config_type: "type_one".to_string(),
+            ..Default::default()
         },
     ];
-    let logs_config = LogPluginConfig { files };
+    let logs_config = LogPluginConfig {
+        files,
+        ..Default::default()
+    };
     assert_eq!(
         logs_config.get_all_file_types(),
         vec!["type_one".to_string()]
     );
 }
+
+#[test]
+fn test_journal_and_command_sources() {
+    let logs_config: LogPluginConfig = toml::from_str(
+        r#"files = [
+            { type = "mosquitto", journal = { unit = "mosquitto.service", priority = "warning" } },
+            { type = "dmesg", command = "dmesg --time-format iso" },
+            { type = "software-management", path = "/var/log/tedge/agent/software-*" },
+        ]"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        logs_config.files[0].source(),
+        LogSource::Journal(&JournalSource {
+            unit: Some("mosquitto.service".to_string()),
+            priority: Some("warning".to_string()),
+        })
+    );
+    assert_eq!(
+        logs_config.files[1].source(),
+        LogSource::Command("dmesg --time-format iso")
+    );
+    assert_eq!(
+        logs_config.files[2].source(),
+        LogSource::Files("/var/log/tedge/agent/software-*")
+    );
+}
+
+#[test]
+fn test_compression_per_log_type() {
+    let logs_config: LogPluginConfig = toml::from_str(
+        r#"files = [
+            { type = "syslog", path = "/var/log/syslog", compression = "gzip" },
+            { type = "mosquitto", path = "/var/log/mosquitto/mosquitto.log" },
+            { type = "kernel", path = "/var/log/kern.log" },
+            { type = "kernel", command = "dmesg --time-format iso", compression = "zstd" },
+        ]"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        logs_config.compression("syslog"),
+        Some(LogCompression::Gzip)
+    );
+    assert_eq!(logs_config.compression("mosquitto"), None);
+    assert_eq!(
+        logs_config.compression("kernel"),
+        Some(LogCompression::Zstd)
+    );
+    assert_eq!(logs_config.compression("unknown"), None);
+}
+
+#[test]
+fn test_utc_offset() {
+    let logs_config: LogPluginConfig = toml::from_str(
+        r#"utc_offset = "+05:30"
+        files = [ { type = "syslog", path = "/var/log/syslog" } ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        logs_config.utc_offset,
+        Some(UtcOffset::from_hms(5, 30, 0).unwrap())
+    );
+
+    for (offset, expected) in [
+        ("Z", Some(UtcOffset::UTC)),
+        ("-03:00", Some(UtcOffset::from_hms(-3, 0, 0).unwrap())),
+        ("+0200", None),
+        ("02:00", None),
+        ("++2:00", None),
+        ("+26:00", None),
+        ("Europe/Paris", None),
+    ] {
+        assert_eq!(parse_utc_offset(offset), expected, "{offset}");
+    }
+}
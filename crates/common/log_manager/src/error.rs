@@ -15,6 +15,12 @@ pub enum LogRetrievalError {
     #[error(transparent)]
     FromFileError(#[from] tedge_utils::file::FileError),
 
+    #[error("Invalid search pattern: {0}")]
+    InvalidSearchPattern(#[from] regex::Error),
+
+    #[error("Failed to read logs from {command:?}: {reason}")]
+    CommandFailed { command: String, reason: String },
+
     // NOTE: `MaxLines` is not a client-facing error. It is used
     // to break out of `read_log_content`.
     #[error("Log file has maximum number of lines.")]
@@ -1,33 +1,71 @@
 use super::config::FileEntry;
+use super::config::JournalSource;
+use super::config::LogPluginConfig;
+use super::config::LogSource;
 use super::error::LogRetrievalError;
 use easy_reader::EasyReader;
 use glob::glob;
+use regex::Regex;
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
+use time::UtcOffset;
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
+use tokio::process::Command;
+
+/// The program used to read the systemd journal
+pub const JOURNALCTL: &str = "journalctl";
+
+/// The time given to `journalctl` or to a log command to output the logs, before being killed
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
 
 /// read any log file coming from `obj.log.log_type`
-pub fn new_read_logs(
-    files: &Vec<FileEntry>,
+pub async fn new_read_logs(
+    config: &LogPluginConfig,
     log_type: &str,
     date_from: OffsetDateTime,
+    date_to: OffsetDateTime,
     lines: usize,
     search_text: &Option<String>,
     tmp_dir: &Path,
 ) -> Result<PathBuf, LogRetrievalError> {
+    let files = &config.files;
+    let filter = LogFilter::new(date_from, date_to, search_text)?
+        .with_utc_offset(config.utc_offset.unwrap_or(UtcOffset::UTC));
+    let other_sources = files
+        .iter()
+        .filter(|file| file.config_type == log_type)
+        .map(FileEntry::source)
+        .filter(|source| !matches!(source, LogSource::Files(_)))
+        .collect::<Vec<_>>();
+
     // first filter logs on type
-    let mut logfiles_to_read = filter_logs_on_type(files, log_type)?;
-    logfiles_to_read = filter_logs_path_on_metadata(log_type, date_from, logfiles_to_read)?;
+    let logfiles_to_read = match filter_logs_on_type(files, log_type)
+        .and_then(|logfiles| filter_logs_path_on_metadata(log_type, date_from, logfiles))
+    {
+        Ok(logfiles) => logfiles,
+        Err(LogRetrievalError::NoLogsAvailableForType { .. }) if !other_sources.is_empty() => {
+            vec![]
+        }
+        Err(err) => return Err(err),
+    };
 
     let temp_path = tmp_dir.join(format!("{log_type}-{}", rand::random::<u128>()));
     let mut temp_file = File::create(&temp_path)?;
 
     let mut line_counter = 0usize;
     for logfile in logfiles_to_read {
-        match read_log_content(logfile.as_path(), line_counter, lines, search_text) {
+        match read_log_content(logfile.as_path(), line_counter, lines, &filter) {
             Ok((lines, file_content)) => {
                 line_counter = lines;
                 temp_file.write_all(file_content.as_bytes())?;
@@ -41,14 +79,307 @@ pub fn new_read_logs(
         };
     }
 
+    for source in other_sources {
+        if line_counter >= lines {
+            break;
+        }
+        let max_lines = lines - line_counter;
+        let (header, selected_lines) = match source {
+            LogSource::Journal(journal) => (
+                journal.to_string(),
+                read_journal(JOURNALCTL, journal, &filter, max_lines, COMMAND_TIMEOUT).await?,
+            ),
+            LogSource::Command(command) => (
+                format!("command: {command}"),
+                read_command_output(command, &filter, max_lines, COMMAND_TIMEOUT).await?,
+            ),
+            LogSource::Files(_) => continue,
+        };
+        line_counter += selected_lines.len();
+
+        writeln!(temp_file, "{header}")?;
+        for line in selected_lines {
+            writeln!(temp_file, "{line}")?;
+        }
+    }
+
     Ok(temp_path)
 }
 
+/// The criteria used to select the log lines to be retrieved
+pub struct LogFilter {
+    date_from: OffsetDateTime,
+    date_to: OffsetDateTime,
+    pattern: Option<Regex>,
+    utc_offset: UtcOffset,
+}
+
+/// The outcome of checking a log line against a [LogFilter]
+#[derive(Debug, Eq, PartialEq)]
+pub enum LineSelection {
+    /// The line is to be retrieved
+    Selected,
+    /// The line is not to be retrieved
+    Rejected,
+    /// The line is timestamped before the date range: so are all the lines before it
+    Older,
+}
+
+impl LogFilter {
+    /// Build a filter selecting the lines in a date range and matching a regular expression
+    pub fn new(
+        date_from: OffsetDateTime,
+        date_to: OffsetDateTime,
+        search_text: &Option<String>,
+    ) -> Result<Self, LogRetrievalError> {
+        let pattern = search_text.as_deref().map(Regex::new).transpose()?;
+        Ok(LogFilter {
+            date_from,
+            date_to,
+            pattern,
+            utc_offset: UtcOffset::UTC,
+        })
+    }
+
+    /// Set the UTC offset of the line timestamps given without offset, UTC by default
+    pub fn with_utc_offset(self, utc_offset: UtcOffset) -> Self {
+        LogFilter { utc_offset, ..self }
+    }
+
+    /// Check if a line is to be retrieved
+    ///
+    /// The date range is only checked for the lines starting with a timestamp.
+    /// The lines without a timestamp are only checked against the search pattern.
+    pub fn select(&self, line: &str) -> LineSelection {
+        if let Some(timestamp) = line_timestamp(line, self.utc_offset) {
+            if timestamp < self.date_from {
+                return LineSelection::Older;
+            }
+            if timestamp > self.date_to {
+                return LineSelection::Rejected;
+            }
+        }
+        match &self.pattern {
+            Some(pattern) if !pattern.is_match(line) => LineSelection::Rejected,
+            _ => LineSelection::Selected,
+        }
+    }
+
+    /// Return the last `max_lines` lines of some log output that are selected by this filter
+    pub fn last_matching_lines(&self, output: &str, max_lines: usize) -> Vec<String> {
+        let mut selected_lines = LastMatchingLines::new(self, max_lines);
+        for line in output.lines() {
+            selected_lines.push(line);
+        }
+        selected_lines.into()
+    }
+}
+
+/// The last lines of some log output that are selected by a [LogFilter]
+///
+/// The lines are pushed in order, as produced, and only the last `max_lines` selected lines are kept,
+/// so the log output doesn't have to be buffered as a whole.
+pub struct LastMatchingLines<'a> {
+    filter: &'a LogFilter,
+    max_lines: usize,
+    lines: VecDeque<String>,
+}
+
+impl<'a> LastMatchingLines<'a> {
+    pub fn new(filter: &'a LogFilter, max_lines: usize) -> Self {
+        LastMatchingLines {
+            filter,
+            max_lines,
+            lines: VecDeque::new(),
+        }
+    }
+
+    /// Push the next line of the log output
+    pub fn push(&mut self, line: &str) {
+        match self.filter.select(line) {
+            LineSelection::Selected => {
+                self.lines.push_back(line.to_string());
+                if self.lines.len() > self.max_lines {
+                    self.lines.pop_front();
+                }
+            }
+            LineSelection::Rejected => {}
+            // all the lines before an older line are older too
+            LineSelection::Older => self.lines.clear(),
+        }
+    }
+}
+
+impl From<LastMatchingLines<'_>> for Vec<String> {
+    fn from(selected_lines: LastMatchingLines<'_>) -> Self {
+        selected_lines.lines.into()
+    }
+}
+
+/// Extract the timestamp at the beginning of a log line, if any
+///
+/// The supported timestamps are RFC 3339 / ISO 8601 timestamps, with a `T` or a space as separator,
+/// with or without fractional seconds, and possibly enclosed in square brackets.
+/// A timestamp without UTC offset is assumed to be at the given default offset.
+pub fn line_timestamp(line: &str, default_offset: UtcOffset) -> Option<OffsetDateTime> {
+    static TIMESTAMP: OnceLock<Regex> = OnceLock::new();
+    let timestamp = TIMESTAMP.get_or_init(|| {
+        Regex::new(r"^\[?(\d{4}-\d{2}-\d{2})[T ](\d{2}:\d{2}:\d{2})(\.\d+)?(Z|[+-]\d{2}:?\d{2})?")
+            .expect("a valid regex")
+    });
+
+    let captures = timestamp.captures(line)?;
+    let fraction = captures.get(3).map_or("", |m| m.as_str());
+    let offset = match captures.get(4).map(|m| m.as_str()) {
+        None if default_offset.is_utc() => "Z".to_string(),
+        None => {
+            let (hours, minutes, _) = default_offset.as_hms();
+            let sign = if default_offset.is_negative() {
+                '-'
+            } else {
+                '+'
+            };
+            format!("{sign}{:02}:{:02}", hours.abs(), minutes.abs())
+        }
+        Some("Z") => "Z".to_string(),
+        Some(offset) if offset.contains(':') => offset.to_string(),
+        Some(offset) => format!("{}:{}", &offset[..3], &offset[3..]),
+    };
+    let rfc3339 = format!("{}T{}{fraction}{offset}", &captures[1], &captures[2]);
+    OffsetDateTime::parse(&rfc3339, &Rfc3339).ok()
+}
+
+/// Read the last `max_lines` entries of the systemd journal selected by a filter, using `journalctl`
+///
+/// The date range is checked by `journalctl` itself, as is the number of lines when there is no search pattern.
+pub async fn read_journal(
+    journalctl: &str,
+    source: &JournalSource,
+    filter: &LogFilter,
+    max_lines: usize,
+    timeout: Duration,
+) -> Result<Vec<String>, LogRetrievalError> {
+    let mut command = Command::new(journalctl);
+    command
+        .args(["--no-pager", "--quiet", "--output", "short-iso"])
+        .arg("--since")
+        .arg(format!("@{}", filter.date_from.unix_timestamp()))
+        .arg("--until")
+        .arg(format!("@{}", filter.date_to.unix_timestamp()));
+    if filter.pattern.is_none() {
+        command.arg("--lines").arg(max_lines.to_string());
+    }
+    if let Some(unit) = &source.unit {
+        command.arg("--unit").arg(unit);
+    }
+    if let Some(priority) = &source.priority {
+        command.arg("--priority").arg(priority);
+    }
+    let mut selected_lines = LastMatchingLines::new(filter, max_lines);
+    run_command(command, journalctl, timeout, &mut selected_lines).await?;
+    Ok(selected_lines.into())
+}
+
+/// Run a command line and return the last `max_lines` lines of its standard output selected by a filter
+///
+/// The command is killed if not completed within the given timeout.
+pub async fn read_command_output(
+    command_line: &str,
+    filter: &LogFilter,
+    max_lines: usize,
+    timeout: Duration,
+) -> Result<Vec<String>, LogRetrievalError> {
+    let args =
+        shell_words::split(command_line).map_err(|err| LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: err.to_string(),
+        })?;
+    let Some((program, args)) = args.split_first() else {
+        return Err(LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: "command line is empty".to_string(),
+        });
+    };
+
+    let mut command = Command::new(program);
+    command.args(args);
+    let mut selected_lines = LastMatchingLines::new(filter, max_lines);
+    run_command(command, command_line, timeout, &mut selected_lines).await?;
+    Ok(selected_lines.into())
+}
+
+/// Run a command, pushing the lines of its standard output as they are produced
+async fn run_command(
+    mut command: Command,
+    command_line: &str,
+    timeout: Duration,
+    selected_lines: &mut LastMatchingLines<'_>,
+) -> Result<(), LogRetrievalError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: err.to_string(),
+        })?;
+
+    let stdout = child.stdout.take().expect("the standard output is piped");
+    let mut stderr = child.stderr.take().expect("the standard error is piped");
+    let run = async {
+        let mut stderr_content = Vec::new();
+        let (stdout_read, stderr_read) = tokio::join!(
+            push_lines(BufReader::new(stdout), selected_lines),
+            stderr.read_to_end(&mut stderr_content)
+        );
+        stdout_read?;
+        stderr_read?;
+        let status = child.wait().await?;
+        Ok::<_, std::io::Error>((status, stderr_content))
+    };
+
+    // on timeout, the child process is killed when dropped
+    let (status, stderr_content) = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: format!("killed after {timeout:?}"),
+        })?
+        .map_err(|err| LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: err.to_string(),
+        })?;
+    if !status.success() {
+        return Err(LogRetrievalError::CommandFailed {
+            command: command_line.to_string(),
+            reason: format!(
+                "{status}: {}",
+                String::from_utf8_lossy(&stderr_content).trim()
+            ),
+        });
+    }
+    Ok(())
+}
+
+async fn push_lines(
+    mut output: impl AsyncBufRead + Unpin,
+    selected_lines: &mut LastMatchingLines<'_>,
+) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    while output.read_until(b'\n', &mut line).await? > 0 {
+        selected_lines.push(String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']));
+        line.clear();
+    }
+    Ok(())
+}
+
 pub fn read_log_content(
     logfile: &Path,
     mut line_counter: usize,
     max_lines: usize,
-    search_text: &Option<String>,
+    filter: &LogFilter,
 ) -> Result<(usize, String), LogRetrievalError> {
     if line_counter >= max_lines {
         Err(LogRetrievalError::MaxLines)
@@ -65,14 +396,13 @@ pub fn read_log_content(
                 reader.eof();
                 while line_counter < max_lines {
                     if let Some(haystack) = reader.prev_line()? {
-                        if let Some(needle) = &search_text {
-                            if haystack.contains(needle) {
+                        match filter.select(&haystack) {
+                            LineSelection::Selected => {
                                 file_content_as_vec.push_front(format!("{}\n", haystack));
                                 line_counter += 1;
                             }
-                        } else {
-                            file_content_as_vec.push_front(format!("{}\n", haystack));
-                            line_counter += 1;
+                            LineSelection::Rejected => {}
+                            LineSelection::Older => break,
                         }
                     } else {
                         // there are no more lines.prev_line()
@@ -99,7 +429,9 @@ pub fn filter_logs_on_type(
 ) -> Result<Vec<PathBuf>, LogRetrievalError> {
     let mut files_to_send = Vec::new();
     for file in files {
-        let maybe_file_path = file.path.as_str(); // because it can be a glob pattern
+        let LogSource::Files(maybe_file_path) = file.source() else {
+            continue;
+        }; // because it can be a glob pattern
         let file_type = file.config_type.as_str();
 
         if !file_type.eq(log_type) {
@@ -197,18 +529,22 @@ mod tests {
             FileEntry {
                 path: format!("{tempdir_path}/file_a"),
                 config_type: "type_one".to_string(),
+                ..Default::default()
             },
             FileEntry {
                 path: format!("{tempdir_path}/file_b"),
                 config_type: "type_one".to_string(),
+                ..Default::default()
             },
             FileEntry {
                 path: format!("{tempdir_path}/file_c"),
                 config_type: "type_two".to_string(),
+                ..Default::default()
             },
             FileEntry {
                 path: format!("{tempdir_path}/file_d"),
                 config_type: "type_one".to_string(),
+                ..Default::default()
             },
         ];
 
@@ -300,16 +636,17 @@ mod tests {
 
         let line_counter = 0;
         let max_lines = 4;
-        let filter_text = None;
+        let filter =
+            LogFilter::new(OffsetDateTime::UNIX_EPOCH, OffsetDateTime::now_utc(), &None).unwrap();
 
         let (line_counter, result) =
-            read_log_content(Path::new(file_path), line_counter, max_lines, &filter_text).unwrap();
+            read_log_content(Path::new(file_path), line_counter, max_lines, &filter).unwrap();
 
         assert_eq!(line_counter, max_lines);
         assert_eq!(result, "filename: file_a\nthis is the second line.\nthis is the third line.\nthis is the forth line.\nthis is the fifth line.\n");
     }
 
-    #[test]
+    #[tokio::test]
     /// Inserting 5 lines of logs for each log file { file_a, ..., file_d }.
     /// Each line contains the text: "this is the { line_number } line of { file_name }
     /// where line_number { first, second, third, forth, fifth }
@@ -328,7 +665,7 @@ mod tests {
     ///
     /// - all logs from file_d (5)
     /// - last two logs from file_b (2)
-    fn test_read_log_content_multiple_files() {
+    async fn test_read_log_content_multiple_files() {
         let (tempdir, files) = prepare();
         let tempdir_path = tempdir.path().to_str().unwrap();
 
@@ -354,13 +691,18 @@ mod tests {
             set_file_mtime(file_path, new_mtime).unwrap();
         }
         let temp_path = new_read_logs(
-            &files,
+            &LogPluginConfig {
+                files,
+                ..Default::default()
+            },
             "type_one",
             datetime!(1970-01-01 00:00:03 +00:00),
+            OffsetDateTime::now_utc(),
             7,
             &None,
             tempdir.path(),
         )
+        .await
         .unwrap();
 
         assert_eq!(temp_path.parent().unwrap(), tempdir.path());
@@ -368,4 +710,240 @@ mod tests {
         let result = std::fs::read_to_string(temp_path).unwrap();
         assert_eq!(result, String::from("filename: file_d\nthis is the first line of file_d.\nthis is the second line of file_d.\nthis is the third line of file_d.\nthis is the forth line of file_d.\nthis is the fifth line of file_d.\nfilename: file_b\nthis is the forth line of file_b.\nthis is the fifth line of file_b.\n"))
     }
+
+    #[test]
+    fn test_line_timestamp() {
+        for (line, expected) in [
+            (
+                "2024-01-02T03:04:05Z INFO Starting",
+                Some(datetime!(2024-01-02 03:04:05 +00:00)),
+            ),
+            (
+                "2024-01-02T03:04:05.123+01:00 Starting",
+                Some(datetime!(2024-01-02 03:04:05.123 +01:00)),
+            ),
+            (
+                "2024-01-02T03:04:05+0100 gateway mosquitto[42]: Starting",
+                Some(datetime!(2024-01-02 03:04:05 +01:00)),
+            ),
+            (
+                "[2024-01-02 03:04:05] Starting",
+                Some(datetime!(2024-01-02 03:04:05 +00:00)),
+            ),
+            ("Starting at 2024-01-02T03:04:05Z", None),
+            ("  at some::function()", None),
+        ] {
+            assert_eq!(line_timestamp(line, UtcOffset::UTC), expected, "{line}");
+        }
+    }
+
+    #[test]
+    /// Only the timestamps given without offset are read at the default offset
+    fn test_line_timestamp_with_default_offset() {
+        let offset = UtcOffset::from_hms(-5, -30, 0).unwrap();
+        for (line, expected) in [
+            (
+                "2024-01-02T03:04:05 INFO Starting",
+                datetime!(2024-01-02 03:04:05 -05:30),
+            ),
+            (
+                "[2024-01-02 03:04:05] Starting",
+                datetime!(2024-01-02 03:04:05 -05:30),
+            ),
+            (
+                "2024-01-02T03:04:05Z INFO Starting",
+                datetime!(2024-01-02 03:04:05 +00:00),
+            ),
+            (
+                "2024-01-02T03:04:05+01:00 Starting",
+                datetime!(2024-01-02 03:04:05 +01:00),
+            ),
+        ] {
+            assert_eq!(line_timestamp(line, offset), Some(expected), "{line}");
+        }
+    }
+
+    #[test]
+    /// The date range is checked against the timestamps of the lines, and the search text is a regex.
+    /// The lines without a timestamp are only checked against the search text.
+    fn test_log_filter() {
+        let output = "2024-01-01T09:00:00Z ERROR too old\n\
+                      2024-01-01T10:00:00Z INFO in range\n\
+                      2024-01-01T10:30:00Z ERROR in range\n\
+                      \tcaused by: ERROR without timestamp\n\
+                      2024-01-01T10:45:00Z WARN in range\n\
+                      2024-01-01T12:00:00Z ERROR too recent\n";
+        let filter = LogFilter::new(
+            datetime!(2024-01-01 10:00:00 +00:00),
+            datetime!(2024-01-01 11:00:00 +00:00),
+            &Some("ERROR|WARN".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            filter.last_matching_lines(output, 10),
+            vec![
+                "2024-01-01T10:30:00Z ERROR in range",
+                "\tcaused by: ERROR without timestamp",
+                "2024-01-01T10:45:00Z WARN in range",
+            ]
+        );
+        assert_eq!(
+            filter.last_matching_lines(output, 1),
+            vec!["2024-01-01T10:45:00Z WARN in range"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_search_pattern() {
+        let result = LogFilter::new(
+            OffsetDateTime::UNIX_EPOCH,
+            OffsetDateTime::now_utc(),
+            &Some("error(".to_string()),
+        );
+        assert!(matches!(
+            result,
+            Err(LogRetrievalError::InvalidSearchPattern(_))
+        ));
+    }
+
+    #[tokio::test]
+    /// journalctl is replaced by a script printing its arguments
+    async fn test_read_journal() {
+        let tempdir = TempTedgeDir::new();
+        let journalctl = tempdir.path().join("journalctl");
+        tedge_test_utils::fs::with_exec_permission(
+            &journalctl,
+            "#!/bin/sh\necho \"2024-01-01T10:00:00+0000 gateway mosquitto[42]: $*\"\n",
+        );
+        let source = JournalSource {
+            unit: Some("mosquitto.service".to_string()),
+            priority: Some("warning".to_string()),
+        };
+
+        let filter = LogFilter::new(
+            datetime!(2024-01-01 00:00:00 +00:00),
+            datetime!(2024-01-02 00:00:00 +00:00),
+            &None,
+        )
+        .unwrap();
+
+        let output = read_journal(
+            journalctl.to_str().unwrap(),
+            &source,
+            &filter,
+            100,
+            COMMAND_TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                "2024-01-01T10:00:00+0000 gateway mosquitto[42]: --no-pager --quiet --output short-iso \
+                 --since @1704067200 --until @1704153600 --lines 100 --unit mosquitto.service --priority warning"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    /// Only the last selected lines of a command output are kept, as the output is read
+    async fn test_read_last_lines_of_a_command_output() {
+        let filter = LogFilter::new(
+            OffsetDateTime::UNIX_EPOCH,
+            OffsetDateTime::now_utc(),
+            &Some("0$".to_string()),
+        )
+        .unwrap();
+
+        let output = read_command_output("seq 1 100000", &filter, 3, COMMAND_TIMEOUT)
+            .await
+            .unwrap();
+
+        assert_eq!(output, vec!["99980", "99990", "100000"]);
+    }
+
+    #[tokio::test]
+    /// The logs of a command are filtered on the timestamps of the lines
+    async fn test_read_logs_from_command_output() {
+        let tempdir = TempTedgeDir::new();
+        tempdir.file("output").with_raw_content(
+            "2024-01-01T09:00:00Z too old\n\
+             2024-01-01T10:00:00Z first line\n\
+             2024-01-01T10:30:00Z second line\n",
+        );
+        let files = vec![FileEntry {
+            config_type: "diagnostic".to_string(),
+            command: Some(format!("cat {}/output", tempdir.path().display())),
+            ..Default::default()
+        }];
+
+        let temp_path = new_read_logs(
+            &LogPluginConfig {
+                files,
+                ..Default::default()
+            },
+            "diagnostic",
+            datetime!(2024-01-01 10:00:00 +00:00),
+            datetime!(2024-01-01 11:00:00 +00:00),
+            100,
+            &None,
+            tempdir.path(),
+        )
+        .await
+        .unwrap();
+
+        let result = std::fs::read_to_string(temp_path).unwrap();
+        assert_eq!(
+            result,
+            format!(
+                "command: cat {}/output\n2024-01-01T10:00:00Z first line\n2024-01-01T10:30:00Z second line\n",
+                tempdir.path().display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_logs_from_failing_command() {
+        let tempdir = TempTedgeDir::new();
+        let files = vec![FileEntry {
+            config_type: "diagnostic".to_string(),
+            command: Some("false".to_string()),
+            ..Default::default()
+        }];
+
+        let result = new_read_logs(
+            &LogPluginConfig {
+                files,
+                ..Default::default()
+            },
+            "diagnostic",
+            OffsetDateTime::UNIX_EPOCH,
+            OffsetDateTime::now_utc(),
+            100,
+            &None,
+            tempdir.path(),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(LogRetrievalError::CommandFailed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_log_command_not_completed_in_time_is_killed() {
+        let filter =
+            LogFilter::new(OffsetDateTime::UNIX_EPOCH, OffsetDateTime::now_utc(), &None).unwrap();
+
+        let result =
+            read_command_output("sleep 10", &filter, 100, Duration::from_millis(100)).await;
+
+        assert!(matches!(
+            result,
+            Err(LogRetrievalError::CommandFailed { reason, .. }) if reason.starts_with("killed after")
+        ));
+    }
 }
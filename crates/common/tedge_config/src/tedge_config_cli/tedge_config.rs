@@ -443,7 +443,36 @@ define_tedge_config! {
                 #[tedge_config(note = "If set to 'auto', this cleans the local session accordingly the detected version of mosquitto.")]
                 #[tedge_config(example = "auto", default(variable = "AutoFlag::Auto"))]
                 local_cleansession: AutoFlag,
-            }
+            },
+
+            buffer: {
+                /// Enable the buffering of the telemetry data published to Cumulocity while the bridge is disconnected
+                #[tedge_config(example = "true", default(value = false))]
+                enable: bool,
+
+                /// The maximum size in bytes of the buffered telemetry data, the oldest data being dropped when exceeded
+                #[tedge_config(example = "10485760", default(value = 10485760_u64))]
+                max_size: u64,
+
+                /// The minimum interval in seconds between two buffered measurements of the same type, the others being dropped on replay
+                #[tedge_config(example = "60")]
+                downsampling_interval: Seconds,
+            },
+        },
+
+        aggregation: {
+            /// The size in seconds of the time windows over which the measurements are aggregated before being sent to Cumulocity
+            #[tedge_config(note = "The measurements are forwarded unchanged to Cumulocity if no window is set.")]
+            #[tedge_config(example = "60")]
+            window: Seconds,
+
+            /// The aggregation functions applied to each measurement series: min, max, mean, last and/or count
+            #[tedge_config(example = "min,max,mean", default(value = "mean"))]
+            functions: TemplatesSet,
+
+            /// Set of MQTT topics of the measurements to aggregate
+            #[tedge_config(example = "te/device/main///m/+", default(value = "te/+/+/+/+/m/+"))]
+            topics: TemplatesSet,
         },
 
         entity_store: {
@@ -482,6 +511,33 @@ define_tedge_config! {
         #[tedge_config(example = "te/+/+/+/+/a/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+")]
         #[tedge_config(default(value = "te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
         topics: TemplatesSet,
+
+        bridge: {
+            buffer: {
+                /// Enable the buffering of the telemetry data published to Azure IoT while the bridge is disconnected
+                #[tedge_config(example = "true", default(value = false))]
+                enable: bool,
+
+                /// The maximum size in bytes of the buffered telemetry data, the oldest data being dropped when exceeded
+                #[tedge_config(example = "10485760", default(value = 10485760_u64))]
+                max_size: u64,
+            },
+        },
+
+        aggregation: {
+            /// The size in seconds of the time windows over which the measurements are aggregated before being sent to Azure IoT
+            #[tedge_config(note = "The measurements are forwarded unchanged to Azure IoT if no window is set.")]
+            #[tedge_config(example = "60")]
+            window: Seconds,
+
+            /// The aggregation functions applied to each measurement series: min, max, mean, last and/or count
+            #[tedge_config(example = "min,max,mean", default(value = "mean"))]
+            functions: TemplatesSet,
+
+            /// Set of MQTT topics of the measurements to aggregate
+            #[tedge_config(example = "te/device/main///m/+", default(value = "te/+/+/+/+/m/+"))]
+            topics: TemplatesSet,
+        },
     },
 
     aws: {
@@ -512,6 +568,58 @@ define_tedge_config! {
         #[tedge_config(example = "te/+/+/+/+/a/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+")]
         #[tedge_config(default(value = "te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
         topics: TemplatesSet,
+
+        bridge: {
+            buffer: {
+                /// Enable the buffering of the telemetry data published to AWS IoT while the bridge is disconnected
+                #[tedge_config(example = "true", default(value = false))]
+                enable: bool,
+
+                /// The maximum size in bytes of the buffered telemetry data, the oldest data being dropped when exceeded
+                #[tedge_config(example = "10485760", default(value = 10485760_u64))]
+                max_size: u64,
+
+                /// The minimum interval in seconds between two buffered measurements of the same type, the others being dropped on replay
+                #[tedge_config(example = "60")]
+                downsampling_interval: Seconds,
+            },
+        },
+
+        aggregation: {
+            /// The size in seconds of the time windows over which the measurements are aggregated before being sent to AWS IoT
+            #[tedge_config(note = "The measurements are forwarded unchanged to AWS IoT if no window is set.")]
+            #[tedge_config(example = "60")]
+            window: Seconds,
+
+            /// The aggregation functions applied to each measurement series: min, max, mean, last and/or count
+            #[tedge_config(example = "min,max,mean", default(value = "mean"))]
+            functions: TemplatesSet,
+
+            /// Set of MQTT topics of the measurements to aggregate
+            #[tedge_config(example = "te/device/main///m/+", default(value = "te/+/+/+/+/m/+"))]
+            topics: TemplatesSet,
+        },
+    },
+
+    otel: {
+        /// The base URL of the OpenTelemetry collector receiving the telemetry data over OTLP/HTTP
+        #[tedge_config(example = "http://localhost:4318", default(value = "http://localhost:4318"))]
+        endpoint: String,
+
+        /// Set of MQTT topics the OpenTelemetry mapper should subscribe to
+        #[tedge_config(example = "te/+/+/+/+,te/+/+/+/+/twin/+,te/+/+/+/+/m/+")]
+        #[tedge_config(default(value = "te/+/+/+/+,te/+/+/+/+/twin/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+"))]
+        topics: TemplatesSet,
+
+        batch: {
+            /// The number of data points and log records triggering an export to the collector
+            #[tedge_config(example = "512", default(value = 512u32))]
+            max_size: u32,
+
+            /// The maximum delay in seconds before the pending telemetry data are exported to the collector
+            #[tedge_config(example = "10", default(value = 10_u64))]
+            interval: Seconds,
+        },
     },
 
     mqtt: {
@@ -669,6 +777,35 @@ define_tedge_config! {
         ca_path: Utf8PathBuf,
     },
 
+    metrics: {
+        bind: {
+            /// The IP address the Prometheus metrics endpoints bind to
+            #[tedge_config(example = "127.0.0.1", example = "0.0.0.0", default(variable = "Ipv4Addr::LOCALHOST"))]
+            address: IpAddr,
+        },
+
+        /// The ports of the Prometheus metrics endpoints, each given as `<service>=<port>`
+        #[tedge_config(note = "The metrics of a service are exported only if a port is given for this service.")]
+        #[tedge_config(example = "tedge-agent=9100,tedge-mapper-c8y=9101", default(function = "TemplatesSet::default"))]
+        ports: TemplatesSet,
+
+        /// The file that will be used as the server certificate for the Prometheus metrics endpoints
+        #[tedge_config(example = "/etc/tedge/device-certs/metrics_certificate.pem")]
+        #[doku(as = "PathBuf")]
+        cert_path: Utf8PathBuf,
+
+        /// The file that will be used as the server private key for the Prometheus metrics endpoints
+        #[tedge_config(example = "/etc/tedge/device-certs/metrics_key.pem")]
+        #[doku(as = "PathBuf")]
+        key_path: Utf8PathBuf,
+
+        /// Path to a directory containing the PEM encoded CA certificates that are
+        /// trusted when checking incoming client certificates for the Prometheus metrics endpoints
+        #[tedge_config(example = "/etc/ssl/certs")]
+        #[doku(as = "PathBuf")]
+        ca_path: Utf8PathBuf,
+    },
+
     agent: {
         state: {
             /// The directory where the tedge-agent persists its state across restarts
@@ -691,6 +828,34 @@ define_tedge_config! {
             /// Determines if tedge-agent should enable log_upload operation
             #[tedge_config(example = "true", default(value = true))]
             log_upload: bool,
+
+            /// Determines if tedge-agent should raise alarms on measurements, as defined by the rules in `plugins/tedge-alarm-rules.toml`
+            #[tedge_config(example = "true", default(value = false))]
+            alarm_rules: bool,
+
+            /// Determines if tedge-agent should serve the REST API exposing the entities, their twin data and commands
+            #[tedge_config(note = "The REST API is served on `http.bind.address`, along the file transfer service, without authentication unless `http.ca_path` is set. Only enable it when this address is not reachable from outside the device.")]
+            #[tedge_config(example = "false", default(value = false))]
+            rest_api: bool,
+        },
+
+        /// The maintenance windows during which commands can be scheduled, each given as `<name>=<cron expression> <duration>`
+        #[tedge_config(note = "The cron expressions are evaluated in UTC. Use the TOML array syntax in tedge.toml to define cron expressions with comma-separated lists.")]
+        #[tedge_config(example = "nightly=0 2 * * * 3h", default(function = "TemplatesSet::default"))]
+        maintenance_windows: TemplatesSet,
+
+        concurrency: {
+            /// The maximum number of commands executed concurrently by the agent, whatever their operations
+            #[tedge_config(example = "2")]
+            max_commands: u32,
+
+            /// The maximum number of commands executed concurrently per operation, each limit given as `<operation>=<max>`
+            #[tedge_config(example = "software_update=1,firmware_update=1", default(function = "TemplatesSet::default"))]
+            operations: TemplatesSet,
+
+            /// Groups of operations that must not be executed concurrently, each group given as `<name>=<operation>+<operation>...`
+            #[tedge_config(example = "packages=software_update+firmware_update", default(function = "TemplatesSet::default"))]
+            exclusion_groups: TemplatesSet,
         },
     },
 
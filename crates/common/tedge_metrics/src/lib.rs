@@ -0,0 +1,300 @@
+//! Internal metrics of thin-edge, rendered using the Prometheus text format.
+//!
+//! The metrics of a process are registered in a process-wide [Registry],
+//! each metric being identified by a name and a set of labels.
+//!
+//! ```
+//! let restarts = tedge_metrics::counter(
+//!     "my_plugin_restarts_total",
+//!     "Number of plugin restarts",
+//!     &[("plugin", "apt")],
+//! );
+//! restarts.inc();
+//!
+//! assert!(tedge_metrics::render().contains(r#"my_plugin_restarts_total{plugin="apt"} 1"#));
+//! ```
+//!
+//! The metrics collected by thin-edge itself are created by the functions of the [metrics] module.
+pub mod metrics;
+
+pub use metrics::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+/// The registry used by the process
+pub fn global() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+/// Get or register a counter of the process-wide registry
+pub fn counter(name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+    global().counter(name, help, labels)
+}
+
+/// Get or register a gauge of the process-wide registry
+pub fn gauge(name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+    global().gauge(name, help, labels)
+}
+
+/// Render all the metrics of the process-wide registry
+pub fn render() -> String {
+    global().render()
+}
+
+/// A monotonic counter
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1)
+    }
+
+    pub fn inc_by(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down
+#[derive(Clone, Debug)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Gauge(Arc::new(AtomicU64::new(0f64.to_bits())))
+    }
+}
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + delta).to_bits())
+            });
+    }
+
+    pub fn inc(&self) {
+        self.add(1.0)
+    }
+
+    pub fn dec(&self) {
+        self.add(-1.0)
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    fn name(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+/// All the time series sharing a metric name
+struct MetricFamily {
+    help: String,
+    metric_type: MetricType,
+    series: BTreeMap<Labels, Arc<AtomicU64>>,
+}
+
+/// A set of metrics
+#[derive(Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, MetricFamily>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Get or register a counter
+    ///
+    /// If the name is already used by a gauge, the returned counter is not registered.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        let cell = self
+            .register(name, help, MetricType::Counter, labels, 0)
+            .unwrap_or_default();
+        Counter(cell)
+    }
+
+    /// Get or register a gauge
+    ///
+    /// If the name is already used by a counter, the returned gauge is not registered.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.register(name, help, MetricType::Gauge, labels, 0f64.to_bits()) {
+            Some(cell) => Gauge(cell),
+            None => Gauge::default(),
+        }
+    }
+
+    fn register(
+        &self,
+        name: &str,
+        help: &str,
+        metric_type: MetricType,
+        labels: &[(&str, &str)],
+        init: u64,
+    ) -> Option<Arc<AtomicU64>> {
+        let mut families = self.families.lock().unwrap();
+        let family = families
+            .entry(name.to_string())
+            .or_insert_with(|| MetricFamily {
+                help: help.to_string(),
+                metric_type,
+                series: BTreeMap::new(),
+            });
+        if family.metric_type != metric_type {
+            return None;
+        }
+
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let cell = family
+            .series
+            .entry(labels)
+            .or_insert_with(|| Arc::new(AtomicU64::new(init)));
+        Some(cell.clone())
+    }
+
+    /// Render all the metrics using the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut output = String::new();
+        for (name, family) in families.iter() {
+            let _ = writeln!(output, "# HELP {name} {}", escape_help(&family.help));
+            let _ = writeln!(output, "# TYPE {name} {}", family.metric_type.name());
+            for (labels, cell) in family.series.iter() {
+                let bits = cell.load(Ordering::Relaxed);
+                let value = match family.metric_type {
+                    MetricType::Counter => bits.to_string(),
+                    MetricType::Gauge => format_float(f64::from_bits(bits)),
+                };
+                let _ = writeln!(output, "{name}{} {value}", format_labels(labels));
+            }
+        }
+        output
+    }
+}
+
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", escape_label_value(value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    escape_help(value).replace('"', r#"\""#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_shared_by_name_and_labels() {
+        let registry = Registry::new();
+        let counter = registry.counter("requests_total", "Requests", &[("status", "ok")]);
+        counter.inc();
+        registry
+            .counter("requests_total", "Requests", &[("status", "ok")])
+            .inc_by(2);
+        registry
+            .counter("requests_total", "Requests", &[("status", "failed")])
+            .inc();
+
+        assert_eq!(counter.get(), 3);
+        assert_eq!(
+            registry.render(),
+            r#"# HELP requests_total Requests
+# TYPE requests_total counter
+requests_total{status="failed"} 1
+requests_total{status="ok"} 3
+"#
+        );
+    }
+
+    #[test]
+    fn gauges_go_up_and_down() {
+        let registry = Registry::new();
+        let gauge = registry.gauge("queue_depth", "Depth", &[]);
+        gauge.inc();
+        gauge.inc();
+        gauge.dec();
+        registry
+            .gauge("temperature", "Temperature", &[("sensor", "a\"b")])
+            .set(21.5);
+
+        assert_eq!(gauge.get(), 1.0);
+        assert_eq!(
+            registry.render(),
+            r#"# HELP queue_depth Depth
+# TYPE queue_depth gauge
+queue_depth 1
+# HELP temperature Temperature
+# TYPE temperature gauge
+temperature{sensor="a\"b"} 21.5
+"#
+        );
+    }
+
+    #[test]
+    fn a_name_is_used_by_a_single_metric_type() {
+        let registry = Registry::new();
+        registry.counter("events", "Events", &[]).inc();
+        registry.gauge("events", "Events", &[]).set(42.0);
+
+        assert!(registry.render().contains("events 1\n"));
+    }
+}
@@ -0,0 +1,81 @@
+//! The metrics collected by thin-edge components
+use crate::counter;
+use crate::gauge;
+use crate::Counter;
+use crate::Gauge;
+
+/// Number of messages successfully converted by a mapper
+pub fn messages_converted(mapper: &str) -> Counter {
+    counter(
+        "tedge_messages_converted_total",
+        "Number of messages converted by a mapper",
+        &[("mapper", mapper)],
+    )
+}
+
+/// Number of messages a mapper failed to convert
+pub fn conversion_errors(mapper: &str) -> Counter {
+    counter(
+        "tedge_conversion_errors_total",
+        "Number of messages a mapper failed to convert",
+        &[("mapper", mapper)],
+    )
+}
+
+/// Number of records a mapper failed to export, and dropped
+pub fn dropped_records(mapper: &str) -> Counter {
+    counter(
+        "tedge_dropped_records_total",
+        "Number of records a mapper failed to export",
+        &[("mapper", mapper)],
+    )
+}
+
+/// Number of messages waiting in the mailbox of an actor
+pub fn mailbox_depth(actor: &str) -> Gauge {
+    gauge(
+        "tedge_actor_mailbox_depth",
+        "Number of messages waiting in the mailbox of an actor",
+        &[("actor", actor)],
+    )
+}
+
+/// Number of bytes downloaded
+pub fn downloaded_bytes() -> Counter {
+    counter(
+        "tedge_downloaded_bytes_total",
+        "Number of bytes downloaded",
+        &[],
+    )
+}
+
+/// Number of bytes uploaded
+pub fn uploaded_bytes() -> Counter {
+    counter(
+        "tedge_uploaded_bytes_total",
+        "Number of bytes uploaded",
+        &[],
+    )
+}
+
+/// Number of commands that reached a status, per operation
+pub fn commands(operation: &str, status: &str) -> Counter {
+    counter(
+        "tedge_commands_total",
+        "Number of commands that reached a status",
+        &[("operation", operation), ("status", status)],
+    )
+}
+
+/// Latest value of a measurement series published by an entity
+pub fn measurement(entity: &str, measurement_type: &str, series: &str) -> Gauge {
+    gauge(
+        "tedge_measurement",
+        "Latest value of a measurement series",
+        &[
+            ("entity", entity),
+            ("type", measurement_type),
+            ("series", series),
+        ],
+    )
+}
@@ -6,6 +6,9 @@ pub enum UploadError {
         source: std::io::Error,
     },
 
+    #[error("Resumable uploads are not supported by {url}")]
+    ResumableUploadNotSupported { url: String },
+
     #[error(transparent)]
     Network(#[from] reqwest::Error),
 }
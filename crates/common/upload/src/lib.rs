@@ -4,6 +4,7 @@
 //!
 //! - using a single uploader to upload related files
 //! - implementing reasonable exponential backoff strategy
+//! - resuming interrupted uploads, when the file is uploaded in chunks
 //!
 //! # Usage
 //!
@@ -6,16 +6,25 @@ use camino::Utf8PathBuf;
 use log::info;
 use log::warn;
 use reqwest::header::CONTENT_LENGTH;
+use reqwest::header::CONTENT_RANGE;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::Body;
 use reqwest::Identity;
+use reqwest::RequestBuilder;
+use reqwest::StatusCode;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::io::SeekFrom;
 use std::time::Duration;
 use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
 use tokio_util::codec::BytesCodec;
 use tokio_util::codec::FramedRead;
 
+/// Header used by a server supporting resumable uploads to tell how many bytes have been received
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+
 fn default_backoff() -> ExponentialBackoff {
     // Default retry is an exponential retry with a limit of 5 minutes total.
     // Let's set some more reasonable retry policy so we don't block the uploads for too long.
@@ -47,6 +56,7 @@ pub struct UploadInfo {
     pub url: String,
     pub auth: Option<Auth>,
     pub content_type: ContentType,
+    pub chunk_size: Option<u64>,
 }
 
 impl From<&str> for UploadInfo {
@@ -61,6 +71,7 @@ impl UploadInfo {
             url: url.into(),
             auth: None,
             content_type: ContentType::ApplicationOctetStream,
+            chunk_size: None,
         }
     }
 
@@ -78,6 +89,21 @@ impl UploadInfo {
         }
     }
 
+    /// Upload the file in chunks of the given size, so an interrupted upload can be resumed
+    ///
+    /// Each chunk is sent with a `Content-Range` header, after having asked the server
+    /// how many bytes have already been received with a `Content-Range: bytes */<length>` header.
+    ///
+    /// This must only be used with a server known to support this protocol, as the file-transfer service:
+    /// a server that ignores the `Content-Range` header creates an empty file on the status request.
+    /// The upload fails if the server doesn't answer the status request with an `Upload-Offset` header.
+    pub fn with_chunk_size(self, chunk_size: u64) -> Self {
+        Self {
+            chunk_size: Some(chunk_size.max(1)),
+            ..self
+        }
+    }
+
     pub fn url(&self) -> &str {
         self.url.as_str()
     }
@@ -109,12 +135,10 @@ impl Uploader {
     }
 
     pub async fn upload(&self, url: &UploadInfo) -> Result<(), UploadError> {
-        self.upload_request(url).await?;
-
-        Ok(())
+        self.upload_request(url).await
     }
 
-    async fn upload_request(&self, url: &UploadInfo) -> Result<reqwest::Response, UploadError> {
+    async fn upload_request(&self, url: &UploadInfo) -> Result<(), UploadError> {
         use crate::error::ErrContext;
 
         let operation = || async {
@@ -133,8 +157,6 @@ impl Uploader {
                 .map_err(backoff::Error::Permanent)?
                 .len();
 
-            let file_body = Body::wrap_stream(FramedRead::new(file, BytesCodec::new()));
-
             let mut client = reqwest::Client::builder();
             if let Some(identity) = self.identity.clone() {
                 client = client.identity(identity);
@@ -166,34 +188,25 @@ impl Uploader {
                 info!("Redirecting request from {} to {target_url}", url.url())
             }
 
-            // Todo: Ideally it detects the appropriate content-type automatically, e.g. UTF-8 => text/plain
-            let mut client = client
-                .put(target_url)
-                .header(CONTENT_TYPE, url.content_type.to_string())
-                .header(CONTENT_LENGTH, file_length);
-
-            if let Some(Auth::Bearer(token)) = &url.auth {
-                client = client.bearer_auth(token)
+            if let Some(chunk_size) = url.chunk_size.filter(|_| file_length > 0) {
+                let offset = upload_offset(&client, target_url, url, file_length).await?;
+                return self
+                    .upload_chunks(&client, target_url, url, file_length, offset, chunk_size)
+                    .await;
             }
 
-            client
+            let file_body = Body::wrap_stream(FramedRead::new(file, BytesCodec::new()));
+            put_request(&client, target_url, url)
+                .header(CONTENT_LENGTH, file_length)
                 .body(file_body)
                 .send()
                 .await
-                .map_err(|err| {
-                    if err.is_builder() || err.is_connect() {
-                        backoff::Error::Permanent(UploadError::Network(err))
-                    } else {
-                        backoff::Error::transient(UploadError::Network(err))
-                    }
-                })?
+                .map_err(send_error)?
                 .error_for_status()
-                .map_err(|err| match err.status() {
-                    Some(status_error) if status_error.is_client_error() => {
-                        backoff::Error::Permanent(UploadError::Network(err))
-                    }
-                    _ => backoff::Error::transient(UploadError::Network(err)),
-                })
+                .map_err(status_error)?;
+
+            tedge_metrics::uploaded_bytes().inc_by(file_length);
+            Ok(())
         };
 
         retry_notify(self.backoff.clone(), operation, |err, dur: Duration| {
@@ -203,15 +216,150 @@ impl Uploader {
         .await
     }
 
+    /// Upload the file chunk by chunk, starting from the given offset
+    ///
+    /// The server can adjust the offset of the next chunk with an `Upload-Offset` header,
+    /// notably when the previous chunk has not been fully received.
+    async fn upload_chunks(
+        &self,
+        client: &reqwest::Client,
+        target_url: &str,
+        url: &UploadInfo,
+        file_length: u64,
+        mut offset: u64,
+        chunk_size: u64,
+    ) -> Result<(), backoff::Error<UploadError>> {
+        while offset < file_length {
+            let end = file_length.min(offset + chunk_size);
+            let chunk_length = end - offset;
+            let response = put_request(client, target_url, url)
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {offset}-{}/{file_length}", end - 1),
+                )
+                .header(CONTENT_LENGTH, chunk_length)
+                .body(self.file_chunk(offset, chunk_length).await?)
+                .send()
+                .await
+                .map_err(send_error)?;
+
+            if response.status() == StatusCode::CONFLICT {
+                match upload_offset_header(&response) {
+                    Some(server_offset) if server_offset != offset => {
+                        info!("Resuming upload to {target_url} from byte {server_offset}");
+                        offset = server_offset;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let response = response.error_for_status().map_err(status_error)?;
+            tedge_metrics::uploaded_bytes().inc_by(chunk_length);
+            offset = upload_offset_header(&response).unwrap_or(end);
+        }
+
+        Ok(())
+    }
+
+    async fn file_chunk(
+        &self,
+        offset: u64,
+        length: u64,
+    ) -> Result<Body, backoff::Error<UploadError>> {
+        use crate::error::ErrContext;
+
+        let mut file = File::open(&self.source_filename)
+            .await
+            .context(format!("Can't open a file {:?}", &self.source_filename))
+            .map_err(backoff::Error::Permanent)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .context(format!(
+                "Can't read a file {:?} from byte {offset}",
+                &self.source_filename
+            ))
+            .map_err(backoff::Error::Permanent)?;
+
+        Ok(Body::wrap_stream(FramedRead::new(
+            file.take(length),
+            BytesCodec::new(),
+        )))
+    }
+
     pub fn filename(&self) -> &Utf8Path {
         self.source_filename.as_path()
     }
 }
 
+fn put_request(client: &reqwest::Client, target_url: &str, url: &UploadInfo) -> RequestBuilder {
+    // Todo: Ideally it detects the appropriate content-type automatically, e.g. UTF-8 => text/plain
+    let mut request = client
+        .put(target_url)
+        .header(CONTENT_TYPE, url.content_type.to_string());
+
+    if let Some(Auth::Bearer(token)) = &url.auth {
+        request = request.bearer_auth(token)
+    }
+    request
+}
+
+/// Ask the server how many bytes of the file have already been received
+async fn upload_offset(
+    client: &reqwest::Client,
+    target_url: &str,
+    url: &UploadInfo,
+    file_length: u64,
+) -> Result<u64, backoff::Error<UploadError>> {
+    let response = put_request(client, target_url, url)
+        .header(CONTENT_RANGE, format!("bytes */{file_length}"))
+        .header(CONTENT_LENGTH, 0)
+        .send()
+        .await
+        .map_err(send_error)?
+        .error_for_status()
+        .map_err(status_error)?;
+
+    upload_offset_header(&response).ok_or_else(|| {
+        backoff::Error::Permanent(UploadError::ResumableUploadNotSupported {
+            url: target_url.to_string(),
+        })
+    })
+}
+
+fn upload_offset_header(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(UPLOAD_OFFSET)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+fn send_error(err: reqwest::Error) -> backoff::Error<UploadError> {
+    if err.is_builder() || err.is_connect() {
+        backoff::Error::Permanent(UploadError::Network(err))
+    } else {
+        backoff::Error::transient(UploadError::Network(err))
+    }
+}
+
+fn status_error(err: reqwest::Error) -> backoff::Error<UploadError> {
+    match err.status() {
+        Some(status_error) if status_error.is_client_error() => {
+            backoff::Error::Permanent(UploadError::Network(err))
+        }
+        _ => backoff::Error::transient(UploadError::Network(err)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::body::Bytes;
     use axum::extract::BodyStream;
+    use axum::http::HeaderMap;
     use axum::http::StatusCode;
     use axum::routing::put;
     use axum::Router;
@@ -219,8 +367,10 @@ mod tests {
     use futures::future::pending;
     use futures::stream::StreamExt;
     use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
     use std::sync::atomic::Ordering;
     use std::sync::Arc;
+    use std::sync::Mutex;
     use tedge_test_utils::fs::TempTedgeDir;
     use tempfile::tempdir;
     use tokio::fs::read_to_string;
@@ -412,6 +562,122 @@ mod tests {
         assert_eq!(source_content, target_content);
     }
 
+    #[tokio::test]
+    async fn chunked_upload_fails_on_a_server_not_supporting_resumable_uploads() {
+        let mut server = mockito::Server::new();
+        // Without an `Upload-Offset` header in the response to the status request,
+        // no chunks are sent
+        let status_request = server
+            .mock("PUT", "/some_file.txt")
+            .match_header("Content-Range", "bytes */13")
+            .with_status(201)
+            .create();
+        let chunk_request = server
+            .mock("PUT", "/some_file.txt")
+            .match_body("Hell")
+            .expect(0)
+            .create();
+
+        let mut target_url = server.url();
+        target_url.push_str("/some_file.txt");
+
+        let url = UploadInfo::new(&target_url).with_chunk_size(4);
+
+        let ttd = TempTedgeDir::new();
+        ttd.file("file_upload.txt")
+            .with_raw_content("Hello, world!");
+
+        let uploader = Uploader::new(ttd.utf8_path().join("file_upload.txt"), None);
+
+        let err = uploader.upload(&url).await.unwrap_err();
+        assert!(matches!(
+            err,
+            UploadError::ResumableUploadNotSupported { .. }
+        ));
+        status_request.assert();
+        chunk_request.assert();
+    }
+
+    #[tokio::test]
+    async fn resume_chunked_upload_after_a_failure() {
+        let listener = TcpListener::bind("localhost:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let received = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sent_bytes = Arc::new(AtomicUsize::new(0));
+        let is_first_failure = Arc::new(AtomicBool::new(true));
+
+        let app = {
+            let received = received.clone();
+            let sent_bytes = sent_bytes.clone();
+            Router::new().route(
+                "/target.txt",
+                put(|headers: HeaderMap, body: Bytes| async move {
+                    let range = headers["Content-Range"].to_str().unwrap().to_owned();
+                    let mut received = received.lock().unwrap();
+                    let offset = received.len();
+                    let status = if range.starts_with("bytes */") {
+                        StatusCode::NO_CONTENT
+                    } else {
+                        sent_bytes.fetch_add(body.len(), Ordering::SeqCst);
+                        let (start, total) = range
+                            .strip_prefix("bytes ")
+                            .and_then(|range| range.split_once('/'))
+                            .map(|(range, total)| (range.split('-').next().unwrap(), total))
+                            .unwrap();
+                        let total: usize = total.parse().unwrap();
+                        if start.parse::<usize>().unwrap() != offset {
+                            StatusCode::CONFLICT
+                        } else if offset > 0 && is_first_failure.fetch_and(false, Ordering::SeqCst)
+                        {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        } else {
+                            received.extend_from_slice(&body);
+                            if received.len() == total {
+                                StatusCode::CREATED
+                            } else {
+                                StatusCode::ACCEPTED
+                            }
+                        }
+                    };
+                    (status, [(UPLOAD_OFFSET, received.len().to_string())])
+                }),
+            )
+        };
+
+        let server_task = tokio::spawn(
+            axum::Server::from_tcp(listener.into_std().unwrap())
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let ttd = TempTedgeDir::new();
+        let source_path = ttd.utf8_path().join("source.txt");
+        let mut source_file = File::create(&source_path).await.unwrap();
+        write_to_file_with_size(&mut source_file, 100 * 1024).await;
+
+        let mut uploader = Uploader::new(source_path.to_owned(), None);
+        uploader.set_backoff(
+            ExponentialBackoffBuilder::new()
+                .with_initial_interval(Duration::from_millis(10))
+                .with_max_elapsed_time(Some(Duration::from_secs(10)))
+                .build(),
+        );
+        let url =
+            UploadInfo::new(&format!("http://localhost:{port}/target.txt")).with_chunk_size(30000);
+
+        uploader.upload(&url).await.unwrap();
+        server_task.abort();
+
+        let source_content = tokio::fs::read(source_path).await.unwrap();
+        assert_eq!(*received.lock().unwrap(), source_content);
+        // Only the failed chunk has been sent twice
+        assert_eq!(
+            sent_bytes.load(Ordering::SeqCst),
+            source_content.len() + 30000
+        );
+    }
+
     async fn write_to_file_with_size(file: &mut File, size: usize) {
         let data: String = "Some data!".into();
         let loops = size / data.len();
@@ -49,6 +49,10 @@ impl C8yEndPoint {
         self.devices_internal_id.insert(device_id, internal_id);
     }
 
+    pub fn remove_internal_id(&mut self, device_id: &str) {
+        self.devices_internal_id.remove(device_id);
+    }
+
     fn get_base_url(&self) -> String {
         let mut url_get_id = String::new();
         if !self.c8y_host.starts_with("http") {
@@ -66,6 +70,13 @@ impl C8yEndPoint {
         url_update_swlist
     }
 
+    pub fn get_url_for_managed_object(&self, internal_id: String) -> String {
+        let mut url_managed_object = self.get_base_url();
+        url_managed_object.push_str("/inventory/managedObjects/");
+        url_managed_object.push_str(&internal_id);
+        url_managed_object
+    }
+
     pub fn get_url_for_internal_id(&self, device_id: String) -> String {
         let mut url_get_id = self.get_base_url();
         url_get_id.push_str("/identity/externalIds/c8y_Serial/");
@@ -210,6 +221,14 @@ mod tests {
         assert_eq!(res, "https://test_host/inventory/managedObjects/12345");
     }
 
+    #[test]
+    fn get_url_for_managed_object_returns_correct_address() {
+        let c8y = C8yEndPoint::new("test_host", "test_device");
+        let res = c8y.get_url_for_managed_object("12345".to_string());
+
+        assert_eq!(res, "https://test_host/inventory/managedObjects/12345");
+    }
+
     #[test_case("http://aaa.test.com")]
     #[test_case("https://aaa.test.com")]
     #[test_case("ftp://aaa.test.com")]
@@ -10,6 +10,7 @@ use tedge_api::entity_store::EntityMetadata;
 use tedge_api::entity_store::EntityType;
 use tedge_api::event::ThinEdgeEvent;
 use tedge_api::messages::SoftwareListCommand;
+use tedge_api::telemetry_metadata::AlarmMetadata;
 use tedge_api::EntityStore;
 use tedge_api::Jsonify;
 use tedge_api::SoftwareModule;
@@ -246,14 +247,18 @@ impl C8yAlarm {
         if let Some(entity) = entity_store.get(&alarm.source) {
             let source = Self::convert_source(entity);
             let alarm_type = Self::convert_alarm_type(&alarm.alarm_type);
+            let metadata = entity
+                .telemetry_metadata
+                .alarm(&alarm.alarm_type)
+                .unwrap_or_default();
 
             let c8y_alarm = match alarm.data.as_ref() {
                 None => C8yAlarm::Clear(C8yClearAlarm { alarm_type, source }),
                 Some(tedge_alarm_data) => C8yAlarm::Create(C8yCreateAlarm {
                     alarm_type: alarm_type.clone(),
                     source,
-                    severity: C8yCreateAlarm::convert_severity(tedge_alarm_data),
-                    text: C8yCreateAlarm::convert_text(tedge_alarm_data, &alarm_type),
+                    severity: C8yCreateAlarm::convert_severity(tedge_alarm_data, &metadata),
+                    text: C8yCreateAlarm::convert_text(tedge_alarm_data, &alarm_type, &metadata),
                     time: C8yCreateAlarm::convert_time(tedge_alarm_data),
                     fragments: C8yCreateAlarm::convert_extras(tedge_alarm_data),
                 }),
@@ -284,8 +289,9 @@ impl C8yAlarm {
 }
 
 impl C8yCreateAlarm {
-    fn convert_severity(alarm_data: &ThinEdgeAlarmData) -> AlarmSeverity {
-        match alarm_data.severity.clone() {
+    /// The severity of an alarm, defaulting to the severity given by the alarm type metadata
+    fn convert_severity(alarm_data: &ThinEdgeAlarmData, metadata: &AlarmMetadata) -> AlarmSeverity {
+        match alarm_data.severity.clone().or(metadata.severity.clone()) {
             Some(severity) => match AlarmSeverity::try_from(severity.as_str()) {
                 Ok(c8y_severity) => c8y_severity,
                 Err(_) => DEFAULT_ALARM_SEVERITY,
@@ -294,8 +300,17 @@ impl C8yCreateAlarm {
         }
     }
 
-    fn convert_text(alarm_data: &ThinEdgeAlarmData, alarm_type: &str) -> String {
-        alarm_data.text.clone().unwrap_or(alarm_type.to_string())
+    /// The text of an alarm, defaulting to the text given by the alarm type metadata or to the alarm type
+    fn convert_text(
+        alarm_data: &ThinEdgeAlarmData,
+        alarm_type: &str,
+        metadata: &AlarmMetadata,
+    ) -> String {
+        alarm_data
+            .text
+            .clone()
+            .or(metadata.text.clone())
+            .unwrap_or(alarm_type.to_string())
     }
 
     fn convert_time(alarm_data: &ThinEdgeAlarmData) -> OffsetDateTime {
@@ -371,6 +386,7 @@ mod tests {
     use tedge_api::entity_store::InvalidExternalIdError;
     use tedge_api::event::ThinEdgeEventData;
     use tedge_api::messages::SoftwareListCommandPayload;
+    use tedge_api::mqtt_topics::Channel;
     use tedge_api::mqtt_topics::EntityTopicId;
     use tedge_api::mqtt_topics::MqttSchema;
     use test_case::test_case;
@@ -733,6 +749,55 @@ mod tests {
         assert_eq!(actual_c8y_alarm, expected_c8y_alarm);
     }
 
+    #[test]
+    fn alarm_translation_uses_the_alarm_metadata_as_default() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let main_device = EntityRegistrationMessage::main_device("test-main".into());
+        let mut entity_store = EntityStore::with_main_device_and_default_service_type(
+            MqttSchema::default(),
+            main_device,
+            "service".into(),
+            dummy_external_id_mapper,
+            dummy_external_id_validator,
+            5,
+            &temp_dir,
+        )
+        .unwrap();
+        entity_store
+            .update_telemetry_metadata(
+                &EntityTopicId::default_main_device(),
+                &Channel::AlarmMetadata {
+                    alarm_type: "temperature_high".into(),
+                },
+                json!({"severity": "major", "text": "Temperature is too high"}),
+            )
+            .unwrap();
+
+        let alarm_data = |severity: Option<&str>| ThinEdgeAlarmData {
+            severity: severity.map(|s| s.to_string()),
+            text: None,
+            time: None,
+            extras: HashMap::new(),
+        };
+        for (severity, expected_severity) in [
+            (None, AlarmSeverity::Major),
+            (Some("critical"), AlarmSeverity::Critical),
+        ] {
+            let tedge_alarm = ThinEdgeAlarm {
+                alarm_type: "temperature_high".into(),
+                source: EntityTopicId::default_main_device(),
+                data: Some(alarm_data(severity)),
+            };
+            match C8yAlarm::try_from(&tedge_alarm, &entity_store).unwrap() {
+                C8yAlarm::Create(value) => {
+                    assert_eq!(value.severity, expected_severity);
+                    assert_eq!(value.text, "Temperature is too high");
+                }
+                C8yAlarm::Clear(_) => panic!("Must be C8yAlarm::Create"),
+            };
+        }
+    }
+
     #[test]
     fn alarm_translation_generates_timestamp_if_not_given() {
         let tedge_alarm = ThinEdgeAlarm {
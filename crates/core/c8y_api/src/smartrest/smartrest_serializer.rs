@@ -77,6 +77,11 @@ pub fn succeed_operation(
     Ok(String::from_utf8(output)?)
 }
 
+/// Generates a SmartREST message to create an event of the provided type with the provided text
+pub fn create_event(event_type: &str, text: &str) -> String {
+    fields_to_csv_string(&["400", event_type, text])
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum CumulocitySupportedOperations {
     C8ySoftwareUpdate,
@@ -248,6 +253,18 @@ mod tests {
         assert_eq!(smartrest, "501,c8y_SoftwareUpdate");
     }
 
+    #[test]
+    fn serialize_smartrest_create_event() {
+        let smartrest = create_event(
+            "c8y_OperationQueued",
+            "software_update operation queued, awaiting the completion of running operations",
+        );
+        assert_eq!(
+            smartrest,
+            "400,c8y_OperationQueued,\"software_update operation queued, awaiting the completion of running operations\""
+        );
+    }
+
     #[test]
     fn serialize_smartrest_set_operation_to_successful() {
         let smartrest =
@@ -24,6 +24,7 @@ mod init;
 mod mqtt;
 mod reconnect;
 mod refresh_bridges;
+mod workflow;
 
 #[derive(clap::Parser, Debug)]
 #[clap(
@@ -112,6 +113,10 @@ pub enum TEdgeOpt {
     /// Publish a message on a topic and subscribe a topic.
     #[clap(subcommand)]
     Mqtt(mqtt::TEdgeMqttCli),
+
+    /// Check, simulate and inspect user-defined operation workflows.
+    #[clap(subcommand)]
+    Workflow(workflow::TEdgeWorkflowCli),
 }
 
 fn styles() -> clap::builder::Styles {
@@ -172,6 +177,7 @@ impl BuildCommand for TEdgeOpt {
             TEdgeOpt::RefreshBridges => RefreshBridgesCmd::new(&context).map(Command::into_boxed),
             TEdgeOpt::Mqtt(opt) => opt.build_command(context),
             TEdgeOpt::Reconnect(opt) => opt.build_command(context),
+            TEdgeOpt::Workflow(opt) => opt.build_command(context),
         }
     }
 }
@@ -0,0 +1,46 @@
+use crate::cli::workflow::WorkflowError;
+use crate::command::Command;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::OperationWorkflow;
+
+pub struct CheckWorkflowCommand {
+    pub file: Utf8PathBuf,
+}
+
+impl Command for CheckWorkflowCommand {
+    fn description(&self) -> String {
+        format!("check the workflow definition {}", self.file)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = load_workflow(&self.file)?;
+
+        let issues = workflow.check();
+        if issues.is_empty() {
+            println!("{}: {} workflow is valid", self.file, workflow.operation);
+            return Ok(());
+        }
+
+        for issue in issues.iter() {
+            eprintln!("{}: {issue}", self.file);
+        }
+        Err(WorkflowError::WorkflowIssues {
+            path: self.file.clone(),
+            count: issues.len(),
+        }
+        .into())
+    }
+}
+
+/// Load a workflow definition from a TOML file
+pub(crate) fn load_workflow(path: &Utf8Path) -> Result<OperationWorkflow, WorkflowError> {
+    let content = std::fs::read_to_string(path).map_err(|source| WorkflowError::FromIo {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&content).map_err(|source| WorkflowError::InvalidDefinition {
+        path: path.to_owned(),
+        source,
+    })
+}
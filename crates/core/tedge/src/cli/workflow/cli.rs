@@ -0,0 +1,105 @@
+use crate::cli::workflow::check::CheckWorkflowCommand;
+use crate::cli::workflow::history::WorkflowHistoryCommand;
+use crate::cli::workflow::simulate::SimulateWorkflowCommand;
+use crate::cli::workflow::WorkflowError;
+use crate::command::BuildCommand;
+use crate::command::BuildContext;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeWorkflowCli {
+    /// Check a workflow definition, reporting undefined, unreachable and dead-end states.
+    Check {
+        /// Path to the workflow definition TOML file
+        file: Utf8PathBuf,
+    },
+
+    /// Simulate the execution of a workflow on a sample command, without executing any action.
+    Simulate {
+        /// Path to the workflow definition TOML file
+        file: Utf8PathBuf,
+
+        /// The initial payload of the command, as a JSON object
+        #[clap(long, default_value = "{}")]
+        payload: String,
+
+        /// The exit code of the action executed on a state, e.g. `scheduled=1` (default to 0)
+        #[clap(long = "exit-code")]
+        #[arg(value_parser = parse_exit_code)]
+        exit_codes: Vec<(String, u8)>,
+
+        /// The maximum number of state transitions
+        #[clap(long, default_value = "100")]
+        max_steps: usize,
+    },
+
+    /// Display the execution history recorded by the agent for a command.
+    History {
+        /// The id of the command
+        cmd_id: String,
+
+        /// The operation of the command, if the id is used by several operations
+        /// or if the name of the operation contains a dash
+        #[clap(long)]
+        operation: Option<String>,
+    },
+}
+
+impl BuildCommand for TEdgeWorkflowCli {
+    fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
+        let cmd = match self {
+            TEdgeWorkflowCli::Check { file } => CheckWorkflowCommand { file }.into_boxed(),
+            TEdgeWorkflowCli::Simulate {
+                file,
+                payload,
+                exit_codes,
+                max_steps,
+            } => SimulateWorkflowCommand {
+                file,
+                payload,
+                exit_codes,
+                max_steps,
+            }
+            .into_boxed(),
+            TEdgeWorkflowCli::History { cmd_id, operation } => {
+                let config = context.config_repository.load()?;
+                WorkflowHistoryCommand {
+                    log_dir: config.logs.path.join("agent"),
+                    cmd_id,
+                    operation,
+                }
+                .into_boxed()
+            }
+        };
+
+        Ok(cmd)
+    }
+}
+
+fn parse_exit_code(src: &str) -> Result<(String, u8), WorkflowError> {
+    let invalid = || WorkflowError::InvalidExitCode(src.to_string());
+    let (state, code) = src.split_once('=').ok_or_else(invalid)?;
+    let code = code.trim().parse().map_err(|_| invalid())?;
+    Ok((state.trim().to_string(), code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_exit_codes() {
+        assert_eq!(
+            parse_exit_code("scheduled=1").unwrap(),
+            ("scheduled".to_string(), 1)
+        );
+        assert_eq!(
+            parse_exit_code("restart = 255").unwrap(),
+            ("restart".to_string(), 255)
+        );
+        assert!(parse_exit_code("scheduled").is_err());
+        assert!(parse_exit_code("scheduled=256").is_err());
+        assert!(parse_exit_code("scheduled=-1").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use camino::Utf8PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WorkflowError {
+    #[error("Failed to read {path}")]
+    FromIo {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid workflow definition {path}")]
+    InvalidDefinition {
+        path: Utf8PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("The workflow definition {path} has {count} issue(s)")]
+    WorkflowIssues { path: Utf8PathBuf, count: usize },
+
+    #[error("The command payload is not a JSON object")]
+    InvalidPayload,
+
+    #[error("Invalid exit code: {0}. Expected `<state>=<code>` with a code between 0 and 255")]
+    InvalidExitCode(String),
+
+    #[error(transparent)]
+    FromJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    FromExecution(#[from] tedge_api::workflow::WorkflowExecutionError),
+
+    #[error("The command has not reached a final state after {steps} transitions")]
+    IncompleteSimulation { steps: usize },
+
+    #[error("No execution history found for the command {cmd_id} in {dir}")]
+    NoHistory { cmd_id: String, dir: Utf8PathBuf },
+}
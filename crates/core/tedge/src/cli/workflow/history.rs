@@ -0,0 +1,157 @@
+use crate::cli::workflow::WorkflowError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::CommandHistory;
+use tedge_api::workflow::CommandHistoryEntry;
+use time::format_description::well_known::Rfc3339;
+
+pub struct WorkflowHistoryCommand {
+    /// The directory where the agent records command histories
+    pub log_dir: Utf8PathBuf,
+    pub cmd_id: String,
+    pub operation: Option<String>,
+}
+
+impl Command for WorkflowHistoryCommand {
+    fn description(&self) -> String {
+        format!(
+            "display the execution history of the command {}",
+            self.cmd_id
+        )
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let files = self.history_files()?;
+        if files.is_empty() {
+            return Err(WorkflowError::NoHistory {
+                cmd_id: self.cmd_id.clone(),
+                dir: self.log_dir.clone(),
+            }
+            .into());
+        }
+
+        for path in files {
+            let content =
+                std::fs::read_to_string(&path).map_err(|source| WorkflowError::FromIo {
+                    path: path.clone(),
+                    source,
+                })?;
+            let history = CommandHistory::from_json_lines(&content).map_err(WorkflowError::from)?;
+
+            println!("{path}");
+            for entry in history.entries.iter() {
+                println!("{}", display_entry(entry));
+            }
+            println!("Time spent per state:");
+            for (state, duration) in history.state_durations() {
+                match duration {
+                    Some(duration) => println!("    {state}: {duration:?}"),
+                    None => println!("    {state}: -"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WorkflowHistoryCommand {
+    /// The history files recorded for the command, sorted by name
+    fn history_files(&self) -> Result<Vec<Utf8PathBuf>, WorkflowError> {
+        let entries = self
+            .log_dir
+            .read_dir_utf8()
+            .map_err(|source| WorkflowError::FromIo {
+                path: self.log_dir.clone(),
+                source,
+            })?;
+
+        let mut files: Vec<Utf8PathBuf> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| self.is_history_file(entry.file_name()))
+            .map(|entry| entry.into_path())
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Tell if a file is named `workflow-{operation}-{cmd_id}.history` for the command
+    ///
+    /// If no operation is given, the file name is split on the first dash,
+    /// so a command id that ends with the id of another command is not mistaken for this id.
+    /// Hence, the operation has to be given for an operation which name contains a dash.
+    fn is_history_file(&self, file_name: &str) -> bool {
+        match &self.operation {
+            Some(operation) => {
+                file_name == CommandHistory::file_name(&operation.as_str().into(), &self.cmd_id)
+            }
+            None => file_name
+                .strip_prefix("workflow-")
+                .and_then(|name| name.strip_suffix(".history"))
+                .and_then(|name| name.split_once('-'))
+                .is_some_and(|(_, cmd_id)| cmd_id == self.cmd_id),
+        }
+    }
+}
+
+fn display_entry(entry: &CommandHistoryEntry) -> String {
+    let timestamp = entry
+        .timestamp
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| entry.timestamp.to_string());
+    let mut line = format!("[{timestamp}] {}: {}", entry.state, entry.action);
+    if let Some(code) = entry.exit_code {
+        line.push_str(&format!("\n    exit code: {code}"));
+    }
+    if let Some(signal) = entry.signal {
+        line.push_str(&format!("\n    killed by signal: {signal}"));
+    }
+    if let Some(error) = &entry.error {
+        line.push_str(&format!("\n    error: {error}"));
+    }
+    if let Some(stdout) = &entry.stdout {
+        line.push_str(&format!("\n    stdout: {}", stdout.trim_end()));
+    }
+    if let Some(stderr) = &entry.stderr {
+        line.push_str(&format!("\n    stderr: {}", stderr.trim_end()));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_command(cmd_id: &str, operation: Option<&str>) -> WorkflowHistoryCommand {
+        WorkflowHistoryCommand {
+            log_dir: Utf8PathBuf::from("/var/log/tedge/agent"),
+            cmd_id: cmd_id.to_string(),
+            operation: operation.map(|operation| operation.to_string()),
+        }
+    }
+
+    #[test]
+    fn history_files_are_matched_on_the_exact_command_id() {
+        let command = history_command("1234", None);
+        assert!(command.is_history_file("workflow-software_update-1234.history"));
+        assert!(command.is_history_file("workflow-restart-1234.history"));
+        assert!(!command.is_history_file("workflow-software_update-c8y-mapper-1234.history"));
+        assert!(!command.is_history_file("workflow-software_update-01234.history"));
+        assert!(!command.is_history_file("workflow-software_update-1234.history.bak"));
+
+        let command = history_command("c8y-mapper-1234", None);
+        assert!(command.is_history_file("workflow-software_update-c8y-mapper-1234.history"));
+        assert!(!command.is_history_file("workflow-software_update-1234.history"));
+    }
+
+    #[test]
+    fn history_files_are_matched_on_the_exact_operation() {
+        let command = history_command("1234", Some("software_update"));
+        assert!(command.is_history_file("workflow-software_update-1234.history"));
+        assert!(!command.is_history_file("workflow-restart-1234.history"));
+
+        let command = history_command("1234", Some("custom-operation"));
+        assert!(command.is_history_file("workflow-custom-operation-1234.history"));
+        assert!(!command.is_history_file("workflow-operation-1234.history"));
+    }
+}
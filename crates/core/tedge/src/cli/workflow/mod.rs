@@ -0,0 +1,8 @@
+pub use self::cli::TEdgeWorkflowCli;
+pub use self::error::WorkflowError;
+
+mod check;
+mod cli;
+mod error;
+mod history;
+mod simulate;
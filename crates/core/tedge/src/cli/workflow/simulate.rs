@@ -0,0 +1,73 @@
+use crate::cli::workflow::check::load_workflow;
+use crate::cli::workflow::WorkflowError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use serde_json::Value;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::WorkflowSimulation;
+
+pub struct SimulateWorkflowCommand {
+    pub file: Utf8PathBuf,
+    pub payload: String,
+    pub exit_codes: Vec<(String, u8)>,
+    pub max_steps: usize,
+}
+
+impl Command for SimulateWorkflowCommand {
+    fn description(&self) -> String {
+        format!("simulate the workflow {}", self.file)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = load_workflow(&self.file)?;
+        let mut simulation = WorkflowSimulation::new(&workflow);
+        for (state, code) in self.exit_codes.iter() {
+            simulation = simulation.with_exit_code(state, *code);
+        }
+
+        let payload: Value = serde_json::from_str(&self.payload).map_err(WorkflowError::from)?;
+        if !payload.is_object() {
+            return Err(WorkflowError::InvalidPayload.into());
+        }
+        let topic = MqttSchema::new().topic_for(
+            &EntityTopicId::default_main_device(),
+            &Channel::Command {
+                operation: workflow.operation.clone(),
+                cmd_id: "simulation".to_string(),
+            },
+        );
+        let init = GenericCommandState {
+            topic,
+            status: "init".to_string(),
+            payload,
+        }
+        .move_to("init".to_string());
+
+        let steps = simulation
+            .run(init.clone(), self.max_steps)
+            .map_err(WorkflowError::from)?;
+        for step in steps.iter() {
+            let update = step.update.clone().into_json();
+            println!("[{}] {}", step.state, step.action);
+            println!("    => {update}");
+        }
+
+        let last_state = steps.last().map(|step| &step.next_state).unwrap_or(&init);
+        println!("{}", last_state.payload);
+        if simulation
+            .step(last_state)
+            .map_err(WorkflowError::from)?
+            .is_some()
+        {
+            return Err(WorkflowError::IncompleteSimulation {
+                steps: self.max_steps,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
@@ -76,6 +76,7 @@
 //!   a [DynSender] can transform the messages sent by the source to adapt them to the sink expectations,
 //!   using an `impl From<SourceMessage> for SinkMessage`. This flexibility allows an actor to receive
 //!   messages from several independent sources (see the [fan_in_message_type](crate::fan_in_message_type) macro).
+use crate::channels::MailboxSender;
 use crate::mpsc;
 use crate::DynSender;
 use crate::LoggingReceiver;
@@ -467,7 +468,7 @@ pub trait ServiceConsumer<Request: Message, Response: Message, Config> {
 ///
 pub struct SimpleMessageBoxBuilder<I: Debug, O> {
     name: String,
-    input_sender: mpsc::Sender<I>,
+    input_sender: MailboxSender<I>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
     output_sender: DynSender<O>,
     input_receiver: LoggingReceiver<I>,
@@ -478,8 +479,11 @@ impl<I: Message, O: Message> SimpleMessageBoxBuilder<I, O> {
         let (input_sender, input_receiver) = mpsc::channel(capacity);
         let (signal_sender, signal_receiver) = mpsc::channel(4);
         let output_sender = NullSender.into();
+        let mailbox_depth = tedge_metrics::mailbox_depth(name);
+        let input_sender = MailboxSender::new(input_sender, mailbox_depth.clone());
         let input_receiver =
-            LoggingReceiver::new(name.to_string(), input_receiver, signal_receiver);
+            LoggingReceiver::new(name.to_string(), input_receiver, signal_receiver)
+                .with_mailbox_depth(mailbox_depth);
 
         SimpleMessageBoxBuilder {
             name: name.to_string(),
@@ -4,6 +4,7 @@ use crate::Message;
 use async_trait::async_trait;
 use futures::channel::mpsc;
 use futures::SinkExt;
+use tedge_metrics::Gauge;
 
 /// A sender of messages of type `M`
 ///
@@ -52,6 +53,46 @@ impl<M: Message, N: Message + Into<M>> Sender<N> for mpsc::Sender<M> {
     }
 }
 
+/// An `mpsc::Sender<M>` counting the messages sent to a mailbox and not yet received
+///
+/// The gauge is decremented by the [LoggingReceiver](crate::LoggingReceiver) of the mailbox.
+pub(crate) struct MailboxSender<M> {
+    sender: mpsc::Sender<M>,
+    mailbox_depth: Gauge,
+}
+
+impl<M> MailboxSender<M> {
+    pub(crate) fn new(sender: mpsc::Sender<M>, mailbox_depth: Gauge) -> Self {
+        MailboxSender {
+            sender,
+            mailbox_depth,
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Message, N: Message + Into<M>> Sender<N> for MailboxSender<M> {
+    async fn send(&mut self, message: N) -> Result<(), ChannelError> {
+        self.mailbox_depth.inc();
+        let result = SinkExt::send(&mut self.sender, message.into()).await;
+        if result.is_err() {
+            self.mailbox_depth.dec();
+        }
+        Ok(result?)
+    }
+
+    fn sender_clone(&self) -> DynSender<N> {
+        Box::new(MailboxSender {
+            sender: self.sender.clone(),
+            mailbox_depth: self.mailbox_depth.clone(),
+        })
+    }
+
+    fn close_sender(&mut self) {
+        self.sender.close_channel();
+    }
+}
+
 /// Make a `DynSender<N>` from a `DynSender<M>`
 ///
 /// This is a workaround to the fact the compiler rejects a From implementation:
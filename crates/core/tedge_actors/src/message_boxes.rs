@@ -99,6 +99,7 @@ use futures::channel::mpsc;
 use futures::StreamExt;
 use log::debug;
 use std::fmt::Debug;
+use tedge_metrics::Gauge;
 
 /// Either a message or a [RuntimeRequest]
 pub enum WrappedInput<Input> {
@@ -141,6 +142,7 @@ pub trait MessageReceiver<Input> {
 pub struct LoggingReceiver<Input: Debug> {
     name: String,
     receiver: CombinedReceiver<Input>,
+    mailbox_depth: Option<Gauge>,
 }
 
 impl<Input: Debug> LoggingReceiver<Input> {
@@ -150,7 +152,26 @@ impl<Input: Debug> LoggingReceiver<Input> {
         signal_receiver: mpsc::Receiver<RuntimeRequest>,
     ) -> Self {
         let receiver = CombinedReceiver::new(input_receiver, signal_receiver);
-        Self { name, receiver }
+        Self {
+            name,
+            receiver,
+            mailbox_depth: None,
+        }
+    }
+
+    /// Track the number of messages waiting in the mailbox,
+    /// the gauge being incremented by the senders and decremented on reception.
+    pub(crate) fn with_mailbox_depth(self, mailbox_depth: Gauge) -> Self {
+        Self {
+            mailbox_depth: Some(mailbox_depth),
+            ..self
+        }
+    }
+
+    fn message_received(&self) {
+        if let Some(mailbox_depth) = &self.mailbox_depth {
+            mailbox_depth.dec()
+        }
     }
 
     /// Splits a `LoggingReceiver` into an input receiver and a signal receiver,
@@ -185,18 +206,27 @@ impl<Input: Send + Debug> MessageReceiver<Input> for LoggingReceiver<Input> {
     async fn try_recv(&mut self) -> Result<Option<Input>, RuntimeRequest> {
         let message = self.receiver.try_recv().await;
         debug!(target: &self.name, "recv {:?}", message);
+        if let Ok(Some(_)) = message {
+            self.message_received()
+        }
         message
     }
 
     async fn recv_message(&mut self) -> Option<WrappedInput<Input>> {
         let message = self.receiver.recv_message().await;
         debug!(target: &self.name, "recv {:?}", message);
+        if let Some(WrappedInput::Message(_)) = message {
+            self.message_received()
+        }
         message
     }
 
     async fn recv(&mut self) -> Option<Input> {
         let message = self.receiver.recv().await;
         debug!(target: &self.name, "recv {:?}", message);
+        if message.is_some() {
+            self.message_received()
+        }
         message
     }
 
@@ -110,3 +110,19 @@ async fn requests_can_be_sent_concurrently() {
     assert_eq!(service_handle.recv().await, Some((client_2, 102)));
     assert_eq!(service_handle.recv().await, Some((client_1, 1000)));
 }
+
+#[tokio::test]
+async fn mailbox_depth_is_tracked() {
+    let box_builder: SimpleMessageBoxBuilder<u64, u64> =
+        SimpleMessageBoxBuilder::new("MailboxDepthTest", 16);
+    let mut sender = box_builder.get_sender();
+    let mut message_box = box_builder.build();
+    let mailbox_depth = tedge_metrics::mailbox_depth("MailboxDepthTest");
+
+    sender.send(1u64).await.unwrap();
+    sender.send(2u64).await.unwrap();
+    assert_eq!(mailbox_depth.get(), 2.0);
+
+    assert_eq!(message_box.recv().await, Some(1));
+    assert_eq!(mailbox_depth.get(), 1.0);
+}
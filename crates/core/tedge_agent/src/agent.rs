@@ -1,5 +1,8 @@
 use crate::file_transfer_server::actor::FileTransferServerBuilder;
 use crate::file_transfer_server::actor::FileTransferServerConfig;
+use crate::firmware_manager::actor::firmware_update_workflow;
+use crate::firmware_manager::builder::FirmwareManagerBuilder;
+use crate::firmware_manager::config::FirmwareManagerConfig;
 use crate::operation_file_cache::FileCacheActorBuilder;
 use crate::restart_manager::builder::RestartManagerBuilder;
 use crate::restart_manager::config::RestartManagerConfig;
@@ -31,9 +34,12 @@ use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
 use tedge_actors::Runtime;
 use tedge_actors::ServerActorBuilder;
+use tedge_alarm_rules_ext::AlarmRulesBuilder;
+use tedge_alarm_rules_ext::AlarmRulesConfig;
 use tedge_api::mqtt_topics::DeviceTopicId;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
 use tedge_api::mqtt_topics::Service;
 use tedge_api::path::DataDir;
 use tedge_api::workflow::OperationWorkflow;
@@ -51,8 +57,11 @@ use tedge_log_manager::LogManagerOptions;
 use tedge_mqtt_ext::MqttActorBuilder;
 use tedge_mqtt_ext::MqttConfig;
 use tedge_mqtt_ext::TopicFilter;
+use tedge_prometheus_ext::PrometheusExporterBuilder;
+use tedge_prometheus_ext::PrometheusExporterConfig;
 use tedge_script_ext::ScriptActor;
 use tedge_signal_ext::SignalActor;
+use tedge_timer_ext::TimerActor;
 use tedge_uploader_ext::UploaderActor;
 use tedge_utils::file::create_directory_with_defaults;
 use tracing::info;
@@ -67,6 +76,7 @@ pub(crate) struct AgentConfig {
     pub http_config: FileTransferServerConfig,
     pub restart_config: RestartManagerConfig,
     pub sw_update_config: SoftwareManagerConfig,
+    pub firmware_config: FirmwareManagerConfig,
     pub operation_config: OperationConfig,
     pub config_dir: Utf8PathBuf,
     pub tmp_dir: Arc<Utf8Path>,
@@ -82,6 +92,7 @@ pub(crate) struct AgentConfig {
     pub fts_url: Arc<str>,
     pub is_sudo_enabled: bool,
     pub capabilities: Capabilities,
+    pub metrics_config: Option<PrometheusExporterConfig>,
 }
 
 impl AgentConfig {
@@ -133,6 +144,9 @@ impl AgentConfig {
         // Software update config
         let sw_update_config = SoftwareManagerConfig::from_tedge_config(tedge_config_location)?;
 
+        // Firmware update config
+        let firmware_config = FirmwareManagerConfig::from_tedge_config(tedge_config_location)?;
+
         // Operation Workflow config
         let operation_config =
             OperationConfig::from_tedge_config(&mqtt_device_topic_id, tedge_config_location)?;
@@ -153,7 +167,12 @@ impl AgentConfig {
             config_update: tedge_config.agent.enable.config_update,
             config_snapshot: tedge_config.agent.enable.config_snapshot,
             log_upload: tedge_config.agent.enable.log_upload,
+            alarm_rules: tedge_config.agent.enable.alarm_rules,
+            rest_api: tedge_config.agent.enable.rest_api,
         };
+        let metrics_config =
+            PrometheusExporterConfig::from_tedge_config(TEDGE_AGENT, &tedge_config)?;
+
         let fts_url = format!(
             "{}:{}",
             tedge_config.http.client.host, tedge_config.http.client.port
@@ -165,6 +184,7 @@ impl AgentConfig {
             http_config,
             restart_config,
             sw_update_config,
+            firmware_config,
             operation_config,
             config_dir,
             run_dir,
@@ -180,6 +200,7 @@ impl AgentConfig {
             is_sudo_enabled,
             service: tedge_config.service.clone(),
             capabilities,
+            metrics_config,
         })
     }
 }
@@ -228,8 +249,9 @@ impl Agent {
         let mut runtime = Runtime::try_new(runtime_events_logger).await?;
 
         // Operation workflows
-        let workflows = self.load_operation_workflows().await?;
+        let mut workflows = self.load_operation_workflows().await?;
         let mut script_runner: ServerActorBuilder<ScriptActor, Concurrent> = ScriptActor::builder();
+        let mut timer_actor = TimerActor::builder();
 
         // Restart actor
         let mut restart_actor_builder = RestartManagerBuilder::new(self.config.restart_config);
@@ -240,14 +262,45 @@ impl Agent {
         // Software update actor
         let mut software_update_builder = SoftwareManagerBuilder::new(self.config.sw_update_config);
 
+        // Downloader actor
+        let mut downloader_actor_builder =
+            DownloaderActor::new(self.config.identity.clone()).builder();
+
+        // Firmware update actor, the firmware_update operation being enabled only with a firmware handler
+        if self.config.firmware_config.is_firmware_handler_installed() {
+            if let Err(err) = workflows.register_custom_workflow(firmware_update_workflow()) {
+                error!("Fail to register built-in workflow for firmware_update operation: {err}");
+            }
+        }
+        // Config and log commands are executed by the config and log managers, once scheduled by the agent
+        let mut delegated_operations = vec![];
+        if self.config.capabilities.config_snapshot {
+            delegated_operations.push(OperationType::ConfigSnapshot);
+            if self.config.capabilities.config_update {
+                delegated_operations.push(OperationType::ConfigUpdate);
+            }
+        }
+        if self.config.capabilities.log_upload {
+            delegated_operations.push(OperationType::LogUpload);
+        }
+        for operation in delegated_operations {
+            if let Err(err) = workflows.register_delegated_workflow(operation.clone()) {
+                error!("Fail to register built-in workflow for {operation} operation: {err}");
+            }
+        }
+        let mut firmware_update_builder =
+            FirmwareManagerBuilder::new(self.config.firmware_config, &mut downloader_actor_builder);
+
         // Converter actor
         let converter_actor_builder = TedgeOperationConverterBuilder::new(
             self.config.operation_config,
             workflows,
             &mut software_update_builder,
             &mut restart_actor_builder,
+            &mut firmware_update_builder,
             &mut mqtt_actor_builder,
             &mut script_runner,
+            &mut timer_actor,
         );
 
         // Shutdown on SIGINT
@@ -273,8 +326,6 @@ impl Agent {
         let tedge_to_te_converter = create_tedge_to_te_converter(&mut mqtt_actor_builder)?;
 
         let mut fs_watch_actor_builder = FsWatchActorBuilder::new();
-        let mut downloader_actor_builder =
-            DownloaderActor::new(self.config.identity.clone()).builder();
         let mut uploader_actor_builder = UploaderActor::new(self.config.identity).builder();
 
         // Instantiate config manager actor if config_snapshot or both operations are enabled
@@ -287,6 +338,7 @@ impl Agent {
                     tmp_path: self.config.tmp_dir.clone(),
                     is_sudo_enabled: self.config.is_sudo_enabled,
                     config_update_enabled: self.config.capabilities.config_update,
+                    scheduled_by_agent: true,
                 })?;
                 Some(
                     ConfigManagerBuilder::try_new(
@@ -305,6 +357,31 @@ impl Agent {
                 None
             };
 
+        // Instantiate alarm rules actor if enabled
+        let alarm_rules_actor_builder = if self.config.capabilities.alarm_rules {
+            let alarm_rules_config =
+                AlarmRulesConfig::new(self.config.config_dir.clone().into(), mqtt_schema.clone());
+            Some(AlarmRulesBuilder::try_new(
+                alarm_rules_config,
+                &mut mqtt_actor_builder,
+                &mut fs_watch_actor_builder,
+            )?)
+        } else {
+            None
+        };
+
+        // Instantiate the Prometheus exporter if a metrics port is configured for the agent
+        let prometheus_exporter_builder = match self.config.metrics_config.clone() {
+            Some(mut metrics_config) => {
+                metrics_config.mqtt_schema = mqtt_schema.clone();
+                Some(
+                    PrometheusExporterBuilder::try_bind(metrics_config)?
+                        .with_telemetry(&mut mqtt_actor_builder),
+                )
+            }
+            None => None,
+        };
+
         // Instantiate log manager actor if the operation is enabled
         let log_actor_builder = if self.config.capabilities.log_upload {
             let log_manager_config = LogManagerConfig::from_options(LogManagerOptions {
@@ -312,6 +389,7 @@ impl Agent {
                 tmp_dir: self.config.config_dir.into(),
                 mqtt_schema: mqtt_schema.clone(),
                 mqtt_device_topic_id: self.config.mqtt_device_topic_id.clone(),
+                scheduled_by_agent: true,
             })?;
             Some(
                 LogManagerBuilder::try_new(
@@ -334,8 +412,16 @@ impl Agent {
 
             runtime.spawn(tedge_to_te_converter).await?;
 
-            let file_transfer_server_builder =
+            let mut file_transfer_server_builder =
                 FileTransferServerBuilder::try_bind(self.config.http_config).await?;
+            if self.config.capabilities.rest_api {
+                file_transfer_server_builder = file_transfer_server_builder.with_rest_api(
+                    mqtt_schema.clone(),
+                    self.config.mqtt_device_topic_id.clone(),
+                    self.config.service.ty.clone(),
+                    &mut mqtt_actor_builder,
+                )?;
+            }
             runtime.spawn(file_transfer_server_builder).await?;
 
             let operation_file_cache_builder = FileCacheActorBuilder::new(
@@ -362,9 +448,17 @@ impl Agent {
         if let Some(log_actor_builder) = log_actor_builder {
             runtime.spawn(log_actor_builder).await?;
         }
+        if let Some(alarm_rules_actor_builder) = alarm_rules_actor_builder {
+            runtime.spawn(alarm_rules_actor_builder).await?;
+        }
+        if let Some(prometheus_exporter_builder) = prometheus_exporter_builder {
+            runtime.spawn(prometheus_exporter_builder).await?;
+        }
         runtime.spawn(restart_actor_builder).await?;
         runtime.spawn(software_update_builder).await?;
+        runtime.spawn(firmware_update_builder).await?;
         runtime.spawn(script_runner).await?;
+        runtime.spawn(timer_actor).await?;
         runtime.spawn(converter_actor_builder).await?;
         runtime.spawn(health_actor).await?;
 
@@ -1,5 +1,6 @@
 use crate::file_transfer_server::error::FileTransferError;
 use crate::file_transfer_server::http_rest::http_file_transfer_server;
+use crate::file_transfer_server::rest_api::RestApiState;
 use anyhow::Context;
 use async_trait::async_trait;
 use axum_tls::config::load_ssl_config;
@@ -17,7 +18,13 @@ use tedge_actors::DynSender;
 use tedge_actors::RuntimeError;
 use tedge_actors::RuntimeRequest;
 use tedge_actors::RuntimeRequestSink;
+use tedge_actors::Sender;
+use tedge_actors::ServiceProvider;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
 use tedge_config::OptionalConfig;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
 use tokio::net::TcpListener;
 use tracing::log::info;
 
@@ -26,6 +33,36 @@ pub struct FileTransferServerActor {
     rustls_config: Option<ServerConfig>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
+    rest_api: Option<RestApiConnection>,
+}
+
+/// The connection of the local REST API to MQTT
+struct RestApiConnection {
+    state: RestApiState,
+    // The messages received from MQTT, used to update the state of the REST API
+    mqtt_input: mpsc::Receiver<MqttMessage>,
+    // The messages published by the REST API handlers, to be forwarded to MQTT
+    published_messages: mpsc::Receiver<MqttMessage>,
+    mqtt_publisher: DynSender<MqttMessage>,
+}
+
+impl RestApiConnection {
+    async fn relay_messages(rest_api: Option<Self>) -> Result<(), RuntimeError> {
+        let Some(mut rest_api) = rest_api else {
+            return futures::future::pending().await;
+        };
+        loop {
+            tokio::select! {
+                Some(message) = rest_api.mqtt_input.next() => {
+                    rest_api.state.process_mqtt_message(&message)
+                }
+                Some(message) = rest_api.published_messages.next() => {
+                    rest_api.mqtt_publisher.send(message).await?
+                }
+                else => return Ok(()),
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -47,14 +84,27 @@ impl Actor for FileTransferServerActor {
     }
 
     async fn run(mut self) -> Result<(), RuntimeError> {
-        let server =
-            http_file_transfer_server(self.listener, self.file_transfer_dir, self.rustls_config)?;
+        let rest_api_state = self
+            .rest_api
+            .as_ref()
+            .map(|rest_api| rest_api.state.clone());
+        let server = http_file_transfer_server(
+            self.listener,
+            self.file_transfer_dir,
+            rest_api_state,
+            self.rustls_config,
+        )?;
+        let relay = RestApiConnection::relay_messages(self.rest_api);
 
         tokio::select! {
             result = server => {
                 info!("Done");
                 return Ok(result.map_err(FileTransferError::FromIo)?);
             }
+            result = relay => {
+                info!("MQTT connection closed");
+                return result;
+            }
             Some(RuntimeRequest::Shutdown) = self.signal_receiver.next() => {
                 info!("Shutdown");
                 return Ok(());
@@ -69,6 +119,7 @@ pub struct FileTransferServerBuilder {
     signal_sender: mpsc::Sender<RuntimeRequest>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
+    rest_api: Option<RestApiConnection>,
 }
 
 impl FileTransferServerBuilder {
@@ -91,8 +142,36 @@ impl FileTransferServerBuilder {
             signal_sender,
             signal_receiver,
             listener,
+            rest_api: None,
         })
     }
+
+    /// Also serve the local REST API exposing the entities, their twin data and commands
+    pub(crate) fn with_rest_api(
+        mut self,
+        mqtt_schema: MqttSchema,
+        main_device: EntityTopicId,
+        default_service_type: String,
+        mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+    ) -> Result<Self, anyhow::Error> {
+        let (input_sender, mqtt_input) = mpsc::channel(16);
+        let (publisher, published_messages) = mpsc::channel(16);
+        let mqtt_publisher = mqtt.connect_consumer(
+            RestApiState::subscriptions(&mqtt_schema),
+            input_sender.into(),
+        );
+        let state =
+            RestApiState::try_new(mqtt_schema, main_device, default_service_type, publisher)
+                .context("Creating the entity store of the REST API")?;
+
+        self.rest_api = Some(RestApiConnection {
+            state,
+            mqtt_input,
+            published_messages,
+            mqtt_publisher,
+        });
+        Ok(self)
+    }
 }
 
 impl RuntimeRequestSink for FileTransferServerBuilder {
@@ -110,6 +189,7 @@ impl Builder<FileTransferServerActor> for FileTransferServerBuilder {
             rustls_config: self.rustls_config,
             signal_receiver: self.signal_receiver,
             listener: self.listener,
+            rest_api: self.rest_api,
         })
     }
 }
@@ -49,6 +49,16 @@ pub enum FileTransferRequestError {
     #[error("Invalid file path: {path:?}")]
     InvalidPath { path: RequestPath },
 
+    #[error("Invalid Content-Range header for {path:?}: {value:?}")]
+    InvalidContentRange { value: String, path: RequestPath },
+
+    #[error("Invalid chunk for {path:?}: {received} bytes instead of {expected}")]
+    InvalidChunkLength {
+        expected: u64,
+        received: u64,
+        path: RequestPath,
+    },
+
     #[error("File not found: {0:?}")]
     FileNotFound(RequestPath),
 
@@ -56,6 +66,33 @@ pub enum FileTransferRequestError {
     PathRejection(#[from] PathRejection),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum RestApiError {
+    #[error("Invalid path: {0:?}")]
+    InvalidPath(String),
+
+    #[error("Unknown entity: {0:?}")]
+    UnknownEntity(String),
+
+    #[error("Unknown command: {0:?}")]
+    UnknownCommand(String),
+
+    #[error("Command already exists: {0:?}")]
+    CommandAlreadyExists(String),
+
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error(transparent)]
+    FromEntityStore(#[from] tedge_api::entity_store::Error),
+
+    #[error("The MQTT connection has been closed")]
+    MqttDisconnected,
+}
+
 impl From<FileTransferError> for RuntimeError {
     fn from(error: FileTransferError) -> Self {
         RuntimeError::ActorError(Box::new(error))
@@ -86,6 +123,33 @@ impl IntoResponse for FileTransferRequestError {
             E::CannotUploadDirectory { .. } => {
                 (StatusCode::CONFLICT, error_message).into_response()
             }
+            E::InvalidContentRange { .. } | E::InvalidChunkLength { .. } => {
+                (StatusCode::BAD_REQUEST, error_message).into_response()
+            }
+        }
+    }
+}
+
+impl IntoResponse for RestApiError {
+    fn into_response(self) -> axum::response::Response {
+        use RestApiError as E;
+        let error_message = self.to_string();
+        match self {
+            E::InvalidPath(_) | E::UnknownEntity(_) | E::UnknownCommand(_) => {
+                (StatusCode::NOT_FOUND, error_message).into_response()
+            }
+            E::CommandAlreadyExists(_) => (StatusCode::CONFLICT, error_message).into_response(),
+            E::InvalidPayload(_) | E::InvalidQuery(_) | E::FromEntityStore(_) => {
+                (StatusCode::BAD_REQUEST, error_message).into_response()
+            }
+            E::MqttDisconnected => {
+                tracing::error!("{error_message}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal error".to_owned(),
+                )
+                    .into_response()
+            }
         }
     }
 }
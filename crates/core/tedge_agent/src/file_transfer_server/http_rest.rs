@@ -2,36 +2,57 @@ use crate::file_transfer_server::error::FileTransferError;
 use anyhow::anyhow;
 use anyhow::Context;
 use axum::body::StreamBody;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use futures::future::FutureExt;
+use hyper::header::CONTENT_RANGE;
 use hyper::Body;
 use hyper::Request;
 use hyper::StatusCode;
 use rustls::ServerConfig;
+use std::collections::HashMap;
 use std::future::Future;
 use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
 use tedge_actors::futures::StreamExt;
 use tedge_utils::paths::create_directories;
 use tokio::fs::File;
+use tokio::fs::OpenOptions;
 use tokio::io;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
 use tokio::net::TcpListener;
+use tokio::sync::OwnedMutexGuard;
 use tokio_util::io::ReaderStream;
+use tracing::warn;
 
 use super::error::FileTransferRequestError as Error;
 use super::request_files::FileTransferDir;
 use super::request_files::FileTransferPath;
 use super::request_files::RequestPath;
+use super::rest_api::rest_api_router;
+use super::rest_api::RestApiState;
+
+/// Header telling the client of a resumable upload how many bytes have been received so far
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+
+/// How long a partial upload is kept without being resumed
+const PARTIAL_UPLOAD_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
 
 async fn upload_file(
+    State(file_transfer_dir): State<FileTransferDir>,
     path: FileTransferPath,
     mut request: Request<Body>,
-) -> Result<StatusCode, Error> {
+) -> Result<Response, Error> {
     fn internal_error(source: impl Into<anyhow::Error>, path: RequestPath) -> Error {
         Error::Upload {
             source: source.into(),
@@ -39,17 +60,62 @@ async fn upload_file(
         }
     }
 
+    let content_range = match request.headers().get(CONTENT_RANGE) {
+        None => None,
+        Some(value) => match value.to_str().ok().and_then(ContentRange::parse) {
+            Some(range) => Some(range),
+            None => {
+                return Err(Error::InvalidContentRange {
+                    value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    path: path.request,
+                })
+            }
+        },
+    };
+
     if let Some(directory) = path.full.parent() {
         if let Err(err) = create_directories(directory) {
             return Err(internal_error(err, path.request));
         }
 
-        match stream_request_body_to_path(&path.full, request.body_mut()).await {
-            Ok(()) => Ok(StatusCode::CREATED),
+        if let Some(ContentRange::Status { .. }) = content_range {
+            // An upload is starting or resuming: time to forget those never resumed
+            let partial_uploads_dir = file_transfer_dir.partial_uploads_dir();
+            tokio::task::spawn_blocking(move || {
+                remove_expired_partial_uploads(&partial_uploads_dir, PARTIAL_UPLOAD_EXPIRY)
+            });
+        }
+
+        // The uploads to the same path are serialized, so the chunks are appended in order
+        let _lock = file_transfer_dir.upload_locks().lock(&path.full).await;
+        let result = match content_range {
+            // A partial upload cannot be resumed once the file has been uploaded as a whole
+            None => match remove_partial_upload(&path.partial).await {
+                Ok(()) => stream_request_body_to_path(&path.full, request.body_mut())
+                    .await
+                    .map(|()| StatusCode::CREATED.into_response()),
+                Err(err) => Err(err.into()),
+            },
+            Some(range) => upload_file_chunk(&path.full, &path.partial, range, request.body_mut())
+                .await
+                .map(|(status, received)| {
+                    (status, [(UPLOAD_OFFSET, received.to_string())]).into_response()
+                }),
+        };
+
+        match result {
+            Ok(response) => Ok(response),
             Err(err) if source_err_is_is_a_directory(&err) => {
                 Err(Error::CannotUploadDirectory { path: path.request })
             }
-            Err(err) => Err(internal_error(err, path.request)),
+            Err(err) => match err.downcast::<ChunkLengthMismatch>() {
+                Ok(ChunkLengthMismatch { expected, received }) => Err(Error::InvalidChunkLength {
+                    expected,
+                    received,
+                    path: path.request,
+                }),
+                Err(err) => Err(internal_error(err, path.request)),
+            },
         }
     } else {
         Err(internal_error(
@@ -59,6 +125,195 @@ async fn upload_file(
     }
 }
 
+/// The part of a file sent by a resumable upload, as given by a `Content-Range` header
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ContentRange {
+    /// `bytes */{total}`: the client asks how many bytes have been received
+    Status { total: u64 },
+
+    /// `bytes {start}-{end}/{total}`: the client sends the bytes `start..=end` of the file
+    Chunk { start: u64, end: u64, total: u64 },
+}
+
+impl ContentRange {
+    fn parse(value: &str) -> Option<Self> {
+        let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+        let total = total.parse().ok()?;
+        if range == "*" {
+            return Some(ContentRange::Status { total });
+        }
+
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        (start <= end && end < total).then_some(ContentRange::Chunk { start, end, total })
+    }
+
+    fn total(&self) -> u64 {
+        match self {
+            ContentRange::Status { total } | ContentRange::Chunk { total, .. } => *total,
+        }
+    }
+}
+
+/// A chunk which length doesn't match its `Content-Range` header
+#[derive(Debug, thiserror::Error)]
+#[error("{received} bytes received while {expected} bytes are expected")]
+struct ChunkLengthMismatch {
+    expected: u64,
+    received: u64,
+}
+
+/// The locks serializing the uploads to the same path
+#[derive(Clone, Default)]
+pub(super) struct UploadLocks(Arc<Mutex<HashMap<Utf8PathBuf, Arc<tokio::sync::Mutex<()>>>>>);
+
+/// The lock on the uploads to a path, released when dropped
+pub(super) struct UploadLock {
+    locks: UploadLocks,
+    path: Utf8PathBuf,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl UploadLocks {
+    /// Wait for the uploads in progress to a path to complete, and lock this path
+    pub(super) async fn lock(&self, path: &Utf8Path) -> UploadLock {
+        let path_lock = {
+            let mut locks = self.0.lock().unwrap();
+            locks.entry(path.to_owned()).or_default().clone()
+        };
+        UploadLock {
+            locks: self.clone(),
+            path: path.to_owned(),
+            _guard: path_lock.lock_owned().await,
+        }
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        let mut locks = self.locks.0.lock().unwrap();
+        // The lock is only shared by the map and this guard, when no other upload is waiting
+        if locks
+            .get(&self.path)
+            .is_some_and(|path_lock| Arc::strong_count(path_lock) <= 2)
+        {
+            locks.remove(&self.path);
+        }
+    }
+}
+
+/// Append a chunk of a resumable upload to the partially uploaded file
+///
+/// The chunks are appended to a `.part` file, that is moved to the target path once complete.
+/// The total length of the file is stored along in a `.part.total` file,
+/// so a partial file is discarded when an upload of a file with a different length is started.
+/// These files are stored under the partial uploads directory, so they cannot be downloaded.
+/// Returns the status of the response along the number of bytes received so far.
+async fn upload_file_chunk(
+    path: &Utf8Path,
+    partial_path: &Utf8Path,
+    range: ContentRange,
+    body_stream: &mut Body,
+) -> anyhow::Result<(StatusCode, u64)> {
+    let part_path = Utf8PathBuf::from(format!("{partial_path}.part"));
+    let total_path = Utf8PathBuf::from(format!("{partial_path}.part.total"));
+    let mut received = match tokio::fs::metadata(&part_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == ErrorKind::NotFound => 0,
+        Err(err) => return Err(err).with_context(|| format!("reading {part_path:?} metadata")),
+    };
+
+    if received > 0 {
+        let stored_total = tokio::fs::read_to_string(&total_path)
+            .await
+            .ok()
+            .and_then(|total| total.parse::<u64>().ok());
+        if stored_total != Some(range.total()) {
+            remove_file_if_exists(&part_path).await?;
+            remove_file_if_exists(&total_path).await?;
+            received = 0;
+        }
+    }
+
+    match range {
+        ContentRange::Status { .. } => Ok((StatusCode::NO_CONTENT, received)),
+        ContentRange::Chunk { start, .. } if start != received => {
+            Ok((StatusCode::CONFLICT, received))
+        }
+        ContentRange::Chunk { start, end, total } => {
+            if received == 0 {
+                if let Some(directory) = partial_path.parent() {
+                    create_directories(directory)?;
+                }
+                tokio::fs::write(&total_path, total.to_string())
+                    .await
+                    .with_context(|| format!("writing {total_path:?}"))?;
+            }
+
+            let received =
+                append_request_body_to_path(&part_path, body_stream, end - start + 1).await?;
+            if received < total {
+                return Ok((StatusCode::ACCEPTED, received));
+            }
+
+            tokio::fs::rename(&part_path, path)
+                .await
+                .with_context(|| format!("moving {part_path:?} to {path:?}"))?;
+            remove_file_if_exists(&total_path).await?;
+            Ok((StatusCode::CREATED, received))
+        }
+    }
+}
+
+/// Remove the partial upload of a file, if any
+async fn remove_partial_upload(partial_path: &Utf8Path) -> io::Result<()> {
+    for suffix in [".part", ".part.total"] {
+        match tokio::fs::remove_file(format!("{partial_path}{suffix}")).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Remove the partial uploads which have not been resumed since the given expiry delay
+fn remove_expired_partial_uploads(dir: &Utf8Path, expiry: Duration) {
+    let Ok(entries) = dir.read_dir_utf8() else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            remove_expired_partial_uploads(entry.path(), expiry);
+            continue;
+        }
+        let expired = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age >= expiry);
+        if expired {
+            if let Err(err) = std::fs::remove_file(entry.path()) {
+                warn!(
+                    "Failed to remove expired partial upload {}: {err}",
+                    entry.path()
+                );
+            }
+        }
+    }
+}
+
+async fn remove_file_if_exists(path: &Utf8Path) -> anyhow::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("removing {path:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
 fn source_err_is_is_a_directory(error: &anyhow::Error) -> bool {
     error
         .downcast_ref()
@@ -99,7 +354,17 @@ fn err_is_is_a_directory(e: &io::Error) -> bool {
     e.kind().to_string() == "is a directory"
 }
 
-async fn delete_file(path: FileTransferPath) -> Result<StatusCode, Error> {
+async fn delete_file(
+    State(file_transfer_dir): State<FileTransferDir>,
+    path: FileTransferPath,
+) -> Result<StatusCode, Error> {
+    let _lock = file_transfer_dir.upload_locks().lock(&path.full).await;
+    if let Err(err) = remove_partial_upload(&path.partial).await {
+        return Err(Error::Delete {
+            source: err,
+            path: path.request,
+        });
+    }
     match tokio::fs::remove_file(&path.full).await {
         Ok(()) => Ok(StatusCode::ACCEPTED),
         Err(e) if e.kind() == ErrorKind::NotFound => Ok(StatusCode::ACCEPTED),
@@ -131,12 +396,68 @@ async fn stream_request_body_to_path(
     Ok(())
 }
 
+/// Append the request body to a file, returning the new length of the file
+///
+/// The data received before an interruption of the request is kept,
+/// so the client can resume the upload from there.
+/// A body which is not of the expected length is rejected as a whole,
+/// the file being truncated back to its previous length.
+async fn append_request_body_to_path(
+    path: &Utf8Path,
+    body_stream: &mut Body,
+    expected_length: u64,
+) -> anyhow::Result<u64> {
+    let mut buffer = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("opening {path:?}"))?;
+    let initial_length = buffer
+        .metadata()
+        .await
+        .with_context(|| format!("reading {path:?} metadata"))?
+        .len();
+
+    let mut received = 0;
+    while let Some(data) = body_stream.next().await {
+        let data =
+            data.with_context(|| format!("reading body of uploaded file (destined for {path:?})"))?;
+        received += data.len() as u64;
+        if received > expected_length {
+            break;
+        }
+        buffer
+            .write_all(&data)
+            .await
+            .with_context(|| format!("writing to {path:?}"))?;
+    }
+    buffer
+        .flush()
+        .await
+        .with_context(|| format!("writing to {path:?}"))?;
+
+    if received != expected_length {
+        buffer
+            .set_len(initial_length)
+            .await
+            .with_context(|| format!("truncating {path:?}"))?;
+        return Err(ChunkLengthMismatch {
+            expected: expected_length,
+            received,
+        }
+        .into());
+    }
+    Ok(initial_length + received)
+}
+
 pub(crate) fn http_file_transfer_server(
     listener: TcpListener,
     file_transfer_dir: Utf8PathBuf,
+    rest_api: Option<RestApiState>,
     rustls_config: Option<ServerConfig>,
 ) -> Result<impl Future<Output = io::Result<()>>, FileTransferError> {
-    let router = http_file_transfer_router(file_transfer_dir);
+    let router = http_router(file_transfer_dir, rest_api);
     let listener = listener.into_std()?;
 
     let server = if let Some(rustls_config) = rustls_config {
@@ -150,6 +471,15 @@ pub(crate) fn http_file_transfer_server(
     Ok(server)
 }
 
+/// The routes of the file transfer service, along with those of the REST API, if enabled
+fn http_router(file_transfer_dir: Utf8PathBuf, rest_api: Option<RestApiState>) -> Router {
+    let router = http_file_transfer_router(file_transfer_dir);
+    match rest_api {
+        Some(rest_api) => router.merge(rest_api_router(rest_api)),
+        None => router,
+    }
+}
+
 fn http_file_transfer_router(file_transfer_dir: Utf8PathBuf) -> Router {
     Router::new()
         .route(
@@ -162,11 +492,15 @@ fn http_file_transfer_router(file_transfer_dir: Utf8PathBuf) -> Router {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_transfer_server::request_files::PARTIAL_UPLOADS_DIR;
     use axum::response::Response;
     use bytes::Bytes;
+    use futures::channel::mpsc;
     use http_body::combinators::UnsyncBoxBody;
     use hyper::Method;
     use hyper::StatusCode;
+    use tedge_api::mqtt_topics::EntityTopicId;
+    use tedge_api::mqtt_topics::MqttSchema;
     use tedge_api::path::DataDir;
     use tedge_test_utils::fs::TempTedgeDir;
     use test_case::test_case;
@@ -220,6 +554,197 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn file_is_uploaded_chunk_by_chunk() {
+        let path = "some/dir/file";
+        let (ttd, mut app) = app();
+        let expected_output_file = ttd.utf8_path().join("file-transfer").join(path);
+
+        let response = upload_chunk(&mut app, path, "bytes */12", "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "0");
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "5");
+        assert!(!expected_output_file.exists());
+
+        // A chunk not starting where the previous one stopped is rejected
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "5");
+
+        let response = upload_chunk(&mut app, path, "bytes */12", "").await;
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "5");
+
+        let response = upload_chunk(&mut app, path, "bytes 5-11/12", "content").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "12");
+
+        assert_eq!(
+            tokio::fs::read_to_string(expected_output_file)
+                .await
+                .unwrap(),
+            "some content"
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_upload_is_discarded_when_the_file_length_changes() {
+        let path = "some/dir/file";
+        let (ttd, mut app) = app();
+        let expected_output_file = ttd.utf8_path().join("file-transfer").join(path);
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "5");
+
+        // The upload of a file with another length starts over
+        let response = upload_chunk(&mut app, path, "bytes */9", "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "0");
+
+        let response = upload_chunk(&mut app, path, "bytes 0-8/9", "new stuff").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "9");
+
+        assert_eq!(
+            tokio::fs::read_to_string(expected_output_file)
+                .await
+                .unwrap(),
+            "new stuff"
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_upload_cannot_be_downloaded() {
+        let path = "some/dir/file";
+        let (_ttd, mut app) = app();
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = download_file(&mut app, path).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let partial_path = format!("{PARTIAL_UPLOADS_DIR}/{path}.part");
+        let response = download_file(&mut app, &partial_path).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = upload_file(&mut app, &partial_path, "other content").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn partial_upload_is_discarded_when_the_file_is_deleted() {
+        let path = "some/dir/file";
+        let (_ttd, mut app) = app();
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = delete_file(&mut app, path).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = upload_chunk(&mut app, path, "bytes */12", "").await;
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "0");
+    }
+
+    #[tokio::test]
+    async fn concurrent_chunks_are_appended_one_after_the_other() {
+        let path = "some/dir/file";
+        let (ttd, app) = app();
+        let expected_output_file = ttd.utf8_path().join("file-transfer").join(path);
+
+        // A first chunk is being received
+        let (mut sender, body) = Body::channel();
+        let first_chunk = tokio::spawn(chunk_request(app.clone(), path, "bytes 0-4/12", body));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // When the same chunk is sent concurrently
+        let same_chunk = tokio::spawn(chunk_request(
+            app.clone(),
+            path,
+            "bytes 0-4/12",
+            Body::from("some "),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        sender.send_data(Bytes::from("some ")).await.unwrap();
+        drop(sender);
+
+        // Then this second chunk is only checked once the first one has been appended
+        assert_eq!(first_chunk.await.unwrap(), StatusCode::ACCEPTED);
+        assert_eq!(same_chunk.await.unwrap(), StatusCode::CONFLICT);
+
+        let mut app = app;
+        let response = upload_chunk(&mut app, path, "bytes 5-11/12", "content").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            tokio::fs::read_to_string(expected_output_file)
+                .await
+                .unwrap(),
+            "some content"
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_partial_uploads_are_removed() {
+        let path = "some/dir/file";
+        let (ttd, mut app) = app();
+        let partial_uploads_dir = ttd
+            .utf8_path()
+            .join("file-transfer")
+            .join(PARTIAL_UPLOADS_DIR);
+        let part_file = partial_uploads_dir.join(format!("{path}.part"));
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        remove_expired_partial_uploads(&partial_uploads_dir, PARTIAL_UPLOAD_EXPIRY);
+        assert!(part_file.exists());
+
+        remove_expired_partial_uploads(&partial_uploads_dir, Duration::ZERO);
+        assert!(!part_file.exists());
+
+        let response = upload_chunk(&mut app, path, "bytes */12", "").await;
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "0");
+    }
+
+    #[test_case("content!" ; "chunk longer than its range")]
+    #[test_case("con" ; "chunk shorter than its range")]
+    #[tokio::test]
+    async fn chunk_not_matching_its_content_range_is_rejected(contents: &str) {
+        let path = "some/dir/file";
+        let (_ttd, mut app) = app();
+
+        let response = upload_chunk(&mut app, path, "bytes 0-4/12", "some ").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = upload_chunk(&mut app, path, "bytes 5-11/12", contents).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Nothing from the rejected chunk has been kept
+        let response = upload_chunk(&mut app, path, "bytes */12", "").await;
+        assert_eq!(response.headers()[UPLOAD_OFFSET], "5");
+    }
+
+    #[tokio::test]
+    async fn upload_with_an_invalid_content_range_is_rejected() {
+        let (_ttd, mut app) = app();
+
+        let response = upload_chunk(&mut app, "some/file", "bytes 0-20/12", "some content").await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test_case("bytes */12", Some(ContentRange::Status { total: 12 }))]
+    #[test_case("bytes 0-4/12", Some(ContentRange::Chunk { start: 0, end: 4, total: 12 }))]
+    #[test_case("bytes 5-12/12", None ; "range beyond the total length")]
+    #[test_case("bytes 5-4/12", None ; "range ending before its start")]
+    #[test_case("lines 0-4/12", None ; "unknown unit")]
+    fn parse_content_range(value: &str, expected: Option<ContentRange>) {
+        assert_eq!(ContentRange::parse(value), expected);
+    }
+
     #[test]
     fn reading_a_directory_returns_a_directory_error() {
         // See comment in `err_is_is_a_directory` implementation
@@ -377,6 +902,33 @@ mod tests {
         request_with(Method::PUT, app, path, contents.to_owned()).await
     }
 
+    async fn upload_chunk(
+        app: &mut Router,
+        path: &str,
+        range: &str,
+        contents: &str,
+    ) -> Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/tedge/file-transfer/{path}"))
+            .header(CONTENT_RANGE, range)
+            .body(Body::from(contents.to_owned()))
+            .expect("request builder");
+
+        app.call(req).await.unwrap()
+    }
+
+    async fn chunk_request(app: Router, path: &str, range: &str, body: Body) -> StatusCode {
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/tedge/file-transfer/{path}"))
+            .header(CONTENT_RANGE, range)
+            .body(body)
+            .expect("request builder");
+
+        app.oneshot(req).await.unwrap().status()
+    }
+
     async fn delete_file(
         app: &mut Router,
         path: &str,
@@ -391,6 +943,43 @@ mod tests {
         request_with(Method::GET, app, path, Body::empty()).await
     }
 
+    #[tokio::test]
+    async fn rest_api_routes_are_missing_when_the_rest_api_is_disabled() {
+        let ttd = TempTedgeDir::new();
+        let ftd = DataDir::from(ttd.utf8_path_buf()).file_transfer_dir();
+        let mut app = http_router(ftd, None);
+
+        let response = get_entities(&mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rest_api_routes_are_served_when_the_rest_api_is_enabled() {
+        let ttd = TempTedgeDir::new();
+        let ftd = DataDir::from(ttd.utf8_path_buf()).file_transfer_dir();
+        let (publisher, _published) = mpsc::channel(16);
+        let rest_api = RestApiState::try_new(
+            MqttSchema::default(),
+            EntityTopicId::default_main_device(),
+            "service".to_string(),
+            publisher,
+        )
+        .unwrap();
+        let mut app = http_router(ftd, Some(rest_api));
+
+        let response = get_entities(&mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    async fn get_entities(app: &mut Router) -> Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/tedge/v1/entities")
+            .body(Body::empty())
+            .expect("request builder");
+        app.call(req).await.unwrap()
+    }
+
     fn app() -> (TempTedgeDir, Router) {
         let ttd = TempTedgeDir::new();
         let ftd = DataDir::from(ttd.utf8_path_buf()).file_transfer_dir();
@@ -1,4 +1,6 @@
 pub mod actor;
 pub mod error;
 pub mod http_rest;
+mod mqtt_stream;
 mod request_files;
+pub mod rest_api;
@@ -0,0 +1,371 @@
+//! Streaming of thin-edge MQTT messages over HTTP, using server-sent events or WebSocket.
+//!
+//! `GET /tedge/v1/stream` streams the messages published on the topics selected by the query parameters:
+//!
+//! - `entity`: the topic id of the entity, e.g. `device/child01//`, all the entities by default.
+//! - `channels`: a comma-separated list of channels, all the channels by default:
+//!   `registration`, `twin`, `m`, `e`, `a`, `m/meta`, `e/meta`, `a/meta`,
+//!   `cmd`, `cmd/<operation>`, `cmd/meta` and `cmd/<operation>/meta`.
+//! - `replay`: when `true`, the retained messages are sent first.
+//!
+//! A WebSocket is opened if the request is a WebSocket upgrade request,
+//! otherwise the messages are sent as server-sent events.
+//! In both cases, each message is sent as a JSON object with the `topic`, the `payload` and the `retain` flag.
+use super::error::RestApiError;
+use super::rest_api::RestApiState;
+use axum::extract::ws::Message as WsMessage;
+use axum::extract::ws::WebSocket;
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use axum::response::sse::Sse;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use futures::future;
+use futures::stream;
+use futures::Stream;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// The number of messages buffered for a slow client, before the oldest are dropped
+const STREAM_CAPACITY: usize = 256;
+
+/// The MQTT messages received by the agent, dispatched to the streaming clients
+#[derive(Clone)]
+pub(crate) struct MqttStream {
+    sender: broadcast::Sender<MqttMessage>,
+    // The last message of each topic used by thin-edge for retained messages
+    retained: Arc<Mutex<BTreeMap<String, MqttMessage>>>,
+}
+
+impl Default for MqttStream {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(STREAM_CAPACITY);
+        MqttStream {
+            sender,
+            retained: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl MqttStream {
+    /// All the channels that can be streamed
+    pub(crate) fn channels() -> Vec<ChannelFilter> {
+        vec![
+            ChannelFilter::EntityMetadata,
+            ChannelFilter::EntityTwinData,
+            ChannelFilter::Measurement,
+            ChannelFilter::Event,
+            ChannelFilter::Alarm,
+            ChannelFilter::MeasurementMetadata,
+            ChannelFilter::EventMetadata,
+            ChannelFilter::AlarmMetadata,
+            ChannelFilter::AnyCommand,
+            ChannelFilter::AnyCommandMetadata,
+        ]
+    }
+
+    /// Dispatch a message to the streaming clients, keeping track of the retained messages
+    pub(crate) fn publish(&self, message: &MqttMessage, channel: &Channel) {
+        if message.retain || is_retained(channel) {
+            let mut retained = self
+                .retained
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if message.payload_bytes().is_empty() {
+                retained.remove(&message.topic.name);
+            } else {
+                retained.insert(message.topic.name.clone(), message.clone().with_retain());
+            }
+        }
+
+        // An error only means there is no client
+        let _ = self.sender.send(message.clone());
+    }
+
+    /// Subscribe to the messages accepted by the filter, starting with the retained messages when `replay` is set
+    fn subscribe(&self, filter: TopicFilter, replay: bool) -> impl Stream<Item = MqttMessage> {
+        // Subscribe before collecting the retained messages, not to miss any update
+        let receiver = self.sender.subscribe();
+        let replayed: Vec<MqttMessage> = if replay {
+            let retained = self
+                .retained
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            retained
+                .values()
+                .filter(|message| filter.accept(message))
+                .cloned()
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let live = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) => return Some((message, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("Slow streaming client: {count} MQTT messages have been dropped")
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .filter(move |message| future::ready(filter.accept(message)));
+
+        stream::iter(replayed).chain(live)
+    }
+}
+
+/// The channels for which thin-edge publishes retained messages
+fn is_retained(channel: &Channel) -> bool {
+    !matches!(channel, Channel::Measurement { .. } | Channel::Event { .. })
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StreamQuery {
+    entity: Option<String>,
+    channels: Option<String>,
+    #[serde(default)]
+    replay: bool,
+}
+
+impl StreamQuery {
+    fn topic_filter(&self, mqtt_schema: &MqttSchema) -> Result<TopicFilter, RestApiError> {
+        let entity = self
+            .entity
+            .as_deref()
+            .map(|entity| {
+                EntityTopicId::from_str(entity)
+                    .map_err(|_| RestApiError::InvalidQuery(format!("invalid entity: {entity:?}")))
+            })
+            .transpose()?;
+        let entity_filter = || match &entity {
+            None => EntityFilter::AnyEntity,
+            Some(entity) => EntityFilter::Entity(entity),
+        };
+
+        let channels = match &self.channels {
+            None => MqttStream::channels(),
+            Some(channels) => channels
+                .split(',')
+                .map(parse_channel_filter)
+                .collect::<Result<_, _>>()?,
+        };
+
+        let mut topics = TopicFilter::empty();
+        for channel in channels {
+            topics.add_all(mqtt_schema.topics(entity_filter(), channel))
+        }
+        Ok(topics)
+    }
+}
+
+fn parse_channel_filter(channel: &str) -> Result<ChannelFilter, RestApiError> {
+    let filter = match channel.split('/').collect::<Vec<_>>()[..] {
+        ["registration"] => ChannelFilter::EntityMetadata,
+        ["twin"] => ChannelFilter::EntityTwinData,
+        ["m"] => ChannelFilter::Measurement,
+        ["e"] => ChannelFilter::Event,
+        ["a"] => ChannelFilter::Alarm,
+        ["m", "meta"] => ChannelFilter::MeasurementMetadata,
+        ["e", "meta"] => ChannelFilter::EventMetadata,
+        ["a", "meta"] => ChannelFilter::AlarmMetadata,
+        ["cmd"] => ChannelFilter::AnyCommand,
+        ["cmd", "meta"] => ChannelFilter::AnyCommandMetadata,
+        ["cmd", operation] if !operation.is_empty() => ChannelFilter::Command(operation.into()),
+        ["cmd", operation, "meta"] if !operation.is_empty() => {
+            ChannelFilter::CommandMetadata(operation.into())
+        }
+        _ => {
+            return Err(RestApiError::InvalidQuery(format!(
+                "invalid channel: {channel:?}"
+            )))
+        }
+    };
+    Ok(filter)
+}
+
+/// The JSON representation of a streamed message
+fn message_json(message: &MqttMessage) -> JsonValue {
+    let payload = serde_json::from_slice(message.payload_bytes()).unwrap_or_else(|_| {
+        JsonValue::String(String::from_utf8_lossy(message.payload_bytes()).into_owned())
+    });
+    json!({
+        "topic": message.topic.name,
+        "payload": payload,
+        "retain": message.retain,
+    })
+}
+
+pub(crate) async fn stream_messages(
+    State(state): State<RestApiState>,
+    Query(query): Query<StreamQuery>,
+    websocket: Option<WebSocketUpgrade>,
+) -> Result<Response, RestApiError> {
+    let filter = query.topic_filter(state.mqtt_schema())?;
+    let messages = state.stream().subscribe(filter, query.replay);
+
+    let response = match websocket {
+        Some(websocket) => websocket
+            .on_upgrade(|socket| send_over_websocket(socket, messages))
+            .into_response(),
+        None => {
+            let events = messages.map(|message| {
+                Ok::<_, Infallible>(Event::default().data(message_json(&message).to_string()))
+            });
+            Sse::new(events)
+                .keep_alive(KeepAlive::default())
+                .into_response()
+        }
+    };
+    Ok(response)
+}
+
+async fn send_over_websocket(
+    mut socket: WebSocket,
+    messages: impl Stream<Item = MqttMessage> + Send,
+) {
+    futures::pin_mut!(messages);
+    loop {
+        tokio::select! {
+            Some(message) = messages.next() => {
+                let frame = WsMessage::Text(message_json(&message).to_string());
+                if socket.send(frame).await.is_err() {
+                    return;
+                }
+            }
+            received = socket.recv() => {
+                // Any message sent by the client is ignored, till the socket is closed
+                match received {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+            else => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_mqtt_ext::Topic;
+
+    #[tokio::test]
+    async fn retained_messages_are_replayed_before_live_messages() {
+        let mqtt_stream = MqttStream::default();
+        mqtt_stream.publish(
+            &message(
+                "te/device/main///a/temperature_high",
+                r#"{"text":"Too hot"}"#,
+            ),
+            &Channel::Alarm {
+                alarm_type: "temperature_high".to_string(),
+            },
+        );
+        mqtt_stream.publish(
+            &message("te/device/main///m/environment", r#"{"temperature":30}"#),
+            &Channel::Measurement {
+                measurement_type: "environment".to_string(),
+            },
+        );
+
+        let filter = query(None, Some("m,a"))
+            .topic_filter(&MqttSchema::default())
+            .unwrap();
+        let messages = mqtt_stream.subscribe(filter, true);
+        futures::pin_mut!(messages);
+        mqtt_stream.publish(
+            &message("te/device/main///m/environment", r#"{"temperature":31}"#),
+            &Channel::Measurement {
+                measurement_type: "environment".to_string(),
+            },
+        );
+
+        assert_eq!(
+            message_json(&messages.next().await.unwrap()),
+            json!({"topic": "te/device/main///a/temperature_high", "payload": {"text": "Too hot"}, "retain": true})
+        );
+        assert_eq!(
+            message_json(&messages.next().await.unwrap()),
+            json!({"topic": "te/device/main///m/environment", "payload": {"temperature": 31}, "retain": false})
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_messages_of_the_selected_entity_and_channels_are_streamed() {
+        let mqtt_stream = MqttStream::default();
+        let filter = query(Some("device/child1//"), Some("cmd/restart"))
+            .topic_filter(&MqttSchema::default())
+            .unwrap();
+        let messages = mqtt_stream.subscribe(filter, false);
+        futures::pin_mut!(messages);
+
+        for (topic, payload) in [
+            ("te/device/main///cmd/restart/123", r#"{"status":"init"}"#),
+            (
+                "te/device/child1///cmd/software_update/456",
+                r#"{"status":"init"}"#,
+            ),
+            (
+                "te/device/child1///cmd/restart/789",
+                r#"{"status":"executing"}"#,
+            ),
+        ] {
+            let topic = Topic::new_unchecked(topic);
+            let (_, channel) = MqttSchema::default().entity_channel_of(&topic).unwrap();
+            mqtt_stream.publish(&MqttMessage::new(&topic, payload), &channel);
+        }
+
+        assert_eq!(
+            messages.next().await.unwrap().topic.name,
+            "te/device/child1///cmd/restart/789"
+        );
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        let schema = MqttSchema::default();
+        assert!(query(Some("device/child1/a/b/c"), None)
+            .topic_filter(&schema)
+            .is_err());
+        assert!(query(None, Some("m,unknown"))
+            .topic_filter(&schema)
+            .is_err());
+        assert!(query(None, Some("cmd//meta"))
+            .topic_filter(&schema)
+            .is_err());
+    }
+
+    fn query(entity: Option<&str>, channels: Option<&str>) -> StreamQuery {
+        StreamQuery {
+            entity: entity.map(str::to_string),
+            channels: channels.map(str::to_string),
+            replay: false,
+        }
+    }
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+}
@@ -9,13 +9,33 @@ use camino::Utf8Path;
 use camino::Utf8PathBuf;
 
 use super::error::FileTransferRequestError;
+use super::http_rest::UploadLocks;
+
+/// The directory, under the file transfer directory, where the partial uploads are stored
+///
+/// This directory is out of reach of the requests, so a partial upload cannot be downloaded.
+pub(super) const PARTIAL_UPLOADS_DIR: &str = ".partial-uploads";
 
 #[derive(Clone)]
-pub(super) struct FileTransferDir(Arc<Utf8Path>);
+pub(super) struct FileTransferDir {
+    dir: Arc<Utf8Path>,
+    upload_locks: UploadLocks,
+}
 
 impl FileTransferDir {
     pub(super) fn new(file_transfer_dir: Utf8PathBuf) -> Self {
-        Self(Arc::from(file_transfer_dir))
+        Self {
+            dir: Arc::from(file_transfer_dir),
+            upload_locks: UploadLocks::default(),
+        }
+    }
+
+    pub(super) fn partial_uploads_dir(&self) -> Utf8PathBuf {
+        self.dir.join(PARTIAL_UPLOADS_DIR)
+    }
+
+    pub(super) fn upload_locks(&self) -> &UploadLocks {
+        &self.upload_locks
     }
 }
 
@@ -23,6 +43,8 @@ impl FileTransferDir {
 pub struct FileTransferPath {
     /// The full path, i.e. the absolute path on disk the request corresponds to
     pub full: Utf8PathBuf,
+    /// The path where a partial upload of the file is stored, out of reach of the requests
+    pub partial: Utf8PathBuf,
     /// The requested path, used to generate error messages, keeping the absolute path encapsulated
     pub request: RequestPath,
 }
@@ -62,14 +84,14 @@ impl FromRequestParts<FileTransferDir> for FileTransferPath {
     ) -> Result<Self, Self::Rejection> {
         let Path(request_path) =
             Path::<Utf8PathBuf>::from_request_parts(parts, &file_transfer_dir).await?;
-        local_path_for_file(RequestPath(request_path), &file_transfer_dir.0)
+        local_path_for_file(RequestPath(request_path), &file_transfer_dir.dir)
     }
 }
 
 /// Return the path of the file associated to the given `uri`
 ///
 /// This cleans up the path using [path_clean::clean] and then verifies that this
-/// path is actually under `config.file_transfer_dir`
+/// path is actually under `config.file_transfer_dir`, but not under the [PARTIAL_UPLOADS_DIR]
 fn local_path_for_file(
     request_path: RequestPath,
     file_transfer_dir: &Utf8Path,
@@ -78,13 +100,17 @@ fn local_path_for_file(
 
     let clean_path = clean_utf8_path(&full_path);
 
-    if clean_path.starts_with(file_transfer_dir) {
-        Ok(FileTransferPath {
-            full: clean_path,
-            request: request_path,
-        })
-    } else {
-        Err(FileTransferRequestError::InvalidPath { path: request_path })
+    match clean_path.strip_prefix(file_transfer_dir) {
+        Ok(relative_path) if !relative_path.starts_with(PARTIAL_UPLOADS_DIR) => {
+            Ok(FileTransferPath {
+                partial: file_transfer_dir
+                    .join(PARTIAL_UPLOADS_DIR)
+                    .join(relative_path),
+                full: clean_path,
+                request: request_path,
+            })
+        }
+        _ => Err(FileTransferRequestError::InvalidPath { path: request_path }),
     }
 }
 
@@ -0,0 +1,621 @@
+//! A local REST API exposing the entities registered on the device, their twin data and commands,
+//! and letting HTTP-only applications publish telemetry data.
+//!
+//! All the routes are prefixed by `/tedge/v1/entities`,
+//! the path of a resource being the MQTT topic of that resource without the topic root.
+//!
+//! - `GET /tedge/v1/entities` lists the registered entities.
+//! - `GET|PUT|DELETE /tedge/v1/entities/<entity>` gets, registers or deregisters an entity,
+//!   e.g. `/tedge/v1/entities/device/child01//`.
+//! - `GET /tedge/v1/entities/<entity>/twin` and `GET /tedge/v1/entities/<entity>/twin/<fragment>`
+//!   get the twin data of an entity.
+//! - `GET /tedge/v1/entities/<entity>/cmd/<operation>` lists the commands of an entity for an operation.
+//! - `GET|PUT|DELETE /tedge/v1/entities/<entity>/cmd/<operation>/<id>` gets, creates or clears a command.
+//! - `POST /tedge/v1/entities/<entity>/m/<type>`, `/e/<type>` and `/a/<type>`
+//!   publish a measurement, an event or an alarm.
+//!
+//! - `GET /tedge/v1/stream` streams MQTT messages, see [super::mqtt_stream].
+//!
+//! The state exposed by the API is built from the messages retained by the MQTT broker,
+//! and all the updates are published over MQTT.
+use super::error::RestApiError;
+use super::mqtt_stream::stream_messages;
+use super::mqtt_stream::MqttStream;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use futures::channel::mpsc;
+use futures::SinkExt;
+use hyper::StatusCode;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use tedge_api::entity_store;
+use tedge_api::entity_store::EntityMetadata;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityStore;
+use tedge_api::entity_store::EntityTwinMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+use tracing::debug;
+use tracing::error;
+
+/// The state shared by the REST API handlers
+#[derive(Clone)]
+pub(crate) struct RestApiState {
+    mqtt_schema: MqttSchema,
+    store: Arc<Mutex<RestApiStore>>,
+    stream: MqttStream,
+    mqtt_publisher: mpsc::Sender<MqttMessage>,
+}
+
+struct RestApiStore {
+    entities: EntityStore,
+    // The current state of the commands, indexed by command topic
+    commands: BTreeMap<String, JsonValue>,
+}
+
+/// A resource of the REST API, i.e. an entity or one of the channels of that entity
+enum Resource {
+    Entity,
+    Twin,
+    Channel(Channel),
+}
+
+impl RestApiState {
+    pub(crate) fn try_new(
+        mqtt_schema: MqttSchema,
+        main_device: EntityTopicId,
+        default_service_type: String,
+        mqtt_publisher: mpsc::Sender<MqttMessage>,
+    ) -> Result<Self, entity_store::InitError> {
+        // The entities are known from the retained registration messages,
+        // hence the external ids are meaningless and are simply derived from the topic ids.
+        let main_device = EntityRegistrationMessage {
+            topic_id: main_device.clone(),
+            external_id: Some(main_device.as_str().into()),
+            r#type: EntityType::MainDevice,
+            parent: None,
+            other: Map::new(),
+        };
+        // The store is not persisted, as rebuilt from the retained messages on restart
+        let entities = EntityStore::in_memory_with_main_device_and_default_service_type(
+            mqtt_schema.clone(),
+            main_device,
+            default_service_type,
+            |topic_id, _| topic_id.as_str().into(),
+            |external_id| Ok(external_id.into()),
+            // No telemetry data is cached, only entity registration and twin data are processed
+            0,
+        )?;
+
+        Ok(RestApiState {
+            mqtt_schema,
+            store: Arc::new(Mutex::new(RestApiStore {
+                entities,
+                commands: BTreeMap::new(),
+            })),
+            stream: MqttStream::default(),
+            mqtt_publisher,
+        })
+    }
+
+    /// The MQTT messages to be received to keep the state up to date and to be streamed
+    ///
+    /// These subscriptions, which include all the telemetry topics, are only made when the REST API is enabled.
+    pub(crate) fn subscriptions(mqtt_schema: &MqttSchema) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for channel in MqttStream::channels() {
+            topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, channel))
+        }
+        topics
+    }
+
+    pub(crate) fn mqtt_schema(&self) -> &MqttSchema {
+        &self.mqtt_schema
+    }
+
+    pub(crate) fn stream(&self) -> &MqttStream {
+        &self.stream
+    }
+
+    /// Update the state from an MQTT message
+    pub(crate) fn process_mqtt_message(&self, message: &MqttMessage) {
+        let Ok((entity, channel)) = self.mqtt_schema.entity_channel_of(&message.topic) else {
+            return;
+        };
+        self.stream.publish(message, &channel);
+
+        let mut store = self.lock();
+        let result = match channel {
+            Channel::EntityMetadata if message.payload_bytes().is_empty() => {
+                store.entities.deregister_entity(&entity).map(|_| ())
+            }
+            Channel::EntityMetadata => match EntityRegistrationMessage::try_from(message) {
+                Ok(registration) => store.entities.update(registration).map(|_| ()),
+                Err(()) => {
+                    error!(
+                        "Invalid registration message received on {}",
+                        message.topic.name
+                    );
+                    Ok(())
+                }
+            },
+            Channel::EntityTwinData { fragment_key } => {
+                let fragment_value = if message.payload_bytes().is_empty() {
+                    JsonValue::Null
+                } else {
+                    serde_json::from_slice(message.payload_bytes()).unwrap_or_else(|_| {
+                        JsonValue::String(message.payload_str().unwrap_or_default().to_string())
+                    })
+                };
+                store
+                    .entities
+                    .update_twin_data(EntityTwinMessage::new(entity, fragment_key, fragment_value))
+                    .map(|_| ())
+            }
+            Channel::CommandMetadata { operation } if !message.payload_bytes().is_empty() => {
+                store.entities.register_capability(&entity, operation);
+                Ok(())
+            }
+            Channel::Command { operation, cmd_id } if message.payload_bytes().is_empty() => {
+                store.commands.remove(&message.topic.name);
+                store.entities.remove_command(&entity, operation, cmd_id);
+                Ok(())
+            }
+            Channel::Command { operation, cmd_id } => {
+                if let Ok(state) = serde_json::from_slice(message.payload_bytes()) {
+                    store.commands.insert(message.topic.name.clone(), state);
+                }
+                store.entities.register_command(&entity, operation, cmd_id);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            debug!("Ignoring {} due to {err}", message.topic.name);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RestApiStore> {
+        // The store is never left in an inconsistent state, hence is still usable even if poisoned
+        self.store
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn parse_resource(&self, path: &str) -> Result<(EntityTopicId, Resource), RestApiError> {
+        let invalid_path = || RestApiError::InvalidPath(path.to_string());
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let segments: Vec<&str> = path.splitn(5, '/').collect();
+        if segments.len() < 4 {
+            return Err(invalid_path());
+        }
+        let entity =
+            EntityTopicId::from_str(&segments[..4].join("/")).map_err(|_| invalid_path())?;
+        let resource = match segments.get(4) {
+            None | Some(&"") => Resource::Entity,
+            Some(&"twin") => Resource::Twin,
+            Some(channel) => Resource::Channel(channel.parse().map_err(|_| invalid_path())?),
+        };
+        Ok((entity, resource))
+    }
+
+    async fn publish(&self, messages: Vec<MqttMessage>) -> Result<(), RestApiError> {
+        let mut publisher = self.mqtt_publisher.clone();
+        for message in messages {
+            publisher
+                .send(message)
+                .await
+                .map_err(|_| RestApiError::MqttDisconnected)?;
+        }
+        Ok(())
+    }
+}
+
+async fn list_entities(State(state): State<RestApiState>) -> Json<Vec<JsonValue>> {
+    let store = state.lock();
+    let mut entities: Vec<&EntityMetadata> = store.entities.iter().map(|(_, e)| e).collect();
+    entities.sort_by(|a, b| a.topic_id.as_str().cmp(b.topic_id.as_str()));
+    Json(entities.into_iter().map(entity_json).collect())
+}
+
+async fn get_resource(
+    State(state): State<RestApiState>,
+    Path(path): Path<String>,
+) -> Result<Response, RestApiError> {
+    let (entity, resource) = state.parse_resource(&path)?;
+    let store = state.lock();
+    let metadata = store
+        .entities
+        .get(&entity)
+        .ok_or_else(|| RestApiError::UnknownEntity(entity.to_string()))?;
+
+    let response = match resource {
+        Resource::Entity => Json(entity_json(metadata)),
+        Resource::Twin => Json(JsonValue::Object(metadata.twin_data.clone())),
+        Resource::Channel(Channel::EntityTwinData { fragment_key }) => {
+            let fragment = metadata
+                .twin_data
+                .get(&fragment_key)
+                .ok_or_else(|| RestApiError::InvalidPath(path.clone()))?;
+            Json(fragment.clone())
+        }
+        Resource::Channel(channel @ Channel::CommandMetadata { .. }) => {
+            let prefix = format!("{}/", state.mqtt_schema.topic_for(&entity, &channel).name);
+            let commands: Map<String, JsonValue> = store
+                .commands
+                .range(prefix.clone()..)
+                .take_while(|(topic, _)| topic.starts_with(&prefix))
+                .map(|(topic, state)| (topic[prefix.len()..].to_string(), state.clone()))
+                .collect();
+            Json(JsonValue::Object(commands))
+        }
+        Resource::Channel(channel @ Channel::Command { .. }) => {
+            let topic = state.mqtt_schema.topic_for(&entity, &channel).name;
+            let command = store
+                .commands
+                .get(&topic)
+                .ok_or_else(|| RestApiError::UnknownCommand(path.clone()))?;
+            Json(command.clone())
+        }
+        Resource::Channel(_) => return Err(RestApiError::InvalidPath(path)),
+    };
+    Ok(response.into_response())
+}
+
+async fn put_resource(
+    State(state): State<RestApiState>,
+    Path(path): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, RestApiError> {
+    let (entity, resource) = state.parse_resource(&path)?;
+    let messages = match resource {
+        Resource::Entity => {
+            let topic = state
+                .mqtt_schema
+                .topic_for(&entity, &Channel::EntityMetadata);
+            let message = MqttMessage::new(&topic, body.to_vec()).with_retain();
+            let registration = EntityRegistrationMessage::try_from(&message)
+                .map_err(|()| RestApiError::InvalidPayload("not a registration message".into()))?;
+            state.lock().entities.update(registration)?;
+            vec![message]
+        }
+        Resource::Channel(channel @ Channel::Command { .. }) => {
+            let mut command = json_object(&body)?;
+            command.entry("status").or_insert_with(|| json!("init"));
+
+            let topic = state.mqtt_schema.topic_for(&entity, &channel);
+            let mut store = state.lock();
+            if store.entities.get(&entity).is_none() {
+                return Err(RestApiError::UnknownEntity(entity.to_string()));
+            }
+            if store.commands.contains_key(&topic.name) {
+                return Err(RestApiError::CommandAlreadyExists(path));
+            }
+            let command = JsonValue::Object(command);
+            store.commands.insert(topic.name.clone(), command.clone());
+            vec![MqttMessage::new(&topic, command.to_string()).with_retain()]
+        }
+        _ => return Err(RestApiError::InvalidPath(path)),
+    };
+
+    state.publish(messages).await?;
+    Ok(StatusCode::CREATED)
+}
+
+async fn post_resource(
+    State(state): State<RestApiState>,
+    Path(path): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, RestApiError> {
+    let (entity, resource) = state.parse_resource(&path)?;
+    let payload = JsonValue::Object(json_object(&body)?).to_string();
+    let message = match resource {
+        Resource::Channel(channel @ (Channel::Measurement { .. } | Channel::Event { .. })) => {
+            MqttMessage::new(&state.mqtt_schema.topic_for(&entity, &channel), payload)
+        }
+        Resource::Channel(channel @ Channel::Alarm { .. }) => {
+            MqttMessage::new(&state.mqtt_schema.topic_for(&entity, &channel), payload).with_retain()
+        }
+        _ => return Err(RestApiError::InvalidPath(path)),
+    };
+
+    state.publish(vec![message]).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn delete_resource(
+    State(state): State<RestApiState>,
+    Path(path): Path<String>,
+) -> Result<StatusCode, RestApiError> {
+    let (entity, resource) = state.parse_resource(&path)?;
+    let messages = match resource {
+        Resource::Entity => {
+            let (removed_entities, clear_messages) =
+                state.lock().entities.deregister_entity(&entity)?;
+            if removed_entities.is_empty() {
+                return Err(RestApiError::UnknownEntity(entity.to_string()));
+            }
+            clear_messages
+        }
+        Resource::Channel(channel @ Channel::Command { .. }) => {
+            let topic = state.mqtt_schema.topic_for(&entity, &channel);
+            if state.lock().commands.remove(&topic.name).is_none() {
+                return Err(RestApiError::UnknownCommand(path));
+            }
+            vec![MqttMessage::new(&topic, "").with_retain()]
+        }
+        _ => return Err(RestApiError::InvalidPath(path)),
+    };
+
+    state.publish(messages).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// The JSON representation of an entity, using the same fields as the registration messages
+fn entity_json(entity: &EntityMetadata) -> JsonValue {
+    let mut json = entity.other.clone();
+    json.insert("@topic-id".into(), entity.topic_id.as_str().into());
+    json.insert("@type".into(), entity.r#type.as_str().into());
+    if let Some(parent) = &entity.parent {
+        json.insert("@parent".into(), parent.as_str().into());
+    }
+    if entity.external_id.as_ref() != entity.topic_id.as_str() {
+        json.insert("@id".into(), entity.external_id.as_ref().into());
+    }
+    JsonValue::Object(json)
+}
+
+fn json_object(body: &[u8]) -> Result<Map<String, JsonValue>, RestApiError> {
+    match serde_json::from_slice(body) {
+        Ok(JsonValue::Object(object)) => Ok(object),
+        Ok(_) => Err(RestApiError::InvalidPayload(
+            "a JSON object is expected".into(),
+        )),
+        Err(err) => Err(RestApiError::InvalidPayload(err.to_string())),
+    }
+}
+
+pub(crate) fn rest_api_router(state: RestApiState) -> Router {
+    Router::new()
+        .route("/tedge/v1/entities", get(list_entities))
+        .route("/tedge/v1/stream", get(stream_messages))
+        .route(
+            "/tedge/v1/entities/*path",
+            get(get_resource)
+                .put(put_resource)
+                .post(post_resource)
+                .delete(delete_resource),
+        )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use futures::StreamExt;
+    use hyper::Method;
+    use hyper::Request;
+    use tedge_mqtt_ext::Topic;
+    use tower::Service;
+
+    #[tokio::test]
+    async fn registered_entities_can_be_listed() {
+        let (mut app, _state, mut published) = app();
+
+        let response = request(
+            &mut app,
+            Method::PUT,
+            "/tedge/v1/entities/device/child1//",
+            r#"{"@type": "child-device", "name": "Child 1"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/child1//");
+        assert!(message.retain);
+
+        let response = request(&mut app, Method::GET, "/tedge/v1/entities", "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            json!([
+                {"@topic-id": "device/child1//", "@type": "child-device", "@parent": "device/main//", "name": "Child 1"},
+                {"@topic-id": "device/main//", "@type": "device"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn twin_data_is_built_from_mqtt_messages() {
+        let (mut app, state, _published) = app();
+
+        state.process_mqtt_message(&MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///twin/maintenance_mode"),
+            "true",
+        ));
+
+        let response = request(
+            &mut app,
+            Method::GET,
+            "/tedge/v1/entities/device/main///twin",
+            "",
+        )
+        .await;
+        assert_eq!(json_body(response).await, json!({"maintenance_mode": true}));
+
+        let response = request(
+            &mut app,
+            Method::GET,
+            "/tedge/v1/entities/device/main///twin/maintenance_mode",
+            "",
+        )
+        .await;
+        assert_eq!(json_body(response).await, json!(true));
+    }
+
+    #[tokio::test]
+    async fn commands_are_created_only_once() {
+        let (mut app, _state, mut published) = app();
+        let path = "/tedge/v1/entities/device/main///cmd/restart/123";
+
+        let response = request(&mut app, Method::PUT, path, "{}").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/main///cmd/restart/123");
+        assert_eq!(message.payload_str().unwrap(), r#"{"status":"init"}"#);
+        assert!(message.retain);
+
+        let response = request(&mut app, Method::PUT, path, "{}").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = request(
+            &mut app,
+            Method::GET,
+            "/tedge/v1/entities/device/main///cmd/restart",
+            "",
+        )
+        .await;
+        assert_eq!(
+            json_body(response).await,
+            json!({"123": {"status": "init"}})
+        );
+
+        let response = request(&mut app, Method::DELETE, path, "").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/main///cmd/restart/123");
+        assert!(message.payload_bytes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn telemetry_data_is_published_over_mqtt() {
+        let (mut app, _state, mut published) = app();
+
+        let response = request(
+            &mut app,
+            Method::POST,
+            "/tedge/v1/entities/device/main///m/environment",
+            r#"{"temperature": 21.5}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/main///m/environment");
+        assert_eq!(message.payload_str().unwrap(), r#"{"temperature":21.5}"#);
+
+        let response = request(
+            &mut app,
+            Method::POST,
+            "/tedge/v1/entities/device/main///m/environment",
+            "21.5",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deregistered_entities_are_cleared_from_mqtt() {
+        let (mut app, state, mut published) = app();
+        state.process_mqtt_message(
+            &MqttMessage::new(
+                &Topic::new_unchecked("te/device/child1//"),
+                r#"{"@type": "child-device"}"#,
+            )
+            .with_retain(),
+        );
+
+        let response = request(
+            &mut app,
+            Method::DELETE,
+            "/tedge/v1/entities/device/child1//",
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/child1//");
+        assert!(message.payload_bytes().is_empty());
+        assert!(message.retain);
+
+        let response = request(
+            &mut app,
+            Method::GET,
+            "/tedge/v1/entities/device/child1//",
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = request(
+            &mut app,
+            Method::DELETE,
+            "/tedge/v1/entities/device/child1//",
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn invalid_paths_are_rejected() {
+        let (mut app, _state, _published) = app();
+
+        for path in [
+            "/tedge/v1/entities/device/main",
+            "/tedge/v1/entities/device/unknown//",
+            "/tedge/v1/entities/device/main///unknown-channel",
+        ] {
+            let response = request(&mut app, Method::GET, path, "").await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+    }
+
+    async fn request(app: &mut Router, method: Method, uri: &str, body: &str) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_owned()))
+            .expect("request builder");
+        app.call(request).await.unwrap().into_response()
+    }
+
+    async fn json_body(response: Response) -> JsonValue {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn app() -> (Router, RestApiState, mpsc::Receiver<MqttMessage>) {
+        let (publisher, published) = mpsc::channel(16);
+        let state = RestApiState::try_new(
+            MqttSchema::default(),
+            EntityTopicId::default_main_device(),
+            "service".to_string(),
+            publisher,
+        )
+        .unwrap();
+        (rest_api_router(state.clone()), state, published)
+    }
+}
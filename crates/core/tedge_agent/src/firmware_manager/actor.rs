@@ -0,0 +1,502 @@
+use crate::firmware_manager::config::FirmwareManagerConfig;
+use crate::firmware_manager::error::FirmwareManagerError;
+use crate::state_repository::error::StateError;
+use crate::state_repository::state::AgentStateRepository;
+use async_trait::async_trait;
+use camino::Utf8PathBuf;
+use logged_command::LoggedCommand;
+use plugin_sm::log_file::LogFile;
+use plugin_sm::operation_logs::LogKind;
+use plugin_sm::operation_logs::OperationLogs;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use tedge_actors::Actor;
+use tedge_actors::ClientMessageBox;
+use tedge_actors::LoggingReceiver;
+use tedge_actors::LoggingSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_api::messages::FirmwareUpdateCmdPayload;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::OperationWorkflow;
+use tedge_downloader_ext::DownloadRequest;
+use tedge_downloader_ext::DownloadResult;
+use tracing::error;
+use tracing::info;
+
+pub type FirmwareDownloadRequest = (String, DownloadRequest);
+pub type FirmwareDownloadResult = (String, DownloadResult);
+
+const SCHEDULED: &str = "scheduled";
+const EXECUTING: &str = "executing";
+const RESTART: &str = "restart";
+const RESTARTING: &str = "restarting";
+const VERIFY: &str = "verify";
+const COMMIT: &str = "commit";
+const ROLLBACK: &str = "rollback";
+const ROLLBACK_RESTART: &str = "rollback_restart";
+const ROLLBACK_RESTARTING: &str = "rollback_restarting";
+const ROLLED_BACK: &str = "rolled_back";
+const SUCCESSFUL: &str = "successful";
+const CANCELLING: &str = "cancelling";
+const CANCELLED: &str = "cancelled";
+
+/// Property of the command payload recording why a firmware update has to be rolled back
+const ROLLBACK_REASON: &str = "rollbackReason";
+
+/// Property of the command payload set by the restart manager when the device failed to restart
+const RESTART_ERROR: &str = "restartError";
+
+/// Property of the command payload telling that the device is still running the previous slot,
+/// the bootloader having fallen back on this slot, so no restart is required after a rollback
+const PREVIOUS_SLOT_RUNNING: &str = "previousSlotRunning";
+
+/// Exit code of `handler verify` when the device is running the previous slot
+const PREVIOUS_SLOT_EXIT_CODE: i32 = 2;
+
+/// The built-in workflow of the `firmware_update` operation
+///
+/// On top of the states common to all the built-in workflows,
+/// the new image is staged into the inactive slot while `executing`,
+/// then the device is restarted and the firmware manager `verify`s the new slot
+/// before making it permanent (`commit`) or restoring the previous slot (`rollback`).
+/// After a rollback, the device is restarted again to run the previous slot,
+/// the command being then marked as failed (`rolled_back`).
+///
+/// A cancelled command is not simply moved to `cancelled`:
+/// the firmware manager has to restore the previous slot, the new image being possibly already installed.
+pub fn firmware_update_workflow() -> OperationWorkflow {
+    let mut workflow = OperationWorkflow::built_in(OperationType::FirmwareUpdate);
+    workflow.states.extend([
+        (
+            RESTART.to_string(),
+            OperationAction::Restart {
+                on_exec: RESTARTING.to_string(),
+                on_success: VERIFY.to_string(),
+                on_error: ROLLBACK.to_string(),
+            },
+        ),
+        (RESTARTING.to_string(), OperationAction::BuiltIn),
+        (VERIFY.to_string(), OperationAction::BuiltIn),
+        (COMMIT.to_string(), OperationAction::BuiltIn),
+        (ROLLBACK.to_string(), OperationAction::BuiltIn),
+        (
+            ROLLBACK_RESTART.to_string(),
+            OperationAction::Restart {
+                on_exec: ROLLBACK_RESTARTING.to_string(),
+                on_success: ROLLED_BACK.to_string(),
+                on_error: ROLLED_BACK.to_string(),
+            },
+        ),
+        (ROLLBACK_RESTARTING.to_string(), OperationAction::BuiltIn),
+        (ROLLED_BACK.to_string(), OperationAction::BuiltIn),
+        (CANCELLING.to_string(), OperationAction::BuiltIn),
+    ]);
+    workflow
+}
+
+/// A `firmware_update` command in one of the states processed by the firmware manager
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FirmwareCommand {
+    pub state: GenericCommandState,
+}
+
+impl From<GenericCommandState> for FirmwareCommand {
+    fn from(state: GenericCommandState) -> Self {
+        FirmwareCommand { state }
+    }
+}
+
+/// Actor which updates the firmware of the device using A/B partitions.
+///
+/// The actual work is delegated to a firmware handler, an executable that:
+/// - `install`s a new image into the inactive slot, making this slot the next one to boot,
+/// - `verify`s that the device has been restarted on the new slot and that this slot is healthy,
+/// - `commit`s the new slot, making it the default one,
+/// - `rollback`s the update, restoring the previous slot as the default one.
+///
+/// The device restarts between the `install` and the `verify` steps,
+/// and after a `rollback` to run the previous slot again,
+/// are not handled by this actor but by the restart manager.
+///
+/// As the software manager, this actor processes only a single request at a time.
+/// On startup, it checks if an image was being installed when the agent stopped,
+/// and if so, marks the operation as failed.
+pub struct FirmwareManagerActor {
+    config: FirmwareManagerConfig,
+    state_repository: AgentStateRepository<FirmwareCommand>,
+    input_receiver: Option<LoggingReceiver<FirmwareCommand>>,
+    output_sender: LoggingSender<FirmwareCommand>,
+    downloader: ClientMessageBox<FirmwareDownloadRequest, FirmwareDownloadResult>,
+}
+
+#[async_trait]
+impl Actor for FirmwareManagerActor {
+    fn name(&self) -> &str {
+        "FirmwareManagerActor"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let operation_logs = OperationLogs::try_new(self.config.log_dir.clone().into())
+            .map_err(FirmwareManagerError::FromOperationsLogs)?;
+
+        self.process_pending_firmware_operation().await?;
+
+        let mut input_receiver = self.input_receiver.take().ok_or(RuntimeError::ActorError(
+            anyhow::anyhow!("actor can't be run more than once").into(),
+        ))?;
+
+        while let Some(request) = input_receiver.recv().await {
+            tokio::select! {
+                result = self.handle_request(request, &operation_logs) => {
+                    if let Err(err) = result {
+                        error!("{err}");
+                    }
+                }
+
+                Some(RuntimeRequest::Shutdown) = input_receiver.recv_signal() => {
+                    info!("Received shutdown request from the runtime, exiting...");
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FirmwareManagerActor {
+    pub fn new(
+        config: FirmwareManagerConfig,
+        message_box: SimpleMessageBox<FirmwareCommand, FirmwareCommand>,
+        downloader: ClientMessageBox<FirmwareDownloadRequest, FirmwareDownloadResult>,
+    ) -> Self {
+        let state_repository = AgentStateRepository::new(
+            config.state_dir.clone(),
+            config.config_dir.clone(),
+            "firmware-current-operation",
+        );
+        let (output_sender, input_receiver) = message_box.into_split();
+
+        Self {
+            config,
+            state_repository,
+            input_receiver: Some(input_receiver),
+            output_sender,
+            downloader,
+        }
+    }
+
+    async fn handle_request(
+        &mut self,
+        request: FirmwareCommand,
+        operation_logs: &OperationLogs,
+    ) -> Result<(), FirmwareManagerError> {
+        let state = request.state;
+        let new_state = match state.status.as_str() {
+            SCHEDULED => return self.handle_firmware_install(state, operation_logs).await,
+            VERIFY => {
+                let mut log_file = Self::new_log_file(operation_logs).await?;
+                match self.run_handler("verify", &[], &mut log_file).await {
+                    Ok(()) => state.move_to(COMMIT.to_string()),
+                    Err(
+                        err @ FirmwareManagerError::HandlerFailed {
+                            exit_code: Some(PREVIOUS_SLOT_EXIT_CODE),
+                            ..
+                        },
+                    ) => Self::rollback_with(state, err)
+                        .update_with_json(json!({ PREVIOUS_SLOT_RUNNING: true })),
+                    Err(err) => Self::rollback_with(state, err),
+                }
+            }
+            COMMIT => {
+                let mut log_file = Self::new_log_file(operation_logs).await?;
+                match self.run_handler("commit", &[], &mut log_file).await {
+                    Ok(()) => state.move_to(SUCCESSFUL.to_string()),
+                    Err(err) => Self::rollback_with(state, err),
+                }
+            }
+            ROLLBACK => {
+                let reason = Self::rollback_reason(&state);
+                let mut log_file = Self::new_log_file(operation_logs).await?;
+                match self.run_handler("rollback", &[], &mut log_file).await {
+                    // No need to restart a device already running the previous slot
+                    Ok(()) if Self::is_previous_slot_running(&state) => state
+                        .update_with_json(json!({ ROLLBACK_REASON: reason }))
+                        .move_to(ROLLED_BACK.to_string()),
+                    // The restart error, if any, has been recorded as the rollback reason
+                    Ok(()) => state
+                        .update_with_json(json!({ ROLLBACK_REASON: reason, RESTART_ERROR: null }))
+                        .move_to(ROLLBACK_RESTART.to_string()),
+                    Err(err) => state.fail_with(format!("{err} (rollback triggered by: {reason})")),
+                }
+            }
+            ROLLED_BACK => {
+                let reason = Self::rollback_reason(&state);
+                let restart_error = state
+                    .payload
+                    .get(RESTART_ERROR)
+                    .and_then(|err| err.as_str())
+                    .map(str::to_string);
+                match restart_error {
+                    None => state.fail_with(format!("Firmware update rolled back: {reason}")),
+                    Some(err) => state.fail_with(format!(
+                        "Firmware update rolled back: {reason}, but the device failed to restart on the previous slot: {err}"
+                    )),
+                }
+            }
+            CANCELLING => self.cancel_firmware_update(state, operation_logs).await?,
+            // Nothing to do while the image is being installed or the device is restarting
+            _ => return Ok(()),
+        };
+
+        self.output_sender.send(new_state.into()).await?;
+        Ok(())
+    }
+
+    /// Restore the previous slot of a cancelled firmware update
+    ///
+    /// The new image might have been installed and even be running, depending on the state of the cancelled command:
+    /// - before the restart, the previous slot is simply restored and the command cancelled
+    /// - once the device is possibly running the new slot, the device is restarted on the previous slot
+    /// - when already rolling back, the rollback is completed
+    async fn cancel_firmware_update(
+        &mut self,
+        state: GenericCommandState,
+        operation_logs: &OperationLogs,
+    ) -> Result<GenericCommandState, FirmwareManagerError> {
+        let cancelled_state = state.cancelled_state().unwrap_or_default();
+        let next_state = match cancelled_state.as_str() {
+            ROLLBACK_RESTART | ROLLBACK_RESTARTING | ROLLED_BACK => {
+                return Ok(state.move_to(ROLLED_BACK.to_string()))
+            }
+            ROLLBACK if Self::is_previous_slot_running(&state) => ROLLED_BACK,
+            RESTART | RESTARTING | VERIFY | COMMIT | ROLLBACK => ROLLBACK_RESTART,
+            _ => CANCELLED,
+        };
+
+        let mut log_file = Self::new_log_file(operation_logs).await?;
+        let new_state = match self.run_handler("rollback", &[], &mut log_file).await {
+            Ok(()) if next_state == CANCELLED => state.move_to(CANCELLED.to_string()),
+            Ok(()) => {
+                let reason = match cancelled_state.as_str() {
+                    ROLLBACK => Self::rollback_reason(&state),
+                    _ => format!("Firmware update cancelled in the {cancelled_state} state"),
+                };
+                state
+                    .update_with_json(json!({ ROLLBACK_REASON: reason, RESTART_ERROR: null }))
+                    .move_to(next_state.to_string())
+            }
+            Err(err) => state.fail_with(format!(
+                "{err} (rollback triggered by the cancellation of the firmware update)"
+            )),
+        };
+        Ok(new_state)
+    }
+
+    async fn process_pending_firmware_operation(&mut self) -> Result<(), FirmwareManagerError> {
+        match self.state_repository.load().await {
+            Ok(Some(FirmwareCommand { state })) => {
+                let response = state.fail_with(
+                    "Firmware update cancelled due to unexpected agent restart".to_string(),
+                );
+                self.output_sender.send(response.into()).await?;
+            }
+            Err(StateError::LoadingFromFileFailed { source, .. })
+                if source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                // file missing means the operation has never been performed, so just do nothing
+            }
+            Err(err) => {
+                // if read failed for some other reason, we should probably log it
+                error!("{err}");
+            }
+            Ok(None) => (),
+        };
+        self.state_repository.clear().await?;
+        Ok(())
+    }
+
+    async fn handle_firmware_install(
+        &mut self,
+        state: GenericCommandState,
+        operation_logs: &OperationLogs,
+    ) -> Result<(), FirmwareManagerError> {
+        let executing = state.move_to(EXECUTING.to_string());
+        self.state_repository
+            .store(&executing.clone().into())
+            .await?;
+        self.output_sender.send(executing.clone().into()).await?;
+
+        let new_state = match self.stage_firmware(&executing, operation_logs).await {
+            Ok(()) => executing.move_to(RESTART.to_string()),
+            Err(err) => {
+                error!("{err}");
+                executing.fail_with(err.to_string())
+            }
+        };
+        self.output_sender.send(new_state.into()).await?;
+
+        self.state_repository.clear().await?;
+        Ok(())
+    }
+
+    /// Download the new image and install it into the inactive slot
+    async fn stage_firmware(
+        &mut self,
+        state: &GenericCommandState,
+        operation_logs: &OperationLogs,
+    ) -> Result<(), FirmwareManagerError> {
+        let request: FirmwareUpdateCmdPayload = serde_json::from_value(state.payload.clone())?;
+        let url = request.tedge_url.unwrap_or(request.remote_url);
+        let cmd_id = state.cmd_id().unwrap_or_default();
+        let image_path = self.config.tmp_dir.join(format!("firmware-{cmd_id}"));
+
+        let image_path = self.download_image(&url, image_path).await?;
+        if let Some(expected) = request.sha256 {
+            if let Err(err) = Self::verify_checksum(&url, &image_path, &expected).await {
+                if let Err(err) = tokio::fs::remove_file(&image_path).await {
+                    error!("Failed to remove the firmware image {image_path}: {err}");
+                }
+                return Err(err);
+            }
+        }
+        let mut log_file = Self::new_log_file(operation_logs).await?;
+        let result = self
+            .run_handler(
+                "install",
+                &[
+                    &request.name,
+                    "--version",
+                    &request.version,
+                    "--file",
+                    image_path.as_str(),
+                ],
+                &mut log_file,
+            )
+            .await;
+
+        if let Err(err) = tokio::fs::remove_file(&image_path).await {
+            error!("Failed to remove the firmware image {image_path}: {err}");
+        }
+        result
+    }
+
+    async fn download_image(
+        &mut self,
+        url: &str,
+        image_path: Utf8PathBuf,
+    ) -> Result<Utf8PathBuf, FirmwareManagerError> {
+        let request = DownloadRequest::new(url, image_path.as_std_path());
+        let (_, result) = self
+            .downloader
+            .await_response((url.to_string(), request))
+            .await?;
+
+        let download_error = |reason: String| FirmwareManagerError::DownloadFailed {
+            url: url.to_string(),
+            reason,
+        };
+        let response = result.map_err(|err| download_error(err.to_string()))?;
+        Utf8PathBuf::try_from(response.file_path).map_err(|err| download_error(err.to_string()))
+    }
+
+    /// Check that a downloaded image has not been truncated or corrupted
+    async fn verify_checksum(
+        url: &str,
+        image_path: &Utf8PathBuf,
+        expected: &str,
+    ) -> Result<(), FirmwareManagerError> {
+        let path = image_path.clone();
+        let actual =
+            tokio::task::spawn_blocking(move || sha256::try_digest(path.as_std_path())).await??;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(FirmwareManagerError::ChecksumMismatch {
+                url: url.to_string(),
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    async fn run_handler(
+        &self,
+        action: &str,
+        args: &[&str],
+        log_file: &mut LogFile,
+    ) -> Result<(), FirmwareManagerError> {
+        let handler_error =
+            |reason: String, exit_code: Option<i32>| FirmwareManagerError::HandlerFailed {
+                action: action.to_string(),
+                reason,
+                exit_code,
+            };
+
+        let mut command = if self.config.use_sudo {
+            let mut command = LoggedCommand::new("sudo")?;
+            command.arg(&self.config.firmware_handler);
+            command
+        } else {
+            LoggedCommand::new(&self.config.firmware_handler)?
+        };
+        command.arg(action);
+        for arg in args {
+            command.arg(arg);
+        }
+
+        let output = command
+            .execute(log_file.buffer())
+            .await
+            .map_err(|err| handler_error(err.to_string(), None))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            };
+            Err(handler_error(reason, output.status.code()))
+        }
+    }
+
+    async fn new_log_file(operation_logs: &OperationLogs) -> Result<LogFile, FirmwareManagerError> {
+        let log_file = operation_logs
+            .new_log_file(LogKind::Operation("firmware-update".to_string()))
+            .await?;
+        Ok(log_file)
+    }
+
+    fn rollback_with(state: GenericCommandState, err: FirmwareManagerError) -> GenericCommandState {
+        error!("{err}");
+        state
+            .update_with_json(json!({ ROLLBACK_REASON: err.to_string() }))
+            .move_to(ROLLBACK.to_string())
+    }
+
+    /// Tell if the device is still running the previous slot, the bootloader having fallen back on it
+    fn is_previous_slot_running(state: &GenericCommandState) -> bool {
+        state
+            .payload
+            .get(PREVIOUS_SLOT_RUNNING)
+            .and_then(|running| running.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Why the firmware update has to be rolled back:
+    /// either the verify or commit step failed, or the device failed to restart
+    fn rollback_reason(state: &GenericCommandState) -> String {
+        [ROLLBACK_REASON, RESTART_ERROR]
+            .iter()
+            .find_map(|key| state.payload.get(key).and_then(|reason| reason.as_str()))
+            .unwrap_or("Unknown reason")
+            .to_string()
+    }
+}
@@ -0,0 +1,68 @@
+use crate::firmware_manager::actor::FirmwareCommand;
+use crate::firmware_manager::actor::FirmwareDownloadRequest;
+use crate::firmware_manager::actor::FirmwareDownloadResult;
+use crate::firmware_manager::actor::FirmwareManagerActor;
+use crate::firmware_manager::config::FirmwareManagerConfig;
+use tedge_actors::Builder;
+use tedge_actors::ClientMessageBox;
+use tedge_actors::DynSender;
+use tedge_actors::LinkError;
+use tedge_actors::NoConfig;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBoxBuilder;
+
+pub struct FirmwareManagerBuilder {
+    config: FirmwareManagerConfig,
+    message_box: SimpleMessageBoxBuilder<FirmwareCommand, FirmwareCommand>,
+    downloader: ClientMessageBox<FirmwareDownloadRequest, FirmwareDownloadResult>,
+}
+
+impl FirmwareManagerBuilder {
+    pub fn new(
+        config: FirmwareManagerConfig,
+        downloader_actor: &mut impl ServiceProvider<
+            FirmwareDownloadRequest,
+            FirmwareDownloadResult,
+            NoConfig,
+        >,
+    ) -> Self {
+        let message_box = SimpleMessageBoxBuilder::new("FirmwareManager", 10);
+        let downloader = ClientMessageBox::new("Firmware Downloader", downloader_actor);
+
+        Self {
+            config,
+            message_box,
+            downloader,
+        }
+    }
+}
+
+impl ServiceProvider<FirmwareCommand, FirmwareCommand, NoConfig> for FirmwareManagerBuilder {
+    fn connect_consumer(
+        &mut self,
+        config: NoConfig,
+        response_sender: DynSender<FirmwareCommand>,
+    ) -> DynSender<FirmwareCommand> {
+        self.message_box.connect_consumer(config, response_sender)
+    }
+}
+
+impl RuntimeRequestSink for FirmwareManagerBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<FirmwareManagerActor> for FirmwareManagerBuilder {
+    type Error = LinkError;
+
+    fn try_build(self) -> Result<FirmwareManagerActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> FirmwareManagerActor {
+        FirmwareManagerActor::new(self.config, self.message_box.build(), self.downloader)
+    }
+}
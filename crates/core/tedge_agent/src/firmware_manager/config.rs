@@ -0,0 +1,38 @@
+use camino::Utf8PathBuf;
+use tedge_config::TEdgeConfigLocation;
+
+#[derive(Debug, Clone)]
+pub struct FirmwareManagerConfig {
+    pub tmp_dir: Utf8PathBuf,
+    pub config_dir: Utf8PathBuf,
+    pub state_dir: Utf8PathBuf,
+    pub log_dir: Utf8PathBuf,
+    pub firmware_handler: Utf8PathBuf,
+    pub use_sudo: bool,
+}
+
+impl FirmwareManagerConfig {
+    pub fn from_tedge_config(
+        tedge_config_location: &TEdgeConfigLocation,
+    ) -> Result<FirmwareManagerConfig, tedge_config::TEdgeConfigError> {
+        let config_repository =
+            tedge_config::TEdgeConfigRepository::new(tedge_config_location.clone());
+        let tedge_config = config_repository.load()?;
+
+        let config_dir = &tedge_config_location.tedge_config_root_path;
+
+        Ok(FirmwareManagerConfig {
+            tmp_dir: tedge_config.tmp.path.clone(),
+            config_dir: config_dir.clone(),
+            state_dir: tedge_config.agent.state.path.clone(),
+            log_dir: tedge_config.logs.path.join("agent"),
+            firmware_handler: config_dir.join("firmware").join("handler"),
+            use_sudo: tedge_config.sudo.enable,
+        })
+    }
+
+    /// The firmware_update operation is only supported when a firmware handler is installed
+    pub fn is_firmware_handler_installed(&self) -> bool {
+        self.firmware_handler.is_file()
+    }
+}
@@ -0,0 +1,44 @@
+#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum FirmwareManagerError {
+    #[error("Incorrect firmware_update request payload: {0}")]
+    InvalidRequest(#[from] serde_json::Error),
+
+    #[error("Failed to download the firmware image from {url}: {reason}")]
+    DownloadFailed { url: String, reason: String },
+
+    #[error("The firmware image downloaded from {url} is corrupted: expected SHA-256 {expected}, got {actual}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("The firmware handler failed to {action} the firmware: {reason}")]
+    HandlerFailed {
+        action: String,
+        reason: String,
+        exit_code: Option<i32>,
+    },
+
+    #[error(transparent)]
+    FromChannelError(#[from] tedge_actors::ChannelError),
+
+    #[error(transparent)]
+    FromState(#[from] crate::state_repository::error::StateError),
+
+    #[error(transparent)]
+    FromOperationsLogs(#[from] plugin_sm::operation_logs::OperationLogsError),
+
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromJoin(#[from] tokio::task::JoinError),
+}
+
+impl From<FirmwareManagerError> for tedge_actors::RuntimeError {
+    fn from(error: FirmwareManagerError) -> Self {
+        tedge_actors::RuntimeError::ActorError(Box::new(error))
+    }
+}
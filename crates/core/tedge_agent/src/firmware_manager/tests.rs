@@ -0,0 +1,433 @@
+use crate::firmware_manager::actor::FirmwareCommand;
+use crate::firmware_manager::actor::FirmwareDownloadRequest;
+use crate::firmware_manager::actor::FirmwareDownloadResult;
+use crate::firmware_manager::builder::FirmwareManagerBuilder;
+use crate::firmware_manager::config::FirmwareManagerConfig;
+use serde_json::json;
+use std::time::Duration;
+use tedge_actors::test_helpers::MessageReceiverExt;
+use tedge_actors::test_helpers::TimedMessageBox;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynError;
+use tedge_actors::MessageReceiver;
+use tedge_actors::Sender;
+use tedge_actors::ServiceConsumer;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::workflow::GenericCommandState;
+use tedge_downloader_ext::DownloadResponse;
+use tedge_mqtt_ext::Topic;
+use tedge_test_utils::fs::with_exec_permission;
+use tedge_test_utils::fs::TempTedgeDir;
+
+const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
+
+type ConverterMessageBox = TimedMessageBox<SimpleMessageBox<FirmwareCommand, FirmwareCommand>>;
+type DownloaderMessageBox =
+    TimedMessageBox<SimpleMessageBox<FirmwareDownloadRequest, FirmwareDownloadResult>>;
+
+/// A firmware handler managing two slots, `a` and `b`, each stored in a loop-back image file.
+///
+/// - `active` is the slot the device is currently running on
+/// - `boot` is the slot the bootloader will use on the next restart
+/// - `committed` is the last slot known to be healthy
+const FIRMWARE_HANDLER: &str = r#"#!/bin/sh
+set -e
+cd "$(dirname "$0")"
+ACTIVE=$(cat active)
+if [ "$ACTIVE" = a ]; then INACTIVE=b; else INACTIVE=a; fi
+case "$1" in
+    install)
+        # install NAME --version VERSION --file IMAGE
+        dd if="$6" of="slot-$INACTIVE.img" conv=notrunc 2>/dev/null
+        echo "$INACTIVE" > boot
+        ;;
+    verify)
+        if [ "$ACTIVE" = "$(cat committed)" ]; then
+            echo "The device is not running the new slot" >&2
+            exit 2
+        fi
+        if ! grep -q healthy "slot-$ACTIVE.img"; then
+            echo "The new slot is not healthy" >&2
+            exit 1
+        fi
+        ;;
+    commit)
+        echo "$ACTIVE" > committed
+        echo "$ACTIVE" > boot
+        ;;
+    rollback)
+        cat committed > boot
+        ;;
+    *)
+        exit 1
+        ;;
+esac
+"#;
+
+#[tokio::test]
+async fn firmware_update_is_committed_once_verified() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    // The new image is staged into the inactive slot
+    converter_box.send(firmware_command("scheduled")).await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    let installed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(installed.status, "restart");
+    assert_eq!(slot_state(&temp_dir, "boot"), "b");
+    assert_eq!(slot_state(&temp_dir, "active"), "a");
+
+    // The bootloader restarts the device on the new slot
+    simulate_device_restart(&temp_dir);
+
+    // The new slot is verified then committed
+    converter_box
+        .send(installed.move_to("verify".to_string()).into())
+        .await?;
+    let verified = converter_box.recv().await.expect("firmware command");
+    assert_eq!(verified.state.status, "commit");
+    converter_box.send(verified).await?;
+    assert_eq!(next_status(&mut converter_box).await, "successful");
+
+    assert_eq!(slot_state(&temp_dir, "committed"), "b");
+    assert_eq!(slot_state(&temp_dir, "boot"), "b");
+    assert!(slot_state(&temp_dir, "slot-b.img").starts_with("healthy image v2"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn firmware_update_is_rolled_back_when_the_new_slot_is_not_healthy() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box.send(firmware_command("scheduled")).await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "corrupted image v2").await?;
+    let installed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(installed.status, "restart");
+
+    simulate_device_restart(&temp_dir);
+
+    // The verification fails and the previous slot is restored
+    converter_box
+        .send(installed.move_to("verify".to_string()).into())
+        .await?;
+    let rollback = converter_box.recv().await.expect("firmware command");
+    assert_eq!(rollback.state.status, "rollback");
+    converter_box.send(rollback).await?;
+
+    // The device is restarted on the previous slot
+    let rolled_back = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(rolled_back.status, "rollback_restart");
+    assert_eq!(slot_state(&temp_dir, "committed"), "a");
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+    simulate_device_restart(&temp_dir);
+    assert_eq!(slot_state(&temp_dir, "active"), "a");
+
+    converter_box
+        .send(rolled_back.move_to("rolled_back".to_string()).into())
+        .await?;
+    let failed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(failed.status, "failed");
+    assert_eq!(
+        failed.failure_reason(),
+        Some("Firmware update rolled back: The firmware handler failed to verify the firmware: The new slot is not healthy".to_string())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn firmware_update_is_rolled_back_when_the_device_fails_to_restart() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box.send(firmware_command("scheduled")).await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    let installed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(installed.status, "restart");
+
+    // The restart manager reports the failure of the restart
+    let restart_failed = installed
+        .update_with_json(json!({ "restartError": "No reboot command" }))
+        .move_to("rollback".to_string());
+    converter_box.send(restart_failed.into()).await?;
+    let rolled_back = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(rolled_back.status, "rollback_restart");
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+
+    // The device also fails to restart on the previous slot
+    let restart_failed = rolled_back
+        .update_with_json(json!({ "restartError": "Still no reboot command" }))
+        .move_to("rolled_back".to_string());
+    converter_box.send(restart_failed.into()).await?;
+    let failed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(failed.status, "failed");
+    assert_eq!(
+        failed.failure_reason(),
+        Some("Firmware update rolled back: No reboot command, but the device failed to restart on the previous slot: Still no reboot command".to_string())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_restart_is_required_when_the_bootloader_fell_back_on_the_previous_slot(
+) -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box.send(firmware_command("scheduled")).await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    let installed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(installed.status, "restart");
+
+    // The device fails to boot on the new slot and the bootloader falls back on the previous slot
+    converter_box
+        .send(installed.move_to("verify".to_string()).into())
+        .await?;
+    let rollback = converter_box.recv().await.expect("firmware command");
+    assert_eq!(rollback.state.status, "rollback");
+    converter_box.send(rollback).await?;
+
+    // The previous slot being already running, the device is not restarted
+    let rolled_back = converter_box.recv().await.expect("firmware command");
+    assert_eq!(rolled_back.state.status, "rolled_back");
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+    converter_box.send(rolled_back).await?;
+    let failed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(failed.status, "failed");
+    assert_eq!(
+        failed.failure_reason(),
+        Some("Firmware update rolled back: The firmware handler failed to verify the firmware: The device is not running the new slot".to_string())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn firmware_image_is_installed_only_if_its_checksum_matches() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    let checksum = sha256::digest("healthy image v2");
+    converter_box
+        .send(firmware_command_with_checksum(&checksum))
+        .await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    assert_eq!(next_status(&mut converter_box).await, "restart");
+    assert_eq!(slot_state(&temp_dir, "boot"), "b");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupted_firmware_image_is_not_installed() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    let checksum = sha256::digest("healthy image v2");
+    converter_box
+        .send(firmware_command_with_checksum(&checksum))
+        .await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy ima").await?;
+    let failed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(failed.status, "failed");
+    assert!(failed.failure_reason().unwrap().starts_with(
+        "The firmware image downloaded from http://example.com/core-image-2.0.img is corrupted"
+    ));
+
+    // The inactive slot is left untouched
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+    assert_eq!(slot_state(&temp_dir, "slot-b.img"), "");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn firmware_update_cancelled_in_the_restart_state_is_rolled_back() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box.send(firmware_command("scheduled")).await?;
+    assert_eq!(next_status(&mut converter_box).await, "executing");
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    let installed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(installed.status, "restart");
+    assert_eq!(slot_state(&temp_dir, "boot"), "b");
+
+    // The command is cancelled while the device might be restarting on the new slot
+    converter_box.send(installed.cancel().into()).await?;
+    let rolled_back = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(rolled_back.status, "rollback_restart");
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+
+    // Hence the device is restarted on the previous slot
+    simulate_device_restart(&temp_dir);
+    assert_eq!(slot_state(&temp_dir, "active"), "a");
+
+    converter_box
+        .send(rolled_back.move_to("rolled_back".to_string()).into())
+        .await?;
+    let failed = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(failed.status, "failed");
+    assert_eq!(
+        failed.failure_reason(),
+        Some(
+            "Firmware update rolled back: Firmware update cancelled in the restart state"
+                .to_string()
+        )
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn firmware_update_cancelled_while_executing_restores_the_previous_slot(
+) -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let (mut converter_box, mut downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box.send(firmware_command("scheduled")).await?;
+    let executing = converter_box.recv().await.expect("firmware command").state;
+    assert_eq!(executing.status, "executing");
+
+    // The cancellation is processed once the image installed
+    converter_box.send(executing.cancel().into()).await?;
+    serve_firmware_image(&mut downloader_box, "healthy image v2").await?;
+    assert_eq!(next_status(&mut converter_box).await, "restart");
+    assert_eq!(next_status(&mut converter_box).await, "cancelled");
+
+    // The device will keep running the previous slot
+    assert_eq!(slot_state(&temp_dir, "boot"), "a");
+    assert_eq!(slot_state(&temp_dir, "committed"), "a");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pending_firmware_update_operation() -> Result<(), DynError> {
+    let temp_dir = TempTedgeDir::new();
+    let pending_command = firmware_command("executing");
+    temp_dir
+        .dir(".agent")
+        .file("firmware-current-operation")
+        .with_raw_content(&serde_json::to_string(&pending_command)?);
+
+    let (mut converter_box, _downloader_box) = spawn_firmware_manager(&temp_dir).await?;
+
+    converter_box
+        .assert_received([FirmwareCommand::from(pending_command.state.fail_with(
+            "Firmware update cancelled due to unexpected agent restart".to_string(),
+        ))])
+        .await;
+
+    Ok(())
+}
+
+fn firmware_command(status: &str) -> FirmwareCommand {
+    GenericCommandState {
+        topic: Topic::new_unchecked("te/device/main///cmd/firmware_update/1234"),
+        status: status.to_string(),
+        payload: json!({
+            "status": status,
+            "name": "core-image",
+            "version": "2.0",
+            "remoteUrl": "http://example.com/core-image-2.0.img",
+        }),
+    }
+    .into()
+}
+
+fn firmware_command_with_checksum(checksum: &str) -> FirmwareCommand {
+    firmware_command("scheduled")
+        .state
+        .update_with_json(json!({ "sha256": checksum }))
+        .into()
+}
+
+async fn next_status(converter_box: &mut ConverterMessageBox) -> String {
+    converter_box
+        .recv()
+        .await
+        .expect("firmware command")
+        .state
+        .status
+}
+
+/// Simulate the downloader, serving an image with the given content
+async fn serve_firmware_image(
+    downloader_box: &mut DownloaderMessageBox,
+    content: &str,
+) -> Result<(), DynError> {
+    let (id, request) = downloader_box.recv().await.expect("download request");
+    assert_eq!(request.url, "http://example.com/core-image-2.0.img");
+    std::fs::write(&request.file_path, content)?;
+    let response = DownloadResponse::new(&request.url, &request.file_path);
+    downloader_box.send((id, Ok(response))).await?;
+    Ok(())
+}
+
+/// Simulate the bootloader restarting the device on the slot marked to boot
+fn simulate_device_restart(temp_dir: &TempTedgeDir) {
+    let firmware_dir = temp_dir.path().join("firmware");
+    std::fs::copy(firmware_dir.join("boot"), firmware_dir.join("active")).unwrap();
+}
+
+fn slot_state(temp_dir: &TempTedgeDir, file: &str) -> String {
+    let path = temp_dir.path().join("firmware").join(file);
+    std::fs::read_to_string(path).unwrap().trim().to_string()
+}
+
+async fn spawn_firmware_manager(
+    tmp_dir: &TempTedgeDir,
+) -> Result<(ConverterMessageBox, DownloaderMessageBox), DynError> {
+    tmp_dir.dir(".agent");
+    let firmware_dir = tmp_dir.dir("firmware");
+    for (file, content) in [
+        ("active", "a"),
+        ("boot", "a"),
+        ("committed", "a"),
+        ("slot-a.img", "healthy image v1"),
+        ("slot-b.img", ""),
+    ] {
+        firmware_dir.file(file).with_raw_content(content);
+    }
+    let firmware_handler = firmware_dir.utf8_path().join("handler");
+    with_exec_permission(firmware_handler.as_std_path(), FIRMWARE_HANDLER);
+
+    let mut converter_builder: SimpleMessageBoxBuilder<FirmwareCommand, FirmwareCommand> =
+        SimpleMessageBoxBuilder::new("Converter", 5);
+    let mut downloader_builder: SimpleMessageBoxBuilder<
+        FirmwareDownloadRequest,
+        FirmwareDownloadResult,
+    > = SimpleMessageBoxBuilder::new("Downloader", 5);
+
+    let config = FirmwareManagerConfig {
+        tmp_dir: tmp_dir.utf8_path_buf(),
+        config_dir: tmp_dir.utf8_path_buf(),
+        state_dir: "/some/unknown/dir".into(),
+        log_dir: tmp_dir.utf8_path_buf(),
+        firmware_handler,
+        use_sudo: false,
+    };
+
+    let mut firmware_actor_builder = FirmwareManagerBuilder::new(config, &mut downloader_builder);
+    converter_builder.set_connection(&mut firmware_actor_builder);
+
+    let converter_box = converter_builder.build().with_timeout(TEST_TIMEOUT_MS);
+    let downloader_box = downloader_builder.build().with_timeout(TEST_TIMEOUT_MS);
+
+    let firmware_actor = firmware_actor_builder.build();
+    tokio::spawn(async move { firmware_actor.run().await });
+
+    Ok((converter_box, downloader_box))
+}
@@ -22,6 +22,7 @@ use tracing::log::warn;
 
 mod agent;
 mod file_transfer_server;
+mod firmware_manager;
 mod operation_file_cache;
 mod restart_manager;
 mod software_manager;
@@ -97,6 +98,8 @@ pub struct Capabilities {
     config_update: bool,
     config_snapshot: bool,
     log_upload: bool,
+    alarm_rules: bool,
+    rest_api: bool,
 }
 
 #[cfg(test)]
@@ -106,6 +109,8 @@ impl Default for Capabilities {
             config_update: true,
             config_snapshot: true,
             log_upload: true,
+            alarm_rules: true,
+            rest_api: true,
         }
     }
 }
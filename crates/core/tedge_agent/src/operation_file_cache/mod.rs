@@ -126,11 +126,8 @@ impl FileCacheActor {
                 &update_payload.config_type,
                 &cmd_id,
             )?,
-            CommandStatus::Failed { .. } => self.delete_symlink_for_config_update(
-                &entity,
-                &update_payload.config_type,
-                &cmd_id,
-            )?,
+            CommandStatus::Failed { .. } | CommandStatus::Cancelled => self
+                .delete_symlink_for_config_update(&entity, &update_payload.config_type, &cmd_id)?,
             _ => {}
         }
 
@@ -1,9 +1,14 @@
+use crate::firmware_manager::actor::FirmwareCommand;
 use crate::software_manager::actor::SoftwareCommand;
 use crate::state_repository::state::AgentStateRepository;
+use crate::tedge_operation_converter::cancellation::RunningScripts;
 use async_trait::async_trait;
 use camino::Utf8PathBuf;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use log::error;
 use log::info;
+use serde_json::json;
 use std::process::Output;
 use std::time::Duration;
 use tedge_actors::fan_in_message_type;
@@ -22,20 +27,35 @@ use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::sub_command_id;
 use tedge_api::workflow::CommandBoard;
+use tedge_api::workflow::CommandHistory;
+use tedge_api::workflow::CommandHistoryEntry;
 use tedge_api::workflow::GenericCommandState;
 use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::ScheduleDecision;
+use tedge_api::workflow::ShellScript;
 use tedge_api::workflow::WorkflowExecutionError;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
 use tedge_script_ext::Execute;
+use tedge_timer_ext::SetTimeout;
+use tedge_timer_ext::Timeout;
 use time::format_description;
 use time::OffsetDateTime;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::time::sleep;
 
-fan_in_message_type!(AgentInput[MqttMessage, GenericCommandState, SoftwareCommand, RestartCommand] : Debug);
+/// A client of the script actor, used to run one script at a time
+pub type ScriptRunner = ClientMessageBox<Execute, std::io::Result<Output>>;
+
+/// A request to process a command state later, when a deferred command has to be resumed
+pub type CommandTimer = SetTimeout<GenericCommandState>;
+pub type CommandTimeout = Timeout<GenericCommandState>;
+
+fan_in_message_type!(AgentInput[MqttMessage, GenericCommandState, SoftwareCommand, RestartCommand, FirmwareCommand, CommandTimeout] : Debug);
 
 pub struct TedgeOperationConverterActor {
     pub(crate) mqtt_schema: MqttSchema,
@@ -46,9 +66,13 @@ pub struct TedgeOperationConverterActor {
     pub(crate) input_receiver: LoggingReceiver<AgentInput>,
     pub(crate) software_sender: LoggingSender<SoftwareCommand>,
     pub(crate) restart_sender: LoggingSender<RestartCommand>,
+    pub(crate) firmware_sender: LoggingSender<FirmwareCommand>,
     pub(crate) command_sender: DynSender<GenericCommandState>,
     pub(crate) mqtt_publisher: LoggingSender<MqttMessage>,
-    pub(crate) script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
+    pub(crate) script_runner: ScriptRunner,
+    pub(crate) parallel_script_runners: Vec<ScriptRunner>,
+    pub(crate) timer_sender: DynSender<CommandTimer>,
+    pub(crate) running_scripts: RunningScripts,
 }
 
 #[async_trait]
@@ -78,6 +102,12 @@ impl Actor for TedgeOperationConverterActor {
                 AgentInput::RestartCommand(cmd) => {
                     self.process_restart_response(cmd).await?;
                 }
+                AgentInput::FirmwareCommand(cmd) => {
+                    self.process_firmware_response(cmd).await?;
+                }
+                AgentInput::CommandTimeout(timeout) => {
+                    self.process_deferred_command(timeout.event).await?;
+                }
             }
         }
         Ok(())
@@ -113,6 +143,7 @@ impl TedgeOperationConverterActor {
                         .log_step("", "The command has been fully processed")
                         .await;
                     self.persist_command_board().await?;
+                    self.start_queued_commands().await?;
                 }
             }
             Ok(Some(state)) => {
@@ -147,6 +178,25 @@ impl TedgeOperationConverterActor {
         };
         let mut log_file = CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
 
+        if self.is_cancelled_meanwhile(&state) {
+            info!(
+                "Ignoring {} state of cancelled {operation} operation",
+                state.status
+            );
+            return Ok(());
+        }
+        if state.is_cancelling() {
+            self.cancel_sub_command(&state).await?;
+        }
+
+        if state.is_terminal() || state.is_queued() {
+            // Some room might have been made for queued commands
+            self.start_queued_commands().await?;
+        }
+        if state.is_queued() {
+            return Ok(());
+        }
+
         let action = match self.workflows.get_action(&state) {
             Ok(action) => action,
             Err(WorkflowExecutionError::UnknownStep { operation, step }) => {
@@ -163,10 +213,50 @@ impl TedgeOperationConverterActor {
             }
         };
 
-        log_file.log_state_action(&state, &action).await;
+        if self.workflows.is_scheduled(&state) {
+            let now = OffsetDateTime::now_utc();
+            let reason = match self.workflows.check_schedule(&state, now) {
+                Ok(ScheduleDecision::Proceed) => None,
+                Ok(ScheduleDecision::Defer { until }) => {
+                    info!("Deferring {operation} operation till {until}");
+                    log_file
+                        .log_step(&state.status, &format!("Deferred till {until}"))
+                        .await;
+                    let delay = Duration::try_from(until - now).unwrap_or_default();
+                    self.timer_sender
+                        .send(SetTimeout::new(delay, state))
+                        .await?;
+                    return Ok(());
+                }
+                Ok(ScheduleDecision::Missed { reason }) => Some(reason),
+                Err(err) => Some(err.to_string()),
+            };
+            if let Some(reason) = reason {
+                log_file.log_step(&state.status, &reason).await;
+                let new_state = state.fail_with(reason);
+                return self.publish_command_state(new_state).await;
+            }
+
+            if !self.workflows.try_admit_command(&state) {
+                info!("Queuing {operation} operation till running operations complete");
+                log_file
+                    .log_step(&state.status, "Queued till running operations complete")
+                    .await;
+                let new_state = state.queue();
+                return self.publish_command_state(new_state).await;
+            }
+        }
+
+        if !has_history(&state) {
+            // Else, the terminal state has been recorded before the history was attached
+            log_file.log_state_action(&state, &action).await;
+        }
 
         match action {
             OperationAction::Clear => {
+                if let Some(command) = self.workflows.parent_command_state(&state).cloned() {
+                    return self.resume_parent_command(command, state).await;
+                }
                 info!(
                     "Waiting {} {operation} operation to be cleared",
                     state.status
@@ -181,7 +271,7 @@ impl TedgeOperationConverterActor {
             OperationAction::BuiltIn => {
                 let step = &state.status;
                 info!("Processing {operation} operation {step} step");
-                self.process_internal_operation(target, operation, cmd_id, state.payload)
+                self.process_internal_operation(target, operation, cmd_id, state)
                     .await
             }
             OperationAction::AwaitingAgentRestart {
@@ -221,52 +311,305 @@ impl TedgeOperationConverterActor {
                 info!("Processing {operation} operation {step} step with script: {script}");
 
                 let script_name = script.command.clone();
-                let command = {
-                    let command = Execute::new(script_name.clone(), script.args);
-                    match (
+                let command = script_command(
+                    script,
+                    handlers.graceful_timeout(),
+                    handlers.forceful_timeout_extension(),
+                );
+                let cancellation = self.running_scripts.register(&state.topic.name);
+                let command = command.with_cancellation(cancellation.clone());
+                let output = self.script_runner.await_response(command).await?;
+                self.running_scripts.unregister(&state.topic.name);
+                log_file
+                    .log_script_output(&state.status, &script_name, &output)
+                    .await;
+                if cancellation.is_cancelled() {
+                    info!(
+                        "{operation} operation {step} step cancelled while running: {script_name}"
+                    );
+                    return Ok(());
+                }
+
+                let attempt = state.retry_attempt();
+                if let Some(delay) = handlers.retry_delay(&output, attempt) {
+                    let attempt = attempt + 1;
+                    let step = &state.status;
+                    info!("Retrying {operation} operation {step} step in {delay:?} (attempt {attempt})");
+                    log_file
+                        .log_step(step, &format!("Retry {attempt} in {delay:?}"))
+                        .await;
+                    let new_state = state.retry(attempt);
+                    return self.publish_delayed_command_state(new_state, delay).await;
+                }
+
+                let new_state =
+                    state
+                        .clear_retry()
+                        .update_with_script_output(script_name, output, handlers);
+                self.publish_command_state(new_state).await
+            }
+            OperationAction::Parallel(scripts, handlers) => {
+                let step = &state.status;
+                info!(
+                    "Processing {operation} operation {step} step with {} scripts in parallel",
+                    scripts.len()
+                );
+
+                let cancellation = self.running_scripts.register(&state.topic.name);
+                // Cancelling the scripts still running once joined must not cancel the command
+                let join_cancellation = cancellation.child_token();
+                let run_script = |mut script_runner: ScriptRunner, script: ShellScript| {
+                    let script_name = script.command.clone();
+                    let command = script_command(
+                        script,
                         handlers.graceful_timeout(),
                         handlers.forceful_timeout_extension(),
-                    ) {
-                        (Some(timeout), Some(extra)) => command
-                            .with_graceful_timeout(timeout)
-                            .with_forceful_timeout_extension(extra),
-                        (Some(timeout), None) => command.with_graceful_timeout(timeout),
-                        (None, _) => command,
+                    )
+                    .with_cancellation(join_cancellation.clone());
+                    async move {
+                        let output = script_runner.await_response(command).await;
+                        (script_runner, script_name, output)
                     }
                 };
-                let output = self.script_runner.await_response(command).await?;
-                log_file.log_script_output(&output).await;
 
-                let new_state = state.update_with_script_output(script_name, output, handlers);
+                // A script is launched only when one of the script runners is available
+                let mut scripts = scripts.into_iter();
+                let mut pending_scripts = FuturesUnordered::new();
+                let mut idle_runners = vec![];
+                for script_runner in self.parallel_script_runners.drain(..) {
+                    match scripts.next() {
+                        Some(script) => pending_scripts.push(run_script(script_runner, script)),
+                        None => idle_runners.push(script_runner),
+                    }
+                }
+
+                let mut outcomes = Vec::new();
+                let mut join_completed = false;
+                let mut channel_error = None;
+                while let Some((script_runner, script_name, output)) = pending_scripts.next().await
+                {
+                    let output = match output {
+                        Ok(output) => output,
+                        Err(err) => {
+                            channel_error = Some(err);
+                            join_cancellation.cancel();
+                            idle_runners.push(script_runner);
+                            continue;
+                        }
+                    };
+                    log_file
+                        .log_script_output(&state.status, &script_name, &output)
+                        .await;
+                    if join_completed {
+                        // The outcome of a script killed once the join completed is ignored
+                        idle_runners.push(script_runner);
+                        continue;
+                    }
+
+                    join_completed = handlers.is_complete_on(&output);
+                    outcomes.push((script_name, output));
+                    if join_completed {
+                        // The remaining scripts are killed before moving to the next state
+                        join_cancellation.cancel();
+                        idle_runners.push(script_runner);
+                    } else if let Some(script) = scripts.next() {
+                        pending_scripts.push(run_script(script_runner, script));
+                    } else {
+                        idle_runners.push(script_runner);
+                    }
+                }
+                self.parallel_script_runners = idle_runners;
+                self.running_scripts.unregister(&state.topic.name);
+                if let Some(err) = channel_error {
+                    return Err(err.into());
+                }
+                if cancellation.is_cancelled() {
+                    let step = &state.status;
+                    info!("{operation} operation {step} step cancelled while running scripts");
+                    return Ok(());
+                }
+
+                let new_state = state.update_with_json(handlers.state_update(outcomes));
                 self.publish_command_state(new_state).await
             }
+            OperationAction::Condition(handlers) => {
+                let update = handlers.state_update(&state);
+                info!("Moving {operation} operation to state: {}", update.status);
+                let new_state = state.update(update);
+                self.publish_command_state(new_state).await
+            }
+            OperationAction::Operation(sub_operation, input, handlers) => {
+                let step = &state.status;
+                if !self.workflows.is_registered(&sub_operation) {
+                    let reason =
+                        format!("No workflow is defined for the sub-operation: {sub_operation}");
+                    error!("{operation} operation {step} step cannot be processed: {reason}");
+                    let new_state = state.fail_with(reason);
+                    return self.publish_command_state(new_state).await;
+                }
+
+                info!("Triggering {sub_operation} sub-operation in the context of {operation} operation {step} step");
+                let sub_command_topic = self.mqtt_schema.topic_for(
+                    &target,
+                    &Channel::Command {
+                        operation: sub_operation,
+                        cmd_id: sub_command_id(&operation, &cmd_id),
+                    },
+                );
+                let sub_command = GenericCommandState {
+                    topic: sub_command_topic.clone(),
+                    status: "init".to_string(),
+                    payload: input.0,
+                }
+                .move_to("init".to_string());
+
+                // The parent command is moved to its next state *before* the sub-command is created,
+                // so the sub-command outcome is always observed in this next state.
+                let new_state = state.update(handlers.on_exec);
+                if let Err(err) = self
+                    .workflows
+                    .start_sub_command(&new_state, &sub_command_topic)
+                {
+                    error!(
+                        "Fail to register the {} sub-command: {err}",
+                        sub_command_topic.name
+                    );
+                }
+                self.publish_command_state(new_state).await?;
+
+                // The sub-command is created over MQTT, as any other command request
+                self.mqtt_publisher.send(sub_command.into_message()).await?;
+                Ok(())
+            }
+            OperationAction::AwaitOperationCompletion(_, _) => {
+                match self.workflows.sub_command_state(&state).cloned() {
+                    Some(sub_command) if sub_command.is_terminal() => {
+                        self.resume_parent_command(state, sub_command).await
+                    }
+                    _ => {
+                        let step = &state.status;
+                        info!("{operation} operation {step} step waiting for sub-operation completion");
+                        Ok(())
+                    }
+                }
+            }
             OperationAction::BgScript(script, handlers) => {
                 let next_state = &handlers.on_exec.status;
                 info!(
                     "Moving {operation} operation to {next_state} state before running: {script}"
                 );
+                let step = state.status.clone();
+                let command_topic = state.topic.name.clone();
                 let new_state = state.update(handlers.on_exec);
                 self.publish_command_state(new_state).await?;
 
                 // Run the command, but ignore its result
-                let command = Execute::new(script.command, script.args);
+                let script_name = script.command.clone();
+                let cancellation = self.running_scripts.register(&command_topic);
+                let command =
+                    Execute::new(script.command, script.args).with_cancellation(cancellation);
                 let output = self.script_runner.await_response(command).await?;
-                log_file.log_script_output(&output).await;
+                self.running_scripts.unregister(&command_topic);
+                log_file
+                    .log_script_output(&step, &script_name, &output)
+                    .await;
                 Ok(())
             }
         }
     }
 
+    /// Resume a command awaiting the completion of a sub-command which reached a terminal state
+    async fn resume_parent_command(
+        &mut self,
+        command: GenericCommandState,
+        sub_command: GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        let (handlers, output) = match self.workflows.get_action(&command) {
+            Ok(OperationAction::AwaitOperationCompletion(handlers, output)) => (handlers, output),
+            _ if command.is_cancelled() => {
+                // The parent command has been cancelled and is no more awaiting the sub-command
+                self.clear_sub_command(&sub_command).await?;
+                return Ok(());
+            }
+            _ => {
+                // The parent command is not yet awaiting the sub-command:
+                // it will be resumed when moving to its awaiting state.
+                return Ok(());
+            }
+        };
+
+        let update = handlers.state_update(&sub_command);
+        info!(
+            "Resuming {} after {} sub-operation completion: moving to state {}",
+            command.topic.name, sub_command.status, update.status
+        );
+        let new_state = command
+            .update_with_json(output.extract_from(&sub_command))
+            .update(update);
+
+        self.clear_sub_command(&sub_command).await?;
+        self.publish_command_state(new_state).await
+    }
+
+    /// Clear a sub-command that reached a terminal state
+    ///
+    /// The sub-command is cleared by its requester, i.e. the parent command
+    async fn clear_sub_command(
+        &mut self,
+        sub_command: &GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        self.workflows.complete_sub_command(sub_command);
+        let clear_sub_command = MqttMessage::new(&sub_command.topic, "")
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce);
+        self.mqtt_publisher.send(clear_sub_command).await?;
+        Ok(())
+    }
+
+    /// Request the cancellation of the sub-command triggered by a command being cancelled, if any
+    async fn cancel_sub_command(
+        &mut self,
+        state: &GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        let Some(sub_command) = self.workflows.sub_command_state(state) else {
+            return Ok(());
+        };
+        if sub_command.is_terminal() || sub_command.is_cancelled() {
+            return Ok(());
+        }
+        info!("Cancelling sub-command {}", sub_command.topic.name);
+        let cancel_sub_command = MqttMessage::new(
+            &sub_command.topic,
+            json!({ "status": "cancelling" }).to_string(),
+        )
+        .with_retain()
+        .with_qos(QoS::AtLeastOnce);
+        self.mqtt_publisher.send(cancel_sub_command).await?;
+        Ok(())
+    }
+
+    /// Tell if the given state is obsolete, the command having been cancelled in the meantime
+    ///
+    /// This is notably the case of the outcome of a builtin action
+    /// or of a retry scheduled before the command was cancelled.
+    fn is_cancelled_meanwhile(&self, state: &GenericCommandState) -> bool {
+        !state.is_cancelled()
+            && self
+                .workflows
+                .command_state(&state.topic.name)
+                .is_some_and(GenericCommandState::is_cancelled)
+    }
+
     async fn process_internal_operation(
         &mut self,
         target: EntityTopicId,
         operation: OperationType,
         cmd_id: String,
-        message: serde_json::Value,
+        state: GenericCommandState,
     ) -> Result<(), RuntimeError> {
         match operation {
             OperationType::SoftwareList => {
-                match SoftwareListCommand::try_from_json(target, cmd_id, message) {
+                match SoftwareListCommand::try_from_json(target, cmd_id, state.payload) {
                     Ok(cmd) => {
                         self.software_sender.send(cmd.into()).await?;
                     }
@@ -275,7 +618,7 @@ impl TedgeOperationConverterActor {
             }
 
             OperationType::SoftwareUpdate => {
-                match SoftwareUpdateCommand::try_from_json(target, cmd_id, message) {
+                match SoftwareUpdateCommand::try_from_json(target, cmd_id, state.payload) {
                     Ok(cmd) => {
                         self.software_sender.send(cmd.into()).await?;
                     }
@@ -284,7 +627,7 @@ impl TedgeOperationConverterActor {
             }
 
             OperationType::Restart => {
-                match RestartCommand::try_from_json(target, cmd_id, message) {
+                match RestartCommand::try_from_json(target, cmd_id, state.payload) {
                     Ok(cmd) => {
                         self.restart_sender.send(cmd).await?;
                     }
@@ -292,6 +635,20 @@ impl TedgeOperationConverterActor {
                 }
             }
 
+            OperationType::FirmwareUpdate => {
+                self.firmware_sender.send(state.into()).await?;
+            }
+
+            // Commands executed by the config and log managers, once moved to executing by the agent
+            OperationType::ConfigSnapshot
+            | OperationType::ConfigUpdate
+            | OperationType::LogUpload
+                if state.status == "scheduled" =>
+            {
+                let new_state = state.move_to("executing".to_string());
+                self.publish_command_state(new_state).await?;
+            }
+
             // Command not managed by the agent
             _ => {}
         }
@@ -325,10 +682,31 @@ impl TedgeOperationConverterActor {
         self.publish_command_state(new_state).await
     }
 
+    async fn process_firmware_response(
+        &mut self,
+        response: FirmwareCommand,
+    ) -> Result<(), RuntimeError> {
+        self.publish_command_state(response.state).await
+    }
+
     async fn publish_command_state(
         &mut self,
         new_state: GenericCommandState,
     ) -> Result<(), RuntimeError> {
+        if self.is_cancelled_meanwhile(&new_state) {
+            info!(
+                "Ignoring {} state of cancelled command {}",
+                new_state.status, new_state.topic.name
+            );
+            return Ok(());
+        }
+        let new_state = if new_state.is_terminal() && self.workflows.is_history_attached(&new_state)
+        {
+            self.record_terminal_state(&new_state).await;
+            self.attach_command_history(new_state).await
+        } else {
+            new_state
+        };
         if let Err(err) = self.workflows.apply_internal_update(new_state.clone()) {
             error!("Fail to persist workflow operation state: {err}");
         }
@@ -338,6 +716,88 @@ impl TedgeOperationConverterActor {
         Ok(())
     }
 
+    /// Resume a command that has been deferred till its schedule allows its execution,
+    /// or till the delay before a retry is elapsed
+    ///
+    /// The command is ignored if it has been updated or cleared in the meantime.
+    async fn process_deferred_command(
+        &mut self,
+        state: GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        if self.workflows.command_state(&state.topic.name) != Some(&state) {
+            return Ok(());
+        }
+        self.process_command_state_update(state).await
+    }
+
+    /// Start the queued commands that can now be executed given the concurrency limits
+    async fn start_queued_commands(&mut self) -> Result<(), RuntimeError> {
+        while let Some(command) = self.workflows.dequeue_command() {
+            info!("Starting queued command {}", command.topic.name);
+            self.publish_command_state(command).await?;
+        }
+        Ok(())
+    }
+
+    /// Record the terminal state of a command, so it's part of the history attached to this state
+    async fn record_terminal_state(&self, state: &GenericCommandState) {
+        let (Some(operation), Some(cmd_id)) = (state.operation(), state.cmd_id()) else {
+            return;
+        };
+        let Ok(action) = self.workflows.get_action(state) else {
+            return;
+        };
+        let mut log_file =
+            CommandLog::new(self.log_dir.clone(), &operation.as_str().into(), &cmd_id).await;
+        log_file.log_state_action(state, &action).await;
+    }
+
+    /// Attach to a command state the history of its execution
+    async fn attach_command_history(&self, state: GenericCommandState) -> GenericCommandState {
+        let (Some(operation), Some(cmd_id)) = (state.operation(), state.cmd_id()) else {
+            return state;
+        };
+        let path = self.log_dir.join(CommandHistory::file_name(
+            &operation.as_str().into(),
+            &cmd_id,
+        ));
+        let history = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => CommandHistory::from_json_lines(&content),
+            Err(err) => {
+                error!("Fail to read the command history from {path}: {err}");
+                return state;
+            }
+        };
+        match history {
+            Ok(history) => state.update_with_json(json!({ HISTORY: history.to_json() })),
+            Err(err) => {
+                error!("Fail to parse the command history from {path}: {err}");
+                state
+            }
+        }
+    }
+
+    /// Publish a new state for a command, that will be processed only after the given delay
+    ///
+    /// The new state is persisted right away, so the command can be resumed after a restart.
+    async fn publish_delayed_command_state(
+        &mut self,
+        new_state: GenericCommandState,
+        delay: Duration,
+    ) -> Result<(), RuntimeError> {
+        if let Err(err) = self.workflows.apply_internal_update(new_state.clone()) {
+            error!("Fail to persist workflow operation state: {err}");
+        }
+        self.persist_command_board().await?;
+        self.mqtt_publisher
+            .send(new_state.clone().into_message())
+            .await?;
+        self.timer_sender
+            .send(SetTimeout::new(delay, new_state))
+            .await?;
+        Ok(())
+    }
+
     /// Reload from disk the current state of the pending command requests
     async fn load_command_board(&mut self) -> Result<(), RuntimeError> {
         match self.state_repository.load().await {
@@ -375,9 +835,35 @@ impl TedgeOperationConverterActor {
     }
 }
 
+/// Build the request to execute a script with the timeouts given by the workflow, if any
+fn script_command(
+    script: ShellScript,
+    graceful_timeout: Option<Duration>,
+    forceful_timeout_extension: Option<Duration>,
+) -> Execute {
+    let command = Execute::new(script.command, script.args);
+    match (graceful_timeout, forceful_timeout_extension) {
+        (Some(timeout), Some(extra)) => command
+            .with_graceful_timeout(timeout)
+            .with_forceful_timeout_extension(extra),
+        (Some(timeout), None) => command.with_graceful_timeout(timeout),
+        (None, _) => command,
+    }
+}
+
+/// Property of a terminal command state holding the history of the command execution
+const HISTORY: &str = "@history";
+
+/// Tell if the history of the command execution has been attached to this state
+fn has_history(state: &GenericCommandState) -> bool {
+    state.payload.get(HISTORY).is_some()
+}
+
 struct CommandLog {
     path: Utf8PathBuf,
     file: Option<File>,
+    history_path: Utf8PathBuf,
+    history_file: Option<File>,
 }
 
 impl CommandLog {
@@ -385,24 +871,35 @@ impl CommandLog {
         let path = log_dir
             .clone()
             .join(format!("workflow-{}-{}.log", operation, cmd_id));
-        match File::options()
-            .append(true)
-            .create(true)
-            .open(path.clone())
-            .await
-        {
-            Ok(file) => CommandLog {
-                path,
-                file: Some(file),
-            },
+        let file = Self::open(&path).await;
+        let history_path = log_dir.join(CommandHistory::file_name(operation, cmd_id));
+        let history_file = Self::open(&history_path).await;
+        CommandLog {
+            path,
+            file,
+            history_path,
+            history_file,
+        }
+    }
+
+    async fn open(path: &Utf8PathBuf) -> Option<File> {
+        match File::options().append(true).create(true).open(path).await {
+            Ok(file) => Some(file),
             Err(err) => {
                 error!("Fail to open log file {path}: {err}");
-                CommandLog { path, file: None }
+                None
             }
         }
     }
 
     async fn log_state_action(&mut self, state: &GenericCommandState, action: &OperationAction) {
+        self.record(CommandHistoryEntry::new(
+            OffsetDateTime::now_utc(),
+            state,
+            action,
+        ))
+        .await;
+
         let step = &state.status;
         let state = &state.payload.to_string();
         let message = format!(
@@ -430,12 +927,46 @@ Action: {action}
         }
     }
 
-    async fn log_script_output(&mut self, result: &Result<Output, std::io::Error>) {
+    async fn log_script_output(
+        &mut self,
+        step: &str,
+        script: &str,
+        result: &Result<Output, std::io::Error>,
+    ) {
+        self.record(CommandHistoryEntry::script_outcome(
+            OffsetDateTime::now_utc(),
+            step,
+            script,
+            result,
+        ))
+        .await;
+
         if let Err(err) = self.write_script_output(result).await {
             error!("Fail to log to {}: {err}", self.path)
         }
     }
 
+    /// Append an entry to the command history
+    async fn record(&mut self, entry: CommandHistoryEntry) {
+        if let Err(err) = self.write_history_entry(&entry).await {
+            error!("Fail to record history to {}: {err}", self.history_path)
+        }
+    }
+
+    async fn write_history_entry(
+        &mut self,
+        entry: &CommandHistoryEntry,
+    ) -> Result<(), std::io::Error> {
+        if let Some(file) = self.history_file.as_mut() {
+            let mut line = serde_json::to_string(entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+            file.sync_all().await?;
+        }
+        Ok(())
+    }
+
     async fn write_script_output(
         &mut self,
         result: &Result<Output, std::io::Error>,
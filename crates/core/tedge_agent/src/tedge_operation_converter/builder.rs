@@ -1,7 +1,13 @@
+use crate::firmware_manager::actor::FirmwareCommand;
 use crate::software_manager::actor::SoftwareCommand;
 use crate::state_repository::state::AgentStateRepository;
 use crate::tedge_operation_converter::actor::AgentInput;
+use crate::tedge_operation_converter::actor::CommandTimeout;
+use crate::tedge_operation_converter::actor::CommandTimer;
+use crate::tedge_operation_converter::actor::ScriptRunner;
 use crate::tedge_operation_converter::actor::TedgeOperationConverterActor;
+use crate::tedge_operation_converter::cancellation::CancellationSender;
+use crate::tedge_operation_converter::cancellation::RunningScripts;
 use crate::tedge_operation_converter::config::OperationConfig;
 use log::error;
 use std::process::Output;
@@ -28,26 +34,37 @@ use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::TopicFilter;
 use tedge_script_ext::Execute;
 
+/// The maximum number of scripts run concurrently by a parallel state,
+/// which is also the number of scripts the script actor runs concurrently
+const MAX_PARALLEL_SCRIPTS: usize = 4;
+
 pub struct TedgeOperationConverterBuilder {
     config: OperationConfig,
     workflows: WorkflowSupervisor,
     input_receiver: LoggingReceiver<AgentInput>,
     software_sender: LoggingSender<SoftwareCommand>,
     restart_sender: LoggingSender<RestartCommand>,
+    firmware_sender: LoggingSender<FirmwareCommand>,
     command_sender: DynSender<GenericCommandState>,
     mqtt_publisher: LoggingSender<MqttMessage>,
-    script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
+    script_runner: ScriptRunner,
+    parallel_script_runners: Vec<ScriptRunner>,
+    timer_sender: DynSender<CommandTimer>,
+    running_scripts: RunningScripts,
     signal_sender: mpsc::Sender<RuntimeRequest>,
 }
 
 impl TedgeOperationConverterBuilder {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: OperationConfig,
         mut workflows: WorkflowSupervisor,
         software_actor: &mut impl ServiceProvider<SoftwareCommand, SoftwareCommand, NoConfig>,
         restart_actor: &mut impl ServiceProvider<RestartCommand, RestartCommand, NoConfig>,
+        firmware_actor: &mut impl ServiceProvider<FirmwareCommand, FirmwareCommand, NoConfig>,
         mqtt_actor: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
         script_runner: &mut impl ServiceProvider<Execute, std::io::Result<Output>, NoConfig>,
+        timer_actor: &mut impl ServiceProvider<CommandTimer, CommandTimeout, NoConfig>,
     ) -> Self {
         let (input_sender, input_receiver) = mpsc::channel(10);
         let (signal_sender, signal_receiver) = mpsc::channel(10);
@@ -64,14 +81,26 @@ impl TedgeOperationConverterBuilder {
 
         let restart_sender = restart_actor.connect_consumer(NoConfig, input_sender.clone().into());
         let restart_sender = LoggingSender::new("RestartSender".into(), restart_sender);
+
+        let firmware_sender =
+            firmware_actor.connect_consumer(NoConfig, input_sender.clone().into());
+        let firmware_sender = LoggingSender::new("FirmwareSender".into(), firmware_sender);
         let command_sender = input_sender.clone().into();
+        let timer_sender = timer_actor.connect_consumer(NoConfig, input_sender.clone().into());
 
+        let running_scripts = RunningScripts::default();
         let mqtt_publisher = mqtt_actor.connect_consumer(
             Self::subscriptions(&config.mqtt_schema, &config.device_topic_id),
-            input_sender.into(),
+            Box::new(CancellationSender {
+                inner: input_sender.into(),
+                running_scripts: running_scripts.clone(),
+            }),
         );
         let mqtt_publisher = LoggingSender::new("MqttPublisher".into(), mqtt_publisher);
 
+        let parallel_script_runners = (0..MAX_PARALLEL_SCRIPTS)
+            .map(|_| ClientMessageBox::new("Operation Parallel Script Runner", script_runner))
+            .collect();
         let script_runner = ClientMessageBox::new("Operation Script Runner", script_runner);
 
         for capability in Self::capabilities() {
@@ -80,6 +109,8 @@ impl TedgeOperationConverterBuilder {
                 error!("Fail to register built-in workflow for {operation} operation: {err}");
             }
         }
+        workflows.set_maintenance_windows(config.maintenance_windows.clone());
+        workflows.set_concurrency_limits(config.concurrency_limits.clone());
 
         Self {
             config,
@@ -87,10 +118,14 @@ impl TedgeOperationConverterBuilder {
             input_receiver,
             software_sender,
             restart_sender,
+            firmware_sender,
             command_sender,
             mqtt_publisher,
             signal_sender,
             script_runner,
+            parallel_script_runners,
+            timer_sender,
+            running_scripts,
         }
     }
 
@@ -132,9 +167,13 @@ impl Builder<TedgeOperationConverterActor> for TedgeOperationConverterBuilder {
             input_receiver: self.input_receiver,
             software_sender: self.software_sender,
             restart_sender: self.restart_sender,
+            firmware_sender: self.firmware_sender,
             mqtt_publisher: self.mqtt_publisher,
             command_sender: self.command_sender,
             script_runner: self.script_runner,
+            parallel_script_runners: self.parallel_script_runners,
+            timer_sender: self.timer_sender,
+            running_scripts: self.running_scripts,
         }
     }
 }
@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tedge_actors::ChannelError;
+use tedge_actors::DynSender;
+use tedge_actors::Sender;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::TopicName;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_script_ext::CancellationToken;
+
+/// The scripts currently executed on behalf of commands
+///
+/// As the agent actor is blocked while a script is running,
+/// the script of a command has to be cancelled *before* the cancellation request reaches the actor.
+/// Hence, this registry is shared by the actor and the [CancellationSender] that forwards the MQTT messages.
+#[derive(Clone, Default)]
+pub struct RunningScripts {
+    tokens: Arc<Mutex<HashMap<TopicName, CancellationToken>>>,
+}
+
+impl RunningScripts {
+    /// Register a script executed on behalf of the given command,
+    /// returning the token to be used to cancel that script
+    pub fn register(&self, command: &TopicName) -> CancellationToken {
+        let token = CancellationToken::new();
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.insert(command.clone(), token.clone());
+        }
+        token
+    }
+
+    /// Unregister the script executed on behalf of the given command
+    pub fn unregister(&self, command: &TopicName) {
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.remove(command);
+        }
+    }
+
+    /// Cancel the script executed on behalf of the given command, if any
+    pub fn cancel(&self, command: &TopicName) {
+        if let Ok(tokens) = self.tokens.lock() {
+            if let Some(token) = tokens.get(command) {
+                token.cancel();
+            }
+        }
+    }
+}
+
+/// A Sender that cancels the running script of a command on reception of a cancellation request
+///
+/// This sender receives the MQTT messages sent to the agent,
+/// and forwards these messages to the agent actor, unchanged.
+pub struct CancellationSender {
+    pub inner: DynSender<MqttMessage>,
+    pub running_scripts: RunningScripts,
+}
+
+#[async_trait]
+impl Sender<MqttMessage> for CancellationSender {
+    async fn send(&mut self, message: MqttMessage) -> Result<(), ChannelError> {
+        if let Ok(Some(state)) = GenericCommandState::from_command_message(&message) {
+            // The cancelling states published by the agent itself record the cancelled state
+            if state.is_cancelling() && state.cancelled_state().is_none() {
+                self.running_scripts.cancel(&message.topic.name);
+            }
+        }
+        self.inner.send(message).await
+    }
+
+    fn sender_clone(&self) -> DynSender<MqttMessage> {
+        Box::new(CancellationSender {
+            inner: self.inner.sender_clone(),
+            running_scripts: self.running_scripts.clone(),
+        })
+    }
+
+    fn close_sender(&mut self) {
+        self.inner.as_mut().close_sender()
+    }
+}
@@ -1,6 +1,9 @@
 use camino::Utf8PathBuf;
+use log::error;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::workflow::ConcurrencyLimits;
+use tedge_api::workflow::MaintenanceWindow;
 
 #[derive(Debug, Clone)]
 pub struct OperationConfig {
@@ -9,6 +12,8 @@ pub struct OperationConfig {
     pub log_dir: Utf8PathBuf,
     pub config_dir: Utf8PathBuf,
     pub state_dir: Utf8PathBuf,
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    pub concurrency_limits: ConcurrencyLimits,
 }
 
 impl OperationConfig {
@@ -26,6 +31,42 @@ impl OperationConfig {
             log_dir: tedge_config.logs.path.join("agent"),
             config_dir: tedge_config_location.tedge_config_root_path.clone(),
             state_dir: tedge_config.agent.state.path.clone(),
+            maintenance_windows: maintenance_windows(&tedge_config.agent.maintenance_windows.0),
+            concurrency_limits: concurrency_limits(&tedge_config),
         })
     }
 }
+
+fn maintenance_windows(definitions: &[String]) -> Vec<MaintenanceWindow> {
+    definitions
+        .iter()
+        .filter_map(|definition| match definition.parse() {
+            Ok(window) => Some(window),
+            Err(err) => {
+                error!("Ignoring maintenance window: {err}");
+                None
+            }
+        })
+        .collect()
+}
+
+fn concurrency_limits(tedge_config: &tedge_config::TEdgeConfig) -> ConcurrencyLimits {
+    let concurrency = &tedge_config.agent.concurrency;
+    let mut limits = ConcurrencyLimits {
+        max_commands: concurrency.max_commands.or_none().map(|max| *max as usize),
+        ..ConcurrencyLimits::default()
+    };
+    for definition in concurrency.operations.0.iter() {
+        match limits.clone().with_operation_limit(definition) {
+            Ok(updated) => limits = updated,
+            Err(err) => error!("Ignoring concurrency limit: {err}"),
+        }
+    }
+    for definition in concurrency.exclusion_groups.0.iter() {
+        match limits.clone().with_exclusion_group(definition) {
+            Ok(updated) => limits = updated,
+            Err(err) => error!("Ignoring concurrency limit: {err}"),
+        }
+    }
+    limits
+}
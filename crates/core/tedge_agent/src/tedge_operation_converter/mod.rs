@@ -1,5 +1,6 @@
 pub mod actor;
 pub mod builder;
+pub mod cancellation;
 pub mod config;
 
 #[cfg(test)]
@@ -1,8 +1,9 @@
+use crate::firmware_manager::actor::firmware_update_workflow;
+use crate::firmware_manager::actor::FirmwareCommand;
 use crate::software_manager::actor::SoftwareCommand;
 use crate::tedge_operation_converter::builder::TedgeOperationConverterBuilder;
 use crate::tedge_operation_converter::config::OperationConfig;
 use camino::Utf8Path;
-use std::process::Output;
 use std::time::Duration;
 use tedge_actors::test_helpers::MessageReceiverExt;
 use tedge_actors::test_helpers::TimedMessageBox;
@@ -22,13 +23,19 @@ use tedge_api::messages::SoftwareRequestResponseSoftwareList;
 use tedge_api::messages::SoftwareUpdateCommandPayload;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::ConcurrencyLimits;
+use tedge_api::workflow::OperationWorkflow;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_api::RestartCommand;
 use tedge_api::SoftwareUpdateCommand;
 use tedge_mqtt_ext::test_helpers::assert_received_contains_str;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
-use tedge_script_ext::Execute;
+use tedge_script_ext::ScriptActor;
+use tedge_timer_ext::TimerActor;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
 
@@ -237,6 +244,888 @@ async fn convert_outgoing_restart_response() -> Result<(), DynError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn trigger_and_await_sub_operation() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "inventory"
+
+[init]
+operation = "software_list"
+on_exec = "awaiting_software_list"
+
+[awaiting_software_list]
+action = "await-operation-completion"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+
+    // Skip the capability messages: inventory, restart, software_list and software_update
+    mqtt_box.skip(4).await;
+
+    // Simulate an inventory request
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/inventory/123"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    // The inventory command awaits the completion of a software_list sub-command
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/inventory/123",
+                r#""status":"awaiting_software_list""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/sub-inventory-123",
+                r#""status":"init""#,
+            ),
+        ],
+    )
+    .await;
+
+    // Simulate the echo of the sub-command creation
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_list/sub-inventory-123"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    // The sub-command is processed as any software_list command
+    let sub_command = SoftwareListCommand::new(
+        &EntityTopicId::default_main_device(),
+        "sub-inventory-123".to_string(),
+    );
+    software_box
+        .assert_received([sub_command.clone().with_status(CommandStatus::Scheduled)])
+        .await;
+    software_box
+        .send(sub_command.with_status(CommandStatus::Successful).into())
+        .await?;
+
+    // On sub-command completion, the sub-command is cleared and the inventory command resumed
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_list/sub-inventory-123",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/sub-inventory-123",
+                r#""status":"successful""#,
+            ),
+            ("te/device/main///cmd/software_list/sub-inventory-123", ""),
+            (
+                "te/device/main///cmd/inventory/123",
+                r#""status":"successful""#,
+            ),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn join_parallel_scripts() -> Result<(), DynError> {
+    let tmp_dir = tempfile::TempDir::new()?;
+    let child_script = create_child_script(&tmp_dir)?;
+    let workflow: OperationWorkflow = toml::from_str(&format!(
+        r#"
+operation = "fan_out"
+
+[init]
+parallel = ["sh {child_script} child1 0", "sh {child_script} child2 0"]
+on_success = "successful"
+on_error = "failed"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#
+    ))?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+
+    // Skip the capability messages: fan_out, restart, software_list and software_update
+    mqtt_box.skip(4).await;
+
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/fan_out/123"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    // The outputs of all the scripts are merged into the next state
+    let successful = mqtt_box.recv().await.expect("fan_out command state");
+    assert_eq!(successful.topic.name, "te/device/main///cmd/fan_out/123");
+    let payload: serde_json::Value = serde_json::from_str(successful.payload_str()?)?;
+    assert_eq!(payload["status"], "successful");
+    assert_eq!(payload["child1"], "done");
+    assert_eq!(payload["child2"], "done");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn join_all_fails_and_kills_the_remaining_scripts_on_first_failure() -> Result<(), DynError> {
+    let tmp_dir = tempfile::TempDir::new()?;
+    let child_script = create_child_script(&tmp_dir)?;
+    let marker = tmp_dir.path().join("slow-script-completed");
+    let marker = marker.display();
+    let workflow: OperationWorkflow = toml::from_str(&format!(
+        r#"
+operation = "fan_out"
+
+[init]
+parallel = ["sh {child_script} child1 3", "sh -c 'sleep 1 && touch {marker}'"]
+join = "all"
+on_success = "successful"
+on_error = "failed"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#
+    ))?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+    mqtt_box.skip(4).await;
+
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/fan_out/123"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/fan_out/123",
+            r#""reason":"sh returned exit code 3","status":"failed""#,
+        )],
+    )
+    .await;
+
+    // The slow script has been killed
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!tmp_dir.path().join("slow-script-completed").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn join_any_succeeds_and_kills_the_remaining_scripts_on_first_success() -> Result<(), DynError>
+{
+    let tmp_dir = tempfile::TempDir::new()?;
+    let child_script = create_child_script(&tmp_dir)?;
+    let marker = tmp_dir.path().join("slow-script-completed");
+    let marker = marker.display();
+    let workflow: OperationWorkflow = toml::from_str(&format!(
+        r#"
+operation = "fan_out"
+
+[init]
+parallel = [
+    "sh {child_script} child1 1",
+    "sh {child_script} child2 0",
+    "sh -c 'sleep 1 && touch {marker}'",
+]
+join = "any"
+on_success = "successful"
+on_error = "failed"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#
+    ))?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+    mqtt_box.skip(4).await;
+
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/fan_out/123"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    // Only the output of the successful script is injected into the next state
+    let successful = mqtt_box.recv().await.expect("fan_out command state");
+    let payload: serde_json::Value = serde_json::from_str(successful.payload_str()?)?;
+    assert_eq!(payload["status"], "successful");
+    assert_eq!(payload["child2"], "done");
+    assert!(payload.get("child1").is_none());
+
+    // The slow script has been killed
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!tmp_dir.path().join("slow-script-completed").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn check_the_schedule_on_the_state_following_init() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "custom"
+
+[init]
+action = "proceed"
+on_success = "waiting"
+
+[waiting]
+action = "proceed"
+on_success = "successful"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+    mqtt_box.skip(4).await;
+
+    // Simulate a custom request with a deadline already reached
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/custom/1234"),
+            r#"{ "status": "init", "not_after": "2023-12-01T02:00:00Z" }"#,
+        ))
+        .await?;
+
+    // The command is failed without being moved further
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            ("te/device/main///cmd/custom/1234", r#""status":"waiting""#),
+            (
+                "te/device/main///cmd/custom/1234",
+                r#""reason":"The command has not been executed before its deadline: 2023-12-01T02:00:00Z""#,
+            ),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn select_next_state_on_conditions() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "dispatch"
+
+[init]
+action = "proceed"
+condition = [
+    { when = "${.payload.target}", equals = "child", then = "child_update" },
+    { when = "${.payload.target}", one_of = ["main", "gateway"], then = "main_update" },
+]
+on_success = "failed"
+
+[child_update]
+action = "proceed"
+on_success = "successful"
+
+[main_update]
+action = "proceed"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+    mqtt_box.skip(4).await;
+
+    for (cmd_id, target, next_states) in [
+        ("1", "child", vec!["child_update", "successful"]),
+        ("2", "gateway", vec!["main_update", "successful"]),
+        ("3", "unknown", vec!["failed"]),
+    ] {
+        let topic = format!("te/device/main///cmd/dispatch/{cmd_id}");
+        mqtt_box
+            .send(MqttMessage::new(
+                &Topic::new_unchecked(&topic),
+                format!(r#"{{ "status": "init", "target": "{target}" }}"#),
+            ))
+            .await?;
+        for next_state in next_states {
+            assert_received_contains_str(
+                &mut mqtt_box,
+                [(
+                    topic.as_str(),
+                    format!(r#""status":"{next_state}""#).as_str(),
+                )],
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn attach_the_history_including_the_terminal_state() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "tracked"
+attach_history = true
+
+[init]
+action = "proceed"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+    mqtt_box.skip(4).await;
+
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/tracked/1"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+
+    let message = mqtt_box.recv().await.expect("successful command");
+    let payload: serde_json::Value = serde_json::from_slice(message.payload_bytes())?;
+    assert_eq!(payload["status"], "successful");
+    let states: Vec<&str> = payload["@history"]
+        .as_array()
+        .expect("command history")
+        .iter()
+        .filter_map(|entry| entry["state"].as_str())
+        .collect();
+    assert_eq!(states, vec!["init", "successful"]);
+
+    Ok(())
+}
+
+/// Create a script emitting `{ "<name>": "done" }` on its stdout and exiting with the given code
+fn create_child_script(tmp_dir: &tempfile::TempDir) -> Result<String, DynError> {
+    let child_script = tmp_dir.path().join("child.sh");
+    std::fs::write(
+        &child_script,
+        r#"echo ':::begin-tedge:::'
+echo "{\"$1\": \"done\"}"
+echo ':::end-tedge:::'
+exit $2
+"#,
+    )?;
+    Ok(child_script.display().to_string())
+}
+
+#[tokio::test]
+async fn verify_firmware_update_after_restart() -> Result<(), DynError> {
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(firmware_update_workflow())?;
+
+    let (_software_box, mut restart_box, mut firmware_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_firmware(
+            "device/main//",
+            workflows,
+            ConcurrencyLimits::default(),
+        )
+        .await?;
+
+    // Skip the capability messages: firmware_update, restart, software_list and software_update
+    mqtt_box.skip(4).await;
+
+    // Simulate a firmware_update request
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            r#"{ "status": "init", "name": "image", "version": "1.0", "remoteUrl": "http://example.com/image" }"#,
+        ))
+        .await?;
+
+    // The firmware manager is requested to stage the new image
+    let scheduled = firmware_box
+        .recv()
+        .await
+        .expect("scheduled firmware command");
+    assert_eq!(scheduled.state.status, "scheduled");
+
+    // Once the image installed, the device is restarted
+    firmware_box
+        .send(scheduled.state.move_to("restart".to_string()).into())
+        .await?;
+    let restart = restart_box.recv().await.expect("restart command");
+    assert_eq!(restart.cmd_id, "123");
+    assert_eq!(restart.status(), CommandStatus::Scheduled);
+
+    // After the restart, the firmware manager is requested to verify the new image
+    restart_box
+        .send(restart.with_status(CommandStatus::Successful))
+        .await?;
+    let verify = firmware_box.recv().await.expect("verify firmware command");
+    assert_eq!(verify.state.status, "verify");
+    assert_eq!(verify.state.payload["version"], "1.0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn restart_device_after_firmware_rollback() -> Result<(), DynError> {
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(firmware_update_workflow())?;
+
+    let (_software_box, mut restart_box, mut firmware_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_firmware(
+            "device/main//",
+            workflows,
+            ConcurrencyLimits::default(),
+        )
+        .await?;
+
+    // Skip the capability messages: firmware_update, restart, software_list and software_update
+    mqtt_box.skip(4).await;
+
+    // Simulate a firmware_update request
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            r#"{ "status": "init", "name": "image", "version": "1.0", "remoteUrl": "http://example.com/image" }"#,
+        ))
+        .await?;
+    let scheduled = firmware_box
+        .recv()
+        .await
+        .expect("scheduled firmware command");
+
+    // Once the previous slot restored, the device is restarted
+    firmware_box
+        .send(
+            scheduled
+                .state
+                .move_to("rollback_restart".to_string())
+                .into(),
+        )
+        .await?;
+    let restart = restart_box.recv().await.expect("restart command");
+    assert_eq!(restart.cmd_id, "123");
+    assert_eq!(restart.status(), CommandStatus::Scheduled);
+
+    // After the restart, the firmware manager is requested to fail the command
+    restart_box
+        .send(restart.with_status(CommandStatus::Successful))
+        .await?;
+    let rolled_back = firmware_box
+        .recv()
+        .await
+        .expect("rolled back firmware command");
+    assert_eq!(rolled_back.state.status, "rolled_back");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancelled_firmware_update_is_forwarded_to_the_firmware_manager() -> Result<(), DynError> {
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(firmware_update_workflow())?;
+
+    let (_software_box, _restart_box, mut firmware_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_firmware(
+            "device/main//",
+            workflows,
+            ConcurrencyLimits::default(),
+        )
+        .await?;
+    mqtt_box.skip(4).await;
+
+    let topic = Topic::new_unchecked("te/device/main///cmd/firmware_update/123");
+    mqtt_box
+        .send(MqttMessage::new(
+            &topic,
+            r#"{ "status": "init", "name": "image", "version": "1.0", "remoteUrl": "http://example.com/image" }"#,
+        ))
+        .await?;
+    let scheduled = firmware_box
+        .recv()
+        .await
+        .expect("scheduled firmware command");
+    firmware_box
+        .send(scheduled.state.move_to("executing".to_string()).into())
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/firmware_update/123",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/firmware_update/123",
+                r#""status":"executing""#,
+            ),
+        ],
+    )
+    .await;
+
+    let executing = firmware_box
+        .recv()
+        .await
+        .expect("executing firmware command");
+    assert_eq!(executing.state.status, "executing");
+
+    // The firmware manager is requested to restore the previous slot
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{ "status": "cancelling" }"#))
+        .await?;
+    let cancelling = firmware_box
+        .recv()
+        .await
+        .expect("cancelled firmware command");
+    assert_eq!(cancelling.state.status, "cancelling");
+    assert_eq!(
+        cancelling.state.cancelled_state(),
+        Some("executing".to_string())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn defer_command_till_not_before() -> Result<(), DynError> {
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate a software_list request not to be executed before a second
+    let not_before = (OffsetDateTime::now_utc() + time::Duration::seconds(1)).format(&Rfc3339)?;
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_list/1234"),
+            format!(r#"{{ "status": "init", "not_before": "{not_before}" }}"#),
+        ))
+        .await?;
+
+    // The command is held in the scheduled state
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_list/1234",
+            r#""status":"scheduled""#,
+        )],
+    )
+    .await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), software_box.recv())
+            .await
+            .is_err()
+    );
+
+    // Till the time has come
+    let software_request = software_box.recv().await.expect("A deferred request");
+    assert!(matches!(
+        software_request,
+        SoftwareCommand::SoftwareListCommand(cmd) if cmd.cmd_id == "1234"
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fail_command_on_missed_deadline() -> Result<(), DynError> {
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate a software_list request with a deadline already reached
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_list/1234"),
+            r#"{ "status": "init", "not_after": "2023-12-01T02:00:00Z" }"#,
+        ))
+        .await?;
+
+    // The command is failed without being executed
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_list/1234",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/1234",
+                r#""reason":"The command has not been executed before its deadline: 2023-12-01T02:00:00Z""#,
+            ),
+        ],
+    )
+    .await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), software_box.recv())
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn queue_commands_exceeding_concurrency_limits() -> Result<(), DynError> {
+    let limits = ConcurrencyLimits::default().with_operation_limit("software_list=1")?;
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_limits(
+            "device/main//",
+            WorkflowSupervisor::default(),
+            limits,
+        )
+        .await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate two software_list requests
+    for cmd_id in ["1", "2"] {
+        mqtt_box
+            .send(MqttMessage::new(
+                &Topic::new_unchecked(&format!("te/device/main///cmd/software_list/{cmd_id}")),
+                r#"{ "status": "init" }"#,
+            ))
+            .await?;
+    }
+
+    // Only the first one is executed, the second one being queued
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_list/1",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/2",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/2",
+                r#""status":"queued""#,
+            ),
+        ],
+    )
+    .await;
+    let first_command =
+        SoftwareListCommand::new(&EntityTopicId::default_main_device(), "1".to_string());
+    software_box
+        .assert_received([first_command.clone().with_status(CommandStatus::Scheduled)])
+        .await;
+
+    // On completion of the first command, the second one is started
+    software_box
+        .send(first_command.with_status(CommandStatus::Successful).into())
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_list/1",
+                r#""status":"successful""#,
+            ),
+            (
+                "te/device/main///cmd/software_list/2",
+                r#""status":"scheduled""#,
+            ),
+        ],
+    )
+    .await;
+    let second_command =
+        SoftwareListCommand::new(&EntityTopicId::default_main_device(), "2".to_string());
+    software_box
+        .assert_received([second_command.with_status(CommandStatus::Scheduled)])
+        .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_update_commands_are_moved_to_executing_given_the_concurrency_limits(
+) -> Result<(), DynError> {
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_delegated_workflow(OperationType::ConfigUpdate)?;
+    let limits = ConcurrencyLimits::default().with_operation_limit("config_update=1")?;
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_limits("device/main//", workflows, limits).await?;
+    // The config_update capability is left to the config manager
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate two config_update requests
+    for cmd_id in ["1", "2"] {
+        mqtt_box
+            .send(MqttMessage::new(
+                &Topic::new_unchecked(&format!("te/device/main///cmd/config_update/{cmd_id}")),
+                r#"{ "status": "init", "type": "mosquitto" }"#,
+            ))
+            .await?;
+    }
+
+    // Only the first one is moved to executing, the second one being queued
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/config_update/1",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/config_update/2",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/config_update/1",
+                r#""status":"executing""#,
+            ),
+            (
+                "te/device/main///cmd/config_update/2",
+                r#""status":"queued""#,
+            ),
+        ],
+    )
+    .await;
+
+    // The first command is counted as running till cleared
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/config_update/1"),
+            r#"{ "status": "successful", "type": "mosquitto" }"#,
+        ))
+        .await?;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), mqtt_box.recv())
+            .await
+            .is_err()
+    );
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/config_update/1"),
+            "",
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/config_update/2",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/config_update/2",
+                r#""status":"executing""#,
+            ),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_command_and_ignore_late_outcome() -> Result<(), DynError> {
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate a software_list request
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_list/1234"),
+            r#"{ "status": "init" }"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_list/1234",
+            r#""status":"scheduled""#,
+        )],
+    )
+    .await;
+    let command =
+        SoftwareListCommand::new(&EntityTopicId::default_main_device(), "1234".to_string());
+    software_box
+        .assert_received([command.clone().with_status(CommandStatus::Scheduled)])
+        .await;
+
+    // Request the command to be cancelled
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_list/1234"),
+            r#"{ "status": "cancelling" }"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_list/1234",
+            r#"{"cancelled_state":"scheduled","status":"cancelled"}"#,
+        )],
+    )
+    .await;
+
+    // The outcome of the cancelled command is ignored
+    software_box
+        .send(command.with_status(CommandStatus::Successful).into())
+        .await?;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), mqtt_box.recv())
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
 async fn spawn_mqtt_operation_converter(
     device_topic_id: &str,
 ) -> Result<
@@ -246,17 +1135,74 @@ async fn spawn_mqtt_operation_converter(
         TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
     ),
     DynError,
+> {
+    spawn_mqtt_operation_converter_with_workflows(device_topic_id, WorkflowSupervisor::default())
+        .await
+}
+
+async fn spawn_mqtt_operation_converter_with_workflows(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    ),
+    DynError,
+> {
+    spawn_mqtt_operation_converter_with_limits(
+        device_topic_id,
+        workflows,
+        ConcurrencyLimits::default(),
+    )
+    .await
+}
+
+async fn spawn_mqtt_operation_converter_with_limits(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+    concurrency_limits: ConcurrencyLimits,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    ),
+    DynError,
+> {
+    let (software_box, restart_box, _firmware_box, mqtt_message_box) =
+        spawn_mqtt_operation_converter_with_firmware(
+            device_topic_id,
+            workflows,
+            concurrency_limits,
+        )
+        .await?;
+    Ok((software_box, restart_box, mqtt_message_box))
+}
+
+async fn spawn_mqtt_operation_converter_with_firmware(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+    concurrency_limits: ConcurrencyLimits,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<FirmwareCommand, FirmwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    ),
+    DynError,
 > {
     let mut software_builder: SimpleMessageBoxBuilder<SoftwareCommand, SoftwareCommand> =
         SimpleMessageBoxBuilder::new("Software", 5);
     let mut restart_builder: SimpleMessageBoxBuilder<RestartCommand, RestartCommand> =
         SimpleMessageBoxBuilder::new("Restart", 5);
+    let mut firmware_builder: SimpleMessageBoxBuilder<FirmwareCommand, FirmwareCommand> =
+        SimpleMessageBoxBuilder::new("Firmware", 5);
     let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
         SimpleMessageBoxBuilder::new("MQTT", 5);
-    let mut script_builder: SimpleMessageBoxBuilder<Execute, std::io::Result<Output>> =
-        SimpleMessageBoxBuilder::new("Script", 5);
-
-    let workflows = WorkflowSupervisor::default();
+    let mut script_builder = ScriptActor::builder();
 
     let tmp_dir = tempfile::TempDir::new().unwrap();
     let tmp_path = Utf8Path::from_path(tmp_dir.path()).unwrap();
@@ -266,24 +1212,38 @@ async fn spawn_mqtt_operation_converter(
         log_dir: tmp_path.into(),
         config_dir: tmp_path.into(),
         state_dir: tmp_path.into(),
+        maintenance_windows: vec![],
+        concurrency_limits,
     };
+    let mut timer_builder = TimerActor::builder();
     let converter_actor_builder = TedgeOperationConverterBuilder::new(
         config,
         workflows,
         &mut software_builder,
         &mut restart_builder,
+        &mut firmware_builder,
         &mut mqtt_builder,
         &mut script_builder,
+        &mut timer_builder,
     );
 
     let software_box = software_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let restart_box = restart_builder.build().with_timeout(TEST_TIMEOUT_MS);
+    let firmware_box = firmware_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let mqtt_message_box = mqtt_builder.build().with_timeout(TEST_TIMEOUT_MS);
 
     let converter_actor = converter_actor_builder.build();
-    tokio::spawn(async move { converter_actor.run().await });
+    tokio::spawn(async move {
+        // The log directory is kept till the end of the test
+        let _tmp_dir = tmp_dir;
+        converter_actor.run().await
+    });
+    let timer_actor = timer_builder.build();
+    tokio::spawn(async move { timer_actor.run().await });
+    let script_actor = script_builder.build();
+    tokio::spawn(async move { script_actor.run().await });
 
-    Ok((software_box, restart_box, mqtt_message_box))
+    Ok((software_box, restart_box, firmware_box, mqtt_message_box))
 }
 
 async fn skip_capability_messages(mqtt: &mut impl MessageReceiver<MqttMessage>, device: &str) {
@@ -42,10 +42,18 @@ impl MeasurementVisitor for ThinEdgeJsonBuilder {
     }
 
     fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error> {
+        self.visit_typed_measurement(name, &ScalarValue::Float(value).into())
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
         if let Some(group) = &mut self.inside_group {
-            group.values.push((name, value).into());
+            group.values.push((name, value.clone()).into());
         } else {
-            self.measurements.push((name, value).into());
+            self.measurements.push((name, value.clone()).into());
         }
         Ok(())
     }
@@ -8,28 +8,39 @@
 // TODO: move entity business logic to its own module
 
 use crate::entity_store;
+use crate::message_log::backup_message_log;
 use crate::message_log::MessageLogReader;
 use crate::message_log::MessageLogWriter;
 use crate::mqtt_topics::Channel;
 use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
+use crate::mqtt_topics::OperationType;
 use crate::mqtt_topics::TopicIdError;
 use crate::pending_entity_store::PendingEntityData;
 use crate::pending_entity_store::PendingEntityStore;
+use crate::telemetry_metadata::TelemetryMetadata;
 use log::debug;
 use log::error;
 use log::info;
 use log::warn;
 use mqtt_channel::Message;
+use mqtt_channel::Topic;
 use serde_json::json;
 use serde_json::Map;
 use serde_json::Value as JsonValue;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::path::Path;
+use std::path::PathBuf;
 use thiserror::Error;
 
+/// The number of stale entries accepted in the entity store log before the log is compacted
+///
+/// An entry is stale when superseded by a more recent entry, e.g. when twin data are updated.
+const MAX_STALE_LOG_ENTRIES: usize = 1000;
+
 /// Represents an "Entity topic identifier" portion of the MQTT topic
 ///
 /// Example:
@@ -143,13 +154,21 @@ pub struct EntityStore {
     main_device: EntityTopicId,
     entities: HashMap<EntityTopicId, EntityMetadata>,
     entity_id_index: HashMap<EntityExternalId, EntityTopicId>,
+    // The operations for which each entity has published a capability, i.e. a retained message
+    capabilities: HashMap<EntityTopicId, HashSet<OperationType>>,
+    // The commands addressed to each entity and not cleared yet, i.e. retained messages
+    commands: HashMap<EntityTopicId, HashSet<(OperationType, String)>>,
     external_id_mapper: ExternalIdMapperFn,
     external_id_validator_fn: ExternalIdValidatorFn,
     // TODO: this is a c8y cloud specific concern and it'd be better to put it somewhere else.
     default_service_type: String,
     pending_entity_store: PendingEntityStore,
-    // The persistent message log to persist entity registrations and twin data messages
-    message_log: MessageLogWriter,
+    // The persistent message log to persist entity registrations and twin data messages, if any
+    message_log: Option<MessageLogWriter>,
+    // The directory where the message log is persisted
+    log_dir: PathBuf,
+    // The number of entries in the message log
+    log_entries: usize,
 }
 
 impl EntityStore {
@@ -168,6 +187,39 @@ impl EntityStore {
         SF: Fn(&str) -> Result<EntityExternalId, InvalidExternalIdError>,
         SF: 'static + Send + Sync,
         P: AsRef<Path>,
+    {
+        let message_log = MessageLogWriter::new(log_dir.as_ref())?;
+        let mut entity_store = EntityStore::in_memory_with_main_device_and_default_service_type(
+            mqtt_schema,
+            main_device,
+            default_service_type,
+            external_id_mapper_fn,
+            external_id_validator_fn,
+            telemetry_cache_size,
+        )?;
+        entity_store.message_log = Some(message_log);
+        entity_store.log_dir = log_dir.as_ref().to_path_buf();
+        entity_store.load_from_message_log(log_dir.as_ref());
+
+        Ok(entity_store)
+    }
+
+    /// Create an entity store that is not persisted on disk
+    ///
+    /// Such a store starts with the main device only and is meant to be rebuilt from the retained messages.
+    pub fn in_memory_with_main_device_and_default_service_type<MF, SF>(
+        mqtt_schema: MqttSchema,
+        main_device: EntityRegistrationMessage,
+        default_service_type: String,
+        external_id_mapper_fn: MF,
+        external_id_validator_fn: SF,
+        telemetry_cache_size: usize,
+    ) -> Result<Self, InitError>
+    where
+        MF: Fn(&EntityTopicId, &EntityExternalId) -> EntityExternalId,
+        MF: 'static + Send + Sync,
+        SF: Fn(&str) -> Result<EntityExternalId, InvalidExternalIdError>,
+        SF: 'static + Send + Sync,
     {
         if main_device.r#type != EntityType::MainDevice {
             return Err(InitError::Custom(
@@ -185,25 +237,24 @@ impl EntityStore {
             parent: None,
             other: main_device.other,
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         };
 
-        let message_log = MessageLogWriter::new(log_dir.as_ref())?;
-
-        let mut entity_store = EntityStore {
+        Ok(EntityStore {
             mqtt_schema: mqtt_schema.clone(),
             main_device: main_device.topic_id.clone(),
             entities: HashMap::from([(main_device.topic_id.clone(), metadata)]),
             entity_id_index: HashMap::from([(entity_id, main_device.topic_id)]),
+            capabilities: HashMap::new(),
+            commands: HashMap::new(),
             external_id_mapper: Box::new(external_id_mapper_fn),
             external_id_validator_fn: Box::new(external_id_validator_fn),
             default_service_type,
             pending_entity_store: PendingEntityStore::new(mqtt_schema, telemetry_cache_size),
-            message_log,
-        };
-
-        entity_store.load_from_message_log(log_dir.as_ref());
-
-        Ok(entity_store)
+            message_log: None,
+            log_dir: PathBuf::new(),
+            log_entries: 0,
+        })
     }
 
     pub fn load_from_message_log<P>(&mut self, log_dir: P)
@@ -211,6 +262,8 @@ impl EntityStore {
         P: AsRef<Path>,
     {
         info!("Loading the entity store from the log");
+        let log_dir = log_dir.as_ref();
+        let mut corrupted_entries = 0;
         match MessageLogReader::new(log_dir) {
             Err(err) => {
                 error!(
@@ -222,28 +275,61 @@ impl EntityStore {
                     match message_log_reader.next_message() {
                         Err(err) => {
                             error!("Parsing log entry failed with {err}");
+                            corrupted_entries += 1;
                             continue;
                         }
                         Ok(None) => {
                             info!("Finished loading the entity store from the log");
-                            return;
+                            break;
                         }
                         Ok(Some(message)) => {
+                            self.log_entries += 1;
                             if let Ok((source, channel)) =
                                 self.mqtt_schema.entity_channel_of(&message.topic)
                             {
                                 match channel {
+                                    Channel::EntityMetadata
+                                        if message.payload_bytes().is_empty() =>
+                                    {
+                                        if let Err(err) = self.remove_entity(&source) {
+                                            error!("Failed to deregister {source} from the persistent entity store due to {err}");
+                                            continue;
+                                        }
+                                    }
                                     Channel::EntityMetadata => {
                                         if let Ok(register_message) =
                                             EntityRegistrationMessage::try_from(&message)
                                         {
-                                            if let Err(err) = self.register_entity(register_message)
-                                            {
-                                                error!("Failed to re-register {source} from the persistent entity store due to {err}");
-                                                continue;
+                                            match self.register_entity(register_message.clone()) {
+                                                Ok(_) => {}
+                                                Err(Error::NoParent(parent)) => {
+                                                    // The parent registration might have been lost with a corrupted entry:
+                                                    // the child is kept aside till its parent is registered again
+                                                    warn!("Missing parent {parent} for {source} in the persistent entity store");
+                                                    self.pending_entity_store
+                                                        .cache_early_registration_message(
+                                                            register_message,
+                                                        );
+                                                }
+                                                Err(err) => {
+                                                    error!("Failed to re-register {source} from the persistent entity store due to {err}");
+                                                    continue;
+                                                }
                                             }
                                         }
                                     }
+                                    Channel::EntityTwinData { .. }
+                                    | Channel::MeasurementMetadata { .. }
+                                    | Channel::EventMetadata { .. }
+                                    | Channel::AlarmMetadata { .. }
+                                        if self
+                                            .pending_entity_store
+                                            .is_registration_pending(&source) =>
+                                    {
+                                        // Keep the data of a child waiting for its parent,
+                                        // so it's restored along the child registration
+                                        self.pending_entity_store.cache_early_data_message(message);
+                                    }
                                     Channel::EntityTwinData { fragment_key } => {
                                         let fragment_value = if message.payload_bytes().is_empty() {
                                             JsonValue::Null
@@ -270,6 +356,29 @@ impl EntityStore {
                                             continue;
                                         }
                                     }
+                                    Channel::MeasurementMetadata { .. }
+                                    | Channel::EventMetadata { .. }
+                                    | Channel::AlarmMetadata { .. } => {
+                                        let metadata = if message.payload_bytes().is_empty() {
+                                            JsonValue::Null
+                                        } else {
+                                            match serde_json::from_slice::<JsonValue>(
+                                                message.payload_bytes(),
+                                            ) {
+                                                Ok(json_value) => json_value,
+                                                Err(err) => {
+                                                    error!("Failed to parse {channel:?} metadata of {source} from the persistent entity store due to {err}");
+                                                    continue;
+                                                }
+                                            }
+                                        };
+                                        if let Err(err) = self.register_telemetry_metadata(
+                                            &source, &channel, metadata,
+                                        ) {
+                                            error!("Failed to restore {channel:?} metadata of {source} from the persistent entity store due to {err}");
+                                            continue;
+                                        }
+                                    }
                                     Channel::CommandMetadata { .. } => {
                                         // Do nothing for now as supported operations are not part of the entity store
                                     }
@@ -291,6 +400,150 @@ impl EntityStore {
                 }
             }
         }
+
+        if corrupted_entries > 0 {
+            // Keep the corrupted entries aside, before they are discarded by the compaction
+            match backup_message_log(log_dir) {
+                Ok(backup) => {
+                    let backup = backup.display();
+                    warn!("Skipped {corrupted_entries} corrupted entity store log entries, saved in {backup}")
+                }
+                Err(err) => {
+                    error!("Failed to backup the corrupted entity store log due to {err}")
+                }
+            }
+            self.compact_message_log();
+        } else {
+            self.compact_message_log_if_needed();
+        }
+    }
+
+    /// The messages required to rebuild the current state of the store
+    ///
+    /// The registration messages are ordered so the parents are registered before their children,
+    /// and are followed by the twin data and telemetry metadata messages.
+    /// The registrations still waiting for their parent come last, along with their data.
+    pub fn snapshot_messages(&self) -> Vec<Message> {
+        let depth = |entity: &EntityMetadata| {
+            let mut depth = 0;
+            let mut parent = entity.parent.as_ref();
+            while let Some(entity) = parent.and_then(|parent| self.entities.get(parent)) {
+                depth += 1;
+                parent = entity.parent.as_ref();
+            }
+            depth
+        };
+        let mut entities: Vec<&EntityMetadata> = self.entities.values().collect();
+        entities.sort_by_key(|entity| (depth(entity), entity.topic_id.to_string()));
+
+        let registrations = entities.iter().map(|entity| {
+            EntityRegistrationMessage {
+                topic_id: entity.topic_id.clone(),
+                external_id: Some(entity.external_id.clone()),
+                r#type: entity.r#type.clone(),
+                parent: entity.parent.clone(),
+                other: entity.other.clone(),
+            }
+            .to_mqtt_message(&self.mqtt_schema)
+        });
+        let twin_data = entities.iter().flat_map(|entity| {
+            entity
+                .twin_data
+                .iter()
+                .map(|(fragment_key, fragment_value)| {
+                    EntityTwinMessage::new(
+                        entity.topic_id.clone(),
+                        fragment_key.clone(),
+                        fragment_value.clone(),
+                    )
+                    .to_mqtt_message(&self.mqtt_schema)
+                })
+        });
+        let telemetry_metadata = entities.iter().flat_map(|entity| {
+            entity
+                .telemetry_metadata
+                .to_mqtt_messages(&self.mqtt_schema, &entity.topic_id)
+        });
+
+        // The children waiting for their parent, possibly lost with a corrupted log entry,
+        // must not be wiped by a compaction
+        let mut pending_entities: Vec<_> =
+            self.pending_entity_store.pending_registrations().collect();
+        pending_entities.sort_by_key(|(registration, _)| registration.topic_id.to_string());
+        let pending_registrations = pending_entities
+            .iter()
+            .map(|(registration, _)| (*registration).clone().to_mqtt_message(&self.mqtt_schema));
+        let pending_metadata = pending_entities
+            .iter()
+            .flat_map(|(_, metadata)| metadata.iter())
+            .filter(|message| {
+                matches!(
+                    self.mqtt_schema.entity_channel_of(&message.topic),
+                    Ok((
+                        _,
+                        Channel::EntityTwinData { .. }
+                            | Channel::MeasurementMetadata { .. }
+                            | Channel::EventMetadata { .. }
+                            | Channel::AlarmMetadata { .. }
+                    ))
+                )
+            })
+            .cloned();
+
+        registrations
+            .chain(twin_data)
+            .chain(telemetry_metadata)
+            .chain(pending_registrations)
+            .chain(pending_metadata)
+            .collect()
+    }
+
+    /// Append a message to the message log, if the store is persisted
+    fn persist_message(&mut self, message: &Message) -> Result<(), Error> {
+        let Some(message_log) = self.message_log.as_mut() else {
+            return Ok(());
+        };
+        message_log.append_message(message)?;
+        self.log_entries += 1;
+        self.compact_message_log_if_needed();
+        Ok(())
+    }
+
+    /// Compact the message log if it contains too many stale entries
+    fn compact_message_log_if_needed(&mut self) {
+        let live_entries = self
+            .entities
+            .values()
+            .map(|entity| 1 + entity.twin_data.len() + entity.telemetry_metadata.len())
+            .sum::<usize>()
+            + self
+                .pending_entity_store
+                .pending_registrations()
+                .map(|(_, metadata)| 1 + metadata.len())
+                .sum::<usize>();
+        if self.log_entries > live_entries + MAX_STALE_LOG_ENTRIES {
+            self.compact_message_log();
+        }
+    }
+
+    /// Replace the message log by a snapshot of the current state of the store
+    fn compact_message_log(&mut self) {
+        if self.message_log.is_none() {
+            return;
+        }
+        let snapshot = self.snapshot_messages();
+        match MessageLogWriter::compact(&self.log_dir, &snapshot) {
+            Ok(message_log) => {
+                info!(
+                    "Compacted the entity store log from {} to {} entries",
+                    self.log_entries,
+                    snapshot.len()
+                );
+                self.message_log = Some(message_log);
+                self.log_entries = snapshot.len();
+            }
+            Err(err) => error!("Failed to compact the entity store log due to {err}"),
+        }
     }
 
     /// Returns information about an entity under a given MQTT entity topic identifier.
@@ -520,6 +773,7 @@ impl EntityStore {
             parent,
             other,
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         };
 
         // device is affected if it was previously registered and was updated
@@ -534,6 +788,7 @@ impl EntityStore {
                 merged_other.extend(entity_metadata.other.clone());
                 let merged_entity = EntityMetadata {
                     twin_data: existing_entity.twin_data.clone(),
+                    telemetry_metadata: existing_entity.telemetry_metadata.clone(),
                     other: merged_other,
                     ..entity_metadata
                 };
@@ -562,13 +817,146 @@ impl EntityStore {
     ) -> Result<Vec<EntityTopicId>, Error> {
         let affected_entities = self.register_entity(message.clone())?;
         if !affected_entities.is_empty() {
-            self.message_log
-                .append_message(&message.to_mqtt_message(&self.mqtt_schema))?;
+            self.persist_message(&message.to_mqtt_message(&self.mqtt_schema))?;
         }
 
         Ok(affected_entities)
     }
 
+    /// Deregisters an entity along with all its descendants.
+    ///
+    /// Returns the metadata of the removed entities, parents before their children,
+    /// along with the messages to be published to clear the retained messages of these entities:
+    /// registration, twin data, telemetry metadata, capabilities, commands and health status.
+    ///
+    /// Deregistering an unknown entity is a no-op, so the removal of an entity can be notified several times.
+    pub fn deregister_entity(
+        &mut self,
+        topic_id: &EntityTopicId,
+    ) -> Result<(Vec<EntityMetadata>, Vec<Message>), Error> {
+        let removed_entities = self.remove_entity(topic_id)?;
+        if removed_entities.is_empty() {
+            return Ok((vec![], vec![]));
+        }
+
+        self.persist_message(&clear_message(
+            self.mqtt_schema
+                .topic_for(topic_id, &Channel::EntityMetadata),
+        ))?;
+
+        let mut clear_messages = vec![];
+        for entity in removed_entities.iter() {
+            let topic_id = &entity.topic_id;
+            let mut channels = vec![Channel::EntityMetadata, Channel::Health];
+            channels.extend(
+                entity
+                    .twin_data
+                    .keys()
+                    .map(|fragment_key| Channel::EntityTwinData {
+                        fragment_key: fragment_key.clone(),
+                    }),
+            );
+            channels.extend(entity.telemetry_metadata.channels());
+            channels.extend(
+                self.capabilities
+                    .remove(topic_id)
+                    .into_iter()
+                    .flatten()
+                    .map(|operation| Channel::CommandMetadata {
+                        operation: operation.clone(),
+                    }),
+            );
+            channels.extend(
+                self.commands
+                    .remove(topic_id)
+                    .into_iter()
+                    .flatten()
+                    .map(|(operation, cmd_id)| Channel::Command { operation, cmd_id }),
+            );
+            clear_messages.extend(
+                channels
+                    .iter()
+                    .map(|channel| clear_message(self.mqtt_schema.topic_for(topic_id, channel))),
+            );
+        }
+
+        Ok((removed_entities, clear_messages))
+    }
+
+    /// Removes an entity and its descendants from the store, without persisting the removal
+    fn remove_entity(&mut self, topic_id: &EntityTopicId) -> Result<Vec<EntityMetadata>, Error> {
+        if topic_id == &self.main_device {
+            return Err(Error::CannotDeregisterMainDevice(topic_id.clone()));
+        }
+
+        let mut removed_entities: Vec<EntityMetadata> =
+            self.entities.remove(topic_id).into_iter().collect();
+        let mut i = 0;
+        while i < removed_entities.len() {
+            let parent = removed_entities[i].topic_id.clone();
+            let children: Vec<EntityTopicId> = self
+                .entities
+                .values()
+                .filter(|entity| entity.parent.as_ref() == Some(&parent))
+                .map(|entity| entity.topic_id.clone())
+                .collect();
+            removed_entities.extend(
+                children
+                    .iter()
+                    .filter_map(|child| self.entities.remove(child)),
+            );
+            i += 1;
+        }
+
+        for entity in removed_entities.iter() {
+            self.entity_id_index.remove(&entity.external_id);
+        }
+        debug!("Updated entity map: {:?}", self.entities);
+
+        Ok(removed_entities)
+    }
+
+    /// Records that an entity has published a capability for the given operation.
+    ///
+    /// These capabilities are cleared when the entity is deregistered.
+    pub fn register_capability(&mut self, topic_id: &EntityTopicId, operation: OperationType) {
+        if self.entities.contains_key(topic_id) {
+            self.capabilities
+                .entry(topic_id.clone())
+                .or_default()
+                .insert(operation);
+        }
+    }
+
+    /// Records that a command has been published for an entity, i.e. a retained message.
+    ///
+    /// The commands not cleared yet are cleared when the entity is deregistered.
+    pub fn register_command(
+        &mut self,
+        topic_id: &EntityTopicId,
+        operation: OperationType,
+        cmd_id: String,
+    ) {
+        if self.entities.contains_key(topic_id) {
+            self.commands
+                .entry(topic_id.clone())
+                .or_default()
+                .insert((operation, cmd_id));
+        }
+    }
+
+    /// Records that a command has been cleared, i.e. its retained message removed.
+    pub fn remove_command(
+        &mut self,
+        topic_id: &EntityTopicId,
+        operation: OperationType,
+        cmd_id: String,
+    ) {
+        if let Some(commands) = self.commands.get_mut(topic_id) {
+            commands.remove(&(operation, cmd_id));
+        }
+    }
+
     /// An iterator over all registered entities.
     pub fn iter(&self) -> impl Iterator<Item = (&EntityTopicId, &EntityMetadata)> {
         self.entities.iter()
@@ -682,13 +1070,46 @@ impl EntityStore {
     ) -> Result<bool, entity_store::Error> {
         let updated = self.register_twin_data(twin_message.clone())?;
         if updated {
-            self.message_log
-                .append_message(&twin_message.to_mqtt_message(&self.mqtt_schema))?;
+            self.persist_message(&twin_message.to_mqtt_message(&self.mqtt_schema))?;
         }
 
         Ok(updated)
     }
 
+    /// Updates the metadata published by an entity on a measurement, event or alarm metadata channel.
+    ///
+    /// A `null` value removes the metadata for that channel.
+    /// Returns `true`, if the metadata got updated.
+    pub fn update_telemetry_metadata(
+        &mut self,
+        topic_id: &EntityTopicId,
+        channel: &Channel,
+        metadata: JsonValue,
+    ) -> Result<bool, entity_store::Error> {
+        let payload = if metadata.is_null() {
+            String::new()
+        } else {
+            metadata.to_string()
+        };
+        let updated = self.register_telemetry_metadata(topic_id, channel, metadata)?;
+        if updated {
+            let topic = self.mqtt_schema.topic_for(topic_id, channel);
+            self.persist_message(&Message::new(&topic, payload).with_retain())?;
+        }
+
+        Ok(updated)
+    }
+
+    fn register_telemetry_metadata(
+        &mut self,
+        topic_id: &EntityTopicId,
+        channel: &Channel,
+        metadata: JsonValue,
+    ) -> Result<bool, entity_store::Error> {
+        let entity = self.try_get_mut(topic_id)?;
+        Ok(entity.telemetry_metadata.update(channel, metadata))
+    }
+
     pub fn cache_early_data_message(&mut self, message: Message) {
         self.pending_entity_store.cache_early_data_message(message)
     }
@@ -705,6 +1126,7 @@ pub struct EntityMetadata {
     // cloud we're currently connected to
     pub other: Map<String, JsonValue>,
     pub twin_data: Map<String, JsonValue>,
+    pub telemetry_metadata: TelemetryMetadata,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -740,6 +1162,7 @@ impl EntityMetadata {
             parent: None,
             other: Map::new(),
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         }
     }
 
@@ -752,6 +1175,7 @@ impl EntityMetadata {
             parent: Some(EntityTopicId::default_main_device()),
             other: Map::new(),
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         })
     }
 }
@@ -771,6 +1195,9 @@ pub enum Error {
     #[error("The specified entity {0} does not exist in the store")]
     UnknownEntity(String),
 
+    #[error("The main device {0} cannot be deregistered")]
+    CannotDeregisterMainDevice(EntityTopicId),
+
     #[error("Auto registration of the entity with topic id {0} failed as it does not match the default topic scheme: 'device/<device-id>/service/<service-id>'. Try explicit registration instead.")]
     NonDefaultTopicScheme(EntityTopicId),
 
@@ -964,6 +1391,11 @@ impl TryFrom<&Message> for EntityRegistrationMessage {
     }
 }
 
+/// An empty retained message, clearing the message retained on the given topic
+fn clear_message(topic: Topic) -> Message {
+    Message::new(&topic, "").with_retain()
+}
+
 /// Parse a MQTT message payload as an entity registration payload.
 ///
 /// Returns `Some(register_payload)` if a payload is valid JSON and is a
@@ -1012,6 +1444,7 @@ mod tests {
     use mqtt_channel::Topic;
     use serde_json::json;
     use std::collections::HashSet;
+    use std::io::Write;
     use std::str::FromStr;
     use tempfile::TempDir;
 
@@ -1457,6 +1890,7 @@ mod tests {
             external_id: "test-device".into(),
             other: json!({}).as_object().unwrap().to_owned(),
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         };
         // Assert main device registered with custom topic scheme
         assert_eq!(
@@ -1487,6 +1921,7 @@ mod tests {
             external_id: "custom:main:service:collectd".into(),
             other: json!({"type": "service"}).as_object().unwrap().to_owned(),
             twin_data: Map::new(),
+            telemetry_metadata: TelemetryMetadata::default(),
         };
         // Assert service registered under main device with custom topic scheme
         assert_eq!(
@@ -1843,6 +2278,417 @@ mod tests {
         }
     }
 
+    #[test]
+    fn deregistering_an_entity_removes_its_descendants() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir);
+
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let nested = EntityTopicId::default_child_device("nested").unwrap();
+        let service = EntityTopicId::default_child_service("child1", "service").unwrap();
+        let child2 = EntityTopicId::default_child_device("child2").unwrap();
+        store
+            .update(EntityRegistrationMessage::new_custom(
+                child1.clone(),
+                EntityType::ChildDevice,
+            ))
+            .unwrap();
+        store
+            .update(
+                EntityRegistrationMessage::new_custom(nested.clone(), EntityType::ChildDevice)
+                    .with_parent(child1.clone()),
+            )
+            .unwrap();
+        store
+            .update(
+                EntityRegistrationMessage::new_custom(service.clone(), EntityType::Service)
+                    .with_parent(child1.clone()),
+            )
+            .unwrap();
+        store
+            .update(EntityRegistrationMessage::new_custom(
+                child2.clone(),
+                EntityType::ChildDevice,
+            ))
+            .unwrap();
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                nested.clone(),
+                "foo".to_string(),
+                json!("bar"),
+            ))
+            .unwrap();
+        store.register_capability(&child1, OperationType::Restart);
+        store.register_command(&nested, OperationType::Restart, "123".to_string());
+        store.register_command(&nested, OperationType::Restart, "456".to_string());
+        store.remove_command(&nested, OperationType::Restart, "456".to_string());
+
+        let (removed_entities, clear_messages) = store.deregister_entity(&child1).unwrap();
+
+        let removed_entities: Vec<_> = removed_entities
+            .into_iter()
+            .map(|entity| entity.topic_id)
+            .collect();
+        assert_eq!(removed_entities[0], child1);
+        assert_eq!(removed_entities.len(), 3);
+        assert!(removed_entities.contains(&nested));
+        assert!(removed_entities.contains(&service));
+
+        assert!(store.get(&child1).is_none());
+        assert!(store.get(&nested).is_none());
+        assert!(store.get(&service).is_none());
+        assert!(store.get_by_external_id(&"device:nested".into()).is_none());
+        assert!(store.get(&child2).is_some());
+
+        let cleared_topics: Vec<_> = clear_messages
+            .iter()
+            .inspect(|message| {
+                assert!(message.payload_bytes().is_empty());
+                assert!(message.retain);
+            })
+            .map(|message| message.topic.name.as_str())
+            .collect();
+        assert!(cleared_topics.contains(&"te/device/child1//"));
+        assert!(cleared_topics.contains(&"te/device/child1///cmd/restart"));
+        assert!(cleared_topics.contains(&"te/device/nested///cmd/restart/123"));
+        assert!(!cleared_topics.contains(&"te/device/nested///cmd/restart/456"));
+        assert!(cleared_topics.contains(&"te/device/nested///twin/foo"));
+        assert!(cleared_topics.contains(&"te/device/child1/service/service/status/health"));
+        assert!(!cleared_topics.iter().any(|topic| topic.contains("child2")));
+
+        // The removal can be notified several times
+        assert_eq!(store.deregister_entity(&child1).unwrap(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn main_device_cannot_be_deregistered() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir);
+
+        let main_device = EntityTopicId::default_main_device();
+        assert!(matches!(
+            store.deregister_entity(&main_device),
+            Err(Error::CannotDeregisterMainDevice(_))
+        ));
+        assert!(store.get(&main_device).is_some());
+    }
+
+    #[test]
+    fn deregistrations_persisted_and_restored() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let nested = EntityTopicId::default_child_device("nested").unwrap();
+        let child2 = EntityTopicId::default_child_device("child2").unwrap();
+
+        {
+            let mut store = new_entity_store(&temp_dir);
+            store
+                .update(EntityRegistrationMessage::new_custom(
+                    child1.clone(),
+                    EntityType::ChildDevice,
+                ))
+                .unwrap();
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(nested.clone(), EntityType::ChildDevice)
+                        .with_parent(child1.clone()),
+                )
+                .unwrap();
+            store
+                .update(EntityRegistrationMessage::new_custom(
+                    child2.clone(),
+                    EntityType::ChildDevice,
+                ))
+                .unwrap();
+            store.deregister_entity(&child1).unwrap();
+        }
+
+        let store = new_entity_store(&temp_dir);
+        assert!(store.get(&child1).is_none());
+        assert!(store.get(&nested).is_none());
+        assert!(store.get(&child2).is_some());
+    }
+
+    #[test]
+    fn telemetry_metadata_persisted_and_restored() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let measurement_metadata = Channel::MeasurementMetadata {
+            measurement_type: "environment".to_string(),
+        };
+        let alarm_metadata = Channel::AlarmMetadata {
+            alarm_type: "temperature_high".to_string(),
+        };
+
+        {
+            let mut store = new_entity_store(&temp_dir);
+            store
+                .update(EntityRegistrationMessage::new_custom(
+                    child1.clone(),
+                    EntityType::ChildDevice,
+                ))
+                .unwrap();
+            store
+                .update_telemetry_metadata(
+                    &child1,
+                    &measurement_metadata,
+                    json!({"temperature": {"unit": "°C"}}),
+                )
+                .unwrap();
+            store
+                .update_telemetry_metadata(&child1, &alarm_metadata, json!({"severity": "major"}))
+                .unwrap();
+            store
+                .update_telemetry_metadata(&child1, &alarm_metadata, JsonValue::Null)
+                .unwrap();
+        }
+
+        let mut store = new_entity_store(&temp_dir);
+        let telemetry_metadata = &store.get(&child1).unwrap().telemetry_metadata;
+        assert_eq!(
+            telemetry_metadata
+                .measurement("environment")
+                .and_then(|metadata| metadata.series(None, "temperature"))
+                .and_then(|series| series.unit),
+            Some("°C".to_string())
+        );
+        assert_eq!(telemetry_metadata.alarm("temperature_high"), None);
+
+        let (_, clear_messages) = store.deregister_entity(&child1).unwrap();
+        assert!(clear_messages
+            .iter()
+            .any(|message| message.topic.name == "te/device/child1///m/environment/meta"));
+    }
+
+    #[test]
+    fn stale_log_entries_are_compacted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child1_topic_id = EntityTopicId::default_child_device("child1").unwrap();
+
+        {
+            let mut store = new_entity_store(&temp_dir);
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(
+                        child1_topic_id.clone(),
+                        EntityType::ChildDevice,
+                    )
+                    .with_external_id("child1".into()),
+                )
+                .unwrap();
+            for i in 0..=2 * MAX_STALE_LOG_ENTRIES {
+                store
+                    .update_twin_data(EntityTwinMessage::new(
+                        child1_topic_id.clone(),
+                        "counter".to_string(),
+                        json!(i),
+                    ))
+                    .unwrap();
+            }
+        }
+
+        // The stale twin values have been removed
+        let log = std::fs::read_to_string(temp_dir.path().join("entity_store.jsonl")).unwrap();
+        assert!(log.lines().count() < 2 * MAX_STALE_LOG_ENTRIES);
+
+        let store = new_entity_store(&temp_dir);
+        assert_eq!(
+            store
+                .get(&child1_topic_id)
+                .unwrap()
+                .twin_data
+                .get("counter")
+                .unwrap(),
+            &json!(2 * MAX_STALE_LOG_ENTRIES)
+        );
+    }
+
+    #[test]
+    fn in_memory_store_is_not_persisted() {
+        let child1_topic_id = EntityTopicId::default_child_device("child1").unwrap();
+        let mut store = EntityStore::in_memory_with_main_device_and_default_service_type(
+            MqttSchema::default(),
+            EntityRegistrationMessage {
+                topic_id: EntityTopicId::default_main_device(),
+                external_id: Some("test-device".into()),
+                r#type: EntityType::MainDevice,
+                parent: None,
+                other: Map::new(),
+            },
+            "service".into(),
+            dummy_external_id_mapper,
+            dummy_external_id_sanitizer,
+            5,
+        )
+        .unwrap();
+
+        store
+            .update(
+                EntityRegistrationMessage::new_custom(
+                    child1_topic_id.clone(),
+                    EntityType::ChildDevice,
+                )
+                .with_external_id("child1".into()),
+            )
+            .unwrap();
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                child1_topic_id.clone(),
+                "counter".to_string(),
+                json!(1),
+            ))
+            .unwrap();
+        store.deregister_entity(&child1_topic_id).unwrap();
+
+        assert!(store.get(&child1_topic_id).is_none());
+    }
+
+    #[test]
+    fn corrupted_parent_entry_does_not_wipe_its_children() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child1_topic_id = EntityTopicId::default_child_device("child1").unwrap();
+        let nested_topic_id = EntityTopicId::default_child_device("nested").unwrap();
+
+        {
+            let mut store = new_entity_store(&temp_dir);
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(
+                        child1_topic_id.clone(),
+                        EntityType::ChildDevice,
+                    )
+                    .with_external_id("child1".into()),
+                )
+                .unwrap();
+            store
+                .update(EntityRegistrationMessage {
+                    topic_id: nested_topic_id.clone(),
+                    external_id: Some("nested".into()),
+                    r#type: EntityType::ChildDevice,
+                    parent: Some(child1_topic_id.clone()),
+                    other: Map::new(),
+                })
+                .unwrap();
+            store
+                .update_twin_data(EntityTwinMessage::new(
+                    nested_topic_id.clone(),
+                    "firmware".to_string(),
+                    json!("1.0"),
+                ))
+                .unwrap();
+        }
+
+        // Corrupt the registration of the parent
+        let log_file = temp_dir.path().join("entity_store.jsonl");
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        let corrupted_log: Vec<&str> = log
+            .lines()
+            .map(|line| {
+                if line.contains("te/device/child1//") && !line.contains("nested") {
+                    "{ not json"
+                } else {
+                    line
+                }
+            })
+            .collect();
+        std::fs::write(&log_file, corrupted_log.join("\n") + "\n").unwrap();
+
+        {
+            // The log is compacted, without the corrupted entry
+            let _store = new_entity_store(&temp_dir);
+            assert_eq!(log_backups(&temp_dir).len(), 1);
+        }
+
+        // The child is not lost by the compaction
+        let mut store = new_entity_store(&temp_dir);
+        assert!(store.get(&child1_topic_id).is_none());
+        assert!(store.get(&nested_topic_id).is_none());
+
+        // The child is restored as soon as its parent is registered again
+        let (_, pending_entities) = store
+            .update(
+                EntityRegistrationMessage::new_custom(
+                    child1_topic_id.clone(),
+                    EntityType::ChildDevice,
+                )
+                .with_external_id("child1".into()),
+            )
+            .unwrap();
+        assert_eq!(pending_entities.len(), 2);
+        assert_eq!(
+            store.get(&nested_topic_id).unwrap().parent,
+            Some(child1_topic_id)
+        );
+
+        // Along with its twin data
+        let nested_data = &pending_entities[1];
+        assert_eq!(nested_data.reg_message.topic_id, nested_topic_id);
+        assert_eq!(nested_data.data_messages.len(), 1);
+        assert_eq!(
+            nested_data.data_messages[0].topic.name,
+            "te/device/nested///twin/firmware"
+        );
+    }
+
+    #[test]
+    fn truncated_entry_is_detected_on_load() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child1_topic_id = EntityTopicId::default_child_device("child1").unwrap();
+        let child2_topic_id = EntityTopicId::default_child_device("child2").unwrap();
+
+        {
+            let mut store = new_entity_store(&temp_dir);
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(
+                        child1_topic_id.clone(),
+                        EntityType::ChildDevice,
+                    )
+                    .with_external_id("child1".into()),
+                )
+                .unwrap();
+        }
+
+        // Simulate an interrupted write
+        let log_file = temp_dir.path().join("entity_store.jsonl");
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log_file)
+            .unwrap();
+        log.write_all(br#"{"topic":{"name":"te/device/child2//"},"pay"#)
+            .unwrap();
+
+        {
+            // The truncated entry is saved aside and removed by the compaction
+            let mut store = new_entity_store(&temp_dir);
+            assert_eq!(log_backups(&temp_dir).len(), 1);
+            assert!(std::fs::read_to_string(&log_file).unwrap().ends_with('\n'));
+
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(
+                        child2_topic_id.clone(),
+                        EntityType::ChildDevice,
+                    )
+                    .with_external_id("child2".into()),
+                )
+                .unwrap();
+        }
+
+        let store = new_entity_store(&temp_dir);
+        assert!(store.get(&child1_topic_id).is_some());
+        assert!(store.get(&child2_topic_id).is_some());
+        assert_eq!(log_backups(&temp_dir).len(), 1);
+    }
+
+    fn log_backups(temp_dir: &TempDir) -> Vec<std::path::PathBuf> {
+        std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bak"))
+            .collect()
+    }
+
     fn new_entity_store(temp_dir: &TempDir) -> EntityStore {
         EntityStore::with_main_device_and_default_service_type(
             MqttSchema::default(),
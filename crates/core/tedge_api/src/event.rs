@@ -54,12 +54,26 @@ impl ThinEdgeEvent {
         entity: &EntityMetadata,
         mqtt_payload: &str,
     ) -> Result<Self, ThinEdgeJsonDeserializerError> {
-        let event_data = if mqtt_payload.is_empty() {
+        let mut event_data: Option<ThinEdgeEventData> = if mqtt_payload.is_empty() {
             None
         } else {
             Some(serde_json::from_str(mqtt_payload)?)
         };
 
+        // Events published without text get the default text given by the event type metadata, if any
+        let default_text = entity
+            .telemetry_metadata
+            .event(event_type)
+            .and_then(|metadata| metadata.text);
+        if let Some(default_text) = default_text {
+            let data = event_data.get_or_insert_with(|| ThinEdgeEventData {
+                text: None,
+                time: None,
+                extras: HashMap::new(),
+            });
+            data.text.get_or_insert(default_text);
+        }
+
         // Parent exists means the device is child device
         let external_source = entity.parent.as_ref().map(|_| entity.external_id.clone());
 
@@ -74,6 +88,7 @@ impl ThinEdgeEvent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mqtt_topics::Channel;
     use assert_matches::assert_matches;
     use serde_json::json;
     use serde_json::Value;
@@ -200,6 +215,24 @@ mod tests {
         assert_matches!(result.data, None);
     }
 
+    #[test]
+    fn default_event_text_is_given_by_the_event_metadata() {
+        let mut entity = EntityMetadata::main_device("main-device".to_string());
+        entity.telemetry_metadata.update(
+            &Channel::EventMetadata {
+                event_type: "click_event".to_string(),
+            },
+            json!({"text": "Someone clicked"}),
+        );
+
+        let event = ThinEdgeEvent::try_from("click_event", &entity, "").unwrap();
+        assert_eq!(event.data.unwrap().text.as_deref(), Some("Someone clicked"));
+
+        let event = ThinEdgeEvent::try_from("click_event", &entity, r#"{"text": "Clicked twice"}"#)
+            .unwrap();
+        assert_eq!(event.data.unwrap().text.as_deref(), Some("Clicked twice"));
+    }
+
     #[test]
     fn event_translation_additional_fields() {
         let event_json = json!({
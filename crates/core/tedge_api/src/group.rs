@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 use time::OffsetDateTime;
 
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
+use crate::measurement::ScalarValue;
 
 #[derive(Debug)]
 pub struct MeasurementGroup {
@@ -32,11 +34,13 @@ impl MeasurementGroup {
     ) -> Option<f64> {
         match group_key {
             Some(group_key) => match self.values.get(group_key) {
-                Some(Measurement::Multi(map)) => map.get(measurement_key).cloned(),
+                Some(Measurement::Multi(map)) => {
+                    map.get(measurement_key).map(|val| val.value.as_f64())
+                }
                 _ => None,
             },
             None => match self.values.get(measurement_key) {
-                Some(Measurement::Single(val)) => Some(*val),
+                Some(Measurement::Single(val)) => Some(val.value.as_f64()),
                 _ => None,
             },
         }
@@ -54,12 +58,12 @@ impl MeasurementGroup {
         for (key, value) in self.values.iter() {
             match value {
                 Measurement::Single(sv) => {
-                    visitor.visit_measurement(key, *sv)?;
+                    visitor.visit_typed_measurement(key, sv)?;
                 }
                 Measurement::Multi(m) => {
                     visitor.visit_start_group(key)?;
                     for (key, value) in m.iter() {
-                        visitor.visit_measurement(key, *value)?;
+                        visitor.visit_typed_measurement(key, value)?;
                     }
                     visitor.visit_end_group()?;
                 }
@@ -86,8 +90,8 @@ struct GroupState {
 
 #[derive(Debug)]
 pub enum Measurement {
-    Single(f64),
-    Multi(HashMap<String, f64>),
+    Single(MeasurementValue),
+    Multi(HashMap<String, MeasurementValue>),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -163,13 +167,21 @@ impl MeasurementVisitor for MeasurementGrouper {
     }
 
     fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error> {
+        self.visit_typed_measurement(name, &ScalarValue::Float(value).into())
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
         let key = name.to_owned();
 
         match self.group_state.in_group {
             false => {
                 self.measurement_group
                     .values
-                    .insert(key, Measurement::Single(value));
+                    .insert(key, Measurement::Single(value.clone()));
                 Ok(())
             }
             true => {
@@ -180,7 +192,7 @@ impl MeasurementVisitor for MeasurementGrouper {
                     .entry(group_key)
                     .or_insert_with(|| Measurement::Multi(HashMap::new()))
                 {
-                    group_map.insert(name.to_owned(), value);
+                    group_map.insert(name.to_owned(), value.clone());
                 }
                 Ok(())
             }
@@ -353,6 +365,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn measurement_grouper_keeps_typed_values() -> anyhow::Result<()> {
+        let mut grouper = MeasurementGrouper::new();
+        grouper.visit_start_group("interface")?;
+        grouper.visit_typed_measurement("rx", &ScalarValue::Integer(12345678901).into())?;
+        grouper.visit_end_group()?;
+
+        let group = grouper.end()?;
+        let mut serializer = crate::serialize::ThinEdgeJsonSerializer::new();
+        group.accept(&mut serializer)?;
+
+        assert_eq!(
+            serializer.into_string()?,
+            r#"{"interface":{"rx":12345678901}}"#
+        );
+
+        Ok(())
+    }
+
     fn test_timestamp(minute: u32) -> OffsetDateTime {
         let mut dt = datetime!(2021-04-08 13:00:00 +05:00);
         dt += Duration::minutes(minute as i64);
@@ -16,6 +16,7 @@ pub mod pending_entity_store;
 mod ring_buffer;
 pub mod serialize;
 mod software;
+pub mod telemetry_metadata;
 pub mod topic;
 pub mod utils;
 pub mod workflow;
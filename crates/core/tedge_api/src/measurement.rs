@@ -80,6 +80,18 @@ pub trait MeasurementVisitor {
     /// Add a new measurement, attached to the current group if any.
     fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error>;
 
+    /// Add a new measurement given with its unit and quality, attached to the current group if any.
+    ///
+    /// Defaults to `visit_measurement` with the value converted into a float,
+    /// for the visitors that are only interested by the numeric values.
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        self.visit_measurement(name, value.value.as_f64())
+    }
+
     /// Add a text property, attached to the current group if any.
     fn visit_text_property(&mut self, _name: &str, _value: &str) -> Result<(), Self::Error>;
 
@@ -103,3 +115,50 @@ pub trait MeasurementVisitor {
         Ok(())
     }
 }
+
+/// A measurement value given along its unit and quality.
+///
+/// In ThinEdge JSON, such a value is given using the extended form:
+///
+/// ```json
+/// {
+///     "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+///     "door_open": true
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementValue {
+    pub value: ScalarValue,
+    pub unit: Option<String>,
+    pub quality: Option<String>,
+}
+
+impl From<ScalarValue> for MeasurementValue {
+    fn from(value: ScalarValue) -> Self {
+        MeasurementValue {
+            value,
+            unit: None,
+            quality: None,
+        }
+    }
+}
+
+/// A number, an integer counter or a boolean
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarValue {
+    Float(f64),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl ScalarValue {
+    /// The value as a float, a boolean being converted into `1.0` or `0.0`
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            ScalarValue::Float(value) => *value,
+            ScalarValue::Integer(value) => *value as f64,
+            ScalarValue::Boolean(true) => 1.0,
+            ScalarValue::Boolean(false) => 0.0,
+        }
+    }
+}
@@ -1,17 +1,29 @@
 //! The message log is a persistent append-only log of MQTT messages.
 //! Each line is the JSON representation of that MQTT message.
 //! The underlying file is a JSON lines file.
+//!
+//! To prevent the log to grow forever, the log can be compacted,
+//! i.e. atomically replaced by a snapshot, a log of the messages required to rebuild the current state.
+//! New messages are then appended to this snapshot.
 use mqtt_channel::Message as MqttMessage;
 use serde_json::json;
+use serde_json::Value as JsonValue;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
+use time::OffsetDateTime;
 
 const LOG_FILE_NAME: &str = "entity_store.jsonl";
+const SNAPSHOT_FILE_NAME: &str = "entity_store.jsonl.tmp";
+const BACKUP_FILE_EXTENSION: &str = "bak";
 const LOG_FORMAT_VERSION: &str = "1.0";
 
 #[derive(thiserror::Error, Debug)]
@@ -21,6 +33,9 @@ pub enum LogEntryError {
 
     #[error("Deserialization failed with {0} while parsing {1}")]
     FromSerdeJson(#[source] serde_json::Error, String),
+
+    #[error("Truncated log entry: {0}")]
+    Truncated(String),
 }
 
 /// A reader to read the log file entries line by line
@@ -40,7 +55,18 @@ impl MessageLogReader {
 
         let mut version_info = String::new();
         reader.read_line(&mut version_info)?;
-        // TODO: Validate if the read version is supported
+        let version = serde_json::from_str::<JsonValue>(&version_info)
+            .ok()
+            .and_then(|header| header.get("version").cloned());
+        if version != Some(JsonValue::from(LOG_FORMAT_VERSION)) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported message log format: {}",
+                    version_info.trim_end()
+                ),
+            ));
+        }
 
         Ok(MessageLogReader { reader })
     }
@@ -52,6 +78,10 @@ impl MessageLogReader {
         let mut buffer = String::new();
         match self.reader.read_line(&mut buffer) {
             Ok(bytes_read) if bytes_read > 0 => {
+                if !buffer.ends_with('\n') {
+                    // The last entry has been truncated, the writer being interrupted
+                    return Err(LogEntryError::Truncated(buffer));
+                }
                 let message: MqttMessage = serde_json::from_str(&buffer)
                     .map_err(|err| LogEntryError::FromSerdeJson(err, buffer))?;
                 Ok(Some(message))
@@ -65,6 +95,8 @@ impl MessageLogReader {
 /// A writer to append new MQTT messages to the end of the log
 pub struct MessageLogWriter {
     writer: BufWriter<File>,
+    // Set when the log ends with a truncated entry, to be terminated before any new entry
+    ends_with_truncated_entry: bool,
 }
 
 impl MessageLogWriter {
@@ -72,11 +104,16 @@ impl MessageLogWriter {
     where
         P: AsRef<Path>,
     {
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .create(true)
+            .read(true)
             .append(true)
             .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
 
+        // A truncated entry left by an interrupted write is not removed,
+        // but reported when the log is read and then discarded by a compaction
+        let ends_with_truncated_entry = ends_with_truncated_entry(&mut file)?;
+
         // If the file is empty append the version information as a header
         let metadata = file.metadata()?;
         let file_is_empty = metadata.len() == 0;
@@ -86,29 +123,123 @@ impl MessageLogWriter {
         if file_is_empty {
             let version_info = json!({"version": LOG_FORMAT_VERSION}).to_string();
             writeln!(writer, "{}", version_info)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
         }
 
-        Ok(MessageLogWriter { writer })
+        Ok(MessageLogWriter {
+            writer,
+            ends_with_truncated_entry,
+        })
     }
 
     /// Append the JSON representation of the given message to the log.
     /// Each message is appended on a new line.
     pub fn append_message(&mut self, message: &MqttMessage) -> Result<(), std::io::Error> {
+        if self.ends_with_truncated_entry {
+            // Keep the truncated entry on its own line, so the new entry is not corrupted
+            writeln!(self.writer)?;
+            self.ends_with_truncated_entry = false;
+        }
         let json_line = serde_json::to_string(message)?;
         writeln!(self.writer, "{}", json_line)?;
         self.writer.flush()?;
         self.writer.get_ref().sync_all()?;
         Ok(())
     }
+
+    /// Replace the log with a snapshot made of the given messages
+    ///
+    /// The snapshot is first fully written and synced in a temporary file,
+    /// which is then renamed over the log file.
+    /// Hence, on a crash, either the former log or the snapshot is found on disk, but never a mix of both.
+    pub fn compact<'a, P>(
+        log_dir: P,
+        messages: impl IntoIterator<Item = &'a MqttMessage>,
+    ) -> Result<MessageLogWriter, std::io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let log_dir = log_dir.as_ref();
+        let snapshot_path = log_dir.join(SNAPSHOT_FILE_NAME);
+        {
+            let file = File::create(&snapshot_path)?;
+            let mut writer = BufWriter::new(file);
+            let version_info = json!({"version": LOG_FORMAT_VERSION}).to_string();
+            writeln!(writer, "{}", version_info)?;
+            for message in messages {
+                let json_line = serde_json::to_string(message)?;
+                writeln!(writer, "{}", json_line)?;
+            }
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        std::fs::rename(&snapshot_path, log_dir.join(LOG_FILE_NAME))?;
+        File::open(log_dir)?.sync_all()?;
+
+        MessageLogWriter::new(log_dir)
+    }
+}
+
+/// Keep a copy of the log, before compacting a log with corrupted entries
+///
+/// The backups are named after the time they are made, e.g. `entity_store.jsonl.1700000000.bak`,
+/// so a backup never overwrites a previous one.
+///
+/// Returns the path to the backup.
+pub fn backup_message_log<P>(log_dir: P) -> Result<PathBuf, std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    let log_dir = log_dir.as_ref();
+    let mut log = File::open(log_dir.join(LOG_FILE_NAME))?;
+    let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+    let mut attempt = 0;
+    loop {
+        let backup_name = match attempt {
+            0 => format!("{LOG_FILE_NAME}.{timestamp}.{BACKUP_FILE_EXTENSION}"),
+            _ => format!("{LOG_FILE_NAME}.{timestamp}-{attempt}.{BACKUP_FILE_EXTENSION}"),
+        };
+        let backup_path = log_dir.join(backup_name);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup_path)
+        {
+            Ok(mut backup) => {
+                std::io::copy(&mut log, &mut backup)?;
+                backup.sync_all()?;
+                return Ok(backup_path);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Tell if the last line of the file is not terminated, i.e. is a truncated entry
+fn ends_with_truncated_entry(file: &mut File) -> Result<bool, std::io::Error> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(false);
+    }
+
+    let mut last_byte = [0u8];
+    file.seek(SeekFrom::Start(len - 1))?;
+    file.read_exact(&mut last_byte)?;
+    Ok(last_byte[0] != b'\n')
 }
 
 #[cfg(test)]
 mod tests {
     use crate::message_log::MessageLogReader;
 
+    use super::backup_message_log;
+    use super::LogEntryError;
     use super::MessageLogWriter;
     use mqtt_channel::Message;
     use mqtt_channel::Topic;
+    use std::io::Write;
     use tempfile::tempdir;
 
     #[test]
@@ -149,4 +280,99 @@ mod tests {
             assert_eq!(message_log_reader.next_message().unwrap(), None);
         }
     }
+
+    #[test]
+    fn truncated_entries_are_reported_and_not_merged_with_new_entries() {
+        let temp_dir = tempdir().unwrap();
+        let message = |i: u32| {
+            Message::new(
+                &Topic::new(&format!("topic{i}")).unwrap(),
+                format!("payload{i}"),
+            )
+        };
+
+        {
+            let mut message_log = MessageLogWriter::new(&temp_dir).unwrap();
+            message_log.append_message(&message(1)).unwrap();
+        }
+
+        // Simulate an interrupted write
+        {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(temp_dir.path().join("entity_store.jsonl"))
+                .unwrap();
+            file.write_all(br#"{"topic":{"name":"topic2"},"pay"#)
+                .unwrap();
+        }
+        {
+            let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+            assert_eq!(message_log_reader.next_message().unwrap(), Some(message(1)));
+            assert!(matches!(
+                message_log_reader.next_message(),
+                Err(LogEntryError::Truncated(_))
+            ));
+            assert_eq!(message_log_reader.next_message().unwrap(), None);
+        }
+
+        // The truncated entry is kept on its own line when the log is re-opened for writes
+        {
+            let mut message_log = MessageLogWriter::new(&temp_dir).unwrap();
+            message_log.append_message(&message(3)).unwrap();
+        }
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(message(1)));
+        assert!(matches!(
+            message_log_reader.next_message(),
+            Err(LogEntryError::FromSerdeJson(_, _))
+        ));
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(message(3)));
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+    }
+
+    #[test]
+    fn backups_do_not_overwrite_previous_backups() {
+        let temp_dir = tempdir().unwrap();
+        let message = Message::new(&Topic::new("topic").unwrap(), "payload");
+        let mut message_log = MessageLogWriter::new(&temp_dir).unwrap();
+
+        let first_backup = backup_message_log(&temp_dir).unwrap();
+        message_log.append_message(&message).unwrap();
+        let second_backup = backup_message_log(&temp_dir).unwrap();
+
+        assert_ne!(first_backup, second_backup);
+        let first_backup = std::fs::read_to_string(first_backup).unwrap();
+        let second_backup = std::fs::read_to_string(second_backup).unwrap();
+        assert_eq!(first_backup.lines().count(), 1);
+        assert_eq!(second_backup.lines().count(), 2);
+    }
+
+    #[test]
+    fn compacted_log_is_replaced_by_the_snapshot() {
+        let temp_dir = tempdir().unwrap();
+        let message = |i: u32| {
+            Message::new(
+                &Topic::new(&format!("topic{i}")).unwrap(),
+                format!("payload{i}"),
+            )
+        };
+
+        {
+            let mut message_log = MessageLogWriter::new(&temp_dir).unwrap();
+            for i in 1..5 {
+                message_log.append_message(&message(i)).unwrap();
+            }
+        }
+
+        let snapshot = vec![message(2), message(4)];
+        let mut message_log = MessageLogWriter::compact(&temp_dir, &snapshot).unwrap();
+        message_log.append_message(&message(5)).unwrap();
+        assert!(!temp_dir.path().join("entity_store.jsonl.tmp").exists());
+
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        for i in [2, 4, 5] {
+            assert_eq!(message_log_reader.next_message().unwrap(), Some(message(i)));
+        }
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+    }
 }
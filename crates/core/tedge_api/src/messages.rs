@@ -585,7 +585,10 @@ struct RestartContext {
 impl RestartContext {
     pub fn resume(&self, status: CommandStatus) -> Option<GenericCommandState> {
         match status {
-            CommandStatus::Init | CommandStatus::Scheduled | CommandStatus::Unknown => None,
+            CommandStatus::Init
+            | CommandStatus::Scheduled
+            | CommandStatus::Queued
+            | CommandStatus::Unknown => None,
             CommandStatus::Executing => Some(self.command.clone().move_to(self.on_exec.clone())),
             CommandStatus::Successful => {
                 Some(self.command.clone().move_to(self.on_success.clone()))
@@ -599,6 +602,7 @@ impl RestartContext {
                     Some(command.move_to(self.on_error.clone()))
                 }
             }
+            CommandStatus::Cancelled => Some(self.command.clone().move_to("cancelled".to_string())),
         }
     }
 }
@@ -609,6 +613,9 @@ pub enum CommandStatus {
     #[default]
     Init,
     Scheduled,
+
+    /// The command is held by the agent till the running commands complete
+    Queued,
     Executing,
     Successful,
     Failed {
@@ -616,6 +623,9 @@ pub enum CommandStatus {
         reason: String,
     },
 
+    /// The command has been cancelled on request
+    Cancelled,
+
     /// Unknown status used by a custom workflow
     #[serde(other)]
     Unknown,
@@ -630,9 +640,11 @@ impl ToString for CommandStatus {
         let str = match self {
             CommandStatus::Init => "init",
             CommandStatus::Scheduled => "scheduled",
+            CommandStatus::Queued => "queued",
             CommandStatus::Executing => "executing",
             CommandStatus::Successful => "successful",
             CommandStatus::Failed { .. } => "failed",
+            CommandStatus::Cancelled => "cancelled",
             CommandStatus::Unknown => "unknown",
         };
         str.to_string()
@@ -671,6 +683,26 @@ pub struct LogUploadCmdPayload {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub search_text: Option<String>,
     pub lines: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<LogCompression>,
+}
+
+/// Compression applied to the collected logs before their upload
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogCompression {
+    Gzip,
+    Zstd,
+}
+
+impl LogCompression {
+    /// The extension of a file compressed with this algorithm
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            LogCompression::Gzip => "gz",
+            LogCompression::Zstd => "zst",
+        }
+    }
 }
 
 impl<'a> Jsonify<'a> for LogUploadCmdPayload {}
@@ -784,6 +816,9 @@ pub struct FirmwareUpdateCmdPayload {
     pub remote_url: String,
     pub name: String,
     pub version: String,
+    /// The SHA-256 checksum of the firmware image, as an hexadecimal string, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 impl<'a> Jsonify<'a> for FirmwareUpdateCmdPayload {}
@@ -894,4 +929,21 @@ mod tests {
         // However, if serialized again the custom status is lost
         assert_eq!(request.to_json(), r#"{"status":"unknown"}"#);
     }
+
+    #[test]
+    fn serde_cancelled_command_status() {
+        let request = SoftwareListCommandPayload {
+            status: CommandStatus::Cancelled,
+            current_software_list: vec![],
+        };
+
+        assert_eq!(
+            request,
+            SoftwareListCommandPayload::from_json(
+                r#"{"status":"cancelled","cancelled_state":"executing"}"#
+            )
+            .unwrap()
+        );
+        assert_eq!(request.to_json(), r#"{"status":"cancelled"}"#);
+    }
 }
@@ -159,6 +159,7 @@ impl MqttSchema {
         };
         let channel = match channel {
             ChannelFilter::EntityMetadata => "".to_string(),
+            ChannelFilter::EntityTwinData => "/twin/+".to_string(),
             ChannelFilter::Measurement => "/m/+".to_string(),
             ChannelFilter::MeasurementMetadata => "/m/+/meta".to_string(),
             ChannelFilter::Event => "/e/+".to_string(),
@@ -677,6 +678,7 @@ pub enum EntityFilter<'a> {
 
 pub enum ChannelFilter {
     EntityMetadata,
+    EntityTwinData,
     Measurement,
     Event,
     Alarm,
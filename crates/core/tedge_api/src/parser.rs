@@ -2,11 +2,14 @@
 //!
 //! [^1]: It only allocates in presence of escaped strings as keys.
 //!
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
+use crate::measurement::ScalarValue;
 use serde::de::DeserializeSeed;
 use serde::de::MapAccess;
 use serde::de::{self};
 use serde::Deserializer;
+use serde_json::Value as JsonValue;
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
@@ -42,7 +45,7 @@ pub struct ThinEdgeJsonParserError {
 /// ```grammar
 /// {
 ///     time?: string,
-///     [key: string]: number | {[key: string]: number},
+///     [key: string]: value | {[key: string]: value},
 /// }
 /// ```
 ///
@@ -53,17 +56,24 @@ where
     visitor: &'vis mut T,
 }
 
-/// Parses a single value (number) or multi-value measurement:
+/// Parses a single value or multi-value measurement:
 ///
 /// ```grammar
-/// number | {[key: string]: number}
+/// value | {[key: string]: value}
+/// ```
+///
+/// where a single value is either a plain number or boolean, or given in an extended form
+/// along its unit and quality:
+///
+/// ```grammar
+/// number | boolean | {value: number | boolean, unit?: string, quality?: string}
 /// ```
 ///
 struct ThinEdgeValueParser<'key, 'vis, T> {
     /// Recursion depth.
     ///
-    /// When `depth = 0`, we accept both number or multi-value measurements.
-    /// When `depth > 0`, we only accept numbers.
+    /// When `depth = 0`, we accept both single or multi-value measurements.
+    /// When `depth > 0`, we only accept single values.
     depth: usize,
     /// The associated key of the single or multi-value measurement.
     key: Cow<'key, str>,
@@ -133,24 +143,36 @@ where
         }
     }
 
-    /// Parses a multi-value measurement: `{[string]: number}` or fails if depth > 0.
+    /// Parses a multi-value measurement: `{[string]: value}` or fails if depth > 0,
+    /// or a single-value measurement given in the extended form: `{value: number | boolean, unit?: string, quality?: string}`.
     ///
-    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    /// A map is only interpreted as an extended value if it has a `value`, plus a `unit` or a `quality`,
+    /// and no other keys. Hence, the values of these keys are buffered,
+    /// till the map is known to be either a multi-value measurement or an extended value.
+    ///
+    fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
     where
         A: MapAccess<'de>,
     {
-        // To support arbitrarily nested measurements remove the following line.
-        if self.depth > 0 {
-            return Err(de::Error::custom("Expect single-value measurement"));
-        }
-
-        self.visitor
-            .visit_start_group(self.key.as_ref())
-            .map_err(de::Error::custom)?;
-
+        let mut extended_value_entries: Vec<(Cow<str>, JsonValue)> = Vec::new();
+        let mut is_group = false;
         let mut measurements_count: usize = 0;
 
         while let Some(key) = map.next_key()? {
+            let key: Cow<str> = key;
+
+            if !is_group && EXTENDED_VALUE_KEYS.contains(&key.as_ref()) {
+                extended_value_entries.push((key, map.next_value()?));
+                continue;
+            }
+
+            if !is_group {
+                self.start_group()?;
+                is_group = true;
+                measurements_count +=
+                    self.visit_buffered_entries(extended_value_entries.drain(..))?;
+            }
+
             let parser = ThinEdgeValueParser {
                 depth: self.depth + 1,
                 key,
@@ -161,6 +183,20 @@ where
             measurements_count += 1;
         }
 
+        if !is_group {
+            if let Some(value) =
+                extended_value(&self.key, &extended_value_entries).map_err(de::Error::custom)?
+            {
+                self.visitor
+                    .visit_typed_measurement(self.key.as_ref(), &value)
+                    .map_err(de::Error::custom)?;
+                return Ok(());
+            }
+
+            self.start_group()?;
+            measurements_count += self.visit_buffered_entries(extended_value_entries.drain(..))?;
+        }
+
         if measurements_count == 0 {
             return Err(de::Error::custom(invalid_empty_measurement(&self.key)));
         }
@@ -198,28 +234,41 @@ where
         Ok(())
     }
 
-    /// Parses a single-value measurement. See `visit_f64`.
+    /// Parses an integer single-value measurement. See `visit_f64`.
     fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        let value = i32::try_from(value)
-            .map_err(|_| de::Error::custom(invalid_json_number(&self.key)))?
-            .into();
+        self.visitor
+            .visit_typed_measurement(self.key.as_ref(), &ScalarValue::Integer(value).into())
+            .map_err(de::Error::custom)?;
 
-        self.visit_f64(value)
+        Ok(())
     }
 
-    /// Parses a single-value measurement. See `visit_f64`.
+    /// Parses an integer single-value measurement. See `visit_f64`.
+    ///
+    /// An integer too large for an `i64` is given as a float.
     fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        let value = u32::try_from(value)
-            .map_err(|_| de::Error::custom(invalid_json_number(&self.key)))?
-            .into();
+        match i64::try_from(value) {
+            Ok(value) => self.visit_i64(value),
+            Err(_) => self.visit_f64(value as f64),
+        }
+    }
+
+    /// Parses a boolean single-value measurement.
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visitor
+            .visit_typed_measurement(self.key.as_ref(), &ScalarValue::Boolean(value).into())
+            .map_err(de::Error::custom)?;
 
-        self.visit_f64(value)
+        Ok(())
     }
 
     fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
@@ -234,6 +283,111 @@ where
     }
 }
 
+impl<'key, 'vis, T> ThinEdgeValueParser<'key, 'vis, T>
+where
+    T: MeasurementVisitor,
+{
+    fn start_group<E>(&mut self) -> Result<(), E>
+    where
+        E: serde::de::Error,
+    {
+        // To support arbitrarily nested measurements remove the following line.
+        if self.depth > 0 {
+            return Err(de::Error::custom("Expect single-value measurement"));
+        }
+
+        self.visitor
+            .visit_start_group(self.key.as_ref())
+            .map_err(de::Error::custom)
+    }
+
+    /// Parses the entries that have been buffered while looking for an extended value,
+    /// as the members of the current group.
+    fn visit_buffered_entries<'a, E>(
+        &mut self,
+        entries: impl Iterator<Item = (Cow<'a, str>, JsonValue)>,
+    ) -> Result<usize, E>
+    where
+        E: serde::de::Error,
+    {
+        let mut count = 0;
+        for (key, value) in entries {
+            let parser = ThinEdgeValueParser {
+                depth: self.depth + 1,
+                key,
+                visitor: &mut *self.visitor,
+            };
+
+            parser.deserialize(value).map_err(de::Error::custom)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// The keys of a measurement value given in the extended form
+const EXTENDED_VALUE_KEYS: [&str; 3] = ["value", "unit", "quality"];
+
+/// Builds an extended value from its entries,
+/// returning `None` if these entries are not those of an extended value,
+/// i.e. if there is no `value`, neither a `unit` nor a `quality`, or a `unit` or a `quality` that is not a string.
+///
+/// Such entries are then the members of a multi-value measurement, as with the previous versions.
+fn extended_value(
+    key: &str,
+    entries: &[(Cow<str>, JsonValue)],
+) -> Result<Option<MeasurementValue>, String> {
+    let get = |name: &str| {
+        entries
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    };
+    let as_string = |value: Option<&JsonValue>| match value {
+        None => Ok(None),
+        Some(JsonValue::String(text)) => Ok(Some(text.clone())),
+        Some(_) => Err(()),
+    };
+    let (value, unit, quality) = match (
+        get("value"),
+        as_string(get("unit")),
+        as_string(get("quality")),
+    ) {
+        (Some(value), Ok(unit), Ok(quality)) if unit.is_some() || quality.is_some() => {
+            (value, unit, quality)
+        }
+        _ => return Ok(None),
+    };
+
+    let value = match value {
+        JsonValue::Bool(value) => ScalarValue::Boolean(*value),
+        JsonValue::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                ScalarValue::Integer(value)
+            } else if let Some(value) = number
+                .as_f64()
+                .filter(|value| *value == 0.0 || value.is_normal())
+            {
+                ScalarValue::Float(value)
+            } else {
+                return Err(invalid_json_number(key));
+            }
+        }
+        _ => {
+            return Err(invalid_extended_value(
+                key,
+                "value",
+                "a number or a boolean",
+            ))
+        }
+    };
+    Ok(Some(MeasurementValue {
+        value,
+        unit,
+        quality,
+    }))
+}
+
 /// The `DeserializeSeed` trait enables us to inject state required for deserialization. In our case
 /// the state is the `visitor` that we want to use for callbacks and the `key` that we are currently
 /// parsing.
@@ -262,6 +416,10 @@ fn invalid_json_number(key: &str) -> String {
     )
 }
 
+fn invalid_extended_value(key: &str, field: &str, expected: &str) -> String {
+    format!("Invalid measurement value: the {field:?} of {key:?} must be {expected}")
+}
+
 fn invalid_empty_root() -> &'static str {
     "Empty Thin Edge measurement: it must contain at least one measurement"
 }
@@ -292,6 +450,8 @@ mod tests {
     #[test]
     fn it_deserializes_thin_edge_json() -> anyhow::Result<()> {
         use crate::builder::ThinEdgeJsonBuilder;
+        use crate::measurement::MeasurementValue;
+        use crate::measurement::ScalarValue;
         let input = r#"{
         "time" : "2021-04-30T17:03:14.123+02:00",
         "pressure": 123.4,
@@ -319,10 +479,18 @@ mod tests {
             output.values,
             vec![
                 ("pressure", 123.4).into(),
-                ("temperature", 24.0).into(),
+                (
+                    "temperature",
+                    MeasurementValue::from(ScalarValue::Integer(24))
+                )
+                    .into(),
                 (
                     "coordinate",
-                    vec![("x", 1.0).into(), ("y", 2.0).into(), ("z", -42.0).into(),]
+                    vec![
+                        ("x", MeasurementValue::from(ScalarValue::Integer(1))).into(),
+                        ("y", 2.0).into(),
+                        ("z", -42.0).into(),
+                    ]
                 )
                     .into(),
                 (r"escaped\", 123.0).into(),
@@ -398,6 +566,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_deserializes_typed_values() -> anyhow::Result<()> {
+        use crate::builder::ThinEdgeJsonBuilder;
+        use crate::measurement::MeasurementValue;
+        use crate::measurement::ScalarValue;
+        let input = r#"{
+            "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+            "door_open": true,
+            "counters": {
+                "packets": {"value": 12345678901, "unit": "packet"},
+                "errors": 3
+            },
+            "pressure": {"value": 1013},
+            "sensor": {"value": 4.5, "unit": 2, "quality": 1}
+        }"#;
+
+        let mut builder = ThinEdgeJsonBuilder::default();
+
+        parse_str(input, &mut builder)?;
+
+        let output = builder.done()?;
+
+        assert_eq!(
+            output.values,
+            vec![
+                (
+                    "temperature",
+                    MeasurementValue {
+                        value: ScalarValue::Float(21.3),
+                        unit: Some("°C".to_string()),
+                        quality: Some("good".to_string()),
+                    }
+                )
+                    .into(),
+                (
+                    "door_open",
+                    MeasurementValue::from(ScalarValue::Boolean(true))
+                )
+                    .into(),
+                (
+                    "counters",
+                    vec![
+                        (
+                            "packets",
+                            MeasurementValue {
+                                value: ScalarValue::Integer(12345678901),
+                                unit: Some("packet".to_string()),
+                                quality: None,
+                            }
+                        )
+                            .into(),
+                        ("errors", MeasurementValue::from(ScalarValue::Integer(3))).into(),
+                    ]
+                )
+                    .into(),
+                // Without unit nor quality, this is a group, as with the previous versions
+                (
+                    "pressure",
+                    vec![("value", MeasurementValue::from(ScalarValue::Integer(1013))).into()]
+                )
+                    .into(),
+                // With a numeric unit and quality, this is also a group
+                (
+                    "sensor",
+                    vec![
+                        ("value", 4.5).into(),
+                        ("unit", MeasurementValue::from(ScalarValue::Integer(2))).into(),
+                        ("quality", MeasurementValue::from(ScalarValue::Integer(1))).into(),
+                    ]
+                )
+                    .into(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_typed_values() {
+        use crate::builder::ThinEdgeJsonBuilder;
+
+        let input = r#"{"temperature": {"value": "hot", "unit": "°C"}}"#;
+        let mut builder = ThinEdgeJsonBuilder::default();
+        let error = parse_str(input, &mut builder).unwrap_err().to_string();
+        assert!(
+            error.contains(r#"the "value" of "temperature" must be a number or a boolean"#),
+            "{error}"
+        );
+    }
+
     #[test]
     fn parse_type_as_measurement() {
         use crate::builder::ThinEdgeJsonBuilder;
@@ -148,6 +148,26 @@ impl PendingEntityStore {
         }
     }
 
+    /// Returns true if the registration of this entity is pending, waiting for its parent
+    pub fn is_registration_pending(&self, entity_tid: &EntityTopicId) -> bool {
+        self.entities
+            .get(entity_tid)
+            .is_some_and(|cached_entity| cached_entity.reg_message.is_some())
+    }
+
+    /// Returns the registration messages still waiting for their parent,
+    /// along with the metadata messages received for these entities
+    pub fn pending_registrations(
+        &self,
+    ) -> impl Iterator<Item = (&EntityRegistrationMessage, &[MqttMessage])> {
+        self.entities.values().filter_map(|cached_entity| {
+            cached_entity
+                .reg_message
+                .as_ref()
+                .map(|reg_message| (reg_message, cached_entity.metadata.as_slice()))
+        })
+    }
+
     pub fn cache_early_registration_message(&mut self, reg_message: EntityRegistrationMessage) {
         let source = reg_message.topic_id.clone();
         let parent = reg_message.parent.clone().unwrap();
@@ -1,4 +1,6 @@
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
+use crate::measurement::ScalarValue;
 use json_writer::JsonWriter;
 use json_writer::JsonWriterError;
 use time::format_description;
@@ -85,6 +87,15 @@ impl ThinEdgeJsonSerializer {
         self.end()?;
         Ok(self.json.clone().into_string()?)
     }
+
+    fn write_scalar(&mut self, value: ScalarValue) -> Result<(), ThinEdgeJsonSerializationError> {
+        match value {
+            ScalarValue::Float(value) => self.json.write_f64(value)?,
+            ScalarValue::Integer(value) => self.json.write_i64(value)?,
+            ScalarValue::Boolean(value) => self.json.write_bool(value)?,
+        }
+        Ok(())
+    }
 }
 
 impl Default for ThinEdgeJsonSerializer {
@@ -117,6 +128,32 @@ impl MeasurementVisitor for ThinEdgeJsonSerializer {
         Ok(())
     }
 
+    /// Write a plain value, unless a unit or a quality is given, using then the extended form
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        self.json.write_key(name)?;
+        if value.unit.is_none() && value.quality.is_none() {
+            return self.write_scalar(value.value);
+        }
+
+        self.json.write_open_obj();
+        self.json.write_key("value")?;
+        self.write_scalar(value.value)?;
+        if let Some(unit) = &value.unit {
+            self.json.write_key("unit")?;
+            self.json.write_str(unit)?;
+        }
+        if let Some(quality) = &value.quality {
+            self.json.write_key("quality")?;
+            self.json.write_str(quality)?;
+        }
+        self.json.write_close_obj();
+        Ok(())
+    }
+
     fn visit_start_group(&mut self, group: &str) -> Result<(), Self::Error> {
         if self.is_within_group {
             return Err(MeasurementStreamError::UnexpectedStartOfGroup.into());
@@ -209,6 +246,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serialize_typed_values() -> anyhow::Result<()> {
+        let mut serializer = ThinEdgeJsonSerializer::new();
+        serializer.visit_typed_measurement(
+            "temperature",
+            &MeasurementValue {
+                value: ScalarValue::Float(21.3),
+                unit: Some("°C".to_string()),
+                quality: Some("good".to_string()),
+            },
+        )?;
+        serializer.visit_typed_measurement("door_open", &ScalarValue::Boolean(true).into())?;
+        serializer.visit_start_group("counters")?;
+        serializer.visit_typed_measurement("packets", &ScalarValue::Integer(12345678901).into())?;
+        serializer.visit_end_group()?;
+        let expected_output = r#"{"temperature":{"value":21.3,"unit":"°C","quality":"good"},"door_open":true,"counters":{"packets":12345678901}}"#;
+        let output = serializer.into_string()?;
+        assert_eq!(expected_output, output);
+        Ok(())
+    }
+
     #[test]
     fn serialize_empty_message() -> anyhow::Result<()> {
         let mut serializer = ThinEdgeJsonSerializer::new();
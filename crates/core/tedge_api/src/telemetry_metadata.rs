@@ -0,0 +1,232 @@
+//! Metadata describing the measurements, events and alarms of an entity.
+//!
+//! These metadata are published as retained messages on the `m/<type>/meta`, `e/<type>/meta` and `a/<type>/meta`
+//! channels of an entity, and are used by the mappers to enrich the telemetry data sent to the cloud.
+//!
+//! - The metadata of a measurement type are given per series, mirroring the structure of the measurements:
+//!   `{"temperature": {"unit": "°C"}, "location": {"latitude": {"unit": "°"}}}`.
+//! - The metadata of an event type provide a default `text` for the events of that type.
+//! - The metadata of an alarm type provide a default `severity` and a default `text` for the alarms of that type.
+use crate::mqtt_topics::Channel;
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::MqttSchema;
+use mqtt_channel::Message;
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+
+/// The metadata of a measurement type, given per series
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MeasurementMetadata(JsonValue);
+
+impl MeasurementMetadata {
+    /// The metadata of a measurement series, possibly within a group
+    pub fn series(&self, group: Option<&str>, name: &str) -> Option<MeasurementSeriesMetadata> {
+        let mut metadata = &self.0;
+        if let Some(group) = group {
+            metadata = metadata.get(group)?;
+        }
+        MeasurementSeriesMetadata::deserialize(metadata.get(name)?).ok()
+    }
+}
+
+/// The metadata of a measurement series
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MeasurementSeriesMetadata {
+    pub unit: Option<String>,
+    pub display_name: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// The metadata of an event type
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct EventMetadata {
+    /// The text used for the events published without text
+    pub text: Option<String>,
+}
+
+/// The metadata of an alarm type
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct AlarmMetadata {
+    /// The severity used for the alarms raised without severity
+    pub severity: Option<String>,
+
+    /// The text used for the alarms raised without text
+    pub text: Option<String>,
+}
+
+/// The metadata published by an entity for its measurements, events and alarms, indexed by type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TelemetryMetadata {
+    measurements: Map<String, JsonValue>,
+    events: Map<String, JsonValue>,
+    alarms: Map<String, JsonValue>,
+}
+
+impl TelemetryMetadata {
+    /// Update the metadata published on a metadata channel, a `null` value removing these metadata.
+    ///
+    /// Returns `true` if the metadata have been updated,
+    /// and `false` if unchanged or if the channel is not a metadata channel.
+    pub fn update(&mut self, channel: &Channel, metadata: JsonValue) -> bool {
+        let (entries, key) = match channel {
+            Channel::MeasurementMetadata { measurement_type } => {
+                (&mut self.measurements, measurement_type)
+            }
+            Channel::EventMetadata { event_type } => (&mut self.events, event_type),
+            Channel::AlarmMetadata { alarm_type } => (&mut self.alarms, alarm_type),
+            _ => return false,
+        };
+
+        if metadata.is_null() {
+            entries.remove(key).is_some()
+        } else {
+            entries.insert(key.clone(), metadata.clone()) != Some(metadata)
+        }
+    }
+
+    /// The number of measurement, event and alarm types with metadata
+    pub fn len(&self) -> usize {
+        self.measurements.len() + self.events.len() + self.alarms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The channels on which metadata have been published
+    pub fn channels(&self) -> impl Iterator<Item = Channel> + '_ {
+        let measurements =
+            self.measurements
+                .keys()
+                .map(|measurement_type| Channel::MeasurementMetadata {
+                    measurement_type: measurement_type.clone(),
+                });
+        let events = self.events.keys().map(|event_type| Channel::EventMetadata {
+            event_type: event_type.clone(),
+        });
+        let alarms = self.alarms.keys().map(|alarm_type| Channel::AlarmMetadata {
+            alarm_type: alarm_type.clone(),
+        });
+        measurements.chain(events).chain(alarms)
+    }
+
+    /// The retained messages publishing these metadata for the given entity
+    pub fn to_mqtt_messages(&self, schema: &MqttSchema, topic_id: &EntityTopicId) -> Vec<Message> {
+        self.channels()
+            .filter_map(|channel| {
+                let metadata = self.get(&channel)?;
+                let topic = schema.topic_for(topic_id, &channel);
+                Some(Message::new(&topic, metadata.to_string()).with_retain())
+            })
+            .collect()
+    }
+
+    fn get(&self, channel: &Channel) -> Option<&JsonValue> {
+        match channel {
+            Channel::MeasurementMetadata { measurement_type } => {
+                self.measurements.get(measurement_type)
+            }
+            Channel::EventMetadata { event_type } => self.events.get(event_type),
+            Channel::AlarmMetadata { alarm_type } => self.alarms.get(alarm_type),
+            _ => None,
+        }
+    }
+
+    /// The metadata of a measurement type
+    pub fn measurement(&self, measurement_type: &str) -> Option<MeasurementMetadata> {
+        let metadata = self.measurements.get(measurement_type)?;
+        Some(MeasurementMetadata(metadata.clone()))
+    }
+
+    /// The metadata of an event type
+    pub fn event(&self, event_type: &str) -> Option<EventMetadata> {
+        EventMetadata::deserialize(self.events.get(event_type)?).ok()
+    }
+
+    /// The metadata of an alarm type
+    pub fn alarm(&self, alarm_type: &str) -> Option<AlarmMetadata> {
+        AlarmMetadata::deserialize(self.alarms.get(alarm_type)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn measurement_metadata_are_given_per_series() {
+        let mut metadata = TelemetryMetadata::default();
+        metadata.update(
+            &Channel::MeasurementMetadata {
+                measurement_type: "environment".to_string(),
+            },
+            json!({
+                "temperature": {"unit": "°C", "displayName": "Temperature", "min": -20, "max": 50},
+                "location": {"altitude": {"unit": "m"}},
+            }),
+        );
+
+        assert_eq!(
+            metadata
+                .measurement("environment")
+                .and_then(|metadata| metadata.series(None, "temperature")),
+            Some(MeasurementSeriesMetadata {
+                unit: Some("°C".to_string()),
+                display_name: Some("Temperature".to_string()),
+                min: Some(-20.0),
+                max: Some(50.0),
+            })
+        );
+        assert_eq!(
+            metadata
+                .measurement("environment")
+                .and_then(|metadata| metadata.series(Some("location"), "altitude"))
+                .and_then(|series| series.unit),
+            Some("m".to_string())
+        );
+        assert_eq!(
+            metadata
+                .measurement("environment")
+                .and_then(|metadata| metadata.series(None, "pressure")),
+            None
+        );
+        assert_eq!(metadata.measurement("other"), None);
+    }
+
+    #[test]
+    fn metadata_are_removed_by_null_values() {
+        let mut metadata = TelemetryMetadata::default();
+        let channel = Channel::AlarmMetadata {
+            alarm_type: "temperature_high".to_string(),
+        };
+
+        assert!(metadata.update(&channel, json!({"severity": "major"})));
+        assert!(!metadata.update(&channel, json!({"severity": "major"})));
+        assert_eq!(
+            metadata.alarm("temperature_high"),
+            Some(AlarmMetadata {
+                severity: Some("major".to_string()),
+                text: None,
+            })
+        );
+
+        assert!(metadata.update(&channel, JsonValue::Null));
+        assert_eq!(metadata.alarm("temperature_high"), None);
+        assert!(metadata.is_empty());
+    }
+
+    #[test]
+    fn only_metadata_channels_are_accepted() {
+        let mut metadata = TelemetryMetadata::default();
+        let channel = Channel::Measurement {
+            measurement_type: "environment".to_string(),
+        };
+
+        assert!(!metadata.update(&channel, json!({"temperature": {"unit": "°C"}})));
+        assert!(metadata.is_empty());
+    }
+}
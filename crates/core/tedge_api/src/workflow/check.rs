@@ -0,0 +1,193 @@
+use crate::workflow::OperationAction;
+use crate::workflow::OperationWorkflow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+/// An issue detected on a workflow definition, that would only be noticed at runtime otherwise
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum WorkflowIssue {
+    #[error("The {state} state refers to an undefined state: {target}")]
+    UndefinedState { state: String, target: String },
+
+    #[error("The {state} state is unreachable from the init state")]
+    UnreachableState { state: String },
+
+    #[error("The {state} state can reach neither the successful nor the failed state")]
+    DeadEndState { state: String },
+}
+
+impl OperationWorkflow {
+    /// Check the state machine of this workflow
+    ///
+    /// Return the issues found on:
+    /// - transitions to undefined states,
+    /// - states that cannot be reached from the `init` state (except the `successful`, `failed` and `cancelled` states),
+    /// - states from which no final state can be reached.
+    pub fn check(&self) -> Vec<WorkflowIssue> {
+        let transitions: BTreeMap<&str, BTreeSet<String>> = self
+            .states
+            .iter()
+            .map(|(state, action)| (state.as_str(), action.next_states().into_iter().collect()))
+            .collect();
+
+        let mut issues = Vec::new();
+        let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut predecessors: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (state, targets) in transitions.iter() {
+            for target in targets.iter() {
+                match transitions.get_key_value(target.as_str()) {
+                    None => issues.push(WorkflowIssue::UndefinedState {
+                        state: state.to_string(),
+                        target: target.to_string(),
+                    }),
+                    Some((target, _)) => {
+                        successors.entry(state).or_default().push(target);
+                        predecessors.entry(target).or_default().push(state);
+                    }
+                }
+            }
+        }
+
+        // A command can also enter the cancelling state, when its cancellation is requested
+        let reachable = explore(["init", "cancelling"], &successors);
+        let final_states = self
+            .states
+            .iter()
+            .filter(|(_, action)| **action == OperationAction::Clear)
+            .map(|(state, _)| state.as_str());
+        let terminating = explore(final_states, &predecessors);
+
+        for state in transitions.keys() {
+            if matches!(*state, "successful" | "failed" | "cancelled") {
+                // These final states are defined by default, even if not used
+                continue;
+            }
+            if !reachable.contains(state) {
+                issues.push(WorkflowIssue::UnreachableState {
+                    state: state.to_string(),
+                })
+            } else if !terminating.contains(state) {
+                issues.push(WorkflowIssue::DeadEndState {
+                    state: state.to_string(),
+                })
+            }
+        }
+
+        issues
+    }
+}
+
+/// Return all the states that can be reached from the given states following the given edges
+fn explore<'a>(
+    from: impl IntoIterator<Item = &'a str>,
+    edges: &HashMap<&'a str, Vec<&'a str>>,
+) -> BTreeSet<&'a str> {
+    let mut visited = BTreeSet::new();
+    let mut pending: Vec<&str> = from.into_iter().collect();
+    while let Some(state) = pending.pop() {
+        if visited.insert(state) {
+            if let Some(next_states) = edges.get(state) {
+                pending.extend(next_states.iter().copied())
+            }
+        }
+    }
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_well_defined_workflow_has_no_issues() {
+        let file = r#"
+operation = "check-test"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+script = "/some/script.sh"
+on_success = "executing"
+on_error = "failed"
+
+[executing]
+background_script = "/some/other/script.sh"
+on_exec = "successful"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(workflow.check(), vec![]);
+    }
+
+    #[test]
+    fn undefined_unreachable_and_dead_end_states_are_reported() {
+        let file = r#"
+operation = "check-test"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+script = "/some/script.sh"
+on_success = "executing"
+on_error = "rollback"
+
+[executing]
+action = "proceed"
+on_success = "waiting"
+
+[waiting]
+action = "proceed"
+on_success = "executing"
+
+[orphan]
+action = "proceed"
+on_success = "successful"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(
+            workflow.check(),
+            vec![
+                WorkflowIssue::UndefinedState {
+                    state: "scheduled".to_string(),
+                    target: "rollback".to_string(),
+                },
+                WorkflowIssue::DeadEndState {
+                    state: "executing".to_string(),
+                },
+                WorkflowIssue::UnreachableState {
+                    state: "orphan".to_string(),
+                },
+                WorkflowIssue::DeadEndState {
+                    state: "waiting".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn states_reached_on_cancel_are_not_unreachable() {
+        let file = r#"
+operation = "check-test"
+on_cancel = "rollback"
+
+[init]
+action = "proceed"
+on_success = "executing"
+
+[executing]
+script = "/some/script.sh"
+on_success = "successful"
+
+[rollback]
+script = "/some/rollback.sh"
+on_success = "cancelled"
+on_error = "failed"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(workflow.check(), vec![]);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::mqtt_topics::OperationType;
+use crate::workflow::GenericCommandState;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum ConcurrencyError {
+    #[error("Invalid operation concurrency limit: {0}. Expecting `<operation>=<max number of commands>`")]
+    InvalidOperationLimit(String),
+
+    #[error("Invalid exclusion group: {0}. Expecting `<group>=<operation>+<operation>...`")]
+    InvalidExclusionGroup(String),
+}
+
+/// Limits on the number of commands executed concurrently by the agent
+///
+/// A command is held in a `queued` state when its execution would break any of these limits:
+/// - the maximum number of commands executed concurrently, whatever their operations,
+/// - the maximum number of commands executed concurrently for its operation,
+/// - the mutual exclusion of the operations of a group, with at most one command executed at a time for all these operations.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConcurrencyLimits {
+    /// The maximum number of commands executed concurrently, if any
+    pub max_commands: Option<usize>,
+
+    /// The maximum number of commands executed concurrently per operation
+    pub max_per_operation: HashMap<OperationType, usize>,
+
+    /// Groups of operations that must not be executed concurrently
+    pub exclusion_groups: Vec<ExclusionGroup>,
+}
+
+/// A named group of operations that must not be executed concurrently
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExclusionGroup {
+    pub name: String,
+    pub operations: HashSet<OperationType>,
+}
+
+impl ConcurrencyLimits {
+    /// Set the maximum number of commands executed concurrently for an operation,
+    /// from a definition of the form `<operation>=<max number of commands>`
+    pub fn with_operation_limit(mut self, definition: &str) -> Result<Self, ConcurrencyError> {
+        let invalid = || ConcurrencyError::InvalidOperationLimit(definition.to_string());
+        let (operation, limit) = definition.split_once('=').ok_or_else(invalid)?;
+        let operation = operation.trim();
+        let limit = limit.trim().parse().map_err(|_| invalid())?;
+        if operation.is_empty() {
+            return Err(invalid());
+        }
+        self.max_per_operation.insert(operation.into(), limit);
+        Ok(self)
+    }
+
+    /// Add a group of mutually exclusive operations,
+    /// from a definition of the form `<group>=<operation>+<operation>...`
+    pub fn with_exclusion_group(mut self, definition: &str) -> Result<Self, ConcurrencyError> {
+        let invalid = || ConcurrencyError::InvalidExclusionGroup(definition.to_string());
+        let (name, operations) = definition.split_once('=').ok_or_else(invalid)?;
+        let name = name.trim();
+        let operations: HashSet<OperationType> = operations
+            .split('+')
+            .map(str::trim)
+            .filter(|operation| !operation.is_empty())
+            .map(OperationType::from)
+            .collect();
+        if name.is_empty() || operations.is_empty() {
+            return Err(invalid());
+        }
+        self.exclusion_groups.push(ExclusionGroup {
+            name: name.to_string(),
+            operations,
+        });
+        Ok(self)
+    }
+
+    /// Tell if a command for the given operation can be started while the given commands are running
+    pub fn can_start<'a>(
+        &self,
+        operation: &OperationType,
+        running: impl IntoIterator<Item = &'a GenericCommandState>,
+    ) -> bool {
+        let running_operations: Vec<OperationType> = running
+            .into_iter()
+            .filter_map(|command| command.operation())
+            .map(|operation| operation.as_str().into())
+            .collect();
+
+        if let Some(max) = self.max_commands {
+            if running_operations.len() >= max {
+                return false;
+            }
+        }
+
+        if let Some(max) = self.max_per_operation.get(operation) {
+            let count = running_operations
+                .iter()
+                .filter(|running| *running == operation)
+                .count();
+            if count >= *max {
+                return false;
+            }
+        }
+
+        !self.exclusion_groups.iter().any(|group| {
+            group.operations.contains(operation)
+                && running_operations
+                    .iter()
+                    .any(|running| group.operations.contains(running))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    fn running(operation: &str, cmd_id: &str) -> GenericCommandState {
+        GenericCommandState {
+            topic: Topic::new_unchecked(&format!("te/device/main///cmd/{operation}/{cmd_id}")),
+            status: "executing".to_string(),
+            payload: json!({"status": "executing"}),
+        }
+    }
+
+    #[test]
+    fn no_limits_by_default() {
+        let limits = ConcurrencyLimits::default();
+        let commands = [
+            running("software_update", "1"),
+            running("software_update", "2"),
+        ];
+        assert!(limits.can_start(&OperationType::SoftwareUpdate, &commands));
+    }
+
+    #[test]
+    fn global_and_per_operation_limits() {
+        let limits = ConcurrencyLimits {
+            max_commands: Some(2),
+            ..Default::default()
+        }
+        .with_operation_limit("software_update=1")
+        .unwrap();
+
+        let commands = [running("software_update", "1")];
+        assert!(!limits.can_start(&OperationType::SoftwareUpdate, &commands));
+        assert!(limits.can_start(&OperationType::Restart, &commands));
+
+        let commands = [running("config_update", "1"), running("log_upload", "2")];
+        assert!(!limits.can_start(&OperationType::Restart, &commands));
+    }
+
+    #[test]
+    fn mutually_exclusive_operations() {
+        let limits = ConcurrencyLimits::default()
+            .with_exclusion_group("packages = software_update + firmware_update")
+            .unwrap();
+
+        let commands = [running("firmware_update", "1")];
+        assert!(!limits.can_start(&OperationType::SoftwareUpdate, &commands));
+        assert!(!limits.can_start(&OperationType::FirmwareUpdate, &commands));
+        assert!(limits.can_start(&OperationType::ConfigUpdate, &commands));
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let limits = ConcurrencyLimits::default();
+        assert!(limits
+            .clone()
+            .with_operation_limit("software_update")
+            .is_err());
+        assert!(limits
+            .clone()
+            .with_operation_limit("software_update=x")
+            .is_err());
+        assert!(limits.clone().with_exclusion_group("packages").is_err());
+        assert!(limits.with_exclusion_group("packages=").is_err());
+    }
+}
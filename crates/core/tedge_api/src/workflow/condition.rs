@@ -0,0 +1,195 @@
+use crate::workflow::GenericCommandState;
+use crate::workflow::GenericStateUpdate;
+use serde_json::Value;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// A condition on a command state, used to select the next state of a workflow
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateCondition {
+    /// The path to the value to be checked, e.g. `.payload.x.y`
+    pub path: String,
+
+    /// The predicate the value has to satisfy
+    pub predicate: StatePredicate,
+}
+
+/// A predicate on the value extracted from a command state
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StatePredicate {
+    /// The value is equal to the given json value
+    Equals(Value),
+
+    /// The value is not equal to the given json value
+    NotEquals(Value),
+
+    /// The value is one of the given json values
+    OneOf(Vec<Value>),
+
+    /// The value is defined (or undefined if false)
+    Exists(bool),
+}
+
+impl StateCondition {
+    /// Check if the given command state satisfies this condition
+    pub fn is_satisfied_by(&self, state: &GenericCommandState) -> bool {
+        let value = state.extract_value(&self.path);
+        match (&self.predicate, value) {
+            (StatePredicate::Exists(expected), value) => *expected == value.is_some(),
+            (_, None) => false,
+            (StatePredicate::Equals(expected), Some(value)) => expected == &value,
+            (StatePredicate::NotEquals(expected), Some(value)) => expected != &value,
+            (StatePredicate::OneOf(expected), Some(value)) => expected.contains(&value),
+        }
+    }
+}
+
+impl Display for StateCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let path = &self.path;
+        match &self.predicate {
+            StatePredicate::Equals(value) => write!(f, "${{{path}}} == {value}"),
+            StatePredicate::NotEquals(value) => write!(f, "${{{path}}} != {value}"),
+            StatePredicate::OneOf(values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "${{{path}}} in [{}]", values.join(", "))
+            }
+            StatePredicate::Exists(true) => write!(f, "${{{path}}} is defined"),
+            StatePredicate::Exists(false) => write!(f, "${{{path}}} is undefined"),
+        }
+    }
+}
+
+/// Define how to select the next state of a command given its current payload
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConditionHandlers {
+    /// The conditions that are checked in order, the first satisfied one giving the next state
+    pub conditions: Vec<(StateCondition, GenericStateUpdate)>,
+
+    /// The next state when none of the conditions is satisfied
+    pub otherwise: GenericStateUpdate,
+}
+
+impl ConditionHandlers {
+    pub fn new(
+        conditions: Vec<(StateCondition, GenericStateUpdate)>,
+        otherwise: GenericStateUpdate,
+    ) -> Self {
+        ConditionHandlers {
+            conditions,
+            otherwise,
+        }
+    }
+
+    /// Return the state update of the first condition satisfied by the command state
+    pub fn state_update(&self, state: &GenericCommandState) -> GenericStateUpdate {
+        self.conditions
+            .iter()
+            .find(|(condition, _)| condition.is_satisfied_by(state))
+            .map(|(_, update)| update.clone())
+            .unwrap_or_else(|| self.otherwise.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    #[test]
+    fn check_conditions_on_payload() {
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/make_it/123"),
+            status: "init".to_string(),
+            payload: json!({
+                "status": "init",
+                "target": "child",
+                "count": 3,
+                "options": { "force": true }
+            }),
+        };
+
+        let check = |path: &str, predicate: StatePredicate| {
+            StateCondition {
+                path: path.to_string(),
+                predicate,
+            }
+            .is_satisfied_by(&state)
+        };
+
+        assert!(check(
+            ".payload.target",
+            StatePredicate::Equals(json!("child"))
+        ));
+        assert!(!check(
+            ".payload.target",
+            StatePredicate::Equals(json!("main"))
+        ));
+        assert!(check(".payload.count", StatePredicate::Equals(json!(3))));
+        assert!(!check(".payload.count", StatePredicate::Equals(json!("3"))));
+        assert!(check(
+            ".payload.options.force",
+            StatePredicate::Equals(json!(true))
+        ));
+        assert!(check(
+            ".payload.target",
+            StatePredicate::NotEquals(json!("main"))
+        ));
+        assert!(!check(
+            ".payload.unknown",
+            StatePredicate::NotEquals(json!("main"))
+        ));
+        assert!(check(
+            ".payload.target",
+            StatePredicate::OneOf(vec![json!("main"), json!("child")])
+        ));
+        assert!(check(".payload.options", StatePredicate::Exists(true)));
+        assert!(check(".payload.unknown", StatePredicate::Exists(false)));
+        assert!(check(
+            ".topic.operation",
+            StatePredicate::Equals(json!("make_it"))
+        ));
+    }
+
+    #[test]
+    fn first_satisfied_condition_determines_next_state() {
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/make_it/123"),
+            status: "init".to_string(),
+            payload: json!({ "status": "init", "mode": "parallel" }),
+        };
+        let condition = |value: Value| StateCondition {
+            path: ".payload.mode".to_string(),
+            predicate: StatePredicate::Equals(value),
+        };
+
+        let handlers = ConditionHandlers::new(
+            vec![
+                (
+                    condition(json!("sequential")),
+                    "sequential".to_string().into(),
+                ),
+                (condition(json!("parallel")), "parallel".to_string().into()),
+                (
+                    StateCondition {
+                        path: ".payload.mode".to_string(),
+                        predicate: StatePredicate::Exists(true),
+                    },
+                    "other".to_string().into(),
+                ),
+            ],
+            "default".to_string().into(),
+        );
+        assert_eq!(handlers.state_update(&state).status, "parallel");
+
+        let handlers = ConditionHandlers::new(
+            vec![(
+                condition(json!("sequential")),
+                "sequential".to_string().into(),
+            )],
+            "default".to_string().into(),
+        );
+        assert_eq!(handlers.state_update(&state).status, "default");
+    }
+}
@@ -12,6 +12,9 @@ pub enum WorkflowDefinitionError {
 
     #[error("Unknown action: {action}")]
     UnknownAction { action: String },
+
+    #[error("Invalid condition on {path}: {reason}")]
+    InvalidCondition { path: String, reason: String },
 }
 
 /// Error related to a script definition
@@ -31,6 +34,18 @@ pub enum ScriptDefinitionError {
 
     #[error("Invalid exit code range '{from}-{to}' as {from}>{to}")]
     IncorrectRange { from: u8, to: u8 },
+
+    #[error("No scripts provided for parallel execution")]
+    NoParallelScripts,
+
+    #[error("A join policy is provided but no scripts for parallel execution")]
+    JoinWithoutParallelScripts,
+
+    #[error("A retry delay or backoff is provided but no 'retries' limit")]
+    MissingRetries,
+
+    #[error("A retry policy is provided for a state that is not a script")]
+    RetryWithoutScript,
 }
 
 /// Error preventing a workflow to be registered
@@ -0,0 +1,231 @@
+use crate::mqtt_topics::OperationType;
+use crate::workflow::GenericCommandState;
+use crate::workflow::OperationAction;
+use crate::workflow::StateName;
+use crate::workflow::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::os::unix::process::ExitStatusExt;
+use std::time::Duration;
+
+/// The maximum number of bytes of a script stdout or stderr recorded in a command history
+const MAX_OUTPUT_LENGTH: usize = 1024;
+
+/// An entry of the execution history of a command
+///
+/// An entry is recorded each time the command enters a state,
+/// plus an entry for each script executed in that state, with the outcome of the script.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CommandHistoryEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: Timestamp,
+
+    /// The state of the command
+    pub state: StateName,
+
+    /// The action executed on this state
+    pub action: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+
+    /// The error preventing the script to be launched, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// The script stdout, truncated to its last 1024 bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+
+    /// The script stderr, truncated to its last 1024 bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+impl CommandHistoryEntry {
+    /// An entry recording that a command entered a state with some action to be executed
+    pub fn new(
+        timestamp: Timestamp,
+        state: &GenericCommandState,
+        action: &OperationAction,
+    ) -> Self {
+        CommandHistoryEntry {
+            timestamp,
+            state: state.status.clone(),
+            action: action.to_string(),
+            exit_code: None,
+            signal: None,
+            error: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    /// An entry recording the outcome of a script executed on a given state
+    pub fn script_outcome(
+        timestamp: Timestamp,
+        state: &str,
+        script: &str,
+        outcome: &std::io::Result<std::process::Output>,
+    ) -> Self {
+        let mut entry = CommandHistoryEntry {
+            timestamp,
+            state: state.to_string(),
+            action: script.to_string(),
+            exit_code: None,
+            signal: None,
+            error: None,
+            stdout: None,
+            stderr: None,
+        };
+        match outcome {
+            Ok(output) => {
+                entry.exit_code = output.status.code();
+                entry.signal = output.status.signal();
+                entry.stdout = truncated_output(&output.stdout);
+                entry.stderr = truncated_output(&output.stderr);
+            }
+            Err(err) => entry.error = Some(err.to_string()),
+        }
+        entry
+    }
+
+    /// Tell if this entry records a script outcome
+    pub fn is_script_outcome(&self) -> bool {
+        self.exit_code.is_some() || self.signal.is_some() || self.error.is_some()
+    }
+}
+
+/// Keep only the last bytes of a script output
+fn truncated_output(output: &[u8]) -> Option<String> {
+    if output.is_empty() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(output);
+    if output.len() <= MAX_OUTPUT_LENGTH {
+        return Some(output.to_string());
+    }
+    let mut start = output.len() - MAX_OUTPUT_LENGTH;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    Some(format!("...{}", &output[start..]))
+}
+
+/// The execution history of a command
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CommandHistory {
+    pub entries: Vec<CommandHistoryEntry>,
+}
+
+impl CommandHistory {
+    /// The name of the file where the history of a command is recorded, one json entry per line
+    pub fn file_name(operation: &OperationType, cmd_id: &str) -> String {
+        format!("workflow-{operation}-{cmd_id}.history")
+    }
+
+    /// Parse a command history recorded as one json entry per line
+    pub fn from_json_lines(content: &str) -> Result<Self, serde_json::Error> {
+        let entries = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CommandHistory { entries })
+    }
+
+    /// The successive states of the command, with the time spent in each of them
+    ///
+    /// No duration is given for the last state, which might be still in progress.
+    pub fn state_durations(&self) -> Vec<(StateName, Option<Duration>)> {
+        let transitions: Vec<&CommandHistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.is_script_outcome())
+            .collect();
+        transitions
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let duration = transitions.get(i + 1).map(|next| {
+                    Duration::try_from(next.timestamp - entry.timestamp).unwrap_or_default()
+                });
+                (entry.state.clone(), duration)
+            })
+            .collect()
+    }
+
+    /// The json representation of this history, as attached to a command payload
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&self.entries).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+    use std::process::Command;
+    use time::macros::datetime;
+
+    #[test]
+    fn record_and_parse_a_command_history() {
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/make_it/123"),
+            status: "init".to_string(),
+            payload: json!({"status": "init"}),
+        };
+        let action = OperationAction::MoveTo("scheduled".to_string());
+        let init = CommandHistoryEntry::new(datetime!(2023-12-01 10:00:00 UTC), &state, &action);
+
+        let output = Command::new("sh")
+            .args(["-c", "echo hello; echo oops >&2; exit 3"])
+            .output();
+        let script = CommandHistoryEntry::script_outcome(
+            datetime!(2023-12-01 10:00:05 UTC),
+            "scheduled",
+            "sh -c 'echo hello; echo oops >&2; exit 3'",
+            &output,
+        );
+        assert_eq!(script.exit_code, Some(3));
+        assert_eq!(script.stdout, Some("hello\n".to_string()));
+        assert_eq!(script.stderr, Some("oops\n".to_string()));
+
+        let state = state.update("failed".to_string().into());
+        let failed = CommandHistoryEntry::new(
+            datetime!(2023-12-01 10:00:07 UTC),
+            &state,
+            &OperationAction::Clear,
+        );
+
+        let content = [&init, &script, &failed]
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let history = CommandHistory::from_json_lines(&content).unwrap();
+        assert_eq!(history.entries, vec![init, script, failed]);
+        assert_eq!(
+            history.state_durations(),
+            vec![
+                ("init".to_string(), Some(Duration::from_secs(7))),
+                ("failed".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_outputs_are_truncated() {
+        let output = "x".repeat(2 * MAX_OUTPUT_LENGTH);
+        let truncated = truncated_output(output.as_bytes()).unwrap();
+        assert_eq!(truncated.len(), MAX_OUTPUT_LENGTH + 3);
+        assert!(truncated.starts_with("...x"));
+
+        assert_eq!(truncated_output(b""), None);
+    }
+}
@@ -1,6 +1,13 @@
+pub mod check;
+pub mod concurrency;
+pub mod condition;
 pub mod error;
+pub mod history;
 mod on_disk;
+pub mod parallel;
+pub mod schedule;
 pub mod script;
+pub mod simulation;
 pub mod state;
 pub mod supervisor;
 mod toml_config;
@@ -8,11 +15,18 @@ mod toml_config;
 use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::mqtt_topics::OperationType;
+pub use check::*;
+pub use concurrency::*;
+pub use condition::*;
 pub use error::*;
+pub use history::*;
 use mqtt_channel::Message;
 use mqtt_channel::QoS;
+pub use parallel::*;
+pub use schedule::*;
 pub use script::*;
 use serde::Deserialize;
+pub use simulation::*;
 pub use state::*;
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -38,6 +52,9 @@ pub struct OperationWorkflow {
 
     /// The states of the state machine
     pub states: HashMap<StateName, OperationAction>,
+
+    /// Attach the execution history of a command to its final state
+    pub attach_history: bool,
 }
 
 /// What needs to be done to advance an operation request in some state
@@ -91,6 +108,27 @@ pub enum OperationAction {
     /// A script has to be executed
     Script(ShellScript, ExitHandlers),
 
+    /// Several scripts are executed concurrently, the next state being determined once they are joined
+    ///
+    /// ```toml
+    /// parallel = ["/some/script.sh arg1", "/some/script.sh arg2"]
+    /// join = "all"
+    /// on_success = "<state>"
+    /// on_error = "<state>"
+    /// ```
+    Parallel(Vec<ShellScript>, JoinHandlers),
+
+    /// The next state is selected after the command payload
+    ///
+    /// ```toml
+    /// action = "proceed"
+    /// condition = [
+    ///   { when = "${.payload.x}", equals = "some value", then = "<state>" },
+    /// ]
+    /// on_success = "<state>"
+    /// ```
+    Condition(ConditionHandlers),
+
     /// Executes a script but move to the next state without waiting for that script to return
     ///
     /// Notably such a script can trigger a device reboot or an agent restart.
@@ -100,6 +138,27 @@ pub enum OperationAction {
     /// ```
     BgScript(ShellScript, BgExitHandlers),
 
+    /// Trigger a sub-operation and move to the next state without waiting for its completion
+    ///
+    /// The sub-operation is created on the same target with an init state built from the `input` excerpt.
+    /// ```toml
+    /// operation = "<sub-operation>"
+    /// input.x = "${.payload.y}"
+    /// on_exec = "<state>"
+    /// ```
+    Operation(OperationType, StateExcerpt, BgExitHandlers),
+
+    /// Await the completion of the sub-operation triggered on a previous state
+    ///
+    /// The `output` excerpt, built from the final state of the sub-operation, is injected into the command state.
+    /// ```toml
+    /// action = "await-operation-completion"
+    /// output.x = "${.payload.y}"
+    /// on_success = "<state>"
+    /// on_error = "<state>"
+    /// ```
+    AwaitOperationCompletion(AwaitHandlers, StateExcerpt),
+
     /// The command has been fully processed and needs to be cleared
     Clear,
 }
@@ -113,6 +172,28 @@ impl Display for OperationAction {
             OperationAction::Restart { .. } => "trigger device restart".to_string(),
             OperationAction::Script(script, _) => script.to_string(),
             OperationAction::BgScript(script, _) => script.to_string(),
+            OperationAction::Parallel(scripts, handlers) => {
+                let scripts: Vec<String> = scripts.iter().map(|s| s.to_string()).collect();
+                format!("join {} of [{}]", handlers.join, scripts.join(", "))
+            }
+            OperationAction::Condition(handlers) => {
+                let conditions: Vec<String> = handlers
+                    .conditions
+                    .iter()
+                    .map(|(condition, update)| format!("{condition} => {}", update.status))
+                    .collect();
+                format!(
+                    "select the next state: [{}] otherwise {}",
+                    conditions.join(", "),
+                    handlers.otherwise.status
+                )
+            }
+            OperationAction::Operation(operation, _, _) => {
+                format!("trigger {operation} sub-operation")
+            }
+            OperationAction::AwaitOperationCompletion(_, _) => {
+                "await sub-operation completion".to_string()
+            }
             OperationAction::Clear => "wait for the requester to finalize the command".to_string(),
         };
         f.write_str(&str)
@@ -158,11 +239,30 @@ impl OperationWorkflow {
             });
         }
 
+        // The cancelling state can be omitted,
+        // a cancelled command being then simply moved to the cancelled state.
+        states
+            .entry("cancelling".to_string())
+            .or_insert(OperationAction::MoveTo("cancelled".to_string()));
+
+        // The cancelled state can be omitted,
+        // but must be associated to a `clear` if provided.
+        let action_on_cancel = states
+            .entry("cancelled".to_string())
+            .or_insert(OperationAction::Clear);
+        if action_on_cancel != &OperationAction::Clear {
+            return Err(WorkflowDefinitionError::InvalidAction {
+                state: "cancelled".to_string(),
+                action: format!("{action_on_cancel:?}"),
+            });
+        }
+
         Ok(OperationWorkflow {
             operation,
             built_in: false,
             handlers,
             states,
+            attach_history: false,
         })
     }
 
@@ -174,6 +274,11 @@ impl OperationWorkflow {
             ("executing", OperationAction::BuiltIn),
             ("successful", OperationAction::Clear),
             ("failed", OperationAction::Clear),
+            (
+                "cancelling",
+                OperationAction::MoveTo("cancelled".to_string()),
+            ),
+            ("cancelled", OperationAction::Clear),
         ]
         .into_iter()
         .map(|(state, action)| (state.to_string(), action))
@@ -184,6 +289,19 @@ impl OperationWorkflow {
             operation,
             handlers: DefaultHandlers::default(),
             states,
+            attach_history: false,
+        }
+    }
+
+    /// Return the states to which a new command is moved on success of its `init` state
+    ///
+    /// This is where the agent checks the command schedule and the concurrency limits,
+    /// before any action specific to the operation is executed.
+    pub fn scheduled_states(&self) -> Vec<StateName> {
+        match self.states.get("init") {
+            Some(action @ OperationAction::Condition(_)) => action.next_states(),
+            Some(action) => action.next_states().into_iter().take(1).collect(),
+            None => vec![],
         }
     }
 
@@ -231,12 +349,20 @@ impl OperationWorkflow {
     }
 }
 
+/// The id of the sub-command triggered by a command
+pub fn sub_command_id(operation: &OperationType, cmd_id: &str) -> CommandId {
+    format!("sub-{operation}-{cmd_id}")
+}
+
 impl OperationAction {
     pub fn with_default(self, default: &DefaultHandlers) -> Self {
         match self {
             OperationAction::Script(script, handlers) => {
                 OperationAction::Script(script, handlers.with_default(default))
             }
+            OperationAction::Parallel(scripts, handlers) => {
+                OperationAction::Parallel(scripts, handlers.with_default(default))
+            }
             action => action,
         }
     }
@@ -250,7 +376,59 @@ impl OperationAction {
                 },
                 handlers.clone(),
             ),
+            OperationAction::Parallel(scripts, handlers) => OperationAction::Parallel(
+                scripts
+                    .iter()
+                    .map(|script| ShellScript {
+                        command: state.inject_parameter(&script.command),
+                        args: state.inject_parameters(&script.args),
+                    })
+                    .collect(),
+                handlers.clone(),
+            ),
+            OperationAction::Operation(operation, input, handlers) => OperationAction::Operation(
+                operation.clone(),
+                StateExcerpt(input.extract_from(state)),
+                handlers.clone(),
+            ),
             _ => self.clone(),
         }
     }
+
+    /// The states to which this action can move a command
+    ///
+    /// The states a script might dynamically choose on its stdout are not listed,
+    /// unless explicitly declared with `on_stdout`.
+    pub fn next_states(&self) -> Vec<StateName> {
+        match self {
+            OperationAction::MoveTo(state) => vec![state.clone()],
+            OperationAction::BuiltIn => vec![
+                "executing".to_string(),
+                "successful".to_string(),
+                "failed".to_string(),
+            ],
+            OperationAction::AwaitingAgentRestart {
+                on_success,
+                on_timeout,
+                ..
+            } => vec![on_success.status.clone(), on_timeout.status.clone()],
+            OperationAction::Restart {
+                on_exec,
+                on_success,
+                on_error,
+            } => vec![on_exec.clone(), on_success.clone(), on_error.clone()],
+            OperationAction::Script(_, handlers) => handlers.next_states(),
+            OperationAction::Parallel(_, handlers) => handlers.next_states(),
+            OperationAction::Condition(handlers) => handlers
+                .conditions
+                .iter()
+                .map(|(_, update)| update.status.clone())
+                .chain(std::iter::once(handlers.otherwise.status.clone()))
+                .collect(),
+            OperationAction::BgScript(_, handlers) => vec![handlers.on_exec.status.clone()],
+            OperationAction::Operation(_, _, handlers) => vec![handlers.on_exec.status.clone()],
+            OperationAction::AwaitOperationCompletion(handlers, _) => handlers.next_states(),
+            OperationAction::Clear => vec![],
+        }
+    }
 }
@@ -17,6 +17,9 @@ pub(crate) enum OnDiskCommandBoard {
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub(crate) struct OnDiskCommandBoardV1 {
     commands: HashMap<String, OnDiskCommandStateV1>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sub_commands: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -64,7 +67,7 @@ impl TryFrom<OnDiskCommandBoardV1> for CommandBoard {
             };
             commands.insert(topic_name, (timestamp, state));
         }
-        Ok(CommandBoard::new(commands))
+        Ok(CommandBoard::new(commands).with_sub_commands(board.sub_commands))
     }
 }
 
@@ -82,7 +85,14 @@ impl From<CommandBoard> for OnDiskCommandBoardV1 {
                 },
             );
         }
-        OnDiskCommandBoardV1 { commands }
+        let sub_commands = board
+            .iter_sub_commands()
+            .map(|(command, sub_command)| (command.clone(), sub_command.clone()))
+            .collect();
+        OnDiskCommandBoardV1 {
+            commands,
+            sub_commands,
+        }
     }
 }
 
@@ -0,0 +1,251 @@
+use crate::workflow::json_stdout_excerpt;
+use crate::workflow::script::forceful_timeout_extension;
+use crate::workflow::DefaultHandlers;
+use crate::workflow::GenericStateUpdate;
+use crate::workflow::StateName;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::os::unix::prelude::ExitStatusExt;
+use std::time::Duration;
+
+/// Define when a set of scripts executed in parallel are joined
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinPolicy {
+    /// All the scripts have to successfully complete
+    #[default]
+    All,
+
+    /// The first successful script completes the join
+    Any,
+}
+
+impl Display for JoinPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinPolicy::All => f.write_str("all"),
+            JoinPolicy::Any => f.write_str("any"),
+        }
+    }
+}
+
+/// Define how to interpret the outcomes of scripts executed in parallel as the next state for a command
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JoinHandlers {
+    pub join: JoinPolicy,
+    on_success: Option<GenericStateUpdate>,
+    on_error: Option<GenericStateUpdate>,
+    timeout: Option<Duration>,
+}
+
+impl JoinHandlers {
+    pub fn new(
+        join: JoinPolicy,
+        on_success: Option<GenericStateUpdate>,
+        on_error: Option<GenericStateUpdate>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        JoinHandlers {
+            join,
+            on_success,
+            on_error,
+            timeout,
+        }
+    }
+
+    pub fn with_default(mut self, default: &DefaultHandlers) -> Self {
+        if self.timeout.is_none() {
+            self.timeout = default.timeout
+        }
+        if self.on_error.is_none() {
+            self.on_error = default.on_error.clone()
+        }
+
+        self
+    }
+
+    /// Tell if the join is complete, without having to wait for the remaining scripts
+    ///
+    /// This is the case for a `join = "all"` as soon as one script fails,
+    /// and for a `join = "any"` as soon as one script succeeds.
+    pub fn is_complete_on(&self, outcome: &std::io::Result<std::process::Output>) -> bool {
+        match self.join {
+            JoinPolicy::All => !is_successful(outcome),
+            JoinPolicy::Any => is_successful(outcome),
+        }
+    }
+
+    /// Compute the next state given the outcomes of the scripts executed in parallel
+    ///
+    /// The json excerpts returned on stdout by the successful scripts are merged, in order,
+    /// and injected into the next state.
+    pub fn state_update(
+        &self,
+        outcomes: Vec<(String, std::io::Result<std::process::Output>)>,
+    ) -> Value {
+        let mut successes = 0;
+        let mut failures = Vec::new();
+        let mut dynamic_update = serde_json::Map::new();
+        for (program, outcome) in outcomes {
+            match outcome {
+                Ok(output) if output.status.success() => {
+                    successes += 1;
+                    if let Ok(Value::Object(excerpt)) = json_stdout_excerpt(&program, output.stdout)
+                    {
+                        dynamic_update.extend(excerpt)
+                    }
+                }
+                Ok(output) => match output.status.code() {
+                    Some(code) => failures.push(format!("{program} returned exit code {code}")),
+                    None => failures.push(format!(
+                        "{program} killed by signal {}",
+                        output.status.signal().unwrap_or(0)
+                    )),
+                },
+                Err(err) => failures.push(format!("Failed to launch {program}: {err}")),
+            }
+        }
+
+        let is_successful = match self.join {
+            JoinPolicy::All => failures.is_empty(),
+            JoinPolicy::Any => successes > 0,
+        };
+        if is_successful {
+            self.on_success
+                .clone()
+                .unwrap_or_else(GenericStateUpdate::successful)
+                .inject_into_json(Value::Object(dynamic_update))
+        } else {
+            let reason = failures.join("; ");
+            let mut update = self
+                .on_error
+                .clone()
+                .unwrap_or_else(|| GenericStateUpdate::failed(reason.clone()));
+            if update.reason.is_none() {
+                update.reason = Some(reason)
+            }
+            update.into_json()
+        }
+    }
+
+    pub fn graceful_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn forceful_timeout_extension(&self) -> Option<Duration> {
+        self.timeout.map(forceful_timeout_extension)
+    }
+
+    /// The states to which these handlers can move a command
+    pub fn next_states(&self) -> Vec<StateName> {
+        let on_success = self
+            .on_success
+            .as_ref()
+            .map(|update| update.status.clone())
+            .unwrap_or_else(|| "successful".to_string());
+        let on_error = self
+            .on_error
+            .as_ref()
+            .map(|update| update.status.clone())
+            .unwrap_or_else(|| "failed".to_string());
+        vec![on_success, on_error]
+    }
+}
+
+fn is_successful(outcome: &std::io::Result<std::process::Output>) -> bool {
+    matches!(outcome, Ok(output) if output.status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::process::Command;
+
+    fn run(command_line: &str) -> (String, std::io::Result<std::process::Output>) {
+        let output = Command::new("sh").args(["-c", command_line]).output();
+        ("sh".to_string(), output)
+    }
+
+    #[test]
+    fn all_scripts_have_to_succeed_to_join_all() {
+        let handlers = JoinHandlers::new(
+            JoinPolicy::All,
+            Some("next".to_string().into()),
+            Some("oops".to_string().into()),
+            None,
+        );
+
+        let state_update = handlers.state_update(vec![run("exit 0"), run("exit 0")]);
+        assert_eq!(state_update, json!({ "status": "next" }));
+
+        let state_update = handlers.state_update(vec![run("exit 0"), run("exit 3")]);
+        assert_eq!(
+            state_update,
+            json!({ "status": "oops", "reason": "sh returned exit code 3" })
+        );
+
+        let handlers = JoinHandlers::new(
+            JoinPolicy::All,
+            None,
+            Some(GenericStateUpdate {
+                status: "oops".to_string(),
+                reason: Some("some download failed".to_string()),
+            }),
+            None,
+        );
+        let state_update = handlers.state_update(vec![run("exit 0"), run("exit 3")]);
+        assert_eq!(
+            state_update,
+            json!({ "status": "oops", "reason": "some download failed" })
+        );
+    }
+
+    #[test]
+    fn one_script_has_to_succeed_to_join_any() {
+        let handlers = JoinHandlers::new(JoinPolicy::Any, None, None, None);
+
+        let state_update = handlers.state_update(vec![run("exit 1"), run("exit 0")]);
+        assert_eq!(state_update, json!({ "status": "successful" }));
+
+        let state_update = handlers.state_update(vec![run("exit 1"), run("exit 2")]);
+        assert_eq!(
+            state_update,
+            json!({
+                "status": "failed",
+                "reason": "sh returned exit code 1; sh returned exit code 2"
+            })
+        );
+    }
+
+    #[test]
+    fn successful_outputs_are_merged_into_the_next_state() {
+        let handlers = JoinHandlers::new(JoinPolicy::All, None, None, None);
+
+        let state_update = handlers.state_update(vec![
+            run(r#"echo ':::begin-tedge:::'; echo '{"child1": "done"}'; echo ':::end-tedge:::'"#),
+            run(r#"echo ':::begin-tedge:::'; echo '{"child2": "done"}'; echo ':::end-tedge:::'"#),
+        ]);
+        assert_eq!(
+            state_update,
+            json!({ "status": "successful", "child1": "done", "child2": "done" })
+        );
+    }
+
+    #[test]
+    fn join_any_completes_on_first_success() {
+        let handlers = JoinHandlers::new(JoinPolicy::Any, None, None, None);
+        assert!(handlers.is_complete_on(&run("exit 0").1));
+        assert!(!handlers.is_complete_on(&run("exit 1").1));
+    }
+
+    #[test]
+    fn join_all_completes_on_first_failure() {
+        let handlers = JoinHandlers::new(JoinPolicy::All, None, None, None);
+        assert!(!handlers.is_complete_on(&run("exit 0").1));
+        assert!(handlers.is_complete_on(&run("exit 1").1));
+    }
+}
@@ -0,0 +1,490 @@
+use crate::workflow::GenericCommandState;
+use crate::workflow::Timestamp;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::Date;
+use time::PrimitiveDateTime;
+use time::Time;
+
+/// The maximum number of days looked ahead to find the next opening of a maintenance window
+const MAX_LOOK_AHEAD_DAYS: usize = 4 * 366;
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum ScheduleError {
+    #[error("Invalid {field} timestamp: {value}. Expecting an RFC 3339 timestamp, e.g. \"2023-12-01T02:00:00Z\"")]
+    InvalidTimestamp { field: String, value: String },
+
+    #[error("Unknown maintenance window: {name}")]
+    UnknownMaintenanceWindow { name: String },
+
+    #[error("Invalid maintenance window definition: {definition}. {reason}")]
+    InvalidMaintenanceWindow { definition: String, reason: String },
+}
+
+/// The time constraints set on the execution of a command
+///
+/// These constraints are given by the command payload:
+/// - `not_before`: the command must not be executed before this RFC 3339 timestamp
+/// - `not_after`: the command must be failed if not executed before this RFC 3339 timestamp
+/// - `maintenance_window`: the name of the maintenance window during which the command has to be executed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CommandSchedule {
+    pub not_before: Option<Timestamp>,
+    pub not_after: Option<Timestamp>,
+    pub maintenance_window: Option<String>,
+}
+
+/// What to do with a command given its schedule
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScheduleDecision {
+    /// The command can be executed right now
+    Proceed,
+
+    /// The command has to be held till the given time
+    Defer { until: Timestamp },
+
+    /// The command cannot be executed within its schedule and has to be failed
+    Missed { reason: String },
+}
+
+impl CommandSchedule {
+    /// Extract the schedule of a command from its payload
+    ///
+    /// Return `None` if the command can be executed right away.
+    pub fn from_command(command: &GenericCommandState) -> Result<Option<Self>, ScheduleError> {
+        let not_before = timestamp_field(&command.payload, "not_before")?;
+        let not_after = timestamp_field(&command.payload, "not_after")?;
+        let maintenance_window = command
+            .payload
+            .get("maintenance_window")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+
+        if not_before.is_none() && not_after.is_none() && maintenance_window.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(CommandSchedule {
+            not_before,
+            not_after,
+            maintenance_window,
+        }))
+    }
+
+    /// Decide if a command with this schedule can be executed at the given time
+    pub fn check(
+        &self,
+        now: Timestamp,
+        windows: &[MaintenanceWindow],
+    ) -> Result<ScheduleDecision, ScheduleError> {
+        let mut start = match self.not_before {
+            Some(not_before) if not_before > now => not_before,
+            _ => now,
+        };
+
+        if let Some(name) = &self.maintenance_window {
+            let window = windows
+                .iter()
+                .find(|window| &window.name == name)
+                .ok_or_else(|| ScheduleError::UnknownMaintenanceWindow { name: name.clone() })?;
+            let Some(opening) = window.next_opening(start) else {
+                return Ok(ScheduleDecision::Missed {
+                    reason: format!("The {name} maintenance window never opens"),
+                });
+            };
+            if let Some(not_after) = self.not_after {
+                if opening > not_after {
+                    return Ok(ScheduleDecision::Missed {
+                        reason: format!(
+                            "The {name} maintenance window doesn't open before the command deadline: {}",
+                            format_timestamp(not_after)
+                        ),
+                    });
+                }
+            }
+            start = start.max(opening);
+        }
+
+        if let Some(not_after) = self.not_after {
+            if start > not_after {
+                return Ok(ScheduleDecision::Missed {
+                    reason: format!(
+                        "The command has not been executed before its deadline: {}",
+                        format_timestamp(not_after)
+                    ),
+                });
+            }
+        }
+
+        if start > now {
+            Ok(ScheduleDecision::Defer { until: start })
+        } else {
+            Ok(ScheduleDecision::Proceed)
+        }
+    }
+}
+
+fn timestamp_field(payload: &Value, field: &str) -> Result<Option<Timestamp>, ScheduleError> {
+    match payload.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Timestamp::parse(value, &Rfc3339).map(Some).map_err(|_| {
+            ScheduleError::InvalidTimestamp {
+                field: field.to_string(),
+                value: value.to_string(),
+            }
+        }),
+        Some(value) => Err(ScheduleError::InvalidTimestamp {
+            field: field.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+fn format_timestamp(timestamp: Timestamp) -> String {
+    timestamp
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+/// A named and recurring time window during which commands can be executed
+///
+/// A maintenance window is defined by a name, a cron expression giving when the window opens,
+/// and the duration of the window, e.g. `nightly=0 2 * * * 3h` for a window opening every day at 2am for 3 hours.
+///
+/// The cron expression is made of 5 fields: minute, hour, day of the month, month and day of the week (0 being Sunday).
+/// Each field is either `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a comma-separated list of those.
+/// The duration is a number of seconds, or a number followed by a unit: `s`, `m`, `h` or `d`.
+///
+/// The window is evaluated in the time zone of the timestamps it is checked against.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    pub cron: CronExpression,
+    pub duration: Duration,
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = ScheduleError;
+
+    fn from_str(definition: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ScheduleError::InvalidMaintenanceWindow {
+            definition: definition.to_string(),
+            reason: reason.to_string(),
+        };
+        let (name, spec) = definition
+            .split_once('=')
+            .ok_or_else(|| invalid("Expecting `<name>=<cron expression> <duration>`"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(invalid("Missing name"));
+        }
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, day, month, weekday, duration] = fields[..] else {
+            return Err(invalid(
+                "Expecting 5 cron fields (minute, hour, day, month, weekday) followed by a duration",
+            ));
+        };
+        let cron = CronExpression {
+            minutes: CronField::parse(minute, 0, 59).map_err(|reason| invalid(&reason))?,
+            hours: CronField::parse(hour, 0, 23).map_err(|reason| invalid(&reason))?,
+            days: CronField::parse(day, 1, 31).map_err(|reason| invalid(&reason))?,
+            months: CronField::parse(month, 1, 12).map_err(|reason| invalid(&reason))?,
+            weekdays: CronField::parse(weekday, 0, 7)
+                .map_err(|reason| invalid(&reason))?
+                .with_sunday_as_0(),
+        };
+        let duration = parse_duration(duration).map_err(|reason| invalid(&reason))?;
+
+        Ok(MaintenanceWindow {
+            name: name.to_string(),
+            cron,
+            duration,
+        })
+    }
+}
+
+impl MaintenanceWindow {
+    /// Return the time from which this window is open, at or after the given time
+    ///
+    /// This is the given time itself if the window is currently open.
+    pub fn next_opening(&self, after: Timestamp) -> Option<Timestamp> {
+        // A window opened before `after` might still be open
+        let duration = time::Duration::try_from(self.duration).ok()?;
+        let from = after - duration + time::Duration::SECOND;
+        let start = self.cron.next_match(from)?;
+        Some(start.max(after))
+    }
+}
+
+/// When a recurring event occurs, as defined by a cron expression
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CronExpression {
+    minutes: CronField,
+    hours: CronField,
+    days: CronField,
+    months: CronField,
+    weekdays: CronField,
+}
+
+impl CronExpression {
+    /// Return the first minute matching this expression, at or after the given time
+    pub fn next_match(&self, from: Timestamp) -> Option<Timestamp> {
+        let offset = from.offset();
+        let mut date = from.date();
+        let (mut min_hour, mut min_minute) = (from.hour(), from.minute());
+        if from.second() != 0 || from.nanosecond() != 0 {
+            min_minute += 1;
+        }
+
+        for _ in 0..MAX_LOOK_AHEAD_DAYS {
+            if self.matches_date(date) {
+                for hour in self.hours.values.range(min_hour..) {
+                    let min_minute = if *hour == min_hour { min_minute } else { 0 };
+                    if let Some(minute) = self.minutes.values.range(min_minute..).next() {
+                        let time = Time::from_hms(*hour, *minute, 0).ok()?;
+                        return Some(PrimitiveDateTime::new(date, time).assume_offset(offset));
+                    }
+                }
+            }
+            date = date.next_day()?;
+            (min_hour, min_minute) = (0, 0);
+        }
+        None
+    }
+
+    fn matches_date(&self, date: Date) -> bool {
+        if !self.months.values.contains(&u8::from(date.month())) {
+            return false;
+        }
+        let day = self.days.values.contains(&date.day());
+        let weekday = self
+            .weekdays
+            .values
+            .contains(&date.weekday().number_days_from_sunday());
+
+        // As for cron, a day matches either field when both the day of the month and the day of the week are restricted
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+/// The values matched by a cron field
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CronField {
+    values: BTreeSet<u8>,
+    any: bool,
+}
+
+impl CronField {
+    /// Sunday can be given either as 0 or 7 in the day of the week field
+    fn with_sunday_as_0(mut self) -> Self {
+        if self.values.remove(&7) {
+            self.values.insert(0);
+        }
+        self
+    }
+
+    fn parse(field: &str, min: u8, max: u8) -> Result<Self, String> {
+        let invalid =
+            || format!("Invalid cron field `{field}`: expecting values between {min} and {max}");
+        let mut values = BTreeSet::new();
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u8>().map_err(|_| invalid())?),
+                None => (item, 1),
+            };
+            if step == 0 {
+                return Err(invalid());
+            }
+            let (first, last) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((first, last)) => (
+                        first.parse().map_err(|_| invalid())?,
+                        last.parse().map_err(|_| invalid())?,
+                    ),
+                    None => {
+                        let value = range.parse().map_err(|_| invalid())?;
+                        (value, if step > 1 { max } else { value })
+                    }
+                },
+            };
+            if first < min || last > max || first > last {
+                return Err(invalid());
+            }
+            values.extend((first..=last).step_by(step as usize));
+        }
+        Ok(CronField {
+            values,
+            any: field == "*",
+        })
+    }
+}
+
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration `{duration}`: expecting e.g. `3600`, `90m` or `3h`");
+    let (value, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "s"),
+    };
+    let value: u64 = value.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        "d" => value * 86400,
+        _ => return Err(invalid()),
+    };
+    if seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    fn nightly() -> MaintenanceWindow {
+        "nightly=0 2 * * * 3h".parse().unwrap()
+    }
+
+    fn command(payload: Value) -> GenericCommandState {
+        GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            status: "scheduled".to_string(),
+            payload,
+        }
+    }
+
+    #[test]
+    fn parse_maintenance_windows() {
+        let window: MaintenanceWindow = "weekend = 30 22 * * 6,0 90m".parse().unwrap();
+        assert_eq!(window.name, "weekend");
+        assert_eq!(window.duration, Duration::from_secs(5400));
+
+        let window: MaintenanceWindow = "often=*/15 8-18/2 1-7 * * 600".parse().unwrap();
+        assert_eq!(window.cron.minutes.values, BTreeSet::from([0, 15, 30, 45]));
+        assert_eq!(
+            window.cron.hours.values,
+            BTreeSet::from([8, 10, 12, 14, 16, 18])
+        );
+        assert_eq!(window.duration, Duration::from_secs(600));
+
+        assert!("0 2 * * * 3h".parse::<MaintenanceWindow>().is_err());
+        assert!("nightly=0 2 * * 3h".parse::<MaintenanceWindow>().is_err());
+        assert!("nightly=0 24 * * * 3h"
+            .parse::<MaintenanceWindow>()
+            .is_err());
+        assert!("nightly=0 2 * * * 3w".parse::<MaintenanceWindow>().is_err());
+    }
+
+    #[test]
+    fn find_the_next_opening_of_a_maintenance_window() {
+        let window = nightly();
+
+        // Before the window opens
+        assert_eq!(
+            window.next_opening(datetime!(2023-12-01 14:12:37 UTC)),
+            Some(datetime!(2023-12-02 02:00:00 UTC))
+        );
+
+        // While the window is open
+        assert_eq!(
+            window.next_opening(datetime!(2023-12-02 04:59:00 UTC)),
+            Some(datetime!(2023-12-02 04:59:00 UTC))
+        );
+
+        // When the window has just closed
+        assert_eq!(
+            window.next_opening(datetime!(2023-12-02 05:00:00 UTC)),
+            Some(datetime!(2023-12-03 02:00:00 UTC))
+        );
+
+        // On weekends only, 2023-12-02 being a Saturday
+        let window: MaintenanceWindow = "weekend=30 22 * * 6-7 1h".parse().unwrap();
+        assert_eq!(
+            window.next_opening(datetime!(2023-11-29 10:00:00 UTC)),
+            Some(datetime!(2023-12-02 22:30:00 UTC))
+        );
+    }
+
+    #[test]
+    fn commands_without_time_constraints_are_not_scheduled() {
+        let command = command(json!({"status": "scheduled", "url": "https://example.com"}));
+        assert_eq!(CommandSchedule::from_command(&command), Ok(None));
+    }
+
+    #[test]
+    fn schedule_a_command() {
+        let now = datetime!(2023-12-01 14:00:00 UTC);
+        let windows = vec![nightly()];
+        let schedule = |payload| {
+            CommandSchedule::from_command(&command(payload))
+                .unwrap()
+                .unwrap()
+                .check(now, &windows)
+                .unwrap()
+        };
+
+        assert_eq!(
+            schedule(json!({"not_before": "2023-12-01T12:00:00Z"})),
+            ScheduleDecision::Proceed
+        );
+        assert_eq!(
+            schedule(json!({"not_before": "2023-12-01T18:00:00+02:00"})),
+            ScheduleDecision::Defer {
+                until: datetime!(2023-12-01 16:00:00 UTC)
+            }
+        );
+        assert_eq!(
+            schedule(json!({"maintenance_window": "nightly", "not_after": "2023-12-02T06:00:00Z"})),
+            ScheduleDecision::Defer {
+                until: datetime!(2023-12-02 02:00:00 UTC)
+            }
+        );
+        assert_eq!(
+            schedule(json!({"not_after": "2023-12-01T13:00:00Z"})),
+            ScheduleDecision::Missed {
+                reason:
+                    "The command has not been executed before its deadline: 2023-12-01T13:00:00Z"
+                        .to_string()
+            }
+        );
+        assert_eq!(
+            schedule(json!({"maintenance_window": "nightly", "not_after": "2023-12-01T20:00:00Z"})),
+            ScheduleDecision::Missed {
+                reason: "The nightly maintenance window doesn't open before the command deadline: 2023-12-01T20:00:00Z"
+                    .to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        let now = datetime!(2023-12-01 14:00:00 UTC);
+        assert_eq!(
+            CommandSchedule::from_command(&command(json!({"not_before": "tomorrow"}))),
+            Err(ScheduleError::InvalidTimestamp {
+                field: "not_before".to_string(),
+                value: "tomorrow".to_string()
+            })
+        );
+        assert_eq!(
+            CommandSchedule::from_command(&command(json!({"maintenance_window": "weekly"})))
+                .unwrap()
+                .unwrap()
+                .check(now, &[nightly()]),
+            Err(ScheduleError::UnknownMaintenanceWindow {
+                name: "weekly".to_string()
+            })
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use crate::workflow::GenericCommandState;
 use crate::workflow::GenericStateUpdate;
 use crate::workflow::ScriptDefinitionError;
+use crate::workflow::StateName;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -67,6 +69,7 @@ pub struct ExitHandlers {
     on_exit: Vec<(u8, u8, GenericStateUpdate)>,
     on_stdout: Vec<String>,
     timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
 }
 
 impl ExitHandlers {
@@ -131,9 +134,14 @@ impl ExitHandlers {
             on_exit,
             on_stdout,
             timeout,
+            retry: None,
         })
     }
 
+    pub fn with_retry_policy(self, retry: Option<RetryPolicy>) -> Self {
+        ExitHandlers { retry, ..self }
+    }
+
     pub fn with_default(mut self, default: &DefaultHandlers) -> Self {
         if self.timeout.is_none() {
             self.timeout = default.timeout
@@ -161,7 +169,7 @@ impl ExitHandlers {
                 Some(0) => {
                     match (
                         &self.on_success,
-                        self.json_stdout_excerpt(program, output.stdout),
+                        json_stdout_excerpt(program, output.stdout),
                     ) {
                         (None, Err(reason)) => GenericStateUpdate::failed(reason).into_json(),
                         (None, Ok(dynamic_update)) => dynamic_update,
@@ -175,12 +183,10 @@ impl ExitHandlers {
                     None => self
                         .state_update_on_unknown_exit_code(program, code as u8)
                         .into_json(),
-                    Some(error_state) => {
-                        match self.json_stdout_excerpt(program, output.stdout).ok() {
-                            None => error_state.into_json(),
-                            Some(dynamic_update) => error_state.inject_into_json(dynamic_update),
-                        }
-                    }
+                    Some(error_state) => match json_stdout_excerpt(program, output.stdout).ok() {
+                        None => error_state.into_json(),
+                        Some(dynamic_update) => error_state.inject_into_json(dynamic_update),
+                    },
                 },
             },
             Err(err) => self.state_update_on_launch_error(program, err).into_json(),
@@ -214,23 +220,6 @@ impl ExitHandlers {
             .unwrap_or_else(GenericStateUpdate::successful)
     }
 
-    fn json_stdout_excerpt(&self, program: &str, stdout: Vec<u8>) -> Result<Value, String> {
-        match String::from_utf8(stdout) {
-            Err(_) => Err(format!("{program} returned no UTF8 stdout")),
-            Ok(content) => match extract_script_output(content) {
-                None => Err(format!(
-                    "{program} returned no :::tedge::: content on stdout"
-                )),
-                Some(excerpt) => match serde_json::from_str(&excerpt) {
-                    Ok(json) => Ok(json),
-                    Err(err) => Err(format!(
-                        "{program} returned non JSON content on stdout: {err}"
-                    )),
-                },
-            },
-        }
-    }
-
     fn state_update_on_launch_error(
         &self,
         program: &str,
@@ -257,11 +246,82 @@ impl ExitHandlers {
         self.timeout
     }
 
+    /// Return the delay after which a failed script has to be retried, if any
+    ///
+    /// A script is retried, while the number of attempts is below the `retries` limit, when:
+    /// - the script cannot be launched,
+    /// - the script is killed, notably on timeout,
+    /// - the script returns an error exit code that is not explicitly handled by an `on_exit` handler.
+    ///
+    /// The given attempt is the number of times the script has already been retried.
+    pub fn retry_delay(
+        &self,
+        outcome: &std::io::Result<std::process::Output>,
+        attempt: u32,
+    ) -> Option<Duration> {
+        let retry = self.retry.as_ref()?;
+        if attempt >= retry.retries {
+            return None;
+        }
+        let is_retryable = match outcome {
+            Err(_) => true,
+            Ok(output) => match output.status.code() {
+                None => true,
+                Some(0) => false,
+                Some(code) => self.state_update_on_error(code as u8).is_none(),
+            },
+        };
+        is_retryable.then(|| retry.delay_before(attempt + 1))
+    }
+
+    /// The states to which these handlers can move a command
+    pub fn next_states(&self) -> Vec<StateName> {
+        let mut states = vec![];
+        match &self.on_success {
+            Some(update) => states.push(update.status.clone()),
+            None if self.on_stdout.is_empty() => states.push("successful".to_string()),
+            None => states.extend(self.on_stdout.iter().cloned()),
+        }
+        states.extend(
+            self.on_exit
+                .iter()
+                .map(|(_, _, update)| update.status.clone()),
+        );
+        for handler in [&self.on_error, &self.on_kill] {
+            match handler {
+                Some(update) => states.push(update.status.clone()),
+                None => states.push("failed".to_string()),
+            }
+        }
+        states
+    }
+
     pub fn forceful_timeout_extension(&self) -> Option<Duration> {
-        self.timeout.map(|timeout| {
-            let extra = min(60, timeout.as_secs() / 20);
-            Duration::from_secs(extra)
-        })
+        self.timeout.map(forceful_timeout_extension)
+    }
+}
+
+/// The extra time given to a script to terminate after a SIGTERM, before being killed with a SIGKILL
+pub(crate) fn forceful_timeout_extension(graceful_timeout: Duration) -> Duration {
+    let extra = min(60, graceful_timeout.as_secs() / 20);
+    Duration::from_secs(extra)
+}
+
+/// Extract the json excerpt a script emits on its stdout between `:::begin-tedge:::` and `:::end-tedge:::`
+pub(crate) fn json_stdout_excerpt(program: &str, stdout: Vec<u8>) -> Result<Value, String> {
+    match String::from_utf8(stdout) {
+        Err(_) => Err(format!("{program} returned no UTF8 stdout")),
+        Ok(content) => match extract_script_output(content) {
+            None => Err(format!(
+                "{program} returned no :::tedge::: content on stdout"
+            )),
+            Some(excerpt) => match serde_json::from_str(&excerpt) {
+                Ok(json) => Ok(json),
+                Err(err) => Err(format!(
+                    "{program} returned non JSON content on stdout: {err}"
+                )),
+            },
+        },
     }
 }
 
@@ -274,6 +334,44 @@ fn extract_script_output(stdout: String) -> Option<String> {
     None
 }
 
+/// Define how a failing script is retried
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of retries
+    pub retries: u32,
+
+    /// The delay before the first retry
+    pub delay: Duration,
+
+    /// How the delay grows from one retry to the next
+    pub backoff: Backoff,
+}
+
+/// Define how the delay between two retries grows
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backoff {
+    /// The same delay is used for all the retries
+    #[default]
+    Constant,
+
+    /// The delay is doubled after each retry
+    Exponential,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry attempt, starting at 1
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Constant => self.delay,
+            Backoff::Exponential => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                self.delay.saturating_mul(factor)
+            }
+        }
+    }
+}
+
 /// Define how to handle a background script
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct BgExitHandlers {
@@ -288,6 +386,56 @@ impl BgExitHandlers {
     }
 }
 
+/// Define how to proceed once a sub-operation has completed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AwaitHandlers {
+    pub on_success: GenericStateUpdate,
+    pub on_error: Option<GenericStateUpdate>,
+}
+
+impl AwaitHandlers {
+    pub fn new(
+        on_success: Option<GenericStateUpdate>,
+        on_error: Option<GenericStateUpdate>,
+    ) -> Self {
+        AwaitHandlers {
+            on_success: on_success.unwrap_or_else(GenericStateUpdate::successful),
+            on_error,
+        }
+    }
+
+    /// The states to which these handlers can move a command
+    pub fn next_states(&self) -> Vec<StateName> {
+        let on_error = self
+            .on_error
+            .as_ref()
+            .map(|update| update.status.clone())
+            .unwrap_or_else(|| "failed".to_string());
+        vec![self.on_success.status.clone(), on_error]
+    }
+
+    /// The next state given the final state of the sub-operation
+    pub fn state_update(&self, sub_command: &GenericCommandState) -> GenericStateUpdate {
+        if sub_command.status == "successful" {
+            return self.on_success.clone();
+        }
+
+        let operation = sub_command.operation().unwrap_or_default();
+        let reason = match sub_command.failure_reason() {
+            None => format!("{operation} sub-operation failed"),
+            Some(reason) => format!("{operation} sub-operation failed: {reason}"),
+        };
+        match self.on_error.clone() {
+            None => GenericStateUpdate::failed(reason),
+            Some(update) if update.reason.is_some() => update,
+            Some(update) => GenericStateUpdate {
+                status: update.status,
+                reason: Some(reason),
+            },
+        }
+    }
+}
+
 /// Define default handlers for all state of an operation workflow
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct DefaultHandlers {
@@ -507,6 +655,62 @@ on_exit._ = "oops"
         )
     }
 
+    #[test]
+    fn failing_script_is_retried_with_backoff() {
+        let file = r#"
+script = "sh -c 'exit 1'"
+on_exit.2 = "not found"
+retries = 3
+retry_delay_second = 10
+backoff = "exponential"
+        "#;
+        let (script, handlers) = script_from_toml(file);
+
+        let output = script.output();
+        assert_eq!(
+            handlers.retry_delay(&output, 0),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            handlers.retry_delay(&output, 1),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            handlers.retry_delay(&output, 2),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(handlers.retry_delay(&output, 3), None);
+    }
+
+    #[test]
+    fn only_unhandled_errors_are_retried() {
+        let file = r#"
+script = "sh -c 'exit 2'"
+on_exit.2 = "not found"
+retries = 3
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert_eq!(handlers.retry_delay(&script.output(), 0), None);
+
+        let file = r#"
+script = "sh -c 'exit 0'"
+retries = 3
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert_eq!(handlers.retry_delay(&script.output(), 0), None);
+
+        let file = r#"
+script = "/no/such/script.sh"
+retries = 3
+retry_delay_second = 5
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert_eq!(
+            handlers.retry_delay(&script.output(), 0),
+            Some(Duration::from_secs(5))
+        );
+    }
+
     impl ShellScript {
         pub fn output(&self) -> std::io::Result<std::process::Output> {
             Command::new(self.command.clone())
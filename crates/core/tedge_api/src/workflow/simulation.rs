@@ -0,0 +1,281 @@
+use crate::workflow::GenericCommandState;
+use crate::workflow::GenericStateUpdate;
+use crate::workflow::OperationAction;
+use crate::workflow::OperationWorkflow;
+use crate::workflow::StateName;
+use crate::workflow::WorkflowExecutionError;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::process::Output;
+
+/// Walk a command through the states of a workflow, without executing any script nor builtin action
+///
+/// The outcome of each action is given by an exit code, which is 0 (i.e. success) unless scripted otherwise.
+pub struct WorkflowSimulation<'a> {
+    workflow: &'a OperationWorkflow,
+    exit_codes: HashMap<StateName, u8>,
+}
+
+/// A state transition simulated by a [WorkflowSimulation]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimulatedStep {
+    /// The state from which the command moves
+    pub state: StateName,
+
+    /// The action simulated on this state
+    pub action: OperationAction,
+
+    /// The update applied to the command
+    pub update: GenericStateUpdate,
+
+    /// The new command state
+    pub next_state: GenericCommandState,
+}
+
+impl<'a> WorkflowSimulation<'a> {
+    pub fn new(workflow: &'a OperationWorkflow) -> Self {
+        WorkflowSimulation {
+            workflow,
+            exit_codes: HashMap::new(),
+        }
+    }
+
+    /// Set the exit code of the action executed on the given state
+    pub fn with_exit_code(mut self, state: impl Into<StateName>, code: u8) -> Self {
+        self.exit_codes.insert(state.into(), code);
+        self
+    }
+
+    /// Simulate the transitions of a command from its given state
+    ///
+    /// The simulation stops when the command reaches a state that has to be cleared,
+    /// or after `max_steps` transitions, whichever comes first.
+    pub fn run(
+        &self,
+        mut state: GenericCommandState,
+        max_steps: usize,
+    ) -> Result<Vec<SimulatedStep>, WorkflowExecutionError> {
+        let mut steps = Vec::new();
+        while steps.len() < max_steps {
+            let Some(step) = self.step(&state)? else {
+                break;
+            };
+            state = step.next_state.clone();
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+
+    /// Simulate the transition of a command from its given state
+    ///
+    /// Return `None` if the command has been fully processed and has to be cleared.
+    pub fn step(
+        &self,
+        state: &GenericCommandState,
+    ) -> Result<Option<SimulatedStep>, WorkflowExecutionError> {
+        let action = self.workflow.get_action(state)?;
+        let code = self.exit_codes.get(&state.status).copied().unwrap_or(0);
+        let update = match &action {
+            OperationAction::Clear => return Ok(None),
+            OperationAction::MoveTo(next_state) => next_state.clone().into(),
+            OperationAction::BuiltIn if code != 0 => {
+                GenericStateUpdate::failed(format!("builtin action returned exit code {code}"))
+            }
+            OperationAction::BuiltIn if state.status == "scheduled" => {
+                "executing".to_string().into()
+            }
+            OperationAction::BuiltIn => GenericStateUpdate::successful(),
+            OperationAction::AwaitingAgentRestart {
+                on_success,
+                on_timeout,
+                ..
+            } => {
+                if code == 0 {
+                    on_success.clone()
+                } else {
+                    on_timeout.clone()
+                }
+            }
+            OperationAction::Restart {
+                on_exec, on_error, ..
+            } => {
+                if code == 0 {
+                    on_exec.clone().into()
+                } else {
+                    on_error.clone().into()
+                }
+            }
+            OperationAction::Script(script, handlers) => {
+                let attempt = state.retry_attempt();
+                if handlers
+                    .retry_delay(&Ok(simulated_output(code)), attempt)
+                    .is_some()
+                {
+                    return Ok(Some(SimulatedStep {
+                        state: state.status.clone(),
+                        update: state.status.clone().into(),
+                        next_state: state.clone().retry(attempt + 1),
+                        action,
+                    }));
+                }
+                handlers.state_update_on_exit(&script.command, code)
+            }
+            OperationAction::Parallel(scripts, handlers) => {
+                let outcomes = scripts
+                    .iter()
+                    .map(|script| (script.command.clone(), Ok(simulated_output(code))))
+                    .collect();
+                serde_json::from_value(handlers.state_update(outcomes))?
+            }
+            OperationAction::Condition(handlers) => handlers.state_update(state),
+            OperationAction::BgScript(_, handlers) | OperationAction::Operation(_, _, handlers) => {
+                handlers.on_exec.clone()
+            }
+            OperationAction::AwaitOperationCompletion(handlers, _) => {
+                if code == 0 {
+                    handlers.on_success.clone()
+                } else {
+                    handlers.on_error.clone().unwrap_or_else(|| {
+                        GenericStateUpdate::failed("sub-operation failed".to_string())
+                    })
+                }
+            }
+        };
+
+        Ok(Some(SimulatedStep {
+            state: state.status.clone(),
+            action,
+            next_state: state.clone().clear_retry().update(update.clone()),
+            update,
+        }))
+    }
+}
+
+fn simulated_output(code: u8) -> Output {
+    Output {
+        status: ExitStatus::from_raw((code as i32) << 8),
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    fn workflow() -> OperationWorkflow {
+        let file = r#"
+operation = "simulation-test"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+script = "/some/script.sh ${.payload.url}"
+on_success = "executing"
+on_exit.2 = { status = "failed", reason = "no such url" }
+
+[executing]
+action = "proceed"
+condition = [
+  { when = "${.payload.restart}", equals = true, then = "restart" },
+]
+on_success = "successful"
+
+[restart]
+background_script = "sudo reboot"
+on_exec = "successful"
+"#;
+        toml::from_str(file).unwrap()
+    }
+
+    fn init_state(payload: serde_json::Value) -> GenericCommandState {
+        GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/simulation-test/123"),
+            status: "init".to_string(),
+            payload,
+        }
+    }
+
+    fn statuses(steps: &[SimulatedStep]) -> Vec<&str> {
+        steps
+            .iter()
+            .map(|step| step.update.status.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn simulate_a_successful_command() {
+        let workflow = workflow();
+        let simulation = WorkflowSimulation::new(&workflow);
+
+        let steps = simulation
+            .run(init_state(json!({"status": "init", "restart": true})), 10)
+            .unwrap();
+        assert_eq!(
+            statuses(&steps),
+            vec!["scheduled", "executing", "restart", "successful"]
+        );
+
+        let steps = simulation
+            .run(init_state(json!({"status": "init"})), 10)
+            .unwrap();
+        assert_eq!(
+            statuses(&steps),
+            vec!["scheduled", "executing", "successful"]
+        );
+    }
+
+    #[test]
+    fn simulate_a_failing_command() {
+        let workflow = workflow();
+        let simulation = WorkflowSimulation::new(&workflow).with_exit_code("scheduled", 2);
+
+        let steps = simulation
+            .run(init_state(json!({"status": "init"})), 10)
+            .unwrap();
+        assert_eq!(statuses(&steps), vec!["scheduled", "failed"]);
+        assert_eq!(
+            steps.last().unwrap().next_state.failure_reason(),
+            Some("no such url".to_string())
+        );
+    }
+
+    #[test]
+    fn simulate_retries() {
+        let file = r#"
+operation = "simulation-test"
+
+[init]
+script = "/some/script.sh"
+retries = 2
+on_success = "successful"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        let simulation = WorkflowSimulation::new(&workflow).with_exit_code("init", 1);
+
+        let steps = simulation
+            .run(init_state(json!({"status": "init"})), 10)
+            .unwrap();
+        assert_eq!(statuses(&steps), vec!["init", "init", "failed"]);
+        assert_eq!(
+            steps.last().unwrap().next_state.payload,
+            json!({"status": "failed", "reason": "/some/script.sh returned exit code 1"})
+        );
+    }
+
+    #[test]
+    fn simulation_stops_after_max_steps() {
+        let workflow = workflow();
+        let simulation = WorkflowSimulation::new(&workflow);
+
+        let steps = simulation
+            .run(init_state(json!({"status": "init"})), 2)
+            .unwrap();
+        assert_eq!(statuses(&steps), vec!["scheduled", "executing"]);
+    }
+}
@@ -8,6 +8,13 @@ use serde::Serialize;
 use serde_json::json;
 use serde_json::Value;
 
+/// The command payload property used to count the retries of a state action
+const RETRY: &str = "@retry";
+
+/// The command payload property used to record the state of a command when its cancellation was requested
+const CANCELLED_STATE: &str = "cancelled_state";
+const QUEUED_STATE: &str = "queued_state";
+
 /// Generic command state that can be used to manipulate any type of command payload.
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct GenericCommandState {
@@ -16,6 +23,13 @@ pub struct GenericCommandState {
     pub payload: Value,
 }
 
+/// A json template used to build a json value from excerpts of a command state
+///
+/// All the string values of the form `${.x.y.z}` are substituted
+/// with the corresponding values extracted from the command state.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StateExcerpt(pub Value);
+
 /// Update for a command state
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct GenericStateUpdate {
@@ -105,11 +119,61 @@ impl GenericCommandState {
         }
     }
 
+    /// Update the command state to the cancelling status,
+    /// recording the state in which the command was when its cancellation was requested
+    pub fn cancel(mut self) -> Self {
+        let cancelled_state = self.status.clone();
+        GenericCommandState::inject_text_property(
+            &mut self.payload,
+            CANCELLED_STATE,
+            &cancelled_state,
+        );
+        self.move_to("cancelling".to_string())
+    }
+
+    /// Return the state in which the command was when its cancellation was requested, if any
+    pub fn cancelled_state(&self) -> Option<String> {
+        GenericCommandState::extract_text_property(&self.payload, CANCELLED_STATE)
+    }
+
     /// Return the error reason if any
     pub fn failure_reason(&self) -> Option<String> {
         GenericCommandState::extract_text_property(&self.payload, "reason")
     }
 
+    /// Return the number of times the action of the current state has already been retried
+    ///
+    /// This counter is persisted in the command payload, under the `@retry` property,
+    /// along the state to which it applies.
+    pub fn retry_attempt(&self) -> u32 {
+        self.payload
+            .get(RETRY)
+            .filter(|retry| retry.get("state").and_then(|v| v.as_str()) == Some(&self.status))
+            .and_then(|retry| retry.get("attempt"))
+            .and_then(|attempt| attempt.as_u64())
+            .map(|attempt| attempt as u32)
+            .unwrap_or(0)
+    }
+
+    /// Record a new attempt to execute the action of the current state
+    pub fn retry(mut self, attempt: u32) -> Self {
+        if let Some(o) = self.payload.as_object_mut() {
+            o.insert(
+                RETRY.to_string(),
+                json!({ "state": self.status, "attempt": attempt }),
+            );
+        }
+        self
+    }
+
+    /// Remove the retry counter, if any
+    pub fn clear_retry(mut self) -> Self {
+        if let Some(o) = self.payload.as_object_mut() {
+            o.remove(RETRY);
+        }
+        self
+    }
+
     /// Extract a text property from a Json object
     fn extract_text_property(json: &Value, property: &str) -> Option<String> {
         json.as_object()
@@ -149,22 +213,28 @@ impl GenericCommandState {
     }
 
     fn extract(&self, path: &str) -> Option<String> {
+        self.extract_value(path).map(|value| json_as_string(&value))
+    }
+
+    /// Extract the json value at the given path, if any
+    ///
+    /// The paths are the same as those used to inject parameters into a script,
+    /// but without the surrounding `${` and `}` markers: e.g. `.payload.x.y`.
+    pub fn extract_value(&self, path: &str) -> Option<Value> {
         match path {
-            "." => Some(
-                json!({
-                    "topic": self.topic.name,
-                    "payload": self.payload
-                })
-                .to_string(),
-            ),
-            ".topic" => Some(self.topic.name.clone()),
-            ".topic.target" => self.target(),
-            ".topic.operation" => self.operation(),
-            ".topic.cmd_id" => self.cmd_id(),
-            ".payload" => Some(self.payload.to_string()),
+            "." => Some(json!({
+                "topic": self.topic.name,
+                "payload": self.payload
+            })),
+            ".topic" => Some(self.topic.name.clone().into()),
+            ".topic.target" => self.target().map(|v| v.into()),
+            ".topic.operation" => self.operation().map(|v| v.into()),
+            ".topic.cmd_id" => self.cmd_id().map(|v| v.into()),
+            ".payload" => Some(self.payload.clone()),
             path => path
                 .strip_prefix(".payload.")
-                .and_then(|path| json_excerpt(&self.payload, path)),
+                .and_then(|path| json_excerpt(&self.payload, path))
+                .cloned(),
         }
     }
 
@@ -190,7 +260,69 @@ impl GenericCommandState {
     }
 
     pub fn is_terminal(&self) -> bool {
-        matches!(self.status.as_str(), "successful" | "failed")
+        matches!(self.status.as_str(), "successful" | "failed" | "cancelled")
+    }
+
+    /// Tell if the cancellation of the command has been requested
+    pub fn is_cancelling(&self) -> bool {
+        self.status == "cancelling"
+    }
+
+    /// Tell if the command has been cancelled or is being cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelling() || self.status == "cancelled" || self.cancelled_state().is_some()
+    }
+
+    /// Tell if the command is held in a queue, waiting for other commands to complete
+    pub fn is_queued(&self) -> bool {
+        self.status == "queued"
+    }
+
+    /// Move the command to the queued state, recording the state to be resumed once dequeued
+    pub fn queue(mut self) -> Self {
+        let queued_state = self.status.clone();
+        GenericCommandState::inject_text_property(&mut self.payload, QUEUED_STATE, &queued_state);
+        self.move_to("queued".to_string())
+    }
+
+    /// Move a queued command back to the state from which it has been queued
+    pub fn dequeue(mut self) -> Self {
+        let queued_state = GenericCommandState::extract_text_property(&self.payload, QUEUED_STATE)
+            .unwrap_or_else(|| "scheduled".to_string());
+        if let Some(properties) = self.payload.as_object_mut() {
+            properties.remove(QUEUED_STATE);
+        }
+        self.move_to(queued_state)
+    }
+}
+
+impl StateExcerpt {
+    /// Build a json value from this template and the given command state
+    pub fn extract_from(&self, state: &GenericCommandState) -> Value {
+        StateExcerpt::extract_value_from(&self.0, state)
+    }
+
+    fn extract_value_from(template: &Value, state: &GenericCommandState) -> Value {
+        match template {
+            Value::String(pattern) => pattern
+                .strip_prefix("${")
+                .and_then(|s| s.strip_suffix('}'))
+                .and_then(|path| state.extract_value(path))
+                .unwrap_or_else(|| template.clone()),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| StateExcerpt::extract_value_from(item, state))
+                    .collect(),
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), StateExcerpt::extract_value_from(v, state)))
+                    .collect(),
+            ),
+            _ => template.clone(),
+        }
     }
 }
 
@@ -263,10 +395,10 @@ impl From<GenericStateUpdate> for Value {
     }
 }
 
-fn json_excerpt(value: &Value, path: &str) -> Option<String> {
+fn json_excerpt<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
     match path.split_once('.') {
-        None if path.is_empty() => Some(json_as_string(value)),
-        None => value.get(path).map(json_as_string),
+        None if path.is_empty() => Some(value),
+        None => value.get(path),
         Some((key, path)) => value.get(key).and_then(|value| json_excerpt(value, path)),
     }
 }
@@ -287,6 +419,37 @@ mod tests {
     use mqtt_channel::Topic;
     use serde_json::json;
 
+    #[test]
+    fn retry_attempts_are_counted_per_state() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/make_it/123");
+        let payload = r#"{ "status":"download", "url": "https://example.com" }"#;
+        let command = mqtt_channel::Message::new(&topic, payload);
+        let cmd = GenericCommandState::from_command_message(&command)
+            .expect("parsing error")
+            .expect("no message");
+        assert_eq!(cmd.retry_attempt(), 0);
+
+        let cmd = cmd.retry(1).retry(2);
+        assert_eq!(cmd.retry_attempt(), 2);
+        assert_eq!(
+            cmd.payload,
+            json!({
+                "status": "download",
+                "url": "https://example.com",
+                "@retry": { "state": "download", "attempt": 2 }
+            })
+        );
+
+        let cmd = cmd.move_to("install".to_string());
+        assert_eq!(cmd.retry_attempt(), 0);
+
+        let cmd = cmd.clear_retry();
+        assert_eq!(
+            cmd.payload,
+            json!({ "status": "install", "url": "https://example.com" })
+        );
+    }
+
     #[test]
     fn serde_generic_command_payload() {
         let topic = Topic::new_unchecked("te/device/main///cmd/make_it/123");
@@ -402,6 +565,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extract_state_excerpt() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/make_it/123");
+        let payload = r#"{ "status":"init", "foo":42, "bar": { "extra": [1,2,3] }}"#;
+        let command = mqtt_channel::Message::new(&topic, payload);
+        let cmd = GenericCommandState::from_command_message(&command)
+            .expect("parsing error")
+            .expect("no message");
+
+        let excerpt = StateExcerpt(json!({
+            "x": "${.payload.foo}",
+            "y": "${.payload.bar.extra}",
+            "z": ["${.topic.cmd_id}", "${.payload.unknown}", "constant"],
+            "w": 12,
+        }));
+        assert_eq!(
+            excerpt.extract_from(&cmd),
+            json!({
+                "x": 42,
+                "y": [1,2,3],
+                "z": ["123", "${.payload.unknown}", "constant"],
+                "w": 12,
+            })
+        );
+    }
+
     trait JsonContent {
         fn to_json(self) -> Value;
     }
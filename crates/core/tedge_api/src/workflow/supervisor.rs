@@ -1,7 +1,9 @@
 use crate::workflow::*;
 use log::info;
+use mqtt_channel::Topic;
 use on_disk::OnDiskCommandBoard;
 use serde::Serialize;
+use std::collections::HashSet;
 
 /// Dispatch actions to operation participants
 #[derive(Default)]
@@ -11,6 +13,18 @@ pub struct WorkflowSupervisor {
 
     /// Operation instances under execution
     commands: CommandBoard,
+
+    /// The maintenance windows during which commands can be scheduled
+    maintenance_windows: Vec<MaintenanceWindow>,
+
+    /// The limits on the number of commands executed concurrently
+    concurrency_limits: ConcurrencyLimits,
+
+    /// The commands which execution has been started, given the concurrency limits
+    admitted_commands: HashSet<TopicName>,
+
+    /// The operations which capabilities are published by the actors executing the commands
+    delegated_operations: HashSet<OperationType>,
 }
 
 impl WorkflowSupervisor {
@@ -22,6 +36,19 @@ impl WorkflowSupervisor {
         self.register_custom_workflow(OperationWorkflow::built_in(operation))
     }
 
+    /// Register a builtin workflow for an operation executed by another actor
+    ///
+    /// The agent moves the commands to their `executing` state, given the schedule and concurrency limits,
+    /// and leaves the remaining steps to the actor that executes the commands and publishes the capability.
+    pub fn register_delegated_workflow(
+        &mut self,
+        operation: OperationType,
+    ) -> Result<(), WorkflowRegistrationError> {
+        self.register_builtin_workflow(operation.clone())?;
+        self.delegated_operations.insert(operation);
+        Ok(())
+    }
+
     /// Register a user-defined workflow
     pub fn register_custom_workflow(
         &mut self,
@@ -46,6 +73,114 @@ impl WorkflowSupervisor {
         Ok(())
     }
 
+    /// Check if a workflow is registered for the given operation
+    pub fn is_registered(&self, operation: &OperationType) -> bool {
+        self.workflows.contains_key(operation)
+    }
+
+    /// Tell if the execution history of a command has to be attached to its final state
+    pub fn is_history_attached(&self, command: &GenericCommandState) -> bool {
+        command
+            .operation()
+            .and_then(|operation| self.workflows.get(&operation.as_str().into()))
+            .map(|workflow| workflow.attach_history)
+            .unwrap_or(false)
+    }
+
+    /// Set the maintenance windows during which commands can be scheduled
+    pub fn set_maintenance_windows(&mut self, windows: Vec<MaintenanceWindow>) {
+        self.maintenance_windows = windows;
+    }
+
+    /// Tell if a command is in the state to which its workflow moves the new commands,
+    /// i.e. ready to be checked against its schedule and the concurrency limits
+    pub fn is_scheduled(&self, command: &GenericCommandState) -> bool {
+        command
+            .operation()
+            .and_then(|operation| self.workflows.get(&operation.as_str().into()))
+            .is_some_and(|workflow| workflow.scheduled_states().contains(&command.status))
+    }
+
+    /// Decide if a command can be executed at the given time, given the schedule set by its payload
+    pub fn check_schedule(
+        &self,
+        command: &GenericCommandState,
+        now: Timestamp,
+    ) -> Result<ScheduleDecision, ScheduleError> {
+        match CommandSchedule::from_command(command)? {
+            None => Ok(ScheduleDecision::Proceed),
+            Some(schedule) => schedule.check(now, &self.maintenance_windows),
+        }
+    }
+
+    /// Set the limits on the number of commands executed concurrently
+    pub fn set_concurrency_limits(&mut self, limits: ConcurrencyLimits) {
+        self.concurrency_limits = limits;
+    }
+
+    /// Check if the execution of a command can be started given the concurrency limits,
+    /// marking the command as running if so.
+    ///
+    /// Sub-commands are always admitted, as their parent commands are awaiting their completion.
+    pub fn try_admit_command(&mut self, command: &GenericCommandState) -> bool {
+        if self.admitted_commands.contains(&command.topic.name)
+            || self.parent_command_state(command).is_some()
+        {
+            return true;
+        }
+        let Some(operation) = command.operation() else {
+            return true;
+        };
+        let running = self
+            .commands
+            .iter()
+            .map(|(_, state)| state)
+            .filter(|state| state.topic.name != command.topic.name && self.is_running(state));
+        if self
+            .concurrency_limits
+            .can_start(&operation.as_str().into(), running)
+        {
+            self.admitted_commands.insert(command.topic.name.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return the next queued command that can be started given the concurrency limits
+    ///
+    /// The queued commands are considered in the order they have been queued.
+    /// The returned command is marked as running and moved back to the state from which it has been queued.
+    pub fn dequeue_command(&mut self) -> Option<GenericCommandState> {
+        let mut queued: Vec<&(Timestamp, GenericCommandState)> = self
+            .commands
+            .iter()
+            .filter(|(_, state)| state.is_queued())
+            .collect();
+        queued.sort_by_key(|(timestamp, _)| *timestamp);
+        let queued: Vec<GenericCommandState> =
+            queued.into_iter().map(|(_, state)| state.clone()).collect();
+
+        queued
+            .into_iter()
+            .find(|command| self.try_admit_command(command))
+            .map(GenericCommandState::dequeue)
+    }
+
+    /// Tell if a command is running, i.e. has been started and is not terminated
+    fn is_running(&self, command: &GenericCommandState) -> bool {
+        if command.is_terminal() || command.is_queued() {
+            return false;
+        }
+        self.admitted_commands.contains(&command.topic.name)
+            || (command.status != "init" && !self.is_scheduled(command))
+    }
+
+    /// Return the current state of a pending command, if any
+    pub fn command_state(&self, topic_name: &TopicName) -> Option<&GenericCommandState> {
+        self.commands.get(topic_name)
+    }
+
     /// The set of pending commands
     pub fn pending_commands(&self) -> &CommandBoard {
         &self.commands
@@ -59,7 +194,11 @@ impl WorkflowSupervisor {
     /// List the capabilities provided by the registered workflows
     pub fn capability_messages(&self, schema: &MqttSchema, target: &EntityTopicId) -> Vec<Message> {
         // To ease testing the capability messages are emitted in a deterministic order
-        let mut operations = self.workflows.values().collect::<Vec<_>>();
+        let mut operations = self
+            .workflows
+            .values()
+            .filter(|workflow| !self.delegated_operations.contains(&workflow.operation))
+            .collect::<Vec<_>>();
         operations.sort_by(|&a, &b| a.operation.to_string().cmp(&b.operation.to_string()));
         operations
             .iter()
@@ -84,6 +223,7 @@ impl WorkflowSupervisor {
             None => {
                 // The command has been cleared
                 self.commands.remove(&message.topic.name);
+                self.admitted_commands.remove(&message.topic.name);
                 Ok(None)
             }
             Some(command_state) if command_state.status == "init" => {
@@ -91,6 +231,21 @@ impl WorkflowSupervisor {
                 self.commands.insert(command_state.clone())?;
                 Ok(Some(command_state))
             }
+            Some(command_state)
+                if command_state.is_cancelling() && command_state.cancelled_state().is_none() =>
+            {
+                // This is a request to cancel a pending command,
+                // unless the command is already completed or cancelled.
+                let Some(current_state) = self.command_state(&message.topic.name) else {
+                    return Ok(None);
+                };
+                if current_state.is_terminal() || current_state.is_cancelled() {
+                    return Ok(None);
+                }
+                let cancelling_state = current_state.clone().cancel();
+                self.commands.update(cancelling_state.clone())?;
+                Ok(Some(cancelling_state))
+            }
             Some(_) => {
                 // Ignore command updates published over MQTT
                 //
@@ -132,6 +287,38 @@ impl WorkflowSupervisor {
         self.commands.update(new_command_state)
     }
 
+    /// Link a command to the sub-command it triggers
+    ///
+    /// The sub-command itself is added to the board when its init state is received over MQTT.
+    pub fn start_sub_command(
+        &mut self,
+        command: &GenericCommandState,
+        sub_command_topic: &Topic,
+    ) -> Result<(), WorkflowExecutionError> {
+        self.commands
+            .link_sub_command(&command.topic.name, &sub_command_topic.name)
+    }
+
+    /// Return the current state of the sub-command triggered by the given command, if any
+    pub fn sub_command_state(&self, command: &GenericCommandState) -> Option<&GenericCommandState> {
+        self.commands.sub_command_state(&command.topic.name)
+    }
+
+    /// Return the current state of the command that triggered the given sub-command, if any
+    pub fn parent_command_state(
+        &self,
+        sub_command: &GenericCommandState,
+    ) -> Option<&GenericCommandState> {
+        self.commands.parent_command_state(&sub_command.topic.name)
+    }
+
+    /// Remove from the board a sub-command that has been processed by its parent command
+    pub fn complete_sub_command(&mut self, sub_command: &GenericCommandState) {
+        self.commands.unlink_sub_command(&sub_command.topic.name);
+        self.commands.remove(&sub_command.topic.name);
+        self.admitted_commands.remove(&sub_command.topic.name);
+    }
+
     /// Resume the given command when the agent is restarting after an interruption
     pub fn resume_command(
         &self,
@@ -166,6 +353,9 @@ pub struct CommandBoard {
     /// TODO: use the timestamp to mark faulty any request making no progress
     #[serde(flatten)]
     commands: HashMap<TopicName, (Timestamp, GenericCommandState)>,
+
+    /// For each command that triggered a sub-command, the topic of this sub-command
+    sub_commands: HashMap<TopicName, TopicName>,
 }
 
 pub type TopicName = String;
@@ -173,7 +363,23 @@ pub type Timestamp = time::OffsetDateTime;
 
 impl CommandBoard {
     pub fn new(commands: HashMap<TopicName, (Timestamp, GenericCommandState)>) -> Self {
-        CommandBoard { commands }
+        CommandBoard {
+            commands,
+            sub_commands: HashMap::new(),
+        }
+    }
+
+    /// Build a board where some commands are awaiting the completion of sub-commands
+    pub fn with_sub_commands(self, sub_commands: HashMap<TopicName, TopicName>) -> Self {
+        CommandBoard {
+            sub_commands,
+            ..self
+        }
+    }
+
+    /// Iterate over the links from commands to the sub-commands they triggered
+    pub fn iter_sub_commands(&self) -> impl Iterator<Item = (&TopicName, &TopicName)> {
+        self.sub_commands.iter()
     }
 
     /// Iterate over the pending commands
@@ -181,6 +387,11 @@ impl CommandBoard {
         self.commands.values()
     }
 
+    /// Return the current state of an operation request
+    pub fn get(&self, topic_name: &TopicName) -> Option<&GenericCommandState> {
+        self.commands.get(topic_name).map(|(_, state)| state)
+    }
+
     /// Insert a new operation request into the [CommandBoard]
     ///
     /// Reject the request if there is already an entry with the same command id, but in a different state
@@ -224,5 +435,259 @@ impl CommandBoard {
     /// Remove from the board an operation request
     pub fn remove(&mut self, topic_name: &String) {
         self.commands.remove(topic_name);
+        self.sub_commands.remove(topic_name);
+    }
+
+    /// Link a command to the sub-command it triggers
+    ///
+    /// Reject the link if the command is unknown
+    pub fn link_sub_command(
+        &mut self,
+        topic_name: &TopicName,
+        sub_command_topic_name: &TopicName,
+    ) -> Result<(), WorkflowExecutionError> {
+        if !self.commands.contains_key(topic_name) {
+            return Err(WorkflowExecutionError::UnknownRequest {
+                topic: topic_name.clone(),
+            });
+        }
+        self.sub_commands
+            .insert(topic_name.clone(), sub_command_topic_name.clone());
+        Ok(())
+    }
+
+    /// Remove the link from a command to the given sub-command
+    pub fn unlink_sub_command(&mut self, sub_command_topic_name: &TopicName) {
+        self.sub_commands
+            .retain(|_, sub_command| sub_command != sub_command_topic_name);
+    }
+
+    /// Return the current state of the sub-command triggered by the given command, if any
+    pub fn sub_command_state(&self, topic_name: &TopicName) -> Option<&GenericCommandState> {
+        self.sub_commands
+            .get(topic_name)
+            .and_then(|sub_command| self.commands.get(sub_command))
+            .map(|(_, state)| state)
+    }
+
+    /// Return the current state of the command that triggered the given sub-command, if any
+    pub fn parent_command_state(
+        &self,
+        sub_command_topic_name: &TopicName,
+    ) -> Option<&GenericCommandState> {
+        self.sub_commands
+            .iter()
+            .find(|(_, sub_command)| *sub_command == sub_command_topic_name)
+            .and_then(|(command, _)| self.commands.get(command))
+            .map(|(_, state)| state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sub_command_links_are_persisted() {
+        let command = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            status: "awaiting_install".to_string(),
+            payload: json!({ "status": "awaiting_install" }),
+        };
+        let sub_command = GenericCommandState {
+            topic: Topic::new_unchecked(
+                "te/device/main///cmd/software_update/sub-firmware_update-123",
+            ),
+            status: "init".to_string(),
+            payload: json!({ "status": "init" }),
+        };
+
+        let mut board = CommandBoard::default();
+        assert!(board
+            .link_sub_command(&command.topic.name, &sub_command.topic.name)
+            .is_err());
+
+        board.insert(command.clone()).unwrap();
+        board
+            .link_sub_command(&command.topic.name, &sub_command.topic.name)
+            .unwrap();
+        assert_eq!(board.sub_command_state(&command.topic.name), None);
+
+        board.insert(sub_command.clone()).unwrap();
+        assert_eq!(
+            board.sub_command_state(&command.topic.name),
+            Some(&sub_command)
+        );
+        assert_eq!(
+            board.parent_command_state(&sub_command.topic.name),
+            Some(&command)
+        );
+
+        let persisted = serde_json::to_string(&board).unwrap();
+        let reloaded: CommandBoard = serde_json::from_str(&persisted).unwrap();
+        assert_eq!(
+            reloaded.parent_command_state(&sub_command.topic.name),
+            Some(&command)
+        );
+
+        board.unlink_sub_command(&sub_command.topic.name);
+        assert_eq!(board.parent_command_state(&sub_command.topic.name), None);
+    }
+
+    #[test]
+    fn queued_commands_are_started_when_running_commands_complete() {
+        let command = |cmd_id: &str, status: &str| GenericCommandState {
+            topic: Topic::new_unchecked(&format!("te/device/main///cmd/software_update/{cmd_id}")),
+            status: status.to_string(),
+            payload: json!({ "status": status }),
+        };
+        let mut board = CommandBoard::default();
+        board.insert(command("1", "scheduled")).unwrap();
+        board.insert(command("2", "scheduled")).unwrap();
+
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_builtin_workflow(OperationType::SoftwareUpdate)
+            .unwrap();
+        supervisor.load_pending_commands(board);
+        supervisor.set_concurrency_limits(
+            ConcurrencyLimits::default()
+                .with_operation_limit("software_update=1")
+                .unwrap(),
+        );
+
+        assert!(supervisor.try_admit_command(&command("1", "scheduled")));
+        assert!(!supervisor.try_admit_command(&command("2", "scheduled")));
+        supervisor
+            .apply_internal_update(command("2", "scheduled").queue())
+            .unwrap();
+        supervisor
+            .apply_internal_update(command("1", "executing"))
+            .unwrap();
+        assert_eq!(supervisor.dequeue_command(), None);
+
+        supervisor
+            .apply_internal_update(command("1", "successful"))
+            .unwrap();
+        assert_eq!(
+            supervisor.dequeue_command(),
+            Some(command("2", "scheduled"))
+        );
+        assert!(supervisor.try_admit_command(&command("2", "scheduled")));
+    }
+
+    #[test]
+    fn queued_commands_are_resumed_in_the_state_following_init() {
+        let command = |cmd_id: &str, status: &str| GenericCommandState {
+            topic: Topic::new_unchecked(&format!("te/device/main///cmd/custom/{cmd_id}")),
+            status: status.to_string(),
+            payload: json!({ "status": status }),
+        };
+        let states = [
+            ("init", OperationAction::MoveTo("waiting".to_string())),
+            ("waiting", OperationAction::MoveTo("successful".to_string())),
+        ]
+        .into_iter()
+        .map(|(state, action)| (state.to_string(), action))
+        .collect();
+        let workflow =
+            OperationWorkflow::try_new("custom".into(), DefaultHandlers::default(), states)
+                .unwrap();
+
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor.register_custom_workflow(workflow).unwrap();
+        supervisor.set_concurrency_limits(
+            ConcurrencyLimits::default()
+                .with_operation_limit("custom=1")
+                .unwrap(),
+        );
+        assert!(supervisor.is_scheduled(&command("1", "waiting")));
+        assert!(!supervisor.is_scheduled(&command("1", "init")));
+
+        let mut board = CommandBoard::default();
+        board.insert(command("1", "waiting")).unwrap();
+        board.insert(command("2", "waiting")).unwrap();
+        supervisor.load_pending_commands(board);
+
+        assert!(supervisor.try_admit_command(&command("1", "waiting")));
+        assert!(!supervisor.try_admit_command(&command("2", "waiting")));
+        supervisor
+            .apply_internal_update(command("2", "waiting").queue())
+            .unwrap();
+
+        supervisor
+            .apply_internal_update(command("1", "successful"))
+            .unwrap();
+        assert_eq!(supervisor.dequeue_command(), Some(command("2", "waiting")));
+    }
+
+    #[test]
+    fn cancellation_requests_are_only_applied_to_pending_commands() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/1");
+        let request = |payload: serde_json::Value| Message::new(&topic, payload.to_string());
+
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_builtin_workflow(OperationType::SoftwareUpdate)
+            .unwrap();
+        let operation = OperationType::SoftwareUpdate;
+
+        // Unknown commands cannot be cancelled
+        let cancel = request(json!({ "status": "cancelling" }));
+        assert_eq!(
+            supervisor
+                .apply_external_update(&operation, &cancel)
+                .unwrap(),
+            None
+        );
+
+        let init = request(json!({ "status": "init", "modules": [] }));
+        supervisor.apply_external_update(&operation, &init).unwrap();
+        let cancelling = supervisor
+            .apply_external_update(&operation, &cancel)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cancelling.status, "cancelling");
+        assert_eq!(cancelling.cancelled_state(), Some("init".to_string()));
+        assert_eq!(
+            cancelling.payload,
+            json!({ "status": "cancelling", "cancelled_state": "init", "modules": [] })
+        );
+
+        // The cancelling state published by the agent is not a new request
+        let echo = cancelling.clone().into_message();
+        assert_eq!(
+            supervisor.apply_external_update(&operation, &echo).unwrap(),
+            None
+        );
+
+        // A command cannot be cancelled twice
+        assert_eq!(
+            supervisor
+                .apply_external_update(&operation, &cancel)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn capabilities_of_delegated_operations_are_left_to_their_actors() {
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_builtin_workflow(OperationType::SoftwareUpdate)
+            .unwrap();
+        supervisor
+            .register_delegated_workflow(OperationType::ConfigUpdate)
+            .unwrap();
+        assert!(supervisor.is_registered(&OperationType::ConfigUpdate));
+
+        let schema = MqttSchema::default();
+        let capabilities: Vec<String> = supervisor
+            .capability_messages(&schema, &EntityTopicId::default_main_device())
+            .into_iter()
+            .map(|message| message.topic.name)
+            .collect();
+        assert_eq!(capabilities, vec!["te/device/main///cmd/software_update"]);
     }
 }
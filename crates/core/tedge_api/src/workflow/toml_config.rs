@@ -1,18 +1,28 @@
 use crate::mqtt_topics::OperationType;
+use crate::workflow::AwaitHandlers;
+use crate::workflow::Backoff;
 use crate::workflow::BgExitHandlers;
+use crate::workflow::ConditionHandlers;
 use crate::workflow::DefaultHandlers;
 use crate::workflow::ExitHandlers;
 use crate::workflow::GenericStateUpdate;
+use crate::workflow::JoinHandlers;
+use crate::workflow::JoinPolicy;
 use crate::workflow::OperationAction;
 use crate::workflow::OperationWorkflow;
+use crate::workflow::RetryPolicy;
 use crate::workflow::ScriptDefinitionError;
 use crate::workflow::ShellScript;
+use crate::workflow::StateCondition;
+use crate::workflow::StateExcerpt;
+use crate::workflow::StatePredicate;
 use crate::workflow::WorkflowDefinitionError;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
 use serde::Serializer;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
@@ -29,6 +39,14 @@ pub struct TomlOperationWorkflow {
     /// The operation to which this workflow applies
     pub operation: OperationType,
 
+    /// Attach the execution history of a command to its final state
+    #[serde(default)]
+    pub attach_history: bool,
+
+    /// The state to move to when the cancellation of a command is requested
+    #[serde(default)]
+    pub on_cancel: Option<String>,
+
     /// Default handlers used to determine the next state from an action outcome
     #[serde(flatten)]
     pub handlers: TomlExitHandlers,
@@ -45,6 +63,22 @@ pub struct TomlOperationState {
     #[serde(default, flatten)]
     pub action: TomlOperationAction,
 
+    /// Conditions used to select the next state from the command payload
+    #[serde(default)]
+    pub condition: Vec<TomlCondition>,
+
+    /// How the outcomes of parallel scripts are joined
+    #[serde(default)]
+    pub join: Option<JoinPolicy>,
+
+    /// The init state of a sub-operation, built from excerpts of the command state
+    #[serde(default)]
+    pub input: Option<serde_json::Map<String, Value>>,
+
+    /// The excerpt of a sub-operation final state to be injected into the command state
+    #[serde(default)]
+    pub output: Option<serde_json::Map<String, Value>>,
+
     /// Handlers used to determine the next state from the action outcome
     #[serde(flatten)]
     pub handlers: TomlExitHandlers,
@@ -56,6 +90,8 @@ pub struct TomlOperationState {
 pub enum TomlOperationAction {
     Script(ShellScript),
     BackgroundScript(ShellScript),
+    Parallel(Vec<ShellScript>),
+    Operation(OperationType),
     Action(String),
 }
 
@@ -90,10 +126,91 @@ impl From<TomlStateUpdate> for GenericStateUpdate {
     }
 }
 
+/// User-friendly representation of a [StateCondition] and the associated next state, eg.
+/// `{ when = "${.payload.target}", equals = "child", then = "<status>" }`
+///
+/// Exactly one predicate among `equals`, `not_equals`, `one_of` and `exists` must be provided.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct TomlCondition {
+    /// The path to the value to be checked, e.g. `${.payload.x.y}`
+    when: String,
+
+    equals: Option<Value>,
+
+    not_equals: Option<Value>,
+
+    one_of: Option<Vec<Value>>,
+
+    exists: Option<bool>,
+
+    /// The next state when the condition is satisfied
+    then: TomlStateUpdate,
+}
+
+impl TryFrom<TomlCondition> for (StateCondition, GenericStateUpdate) {
+    type Error = WorkflowDefinitionError;
+
+    fn try_from(input: TomlCondition) -> Result<Self, Self::Error> {
+        let invalid_condition = |reason: &str| WorkflowDefinitionError::InvalidCondition {
+            path: input.when.clone(),
+            reason: reason.to_string(),
+        };
+
+        let path = input
+            .when
+            .strip_prefix("${")
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or_else(|| invalid_condition("expecting a path as ${.payload.x.y}"))?
+            .to_string();
+
+        let mut predicates = vec![];
+        if let Some(value) = input.equals {
+            predicates.push(StatePredicate::Equals(value))
+        }
+        if let Some(value) = input.not_equals {
+            predicates.push(StatePredicate::NotEquals(value))
+        }
+        if let Some(values) = input.one_of {
+            predicates.push(StatePredicate::OneOf(values))
+        }
+        if let Some(value) = input.exists {
+            predicates.push(StatePredicate::Exists(value))
+        }
+        if predicates.len() != 1 {
+            return Err(invalid_condition(
+                "expecting one and only one of equals, not_equals, one_of or exists",
+            ));
+        }
+        let predicate = predicates.remove(0);
+
+        Ok((StateCondition { path, predicate }, input.then.into()))
+    }
+}
+
 impl TryFrom<TomlOperationState> for OperationAction {
     type Error = WorkflowDefinitionError;
 
     fn try_from(input: TomlOperationState) -> Result<Self, Self::Error> {
+        if let Some(condition) = input.condition.first() {
+            if !matches!(&input.action, TomlOperationAction::Action(action) if action == "proceed")
+            {
+                return Err(WorkflowDefinitionError::InvalidCondition {
+                    path: condition.when.clone(),
+                    reason: "conditions can only be attached to a proceed action".to_string(),
+                });
+            }
+        }
+
+        if input.join.is_some() && !matches!(&input.action, TomlOperationAction::Parallel(_)) {
+            return Err(ScriptDefinitionError::JoinWithoutParallelScripts.into());
+        }
+
+        if input.handlers.has_retry_policy()
+            && !matches!(&input.action, TomlOperationAction::Script(_))
+        {
+            return Err(ScriptDefinitionError::RetryWithoutScript.into());
+        }
+
         match input.action {
             TomlOperationAction::Script(script) => {
                 let handlers = TryInto::<ExitHandlers>::try_into(input.handlers)?;
@@ -103,6 +220,23 @@ impl TryFrom<TomlOperationState> for OperationAction {
                 let handlers = TryInto::<BgExitHandlers>::try_into(input.handlers)?;
                 Ok(OperationAction::BgScript(script, handlers))
             }
+            TomlOperationAction::Parallel(scripts) => {
+                if scripts.is_empty() {
+                    return Err(ScriptDefinitionError::NoParallelScripts.into());
+                }
+                let handlers = JoinHandlers::new(
+                    input.join.unwrap_or_default(),
+                    input.handlers.on_success.map(|u| u.into()),
+                    input.handlers.on_error.map(|u| u.into()),
+                    input.handlers.timeout_second.map(Duration::from_secs),
+                );
+                Ok(OperationAction::Parallel(scripts, handlers))
+            }
+            TomlOperationAction::Operation(operation) => {
+                let sub_input = StateExcerpt(Value::Object(input.input.unwrap_or_default()));
+                let handlers = TryInto::<BgExitHandlers>::try_into(input.handlers)?;
+                Ok(OperationAction::Operation(operation, sub_input, handlers))
+            }
             TomlOperationAction::Action(command) => match command.as_str() {
                 "builtin" => Ok(OperationAction::BuiltIn),
                 "cleanup" => Ok(OperationAction::Clear),
@@ -112,7 +246,18 @@ impl TryFrom<TomlOperationState> for OperationAction {
                         .on_success
                         .map(|u| u.into())
                         .unwrap_or_else(|| "successful".to_string().into());
-                    Ok(OperationAction::MoveTo(on_success.status))
+                    if input.condition.is_empty() {
+                        return Ok(OperationAction::MoveTo(on_success.status));
+                    }
+
+                    let conditions = input
+                        .condition
+                        .into_iter()
+                        .map(TryInto::<(StateCondition, GenericStateUpdate)>::try_into)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(OperationAction::Condition(ConditionHandlers::new(
+                        conditions, on_success,
+                    )))
                 }
                 "restart" => {
                     // TODO impl a clean From<TomlExitHandlers> implementation
@@ -156,6 +301,14 @@ impl TryFrom<TomlOperationState> for OperationAction {
                         on_timeout,
                     })
                 }
+                "await-operation-completion" => {
+                    let handlers = AwaitHandlers::new(
+                        input.handlers.on_success.map(|u| u.into()),
+                        input.handlers.on_error.map(|u| u.into()),
+                    );
+                    let output = StateExcerpt(Value::Object(input.output.unwrap_or_default()));
+                    Ok(OperationAction::AwaitOperationCompletion(handlers, output))
+                }
                 _ => Err(WorkflowDefinitionError::UnknownAction { action: command }),
             },
         }
@@ -173,8 +326,17 @@ impl TryFrom<TomlOperationWorkflow> for OperationWorkflow {
             let action = TryInto::<OperationAction>::try_into(action_spec)?;
             states.insert(state, action.with_default(&default_handlers));
         }
+        if let Some(on_cancel) = input.on_cancel {
+            states
+                .entry("cancelling".to_string())
+                .or_insert(OperationAction::MoveTo(on_cancel));
+        }
 
-        OperationWorkflow::try_new(operation, default_handlers, states)
+        let workflow = OperationWorkflow::try_new(operation, default_handlers, states)?;
+        Ok(OperationWorkflow {
+            attach_history: input.attach_history,
+            ..workflow
+        })
     }
 }
 
@@ -217,6 +379,22 @@ pub struct TomlExitHandlers {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     on_exec: Option<TomlStateUpdate>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retries: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_delay_second: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backoff: Option<Backoff>,
+}
+
+impl TomlExitHandlers {
+    /// Tell if any of the `retries`, `retry_delay_second` or `backoff` properties is set
+    fn has_retry_policy(&self) -> bool {
+        self.retries.is_some() || self.retry_delay_second.is_some() || self.backoff.is_some()
+    }
 }
 
 impl TryFrom<TomlExitHandlers> for ExitHandlers {
@@ -244,10 +422,20 @@ impl TryFrom<TomlExitHandlers> for ExitHandlers {
             })
             .collect();
         let timeout = value.timeout_second.map(Duration::from_secs);
+        let retry = match (value.retries, value.retry_delay_second, value.backoff) {
+            (None, None, None) => None,
+            (None, _, _) => return Err(ScriptDefinitionError::MissingRetries),
+            (Some(retries), delay, backoff) => Some(RetryPolicy {
+                retries,
+                delay: Duration::from_secs(delay.unwrap_or_default()),
+                backoff: backoff.unwrap_or_default(),
+            }),
+        };
 
-        ExitHandlers::try_new(
+        Ok(ExitHandlers::try_new(
             on_exit, on_success, on_error, on_kill, on_stdout, wildcard, timeout,
-        )
+        )?
+        .with_retry_policy(retry))
     }
 }
 
@@ -340,6 +528,7 @@ impl FromStr for ExitCodes {
 mod tests {
     use super::*;
     use crate::workflow::GenericStateUpdate;
+    use test_case::test_case;
     use ExitCodes::*;
 
     #[test]
@@ -383,6 +572,9 @@ on_kill = { status = "failed", reason = "killed"}         # next status when kil
                 on_timeout: None,
                 on_stdout: Vec::new(),
                 on_exec: None,
+                retries: None,
+                retry_delay_second: None,
+                backoff: None,
             }
         )
     }
@@ -534,6 +726,206 @@ on_error = "failed_reboot"
         );
     }
 
+    #[test]
+    fn parse_conditional_state() {
+        let file = r#"
+action = "proceed"
+condition = [
+  { when = "${.payload.target}", equals = "child", then = "child-update" },
+  { when = "${.payload.url}", exists = false, then = { status = "failed", reason = "no url" } },
+]
+on_success = "main-update"
+"#;
+        let action: OperationAction = toml::from_str(file).unwrap();
+        let OperationAction::Condition(handlers) = action else {
+            panic!("Expect a condition, got: {action:?}");
+        };
+        assert_eq!(
+            handlers.conditions,
+            vec![
+                (
+                    StateCondition {
+                        path: ".payload.target".to_string(),
+                        predicate: StatePredicate::Equals("child".into()),
+                    },
+                    "child-update".to_string().into()
+                ),
+                (
+                    StateCondition {
+                        path: ".payload.url".to_string(),
+                        predicate: StatePredicate::Exists(false),
+                    },
+                    GenericStateUpdate::failed("no url".to_string())
+                ),
+            ]
+        );
+        assert_eq!(handlers.otherwise.status, "main-update");
+    }
+
+    #[test]
+    fn reject_ill_formed_condition() {
+        let file = r#"
+action = "proceed"
+condition = [
+  { when = "${.payload.target}", equals = "child", exists = true, then = "child-update" },
+]
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert!(matches!(
+            error,
+            WorkflowDefinitionError::InvalidCondition { .. }
+        ));
+
+        let file = r#"
+action = "proceed"
+condition = [
+  { when = ".payload.target", equals = "child", then = "child-update" },
+]
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert!(matches!(
+            error,
+            WorkflowDefinitionError::InvalidCondition { .. }
+        ));
+    }
+
+    #[test]
+    fn reject_condition_on_script() {
+        let file = r#"
+script = "/some/script.sh"
+condition = [
+  { when = "${.payload.target}", equals = "child", then = "child-update" },
+]
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert!(matches!(
+            error,
+            WorkflowDefinitionError::InvalidCondition { .. }
+        ));
+    }
+
+    #[test]
+    fn parse_parallel_state() {
+        let file = r#"
+parallel = [
+  "/some/download.sh child-1 ${.payload.url}",
+  "/some/download.sh child-2 ${.payload.url}",
+]
+join = "any"
+on_success = "downloaded"
+on_error = "download-failed"
+"#;
+        let action: OperationAction = toml::from_str(file).unwrap();
+        let OperationAction::Parallel(scripts, handlers) = action else {
+            panic!("Expect parallel scripts, got: {action:?}");
+        };
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[1].args, vec!["child-2", "${.payload.url}"]);
+        assert_eq!(handlers.join, JoinPolicy::Any);
+    }
+
+    #[test]
+    fn reject_empty_parallel_state() {
+        let file = r#"
+parallel = []
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::ScriptDefinitionError(
+                ScriptDefinitionError::NoParallelScripts
+            )
+        );
+    }
+
+    #[test]
+    fn reject_join_on_non_parallel_state() {
+        let file = r#"
+script = "/some/script.sh"
+join = "all"
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::ScriptDefinitionError(
+                ScriptDefinitionError::JoinWithoutParallelScripts
+            )
+        );
+    }
+
+    #[test_case(r#"background_script = "/some/script.sh""#)]
+    #[test_case(r#"parallel = ["/some/script.sh", "/some/other/script.sh"]"#)]
+    #[test_case(r#"operation = "firmware_update""#)]
+    fn reject_retries_on_non_script_state(action: &str) {
+        let file = format!(
+            r#"
+{action}
+retries = 3
+retry_delay_second = 10
+"#
+        );
+        let input: TomlOperationState = toml::from_str(&file).unwrap();
+        let error = OperationAction::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::ScriptDefinitionError(
+                ScriptDefinitionError::RetryWithoutScript
+            )
+        );
+    }
+
+    #[test]
+    fn parse_sub_operation_states() {
+        let file = r#"
+operation = "firmware_update"
+
+[init]
+action = "proceed"
+on_success = "install"
+
+[install]
+operation = "software_update"
+input.updateList = "${.payload.updateList}"
+on_exec = "awaiting_install"
+
+[awaiting_install]
+action = "await-operation-completion"
+output.installed = "${.payload.updateList}"
+on_success = "successful"
+on_error = { status = "failed", reason = "fail to install" }
+"#;
+        let input: TomlOperationWorkflow = toml::from_str(file).unwrap();
+        let workflow = OperationWorkflow::try_from(input).unwrap();
+
+        let OperationAction::Operation(operation, input, handlers) =
+            workflow.states.get("install").unwrap()
+        else {
+            panic!("Expect a sub-operation")
+        };
+        assert_eq!(operation, &OperationType::SoftwareUpdate);
+        assert_eq!(
+            input,
+            &StateExcerpt(serde_json::json!({"updateList": "${.payload.updateList}"}))
+        );
+        assert_eq!(handlers.on_exec.status, "awaiting_install");
+
+        let OperationAction::AwaitOperationCompletion(handlers, output) =
+            workflow.states.get("awaiting_install").unwrap()
+        else {
+            panic!("Expect an await-operation-completion action")
+        };
+        assert_eq!(
+            output,
+            &StateExcerpt(serde_json::json!({"installed": "${.payload.updateList}"}))
+        );
+        assert_eq!(handlers.on_success.status, "successful");
+    }
+
     #[test]
     fn reject_script_on_the_failed_state() {
         let file = r#"
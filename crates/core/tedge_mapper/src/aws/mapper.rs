@@ -1,5 +1,10 @@
+use crate::core::aggregator::AggregatorConfig;
+use crate::core::aggregator::AggregatorConfigError;
+use crate::core::aggregator::MeasurementAggregatorBuilder;
 use crate::core::component::TEdgeComponent;
 use crate::core::mapper::start_basic_actors;
+use crate::core::telemetry_buffer::TelemetryBuffer;
+use crate::core::telemetry_buffer::TelemetryBufferConfig;
 use async_trait::async_trait;
 use aws_mapper_ext::converter::AwsConverter;
 use clock::WallClock;
@@ -44,8 +49,23 @@ impl TEdgeComponent for AwsMapper {
             get_topic_filter(&tedge_config),
         );
 
-        aws_converting_actor.add_input(&mut mqtt_actor);
-        aws_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        if let Some(aggregator_config) = aggregator_config(&tedge_config)? {
+            // The measurements are aggregated before being sent to the converter
+            let mut aggregator_actor = MeasurementAggregatorBuilder::new(aggregator_config);
+            aws_converting_actor.add_input(&mut aggregator_actor.with_mqtt(&mut mqtt_actor));
+            runtime.spawn(aggregator_actor).await?;
+        } else {
+            aws_converting_actor.add_input(&mut mqtt_actor);
+        }
+        if tedge_config.aws.bridge.buffer.enable {
+            let buffer_config = telemetry_buffer_config(&tedge_config);
+            let buffer_actor =
+                TelemetryBuffer::builder(buffer_config, Box::new(WallClock), &mut mqtt_actor)?;
+            aws_converting_actor.register_peer(NoConfig, buffer_actor.get_sender());
+            runtime.spawn(buffer_actor).await?;
+        } else {
+            aws_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        }
 
         runtime.spawn(aws_converting_actor).await?;
         runtime.spawn(mqtt_actor).await?;
@@ -63,3 +83,35 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
     }
     topics
 }
+
+fn aggregator_config(
+    tedge_config: &TEdgeConfig,
+) -> Result<Option<AggregatorConfig>, AggregatorConfigError> {
+    let aggregation = &tedge_config.aws.aggregation;
+    aggregation
+        .window
+        .or_none()
+        .map(|window| {
+            AggregatorConfig::from_settings(
+                window.duration(),
+                &aggregation.functions.0,
+                &aggregation.topics.0,
+            )
+        })
+        .transpose()
+}
+
+fn telemetry_buffer_config(tedge_config: &TEdgeConfig) -> TelemetryBufferConfig {
+    let buffer = &tedge_config.aws.bridge.buffer;
+    TelemetryBufferConfig::new(
+        "aws",
+        &MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+        &tedge_config.data.path,
+        buffer.max_size,
+        buffer
+            .downsampling_interval
+            .or_none()
+            .map(|interval| interval.duration()),
+    )
+    .with_downsampled_topics(TopicFilter::new_unchecked("aws/td/+/m/+"))
+}
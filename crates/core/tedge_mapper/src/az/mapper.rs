@@ -1,5 +1,10 @@
+use crate::core::aggregator::AggregatorConfig;
+use crate::core::aggregator::AggregatorConfigError;
+use crate::core::aggregator::MeasurementAggregatorBuilder;
 use crate::core::component::TEdgeComponent;
 use crate::core::mapper::start_basic_actors;
+use crate::core::telemetry_buffer::TelemetryBuffer;
+use crate::core::telemetry_buffer::TelemetryBufferConfig;
 use async_trait::async_trait;
 use az_mapper_ext::converter::AzureConverter;
 use clock::WallClock;
@@ -39,9 +44,24 @@ impl TEdgeComponent for AzureMapper {
         );
         let mut az_converting_actor =
             ConvertingActor::builder("AzConverter", az_converter, get_topic_filter(&tedge_config));
-        az_converting_actor.add_input(&mut mqtt_actor);
+        if let Some(aggregator_config) = aggregator_config(&tedge_config)? {
+            // The measurements are aggregated before being sent to the converter
+            let mut aggregator_actor = MeasurementAggregatorBuilder::new(aggregator_config);
+            az_converting_actor.add_input(&mut aggregator_actor.with_mqtt(&mut mqtt_actor));
+            runtime.spawn(aggregator_actor).await?;
+        } else {
+            az_converting_actor.add_input(&mut mqtt_actor);
+        }
 
-        az_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        if tedge_config.az.bridge.buffer.enable {
+            let buffer_config = telemetry_buffer_config(&tedge_config);
+            let buffer_actor =
+                TelemetryBuffer::builder(buffer_config, Box::new(WallClock), &mut mqtt_actor)?;
+            az_converting_actor.register_peer(NoConfig, buffer_actor.get_sender());
+            runtime.spawn(buffer_actor).await?;
+        } else {
+            az_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        }
 
         runtime.spawn(az_converting_actor).await?;
         runtime.spawn(mqtt_actor).await?;
@@ -59,3 +79,32 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
     }
     topics
 }
+
+fn aggregator_config(
+    tedge_config: &TEdgeConfig,
+) -> Result<Option<AggregatorConfig>, AggregatorConfigError> {
+    let aggregation = &tedge_config.az.aggregation;
+    aggregation
+        .window
+        .or_none()
+        .map(|window| {
+            AggregatorConfig::from_settings(
+                window.duration(),
+                &aggregation.functions.0,
+                &aggregation.topics.0,
+            )
+        })
+        .transpose()
+}
+
+fn telemetry_buffer_config(tedge_config: &TEdgeConfig) -> TelemetryBufferConfig {
+    let buffer = &tedge_config.az.bridge.buffer;
+    TelemetryBufferConfig::new(
+        "az",
+        &MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+        &tedge_config.data.path,
+        buffer.max_size,
+        // All the messages being sent on the same topic, the measurements cannot be down-sampled
+        None,
+    )
+}
@@ -1,5 +1,11 @@
+use crate::core::aggregator::AggregatedMqtt;
+use crate::core::aggregator::AggregatorConfig;
+use crate::core::aggregator::AggregatorConfigError;
+use crate::core::aggregator::MeasurementAggregatorBuilder;
 use crate::core::component::TEdgeComponent;
 use crate::core::mapper::start_basic_actors;
+use crate::core::telemetry_buffer::TelemetryBuffer;
+use crate::core::telemetry_buffer::TelemetryBufferConfig;
 use anyhow::Context;
 use async_trait::async_trait;
 use c8y_auth_proxy::actor::C8yAuthProxyBuilder;
@@ -9,10 +15,13 @@ use c8y_mapper_ext::actor::C8yMapperBuilder;
 use c8y_mapper_ext::compatibility_adapter::OldAgentAdapter;
 use c8y_mapper_ext::config::C8yMapperConfig;
 use c8y_mapper_ext::converter::CumulocityConverter;
+use clock::WallClock;
 use mqtt_channel::Config;
+use mqtt_channel::TopicFilter;
 use std::path::Path;
 use tedge_api::entity_store::EntityExternalId;
 use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
 use tedge_config::TEdgeConfig;
 use tedge_downloader_ext::DownloaderActor;
 use tedge_file_system_ext::FsWatchActorBuilder;
@@ -52,15 +61,39 @@ impl TEdgeComponent for CumulocityMapper {
         let mut downloader_actor = DownloaderActor::new(identity).builder();
 
         let c8y_mapper_config = C8yMapperConfig::from_tedge_config(cfg_dir, &tedge_config)?;
-        let c8y_mapper_actor = C8yMapperBuilder::try_new(
-            c8y_mapper_config,
-            &mut mqtt_actor,
-            &mut c8y_http_proxy_actor,
-            &mut timer_actor,
-            &mut uploader_actor,
-            &mut downloader_actor,
-            &mut fs_watch_actor,
-        )?;
+        // The measurements received by the mapper are aggregated first, if configured
+        let mut aggregator_actor =
+            aggregator_config(&tedge_config)?.map(MeasurementAggregatorBuilder::new);
+        let (c8y_mapper_actor, telemetry_buffer_actor) = if tedge_config.c8y.bridge.buffer.enable {
+            // The messages published by the mapper are sent to MQTT through the telemetry buffer
+            let buffer_config = telemetry_buffer_config(&tedge_config);
+            let buffer_actor =
+                TelemetryBuffer::builder(buffer_config, Box::new(WallClock), &mut mqtt_actor)?;
+            let c8y_mapper_actor = C8yMapperBuilder::try_new(
+                c8y_mapper_config,
+                &mut AggregatedMqtt::new(
+                    aggregator_actor.as_mut(),
+                    &mut buffer_actor.with_mqtt(&mut mqtt_actor),
+                ),
+                &mut c8y_http_proxy_actor,
+                &mut timer_actor,
+                &mut uploader_actor,
+                &mut downloader_actor,
+                &mut fs_watch_actor,
+            )?;
+            (c8y_mapper_actor, Some(buffer_actor))
+        } else {
+            let c8y_mapper_actor = C8yMapperBuilder::try_new(
+                c8y_mapper_config,
+                &mut AggregatedMqtt::new(aggregator_actor.as_mut(), &mut mqtt_actor),
+                &mut c8y_http_proxy_actor,
+                &mut timer_actor,
+                &mut uploader_actor,
+                &mut downloader_actor,
+                &mut fs_watch_actor,
+            )?;
+            (c8y_mapper_actor, None)
+        };
 
         // Adaptor translating commands sent on te/device/main///cmd/+/+ into requests on tedge/commands/req/+/+
         // and translating the responses received on tedge/commands/res/+/+ to te/device/main///cmd/+/+
@@ -84,12 +117,52 @@ impl TEdgeComponent for CumulocityMapper {
         runtime.spawn(uploader_actor).await?;
         runtime.spawn(downloader_actor).await?;
         runtime.spawn(old_to_new_agent_adapter).await?;
+        if let Some(telemetry_buffer_actor) = telemetry_buffer_actor {
+            runtime.spawn(telemetry_buffer_actor).await?;
+        }
+        if let Some(aggregator_actor) = aggregator_actor {
+            runtime.spawn(aggregator_actor).await?;
+        }
         runtime.run_to_completion().await?;
 
         Ok(())
     }
 }
 
+fn aggregator_config(
+    tedge_config: &TEdgeConfig,
+) -> Result<Option<AggregatorConfig>, AggregatorConfigError> {
+    let aggregation = &tedge_config.c8y.aggregation;
+    aggregation
+        .window
+        .or_none()
+        .map(|window| {
+            AggregatorConfig::from_settings(
+                window.duration(),
+                &aggregation.functions.0,
+                &aggregation.topics.0,
+            )
+        })
+        .transpose()
+}
+
+fn telemetry_buffer_config(tedge_config: &TEdgeConfig) -> TelemetryBufferConfig {
+    let buffer = &tedge_config.c8y.bridge.buffer;
+    TelemetryBufferConfig::new(
+        "c8y",
+        &MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+        &tedge_config.data.path,
+        buffer.max_size,
+        buffer
+            .downsampling_interval
+            .or_none()
+            .map(|interval| interval.duration()),
+    )
+    .with_downsampled_topics(TopicFilter::new_unchecked(
+        "c8y/measurement/measurements/create",
+    ))
+}
+
 pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Config, anyhow::Error> {
     let main_device_xid: EntityExternalId = tedge_config.device.id.try_read(tedge_config)?.into();
     let service_type = &tedge_config.service.ty;
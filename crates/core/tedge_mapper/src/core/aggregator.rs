@@ -0,0 +1,779 @@
+//! Edge-side aggregation of the measurements, before their conversion to the cloud format.
+//!
+//! The aggregator is inserted between MQTT and a cloud mapper:
+//! - the measurements published on the aggregated topics are grouped into time windows, e.g. one per minute,
+//! - at the end of each window, one aggregated measurement is forwarded to the mapper per topic,
+//!   with the `min`, `max`, `mean`, `last` value and/or the `count` of each series,
+//! - all the other messages are forwarded unchanged to the mapper.
+//!
+//! The raw measurements are left untouched on the local MQTT bus.
+use async_trait::async_trait;
+use batcher::BatchConfigBuilder;
+use batcher::Batchable;
+use batcher::TimeWindow;
+use batcher::TimeWindows;
+use mqtt_channel::Topic;
+use mqtt_channel::TopicFilter;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::ChannelError;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::Sender;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::builder::ThinEdgeJsonBuilder;
+use tedge_api::data::ThinEdgeValue;
+use tedge_api::measurement::MeasurementValue;
+use tedge_api::measurement::MeasurementVisitor;
+use tedge_api::measurement::ScalarValue;
+use tedge_api::serialize::ThinEdgeJsonSerializer;
+use tedge_mqtt_ext::MqttMessage;
+use time::OffsetDateTime;
+use tracing::error;
+use tracing::warn;
+
+/// How long to wait for late measurements, once a window is over
+const DELIVERY_JITTER: Duration = Duration::from_secs(1);
+
+/// How far in the future a measurement can be, the device clock being possibly ahead of the mapper's
+const MESSAGE_LEAP_LIMIT: Duration = Duration::from_secs(10);
+
+/// The aggregation functions applied to each measurement series
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationFunction {
+    Min,
+    Max,
+    Mean,
+    Last,
+    Count,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown aggregation function {0}: expecting one of min, max, mean, last or count")]
+pub struct UnknownAggregationFunction(String);
+
+#[derive(thiserror::Error, Debug)]
+pub enum AggregatorConfigError {
+    #[error(transparent)]
+    UnknownFunction(#[from] UnknownAggregationFunction),
+
+    #[error("The aggregation window of {0:?} is too large: expecting at most {max:?}", max = Duration::from_millis(u32::MAX.into()))]
+    WindowTooLarge(Duration),
+}
+
+impl FromStr for AggregationFunction {
+    type Err = UnknownAggregationFunction;
+
+    fn from_str(function: &str) -> Result<Self, Self::Err> {
+        match function {
+            "min" => Ok(AggregationFunction::Min),
+            "max" => Ok(AggregationFunction::Max),
+            "mean" => Ok(AggregationFunction::Mean),
+            "last" => Ok(AggregationFunction::Last),
+            "count" => Ok(AggregationFunction::Count),
+            _ => Err(UnknownAggregationFunction(function.to_string())),
+        }
+    }
+}
+
+impl AggregationFunction {
+    fn name(&self) -> &'static str {
+        match self {
+            AggregationFunction::Min => "min",
+            AggregationFunction::Max => "max",
+            AggregationFunction::Mean => "mean",
+            AggregationFunction::Last => "last",
+            AggregationFunction::Count => "count",
+        }
+    }
+}
+
+/// The configuration of the measurement aggregation
+#[derive(Debug, Clone)]
+pub struct AggregatorConfig {
+    /// The measurement topics to aggregate
+    pub topics: TopicFilter,
+
+    /// The size of the aggregation windows
+    pub window: Duration,
+
+    /// The functions applied to each series, at least one
+    pub functions: Vec<AggregationFunction>,
+}
+
+impl AggregatorConfig {
+    pub fn new(topics: TopicFilter, window: Duration, functions: Vec<AggregationFunction>) -> Self {
+        let functions = if functions.is_empty() {
+            vec![AggregationFunction::Mean]
+        } else {
+            functions
+        };
+        AggregatorConfig {
+            topics,
+            window,
+            functions,
+        }
+    }
+
+    /// Build the config from the `<cloud>.aggregation` settings, ignoring the invalid topics
+    ///
+    /// The window has to fit in `u32` milliseconds, as expected by the batcher.
+    pub fn from_settings(
+        window: Duration,
+        functions: &[String],
+        topics: &[String],
+    ) -> Result<Self, AggregatorConfigError> {
+        if u32::try_from(window.as_millis()).is_err() {
+            return Err(AggregatorConfigError::WindowTooLarge(window));
+        }
+        let functions = functions
+            .iter()
+            .map(|function| function.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut topic_filter = TopicFilter::empty();
+        for topic in topics {
+            if topic_filter.add(topic).is_err() {
+                warn!("The configured aggregation topic '{topic}' is invalid and ignored.");
+            }
+        }
+        Ok(AggregatorConfig::new(topic_filter, window, functions))
+    }
+}
+
+/// A measurement value, as published on a given topic at a given time
+#[derive(Debug, Clone, PartialEq)]
+struct MeasurementPoint {
+    topic: String,
+    series: Series,
+    value: f64,
+    unit: Option<String>,
+    time: OffsetDateTime,
+}
+
+/// A measurement series, possibly part of a group
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Series {
+    group: Option<String>,
+    name: String,
+}
+
+impl Batchable for MeasurementPoint {
+    type Key = (String, Series, OffsetDateTime);
+
+    fn key(&self) -> Self::Key {
+        (self.topic.clone(), self.series.clone(), self.time)
+    }
+
+    fn event_time(&self) -> OffsetDateTime {
+        self.time
+    }
+}
+
+/// The statistics of a series over a window
+#[derive(Debug)]
+struct SeriesStats {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: usize,
+    last: (OffsetDateTime, f64),
+    unit: Option<String>,
+}
+
+impl SeriesStats {
+    fn new(point: &MeasurementPoint) -> Self {
+        SeriesStats {
+            min: point.value,
+            max: point.value,
+            sum: point.value,
+            count: 1,
+            last: (point.time, point.value),
+            unit: point.unit.clone(),
+        }
+    }
+
+    fn add(&mut self, point: &MeasurementPoint) {
+        self.min = self.min.min(point.value);
+        self.max = self.max.max(point.value);
+        self.sum += point.value;
+        self.count += 1;
+        if point.time >= self.last.0 {
+            self.last = (point.time, point.value);
+        }
+    }
+
+    fn value(&self, function: AggregationFunction) -> MeasurementValue {
+        let value = match function {
+            AggregationFunction::Min => self.min,
+            AggregationFunction::Max => self.max,
+            AggregationFunction::Mean => self.sum / self.count as f64,
+            AggregationFunction::Last => self.last.1,
+            AggregationFunction::Count => {
+                return ScalarValue::Integer(self.count as i64).into();
+            }
+        };
+        MeasurementValue {
+            value: ScalarValue::Float(value),
+            unit: self.unit.clone(),
+            quality: None,
+        }
+    }
+}
+
+/// Group the measurements into time windows and compute the aggregated measurements
+pub struct MeasurementAggregator {
+    config: AggregatorConfig,
+    windows: TimeWindows<MeasurementPoint>,
+}
+
+impl MeasurementAggregator {
+    pub fn new(config: AggregatorConfig) -> Self {
+        // A window too large for the batcher is rejected on config load, hence the saturation is never applied
+        let window = u32::try_from(config.window.as_millis()).unwrap_or(u32::MAX);
+        let batch_config = BatchConfigBuilder::new()
+            .event_jitter(window)
+            .delivery_jitter(DELIVERY_JITTER.as_millis() as u32)
+            .message_leap_limit(MESSAGE_LEAP_LIMIT.as_millis() as u32)
+            .build();
+        MeasurementAggregator {
+            config,
+            windows: TimeWindows::new(batch_config),
+        }
+    }
+
+    /// Tell if the message has to be aggregated
+    pub fn accept(&self, message: &MqttMessage) -> bool {
+        self.config.topics.accept(message)
+    }
+
+    /// Add the values of a measurement to the current window
+    ///
+    /// Return the messages to forward unchanged to the mapper,
+    /// i.e. the message itself if this is not a valid measurement, the mapper being in charge of reporting errors,
+    /// or if the measurement cannot be aggregated, being too late for its window or too far in the future.
+    pub fn aggregate(
+        &mut self,
+        processing_time: OffsetDateTime,
+        message: MqttMessage,
+    ) -> Vec<MqttMessage> {
+        let Some(points) = measurement_points(processing_time, &message) else {
+            return vec![message];
+        };
+        // All the points of a measurement share the same time: they are all accepted or all rejected
+        let mut accepted = true;
+        for point in points {
+            accepted &= self.windows.event(processing_time, point).is_some();
+        }
+        if accepted {
+            vec![]
+        } else {
+            warn!(
+                "Forwarding without aggregation a measurement published on {}, its time being out of the open windows",
+                message.topic.name
+            );
+            vec![message]
+        }
+    }
+
+    /// Build the aggregated measurements of the windows closed at the given time
+    pub fn close_windows(&mut self, time: OffsetDateTime) -> Vec<MqttMessage> {
+        let windows = self.windows.time(time);
+        self.aggregated_measurements(windows)
+    }
+
+    /// Build the aggregated measurements of all the pending windows
+    pub fn flush(&mut self) -> Vec<MqttMessage> {
+        let windows = self.windows.flush();
+        self.aggregated_measurements(windows)
+    }
+
+    fn aggregated_measurements(
+        &self,
+        windows: Vec<TimeWindow<MeasurementPoint>>,
+    ) -> Vec<MqttMessage> {
+        let mut messages = vec![];
+        for window in windows {
+            let mut topics: BTreeMap<String, BTreeMap<Series, SeriesStats>> = BTreeMap::new();
+            for point in window.events.iter() {
+                topics
+                    .entry(point.topic.clone())
+                    .or_default()
+                    .entry(point.series.clone())
+                    .and_modify(|stats| stats.add(point))
+                    .or_insert_with(|| SeriesStats::new(point));
+            }
+
+            for (topic, series) in topics {
+                match self.serialize(window.start, series) {
+                    Ok(payload) => {
+                        messages.push(MqttMessage::new(&Topic::new_unchecked(&topic), payload))
+                    }
+                    Err(err) => {
+                        error!("Fail to aggregate the measurements published on {topic}: {err}")
+                    }
+                }
+            }
+        }
+        messages
+    }
+
+    /// Serialize the aggregated series as a thin-edge JSON measurement
+    ///
+    /// With a single aggregation function, the series keep their names: `{"temperature": 21.3}`.
+    /// Otherwise, the values of a series are grouped:
+    /// `{"temperature": {"min": 20.1, "max": 22.0}, "pump": {"pressure_min": 3.1, "pressure_max": 3.4}}`.
+    fn serialize(
+        &self,
+        time: OffsetDateTime,
+        series: BTreeMap<Series, SeriesStats>,
+    ) -> Result<String, tedge_api::serialize::ThinEdgeJsonSerializationError> {
+        let single_function = self.config.functions.len() == 1;
+        let mut serializer = ThinEdgeJsonSerializer::new();
+        serializer.visit_timestamp(time)?;
+
+        let mut current_group: Option<&str> = None;
+        for (series, stats) in series.iter() {
+            if current_group.is_some() && current_group != series.group.as_deref() {
+                serializer.visit_end_group()?;
+                current_group = None;
+            }
+            match &series.group {
+                Some(group) => {
+                    if current_group.is_none() {
+                        serializer.visit_start_group(group)?;
+                        current_group = Some(group);
+                    }
+                    for function in self.config.functions.iter() {
+                        let name = if single_function {
+                            series.name.clone()
+                        } else {
+                            format!("{}_{}", series.name, function.name())
+                        };
+                        serializer.visit_typed_measurement(&name, &stats.value(*function))?;
+                    }
+                }
+                None if single_function => {
+                    let function = self.config.functions[0];
+                    serializer.visit_typed_measurement(&series.name, &stats.value(function))?;
+                }
+                None => {
+                    serializer.visit_start_group(&series.name)?;
+                    for function in self.config.functions.iter() {
+                        serializer
+                            .visit_typed_measurement(function.name(), &stats.value(*function))?;
+                    }
+                    serializer.visit_end_group()?;
+                }
+            }
+        }
+        if current_group.is_some() {
+            serializer.visit_end_group()?;
+        }
+
+        serializer.into_string()
+    }
+}
+
+/// Extract the numeric values of a thin-edge JSON measurement
+fn measurement_points(
+    processing_time: OffsetDateTime,
+    message: &MqttMessage,
+) -> Option<Vec<MeasurementPoint>> {
+    let payload = message.payload_str().ok()?;
+    let mut builder = ThinEdgeJsonBuilder::default();
+    tedge_api::parser::parse_str(payload, &mut builder).ok()?;
+    let measurement = builder.done().ok()?;
+
+    let time = measurement.timestamp.unwrap_or(processing_time);
+    let point = |group: Option<&str>, name: &str, value: &MeasurementValue| MeasurementPoint {
+        topic: message.topic.name.clone(),
+        series: Series {
+            group: group.map(str::to_string),
+            name: name.to_string(),
+        },
+        value: value.value.as_f64(),
+        unit: value.unit.clone(),
+        time,
+    };
+
+    let mut points = vec![];
+    for value in measurement.values.iter() {
+        match value {
+            ThinEdgeValue::Single(single) => points.push(point(None, &single.name, &single.value)),
+            ThinEdgeValue::Multi(multi) => {
+                for single in multi.values.iter() {
+                    points.push(point(Some(&multi.name), &single.name, &single.value))
+                }
+            }
+        }
+    }
+    Some(points)
+}
+
+/// An actor aggregating the measurements received from MQTT before sending them to a mapper
+pub struct MeasurementAggregatorActor {
+    aggregator: MeasurementAggregator,
+    messages: SimpleMessageBox<MqttMessage, MqttMessage>,
+}
+
+#[async_trait]
+impl Actor for MeasurementAggregatorActor {
+    fn name(&self) -> &str {
+        "MeasurementAggregator"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let tick = DELIVERY_JITTER.min(self.aggregator.config.window);
+        loop {
+            match tokio::time::timeout(tick, self.messages.recv()).await {
+                Err(_) => {}       // no message received since the last tick
+                Ok(None) => break, // input channel closed
+                Ok(Some(message)) => {
+                    let forwarded = self
+                        .aggregator
+                        .aggregate(OffsetDateTime::now_utc(), message);
+                    self.send_all(forwarded).await?;
+                }
+            }
+            let aggregated = self.aggregator.close_windows(OffsetDateTime::now_utc());
+            self.send_all(aggregated).await?;
+        }
+
+        let aggregated = self.aggregator.flush();
+        Ok(self.send_all(aggregated).await?)
+    }
+}
+
+impl MeasurementAggregatorActor {
+    async fn send_all(&mut self, messages: Vec<MqttMessage>) -> Result<(), ChannelError> {
+        for message in messages {
+            self.messages.send(message).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The builder of the measurement aggregator actor
+///
+/// As the actor is inserted between the MQTT actor and a mapper,
+/// the mapper has to subscribe to MQTT using the service provider returned by [Self::with_mqtt].
+pub struct MeasurementAggregatorBuilder {
+    config: AggregatorConfig,
+    message_box: SimpleMessageBoxBuilder<MqttMessage, MqttMessage>,
+    mapper_connected: bool,
+}
+
+impl MeasurementAggregatorBuilder {
+    pub fn new(config: AggregatorConfig) -> Self {
+        MeasurementAggregatorBuilder {
+            config,
+            message_box: SimpleMessageBoxBuilder::new("MeasurementAggregator", 16),
+            mapper_connected: false,
+        }
+    }
+
+    /// An MQTT service provider, for a mapper to subscribe to the MQTT actor,
+    /// but with the measurements sent through this aggregator.
+    pub fn with_mqtt<'a, Mqtt>(&'a mut self, mqtt: &'a mut Mqtt) -> AggregatedMqtt<'a, Mqtt> {
+        AggregatedMqtt::new(Some(self), mqtt)
+    }
+
+    /// Route the aggregated topics to the aggregator, the other messages directly to the mapper
+    ///
+    /// The aggregated measurements being sent to a single peer,
+    /// only the first consumer connected to MQTT gets aggregated measurements.
+    fn connect_mapper(&mut self, mapper: DynSender<MqttMessage>) -> DynSender<MqttMessage> {
+        if self.mapper_connected {
+            return mapper;
+        }
+        self.mapper_connected = true;
+        self.message_box
+            .register_peer(NoConfig, mapper.sender_clone());
+        Box::new(MeasurementRouter {
+            topics: self.config.topics.clone(),
+            aggregator: self.message_box.get_sender(),
+            mapper,
+        })
+    }
+}
+
+impl RuntimeRequestSink for MeasurementAggregatorBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<MeasurementAggregatorActor> for MeasurementAggregatorBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<MeasurementAggregatorActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> MeasurementAggregatorActor {
+        MeasurementAggregatorActor {
+            aggregator: MeasurementAggregator::new(self.config),
+            messages: self.message_box.build(),
+        }
+    }
+}
+
+/// An MQTT service provider whose measurements are aggregated before being sent to the consumer
+///
+/// If no aggregator is given, the consumer is directly connected to MQTT.
+pub struct AggregatedMqtt<'a, Mqtt> {
+    mqtt: &'a mut Mqtt,
+    aggregator: Option<&'a mut MeasurementAggregatorBuilder>,
+}
+
+impl<'a, Mqtt> AggregatedMqtt<'a, Mqtt> {
+    pub fn new(
+        aggregator: Option<&'a mut MeasurementAggregatorBuilder>,
+        mqtt: &'a mut Mqtt,
+    ) -> Self {
+        AggregatedMqtt { mqtt, aggregator }
+    }
+
+    fn connect_mapper(&mut self, mapper: DynSender<MqttMessage>) -> DynSender<MqttMessage> {
+        match self.aggregator.as_mut() {
+            Some(aggregator) => aggregator.connect_mapper(mapper),
+            None => mapper,
+        }
+    }
+}
+
+impl<'a, Mqtt> ServiceProvider<MqttMessage, MqttMessage, TopicFilter> for AggregatedMqtt<'a, Mqtt>
+where
+    Mqtt: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+{
+    fn connect_consumer(
+        &mut self,
+        subscriptions: TopicFilter,
+        response_sender: DynSender<MqttMessage>,
+    ) -> DynSender<MqttMessage> {
+        let router = self.connect_mapper(response_sender);
+        self.mqtt.connect_consumer(subscriptions, router)
+    }
+}
+
+impl<'a, Mqtt> MessageSource<MqttMessage, TopicFilter> for AggregatedMqtt<'a, Mqtt>
+where
+    Mqtt: MessageSource<MqttMessage, TopicFilter>,
+{
+    fn register_peer(&mut self, subscriptions: TopicFilter, sender: DynSender<MqttMessage>) {
+        let router = self.connect_mapper(sender);
+        self.mqtt.register_peer(subscriptions, router)
+    }
+}
+
+/// A sender dispatching the measurements to aggregate to the aggregator and the other messages to the mapper
+struct MeasurementRouter {
+    topics: TopicFilter,
+    aggregator: DynSender<MqttMessage>,
+    mapper: DynSender<MqttMessage>,
+}
+
+#[async_trait]
+impl Sender<MqttMessage> for MeasurementRouter {
+    async fn send(&mut self, message: MqttMessage) -> Result<(), ChannelError> {
+        if self.topics.accept(&message) {
+            self.aggregator.send(message).await
+        } else {
+            self.mapper.send(message).await
+        }
+    }
+
+    fn sender_clone(&self) -> DynSender<MqttMessage> {
+        Box::new(MeasurementRouter {
+            topics: self.topics.clone(),
+            aggregator: self.aggregator.sender_clone(),
+            mapper: self.mapper.sender_clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use serde_json::Value;
+
+    fn aggregator(functions: &[&str]) -> MeasurementAggregator {
+        let functions = functions.iter().map(|f| f.parse().unwrap()).collect();
+        MeasurementAggregator::new(AggregatorConfig::new(
+            TopicFilter::new_unchecked("te/+/+/+/+/m/+"),
+            Duration::from_secs(60),
+            functions,
+        ))
+    }
+
+    fn at(secs: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(secs).unwrap()
+    }
+
+    fn measurement(payload: Value) -> MqttMessage {
+        MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            payload.to_string(),
+        )
+    }
+
+    fn payload(message: &MqttMessage) -> Value {
+        serde_json::from_slice(message.payload_bytes()).unwrap()
+    }
+
+    #[test]
+    fn aggregate_measurements_per_window() {
+        let mut aggregator = aggregator(&["mean"]);
+
+        for (secs, temperature) in [(60, 20.0), (80, 21.0), (119, 25.0), (121, 30.0)] {
+            let forwarded = aggregator.aggregate(
+                at(secs),
+                measurement(json!({"time": secs, "temperature": temperature})),
+            );
+            assert!(forwarded.is_empty());
+        }
+
+        // The window is closed once the delivery jitter has elapsed
+        assert!(aggregator.close_windows(at(120)).is_empty());
+        let aggregated = aggregator.close_windows(at(121));
+        assert_eq!(aggregated.len(), 1);
+        assert_eq!(aggregated[0].topic.name, "te/device/main///m/environment");
+        assert_eq!(
+            payload(&aggregated[0]),
+            json!({"time": "1970-01-01T00:01:00Z", "temperature": 22.0})
+        );
+
+        let aggregated = aggregator.flush();
+        assert_eq!(
+            payload(&aggregated[0]),
+            json!({"time": "1970-01-01T00:02:00Z", "temperature": 30.0})
+        );
+    }
+
+    #[test]
+    fn apply_several_aggregation_functions() {
+        let mut aggregator = aggregator(&["min", "max", "last", "count"]);
+
+        aggregator.aggregate(
+            at(1),
+            measurement(json!({
+                "time": 1,
+                "temperature": {"value": 20.0, "unit": "°C"},
+                "pump": {"pressure": 3.0}
+            })),
+        );
+        aggregator.aggregate(
+            at(2),
+            measurement(json!({"time": 2, "temperature": {"value": 22.0, "unit": "°C"}})),
+        );
+        aggregator.aggregate(
+            at(3),
+            measurement(json!({"time": 3, "temperature": {"value": 21.0, "unit": "°C"}, "pump": {"pressure": 4.0}})),
+        );
+
+        let aggregated = aggregator.flush();
+        assert_eq!(
+            payload(&aggregated[0]),
+            json!({
+                "time": "1970-01-01T00:00:00Z",
+                "temperature": {
+                    "min": {"value": 20.0, "unit": "°C"},
+                    "max": {"value": 22.0, "unit": "°C"},
+                    "last": {"value": 21.0, "unit": "°C"},
+                    "count": 3
+                },
+                "pump": {
+                    "pressure_min": 3.0,
+                    "pressure_max": 4.0,
+                    "pressure_last": 4.0,
+                    "pressure_count": 2
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_measurements_are_forwarded_unchanged() {
+        let mut aggregator = aggregator(&["mean"]);
+        let invalid = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            "not a measurement",
+        );
+
+        assert_eq!(aggregator.aggregate(at(1), invalid.clone()), vec![invalid]);
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn measurements_out_of_the_open_windows_are_forwarded_unchanged() {
+        let mut aggregator = aggregator(&["mean"]);
+
+        // Too late for its window
+        let late = measurement(json!({"time": 10, "temperature": 20.0}));
+        assert_eq!(aggregator.aggregate(at(100), late.clone()), vec![late]);
+
+        // Too far in the future
+        let futuristic = measurement(json!({"time": 200, "temperature": 20.0}));
+        assert_eq!(
+            aggregator.aggregate(at(100), futuristic.clone()),
+            vec![futuristic]
+        );
+
+        // Slightly ahead of the mapper clock
+        let ahead = measurement(json!({"time": 102, "temperature": 21.0}));
+        assert!(aggregator.aggregate(at(100), ahead).is_empty());
+
+        let aggregated = aggregator.flush();
+        assert_eq!(aggregated.len(), 1);
+        assert_eq!(
+            payload(&aggregated[0]),
+            json!({"time": "1970-01-01T00:01:00Z", "temperature": 21.0})
+        );
+    }
+
+    #[test]
+    fn parse_aggregation_settings() {
+        let config = AggregatorConfig::from_settings(
+            Duration::from_secs(60),
+            &["min".to_string(), "last".to_string()],
+            &["te/+/+/+/+/m/+".to_string(), "te/#/invalid".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            config.functions,
+            vec![AggregationFunction::Min, AggregationFunction::Last]
+        );
+        assert_eq!(config.topics, TopicFilter::new_unchecked("te/+/+/+/+/m/+"));
+
+        assert!(AggregatorConfig::from_settings(
+            Duration::from_secs(60),
+            &["median".to_string()],
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_aggregation_windows_too_large_for_the_batcher() {
+        let max_window = Duration::from_millis(u32::MAX.into());
+        assert!(AggregatorConfig::from_settings(max_window, &[], &[]).is_ok());
+
+        let error =
+            AggregatorConfig::from_settings(max_window + Duration::from_millis(1), &[], &[])
+                .unwrap_err();
+        assert!(matches!(error, AggregatorConfigError::WindowTooLarge(_)));
+    }
+}
@@ -9,6 +9,8 @@ use tedge_api::mqtt_topics::ServiceTopicId;
 use tedge_config::TEdgeConfig;
 use tedge_health_ext::HealthMonitorBuilder;
 use tedge_mqtt_ext::MqttActorBuilder;
+use tedge_prometheus_ext::PrometheusExporterBuilder;
+use tedge_prometheus_ext::PrometheusExporterConfig;
 use tedge_signal_ext::SignalActor;
 
 pub async fn start_basic_actors(
@@ -40,6 +42,14 @@ pub async fn start_basic_actors(
 
     runtime.spawn(signal_actor).await?;
     runtime.spawn(health_actor).await?;
+
+    // Export the internal metrics of the mapper, if a metrics port is configured for this mapper
+    if let Some(metrics_config) = PrometheusExporterConfig::from_tedge_config(mapper_name, config)?
+    {
+        let prometheus_exporter = PrometheusExporterBuilder::try_bind(metrics_config)?;
+        runtime.spawn(prometheus_exporter).await?;
+    }
+
     Ok((runtime, mqtt_actor))
 }
 
@@ -1,2 +1,4 @@
+pub mod aggregator;
 pub mod component;
 pub mod mapper;
+pub mod telemetry_buffer;
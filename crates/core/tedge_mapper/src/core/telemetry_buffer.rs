@@ -0,0 +1,631 @@
+//! A store-and-forward buffer for the telemetry data sent by a mapper to the cloud.
+//!
+//! While the mosquitto bridge to the cloud is disconnected, the messages published by the mapper
+//! on the cloud topics are either queued in memory by mosquitto or lost.
+//! To prevent this, the buffer:
+//! - monitors the health of the bridge, as published by mosquitto on `te/device/main/service/mosquitto-<cloud>-bridge/status/health`,
+//! - persists on disk the messages published on the cloud topics while the bridge is down,
+//!   dropping the oldest messages when the buffer exceeds its maximum size,
+//! - replays in order the buffered messages when the bridge is up again,
+//!   possibly down-sampling the measurements to at most one per interval and measurement type.
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use clock::Clock;
+use mqtt_channel::Topic;
+use mqtt_channel::TopicFilter;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::time::Duration;
+use tedge_actors::Builder;
+use tedge_actors::Converter;
+use tedge_actors::ConvertingActor;
+use tedge_actors::ConvertingActorBuilder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceProvider;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// The configuration of the telemetry buffer of a mapper
+#[derive(Debug, Clone)]
+pub struct TelemetryBufferConfig {
+    /// The topic on which mosquitto publishes the health status of the bridge
+    pub bridge_health_topic: Topic,
+
+    /// The topics of the messages to buffer while the bridge is down
+    pub buffered_topics: TopicFilter,
+
+    /// The topics of the measurements to down-sample on replay
+    pub downsampled_topics: TopicFilter,
+
+    /// The minimum interval between two replayed measurements of the same type, if any
+    pub downsampling_interval: Option<Duration>,
+
+    /// The maximum size in bytes of the buffer file
+    pub max_size: u64,
+
+    /// The file where the messages are buffered
+    pub buffer_file: Utf8PathBuf,
+}
+
+impl TelemetryBufferConfig {
+    /// The buffer configuration for the messages published on the `<cloud>/#` topics
+    pub fn new(
+        cloud: &str,
+        mqtt_schema: &MqttSchema,
+        data_dir: &Utf8Path,
+        max_size: u64,
+        downsampling_interval: Option<Duration>,
+    ) -> Self {
+        let bridge_service =
+            EntityTopicId::default_main_service(&format!("mosquitto-{cloud}-bridge"))
+                .expect("a valid service name");
+        TelemetryBufferConfig {
+            bridge_health_topic: mqtt_schema.topic_for(&bridge_service, &Channel::Health),
+            buffered_topics: TopicFilter::new_unchecked(&format!("{cloud}/#")),
+            downsampled_topics: TopicFilter::empty(),
+            downsampling_interval,
+            max_size,
+            buffer_file: data_dir
+                .join("telemetry-buffer")
+                .join(format!("{cloud}.jsonl")),
+        }
+    }
+
+    /// Set the topics of the measurements to down-sample on replay
+    pub fn with_downsampled_topics(self, downsampled_topics: TopicFilter) -> Self {
+        TelemetryBufferConfig {
+            downsampled_topics,
+            ..self
+        }
+    }
+}
+
+/// A converter that forwards the messages of a mapper to MQTT,
+/// buffering the cloud messages while the bridge is disconnected.
+pub struct TelemetryBuffer {
+    config: TelemetryBufferConfig,
+    clock: Box<dyn Clock>,
+    bridge_connected: bool,
+    store: BufferStore,
+}
+
+impl TelemetryBuffer {
+    pub fn builder(
+        config: TelemetryBufferConfig,
+        clock: Box<dyn Clock>,
+        mqtt: &mut (impl MessageSource<MqttMessage, TopicFilter> + MessageSink<MqttMessage, NoConfig>),
+    ) -> Result<TelemetryBufferBuilder, std::io::Error> {
+        let bridge_health_topic = TopicFilter::new_unchecked(&config.bridge_health_topic.name);
+        let buffer = TelemetryBuffer::try_new(config, clock)?;
+        let mut builder = ConvertingActor::builder("TelemetryBuffer", buffer, bridge_health_topic);
+        builder.add_input(mqtt);
+        builder.add_sink(mqtt);
+        Ok(TelemetryBufferBuilder { builder })
+    }
+
+    fn try_new(
+        config: TelemetryBufferConfig,
+        clock: Box<dyn Clock>,
+    ) -> Result<Self, std::io::Error> {
+        let store = BufferStore::open(&config.buffer_file, config.max_size)?;
+        if !store.is_empty() {
+            info!(
+                "{} messages buffered in {}, to be sent once the bridge is connected",
+                store.len(),
+                config.buffer_file
+            );
+        }
+        Ok(TelemetryBuffer {
+            config,
+            clock,
+            // Until told otherwise by mosquitto, the bridge is assumed to be connected
+            bridge_connected: true,
+            store,
+        })
+    }
+
+    fn update_bridge_status(&mut self, message: &MqttMessage) -> Vec<MqttMessage> {
+        self.bridge_connected = bridge_is_up(message);
+        if !self.bridge_connected {
+            info!("The bridge is disconnected: buffering the messages sent to the cloud");
+            return vec![];
+        }
+
+        let entries = match self.store.drain() {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("Fail to read the buffered messages: {err}");
+                return vec![];
+            }
+        };
+        if !entries.is_empty() {
+            info!(
+                "The bridge is connected: replaying {} buffered messages",
+                entries.len()
+            );
+        }
+        match self.config.downsampling_interval {
+            Some(interval) => downsample(entries, &self.config.downsampled_topics, interval),
+            None => entries.into_iter().map(|entry| entry.message).collect(),
+        }
+    }
+
+    fn buffer(&mut self, message: &MqttMessage) -> Vec<MqttMessage> {
+        let entry = BufferedMessage {
+            buffered_at: self.clock.now().unix_timestamp(),
+            message: message.clone(),
+        };
+        match self.store.push(entry) {
+            Ok(()) => vec![],
+            Err(err) => {
+                // Let mosquitto queue the message, rather than losing it
+                error!(
+                    "Fail to buffer the message sent to {}: {err}",
+                    message.topic.name
+                );
+                vec![message.clone()]
+            }
+        }
+    }
+}
+
+impl Converter for TelemetryBuffer {
+    type Input = MqttMessage;
+    type Output = MqttMessage;
+    type Error = Infallible;
+
+    fn convert(&mut self, input: &Self::Input) -> Result<Vec<Self::Output>, Self::Error> {
+        if input.topic == self.config.bridge_health_topic {
+            return Ok(self.update_bridge_status(input));
+        }
+
+        if self.bridge_connected || !self.config.buffered_topics.accept(input) {
+            return Ok(vec![input.clone()]);
+        }
+
+        Ok(self.buffer(input))
+    }
+}
+
+/// Tell if the health status published by mosquitto for the bridge is up,
+/// this status being either `1` or `0`, or a JSON health status
+fn bridge_is_up(message: &MqttMessage) -> bool {
+    match message.payload_str() {
+        Ok("1") => true,
+        Ok(payload) => serde_json::from_str::<JsonValue>(payload)
+            .ok()
+            .and_then(|health| health.get("status").cloned())
+            .is_some_and(|status| status == "up"),
+        Err(_) => false,
+    }
+}
+
+/// Keep only the measurements sent at least `interval` after the previous one of the same type
+fn downsample(
+    entries: Vec<BufferedMessage>,
+    downsampled_topics: &TopicFilter,
+    interval: Duration,
+) -> Vec<MqttMessage> {
+    let interval = interval.as_secs() as i64;
+    let mut last_replayed = HashMap::new();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            if !downsampled_topics.accept(&entry.message) {
+                return true;
+            }
+            let key = downsampling_key(&entry.message);
+            match last_replayed.get(&key) {
+                Some(last) if entry.buffered_at - last < interval => false,
+                _ => {
+                    last_replayed.insert(key, entry.buffered_at);
+                    true
+                }
+            }
+        })
+        .map(|entry| entry.message)
+        .collect()
+}
+
+/// The measurements are down-sampled per topic, and per `type` and `externalSource` if any,
+/// so the measurements of different types or devices sent on the same topic are down-sampled independently
+fn downsampling_key(message: &MqttMessage) -> String {
+    let payload: Option<JsonValue> = serde_json::from_slice(message.payload_bytes()).ok();
+    let field = |name: &str| {
+        payload
+            .as_ref()
+            .and_then(|payload| payload.get(name))
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    };
+    format!(
+        "{} {} {}",
+        message.topic.name,
+        field("type"),
+        field("externalSource")
+    )
+}
+
+/// A message buffered while the bridge is down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct BufferedMessage {
+    /// The unix timestamp, in seconds, when the message has been buffered
+    buffered_at: i64,
+
+    message: MqttMessage,
+}
+
+/// A disk-backed FIFO of messages, bounded in size
+///
+/// The messages are appended to a JSON lines file, which is truncated once the messages replayed.
+/// An in-memory copy of the buffered messages is kept to drop the oldest ones when the buffer is full,
+/// the file being then rewritten.
+struct BufferStore {
+    path: Utf8PathBuf,
+    max_size: u64,
+    size: u64,
+    entries: VecDeque<(u64, BufferedMessage)>,
+}
+
+impl BufferStore {
+    fn open(path: &Utf8Path, max_size: u64) -> Result<Self, std::io::Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut store = BufferStore {
+            path: path.to_owned(),
+            max_size,
+            size: 0,
+            entries: VecDeque::new(),
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut corrupted_entries = false;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str::<BufferedMessage>(&line) {
+                Ok(entry) => {
+                    let entry_size = line.len() as u64 + 1;
+                    store.size += entry_size;
+                    store.entries.push_back((entry_size, entry));
+                }
+                Err(err) => {
+                    warn!("Ignoring corrupted buffered message in {path}: {err}");
+                    corrupted_entries = true;
+                }
+            }
+        }
+        if corrupted_entries || store.size > max_size {
+            store.drop_oldest_entries(0);
+            store.rewrite()?;
+        }
+
+        Ok(store)
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn push(&mut self, entry: BufferedMessage) -> Result<(), std::io::Error> {
+        let line = serde_json::to_string(&entry)?;
+        let entry_size = line.len() as u64 + 1;
+        if entry_size > self.max_size {
+            warn!(
+                "Dropping the message sent to {}: too large to be buffered",
+                entry.message.topic.name
+            );
+            return Ok(());
+        }
+
+        if self.size + entry_size > self.max_size {
+            // Free 10% of the buffer at once, not to rewrite the file on each new message
+            self.drop_oldest_entries(entry_size + self.max_size / 10);
+            self.size += entry_size;
+            self.entries.push_back((entry_size, entry));
+            return self.rewrite();
+        }
+
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        writeln!(file, "{line}")?;
+        file.sync_all()?;
+        self.size += entry_size;
+        self.entries.push_back((entry_size, entry));
+        Ok(())
+    }
+
+    /// Take all the buffered messages, in order, clearing the buffer
+    fn drain(&mut self) -> Result<Vec<BufferedMessage>, std::io::Error> {
+        if self.entries.is_empty() {
+            return Ok(vec![]);
+        }
+        File::create(&self.path)?.sync_all()?;
+        self.size = 0;
+        Ok(self.entries.drain(..).map(|(_, entry)| entry).collect())
+    }
+
+    /// Drop the oldest messages, till the buffer has `free_space` bytes free
+    fn drop_oldest_entries(&mut self, free_space: u64) {
+        let mut dropped = 0;
+        while self.size + free_space > self.max_size {
+            let Some((entry_size, _)) = self.entries.pop_front() else {
+                break;
+            };
+            self.size -= entry_size;
+            dropped += 1;
+        }
+        if dropped > 0 {
+            warn!("The telemetry buffer is full: dropping the {dropped} oldest messages");
+        }
+    }
+
+    /// Rewrite the buffer file, atomically, with the current messages
+    fn rewrite(&self) -> Result<(), std::io::Error> {
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            for (_, entry) in self.entries.iter() {
+                writeln!(file, "{}", serde_json::to_string(entry)?)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// The builder of the telemetry buffer actor
+///
+/// As the actor is inserted between the mapper and the MQTT actor,
+/// the mapper has to be connected to this builder as the sink of its messages.
+pub struct TelemetryBufferBuilder {
+    builder: ConvertingActorBuilder<TelemetryBuffer, TopicFilter>,
+}
+
+impl TelemetryBufferBuilder {
+    /// An MQTT service provider, for a mapper to subscribe to the MQTT actor,
+    /// but with the messages published by that mapper sent through this buffer.
+    pub fn with_mqtt<'a, Mqtt>(&self, mqtt: &'a mut Mqtt) -> BufferedMqtt<'a, Mqtt>
+    where
+        Mqtt: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+    {
+        BufferedMqtt {
+            mqtt,
+            buffer: self.get_sender(),
+        }
+    }
+}
+
+impl MessageSink<MqttMessage, NoConfig> for TelemetryBufferBuilder {
+    fn get_config(&self) -> NoConfig {
+        NoConfig
+    }
+
+    fn get_sender(&self) -> DynSender<MqttMessage> {
+        MessageSink::<MqttMessage, TopicFilter>::get_sender(&self.builder)
+    }
+}
+
+impl RuntimeRequestSink for TelemetryBufferBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.builder.get_signal_sender()
+    }
+}
+
+impl Builder<ConvertingActor<TelemetryBuffer>> for TelemetryBufferBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<ConvertingActor<TelemetryBuffer>, Self::Error> {
+        self.builder.try_build()
+    }
+}
+
+/// An MQTT service provider whose consumers' messages are published through a telemetry buffer
+pub struct BufferedMqtt<'a, Mqtt> {
+    mqtt: &'a mut Mqtt,
+    buffer: DynSender<MqttMessage>,
+}
+
+impl<'a, Mqtt> ServiceProvider<MqttMessage, MqttMessage, TopicFilter> for BufferedMqtt<'a, Mqtt>
+where
+    Mqtt: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+{
+    fn connect_consumer(
+        &mut self,
+        subscriptions: TopicFilter,
+        response_sender: DynSender<MqttMessage>,
+    ) -> DynSender<MqttMessage> {
+        // The subscriptions are still managed by the MQTT actor
+        let _ = self.mqtt.connect_consumer(subscriptions, response_sender);
+        self.buffer.sender_clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicI64;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use tedge_test_utils::fs::TempTedgeDir;
+    use time::OffsetDateTime;
+
+    #[derive(Clone, Default)]
+    struct TestClock(Arc<AtomicI64>);
+
+    impl TestClock {
+        fn advance(&self, secs: i64) {
+            self.0.fetch_add(secs, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> clock::Timestamp {
+            OffsetDateTime::from_unix_timestamp(self.0.load(Ordering::SeqCst)).unwrap()
+        }
+    }
+
+    fn config(dir: &TempTedgeDir) -> TelemetryBufferConfig {
+        let data_dir = dir.utf8_path();
+        TelemetryBufferConfig::new("c8y", &MqttSchema::default(), data_dir, 10_000, None)
+            .with_downsampled_topics(TopicFilter::new_unchecked(
+                "c8y/measurement/measurements/create",
+            ))
+    }
+
+    fn bridge_status(status: &str) -> MqttMessage {
+        MqttMessage::new(
+            &Topic::new_unchecked("te/device/main/service/mosquitto-c8y-bridge/status/health"),
+            status,
+        )
+    }
+
+    fn measurement(value: u32) -> MqttMessage {
+        MqttMessage::new(
+            &Topic::new_unchecked("c8y/measurement/measurements/create"),
+            format!(
+                r#"{{"type":"environment","temperature":{{"temperature":{{"value":{value}}}}}}}"#
+            ),
+        )
+    }
+
+    #[test]
+    fn messages_are_buffered_while_the_bridge_is_down() {
+        let dir = TempTedgeDir::new();
+        let mut buffer =
+            TelemetryBuffer::try_new(config(&dir), Box::new(TestClock::default())).unwrap();
+        let local_message = MqttMessage::new(&Topic::new_unchecked("te/device/main///twin/x"), "1");
+
+        // While connected, the messages are forwarded
+        assert_eq!(
+            buffer.convert(&measurement(1)).unwrap(),
+            vec![measurement(1)]
+        );
+
+        // While disconnected, the cloud messages are buffered, but not the local ones
+        assert!(buffer.convert(&bridge_status("0")).unwrap().is_empty());
+        assert!(buffer.convert(&measurement(2)).unwrap().is_empty());
+        assert!(buffer.convert(&measurement(3)).unwrap().is_empty());
+        assert_eq!(
+            buffer.convert(&local_message).unwrap(),
+            vec![local_message.clone()]
+        );
+
+        // On reconnection, the buffered messages are replayed in order
+        assert_eq!(
+            buffer.convert(&bridge_status("1")).unwrap(),
+            vec![measurement(2), measurement(3)]
+        );
+        assert!(buffer.store.is_empty());
+        assert_eq!(
+            buffer.convert(&measurement(4)).unwrap(),
+            vec![measurement(4)]
+        );
+    }
+
+    #[test]
+    fn buffered_messages_survive_a_restart() {
+        let dir = TempTedgeDir::new();
+        {
+            let mut buffer =
+                TelemetryBuffer::try_new(config(&dir), Box::new(TestClock::default())).unwrap();
+            buffer.convert(&bridge_status("0")).unwrap();
+            buffer.convert(&measurement(1)).unwrap();
+            buffer.convert(&measurement(2)).unwrap();
+        }
+
+        let mut buffer =
+            TelemetryBuffer::try_new(config(&dir), Box::new(TestClock::default())).unwrap();
+        assert_eq!(
+            buffer.convert(&bridge_status("1")).unwrap(),
+            vec![measurement(1), measurement(2)]
+        );
+
+        // Once replayed, the messages are removed from disk
+        let mut buffer =
+            TelemetryBuffer::try_new(config(&dir), Box::new(TestClock::default())).unwrap();
+        assert!(buffer.convert(&bridge_status("1")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_when_the_buffer_is_full() {
+        let dir = TempTedgeDir::new();
+        let mut config = config(&dir);
+        config.max_size = 1000;
+        let mut buffer = TelemetryBuffer::try_new(config, Box::new(TestClock::default())).unwrap();
+
+        buffer.convert(&bridge_status("0")).unwrap();
+        for i in 0..100 {
+            buffer.convert(&measurement(i)).unwrap();
+        }
+
+        // Only the latest messages are kept, in order
+        let replayed = buffer.convert(&bridge_status("1")).unwrap();
+        let kept = replayed.len() as u32;
+        assert!(0 < kept && kept < 100);
+        assert_eq!(
+            replayed,
+            (100 - kept..100).map(measurement).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn measurements_are_downsampled_on_replay() {
+        let dir = TempTedgeDir::new();
+        let mut config = config(&dir);
+        config.downsampling_interval = Some(Duration::from_secs(60));
+        let clock = TestClock::default();
+        let mut buffer = TelemetryBuffer::try_new(config, Box::new(clock.clone())).unwrap();
+        let alarm = MqttMessage::new(&Topic::new_unchecked("c8y/s/us"), "301,temperature_high");
+
+        buffer.convert(&bridge_status("0")).unwrap();
+        for i in 0..6 {
+            buffer.convert(&measurement(i)).unwrap();
+            buffer.convert(&alarm).unwrap();
+            clock.advance(30);
+        }
+
+        // Only one measurement per minute is replayed, but all the other messages
+        assert_eq!(
+            buffer.convert(&bridge_status("1")).unwrap(),
+            vec![
+                measurement(0),
+                alarm.clone(),
+                alarm.clone(),
+                measurement(2),
+                alarm.clone(),
+                alarm.clone(),
+                measurement(4),
+                alarm.clone(),
+                alarm.clone(),
+            ]
+        );
+    }
+}
@@ -3,6 +3,8 @@ use crate::az::mapper::AzureMapper;
 use crate::c8y::mapper::CumulocityMapper;
 use crate::collectd::mapper::CollectdMapper;
 use crate::core::component::TEdgeComponent;
+use crate::local::mapper::LocalMapper;
+use crate::otel::mapper::OtelMapper;
 use clap::Parser;
 use flockfile::check_another_instance_is_not_running;
 use std::fmt;
@@ -17,6 +19,8 @@ mod az;
 mod c8y;
 mod collectd;
 mod core;
+mod local;
+mod otel;
 
 fn lookup_component(component_name: &MapperName) -> Box<dyn TEdgeComponent> {
     match component_name {
@@ -24,6 +28,8 @@ fn lookup_component(component_name: &MapperName) -> Box<dyn TEdgeComponent> {
         MapperName::Aws => Box::new(AwsMapper),
         MapperName::Collectd => Box::new(CollectdMapper),
         MapperName::C8y => Box::new(CumulocityMapper),
+        MapperName::Local => Box::new(LocalMapper),
+        MapperName::Otel => Box::new(OtelMapper),
     }
 }
 
@@ -67,6 +73,8 @@ pub enum MapperName {
     Aws,
     C8y,
     Collectd,
+    Local,
+    Otel,
 }
 
 impl fmt::Display for MapperName {
@@ -76,6 +84,8 @@ impl fmt::Display for MapperName {
             MapperName::Aws => write!(f, "tedge-mapper-aws"),
             MapperName::C8y => write!(f, "tedge-mapper-c8y"),
             MapperName::Collectd => write!(f, "tedge-mapper-collectd"),
+            MapperName::Local => write!(f, "tedge-mapper-local"),
+            MapperName::Otel => write!(f, "tedge-mapper-otel"),
         }
     }
 }
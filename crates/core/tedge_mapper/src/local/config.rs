@@ -0,0 +1,375 @@
+use mqtt_channel::TopicFilter;
+use serde::Deserialize;
+use std::path::Path;
+use std::path::PathBuf;
+use tedge_api::mqtt_topics::MqttSchema;
+
+/// The name of the file where the local mapper rules are defined, under `/etc/tedge/mappers`
+pub const LOCAL_MAPPER_RULES_FILE: &str = "local.toml";
+
+/// The rules of the local mapper, as defined in `/etc/tedge/mappers/local.toml`
+///
+/// ```toml
+/// [[rules]]
+/// name = "modbus-temperature"
+/// topic = "modbus/+/data"
+/// target = { entity = "device/${.topic[1]}//", channel = "measurement", type = "environment" }
+///
+/// [[rules.fields]]
+/// from = "values.temp_dC"
+/// to = "temperature"
+/// scale = 0.1
+/// unit = "°C"
+///
+/// [[rules.alarms]]
+/// field = "temperature"
+/// above = 30.0
+/// type = "temperature_high"
+/// severity = "major"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalMapperConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// A transformation rule, converting the messages received on some topics into thin-edge messages
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// The name of the rule, used to report errors
+    pub name: String,
+
+    /// The topic filter of the source messages
+    pub topic: String,
+
+    /// The thin-edge entity and channel where the transformed messages are published
+    pub target: Target,
+
+    /// The fields to extract from the source messages
+    ///
+    /// If none, the source payload is forwarded unchanged
+    #[serde(default)]
+    pub fields: Vec<FieldRule>,
+
+    /// The thresholds raising and clearing alarms
+    #[serde(default)]
+    pub alarms: Vec<AlarmRule>,
+}
+
+/// The target of a rule, the entity and type being templates
+///
+/// The templates can refer to:
+/// - `${.topic[n]}`: the nth level of the source topic, starting from 0
+/// - `${.payload.x.y}`: a value of the source payload
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// The entity topic id of the target, e.g. `device/${.topic[1]}//`
+    #[serde(default = "main_device")]
+    pub entity: String,
+
+    /// The channel of the transformed messages
+    pub channel: TargetChannel,
+
+    /// The measurement, event or alarm type, e.g. `environment` or `${.payload.kind}`
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetChannel {
+    Measurement,
+    Event,
+    Alarm,
+}
+
+/// A field of the source payload to be copied, renamed and scaled into the transformed payload
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldRule {
+    /// The path of the field in the source payload, e.g. `values.temp`
+    pub from: String,
+
+    /// The name of the field in the transformed payload, defaulting to the last segment of `from`
+    ///
+    /// A name of the form `group.name` is used to group measurements.
+    pub to: Option<String>,
+
+    /// The factor applied to numeric values
+    #[serde(default = "one")]
+    pub scale: f64,
+
+    /// The offset added to numeric values, after scaling
+    #[serde(default)]
+    pub offset: f64,
+
+    /// The unit of a measurement
+    pub unit: Option<String>,
+}
+
+/// A threshold on a transformed field, raising an alarm when crossed and clearing it when back to normal
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmRule {
+    /// The name of the field in the transformed payload
+    pub field: String,
+
+    /// The alarm is raised when the value is above this threshold
+    pub above: Option<f64>,
+
+    /// The alarm is raised when the value is below this threshold
+    pub below: Option<f64>,
+
+    /// The type of the alarm
+    #[serde(rename = "type")]
+    pub alarm_type: String,
+
+    #[serde(default = "major")]
+    pub severity: String,
+
+    /// The text of the alarm, defaulting to a description of the crossed threshold
+    pub text: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LocalMapperConfigError {
+    #[error("Failed to read the local mapper rules from {path}: {error}")]
+    ReadError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error("Failed to parse the local mapper rules from {path}: {error}")]
+    ParseError {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+
+    #[error("Invalid rule {rule}: {reason}")]
+    InvalidRule { rule: String, reason: String },
+}
+
+impl LocalMapperConfig {
+    /// Load the rules from the given file, no rules being defined if there is no such file
+    pub fn from_file(
+        path: &Path,
+        mqtt_schema: &MqttSchema,
+    ) -> Result<Self, LocalMapperConfigError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(LocalMapperConfig::default())
+            }
+            Err(error) => {
+                return Err(LocalMapperConfigError::ReadError {
+                    path: path.to_path_buf(),
+                    error,
+                })
+            }
+        };
+        let config: LocalMapperConfig =
+            toml::from_str(&content).map_err(|error| LocalMapperConfigError::ParseError {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        config.validate(mqtt_schema)?;
+        Ok(config)
+    }
+
+    /// The topics of all the source messages
+    pub fn topics(&self) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for rule in self.rules.iter() {
+            topics.add_unchecked(&rule.topic);
+        }
+        topics
+    }
+
+    fn validate(&self, mqtt_schema: &MqttSchema) -> Result<(), LocalMapperConfigError> {
+        for rule in self.rules.iter() {
+            rule.validate(mqtt_schema)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    pub fn source_topics(&self) -> TopicFilter {
+        TopicFilter::new_unchecked(&self.topic)
+    }
+
+    fn validate(&self, mqtt_schema: &MqttSchema) -> Result<(), LocalMapperConfigError> {
+        let invalid_rule = |reason: String| LocalMapperConfigError::InvalidRule {
+            rule: self.name.clone(),
+            reason,
+        };
+
+        let source_topics = TopicFilter::new(&self.topic)
+            .map_err(|_| invalid_rule(format!("invalid topic filter: {}", self.topic)))?;
+        if source_topics.accept_topic(&mqtt_schema.error_topic()) {
+            return Err(invalid_rule(format!(
+                "the topic filter {} would match the mapper's own error messages",
+                self.topic
+            )));
+        }
+
+        if self.target.ty.trim().is_empty() {
+            return Err(invalid_rule("no target type given".to_string()));
+        }
+
+        for alarm in self.alarms.iter() {
+            if alarm.above.is_none() && alarm.below.is_none() {
+                return Err(invalid_rule(format!(
+                    "no threshold given for the alarm {}",
+                    alarm.alarm_type
+                )));
+            }
+            if !self.fields.is_empty() && !self.fields.iter().any(|f| f.name() == alarm.field) {
+                return Err(invalid_rule(format!(
+                    "the alarm {} refers to the unknown field {}",
+                    alarm.alarm_type, alarm.field
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FieldRule {
+    /// The name of the field in the transformed payload
+    pub fn name(&self) -> &str {
+        match &self.to {
+            Some(name) => name,
+            None => self.from.rsplit('.').next().unwrap_or(&self.from),
+        }
+    }
+}
+
+fn main_device() -> String {
+    "device/main//".to_string()
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn major() -> String {
+    "major".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn parse_rules() {
+        let config: LocalMapperConfig = toml::from_str(
+            r#"
+[[rules]]
+name = "modbus-temperature"
+topic = "modbus/+/data"
+target = { entity = "device/${.topic[1]}//", channel = "measurement", type = "environment" }
+
+[[rules.fields]]
+from = "values.temp_dC"
+to = "temperature"
+scale = 0.1
+unit = "°C"
+
+[[rules.fields]]
+from = "values.humidity"
+
+[[rules.alarms]]
+field = "temperature"
+above = 30.0
+type = "temperature_high"
+"#,
+        )
+        .unwrap();
+        config.validate(&MqttSchema::default()).unwrap();
+
+        let rule = &config.rules[0];
+        assert_eq!(rule.target.channel, TargetChannel::Measurement);
+        assert_eq!(rule.fields[0].name(), "temperature");
+        assert_eq!(rule.fields[1].name(), "humidity");
+        assert_eq!(rule.fields[1].scale, 1.0);
+        assert_eq!(rule.alarms[0].severity, "major");
+        assert_eq!(config.topics(), TopicFilter::new_unchecked("modbus/+/data"));
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        let config: LocalMapperConfig = toml::from_str(
+            r#"
+[[rules]]
+name = "no-threshold"
+topic = "sensors/#"
+target = { channel = "measurement", type = "sensors" }
+fields = [ { from = "temp" } ]
+alarms = [ { field = "temp", type = "temp_high" } ]
+"#,
+        )
+        .unwrap();
+        assert_matches!(
+            config.validate(&MqttSchema::default()),
+            Err(LocalMapperConfigError::InvalidRule { rule, .. }) if rule == "no-threshold"
+        );
+
+        let config: LocalMapperConfig = toml::from_str(
+            r##"
+[[rules]]
+name = "everything"
+topic = "#"
+target = { channel = "event", type = "all" }
+"##,
+        )
+        .unwrap();
+        assert_matches!(
+            config.validate(&MqttSchema::default()),
+            Err(LocalMapperConfigError::InvalidRule { rule, .. }) if rule == "everything"
+        );
+    }
+
+    #[test]
+    fn reject_rules_with_no_target_type() {
+        let error = toml::from_str::<LocalMapperConfig>(
+            r#"
+[[rules]]
+name = "no-type"
+topic = "sensors/+"
+target = { channel = "measurement" }
+"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("missing field `type`"));
+
+        let config: LocalMapperConfig = toml::from_str(
+            r#"
+[[rules]]
+name = "empty-type"
+topic = "sensors/+"
+target = { channel = "alarm", type = "" }
+"#,
+        )
+        .unwrap();
+        assert_matches!(
+            config.validate(&MqttSchema::default()),
+            Err(LocalMapperConfigError::InvalidRule { rule, .. }) if rule == "empty-type"
+        );
+    }
+
+    #[test]
+    fn a_missing_rules_file_defines_no_rules() {
+        let config = LocalMapperConfig::from_file(
+            Path::new("/some/unknown/local.toml"),
+            &MqttSchema::default(),
+        )
+        .unwrap();
+        assert!(config.rules.is_empty());
+    }
+}
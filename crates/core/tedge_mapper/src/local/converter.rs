@@ -0,0 +1,475 @@
+use crate::local::config::AlarmRule;
+use crate::local::config::FieldRule;
+use crate::local::config::LocalMapperConfig;
+use crate::local::config::Rule;
+use crate::local::config::TargetChannel;
+use mqtt_channel::Topic;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tedge_actors::Converter;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::TopicIdError;
+use tedge_mqtt_ext::MqttMessage;
+use tracing::error;
+
+#[derive(thiserror::Error, Debug)]
+pub enum LocalConversionError {
+    #[error("Invalid JSON payload: {0}")]
+    FromSerdeJson(#[from] serde_json::Error),
+
+    #[error("Unknown variable in template {template}")]
+    UnknownVariable { template: String },
+
+    #[error("Invalid target entity {entity}: {error}")]
+    InvalidEntity { entity: String, error: TopicIdError },
+
+    #[error("The target type {template} expands to an empty type")]
+    EmptyType { template: String },
+
+    #[error("Missing field {0}")]
+    MissingField(String),
+
+    #[error("Cannot scale the non-numeric field {0}")]
+    NotANumber(String),
+
+    #[error("The transformed message cannot be published on {0}, as this topic matches the rule source topics")]
+    PublishingLoop(String),
+}
+
+/// A converter applying user-defined rules to transform messages into thin-edge messages
+pub struct LocalConverter {
+    mqtt_schema: MqttSchema,
+    rules: Vec<Rule>,
+
+    /// Whether the alarms of the threshold rules are currently raised, by alarm topic
+    ///
+    /// An alarm with no entry is in an unknown state, as it is possibly raised before a restart:
+    /// such an alarm is cleared the first time the value is back to normal.
+    raised_alarms: HashMap<String, bool>,
+}
+
+impl LocalConverter {
+    pub fn new(config: LocalMapperConfig, mqtt_schema: MqttSchema) -> Self {
+        LocalConverter {
+            mqtt_schema,
+            rules: config.rules,
+            raised_alarms: HashMap::new(),
+        }
+    }
+
+    fn try_convert(&mut self, input: &MqttMessage) -> Vec<MqttMessage> {
+        let mut messages = vec![];
+        for rule in self.rules.iter() {
+            if !rule.source_topics().accept(input) {
+                continue;
+            }
+            match apply_rule(rule, &self.mqtt_schema, &mut self.raised_alarms, input) {
+                Ok(transformed) => messages.extend(transformed),
+                Err(err) => {
+                    error!("Mapping error with rule {}: {}", rule.name, err);
+                    messages.push(MqttMessage::new(
+                        &self.mqtt_schema.error_topic(),
+                        format!("Rule {}: {}", rule.name, err),
+                    ))
+                }
+            }
+        }
+        messages
+    }
+}
+
+impl Converter for LocalConverter {
+    type Input = MqttMessage;
+    type Output = MqttMessage;
+    type Error = Infallible;
+
+    fn convert(&mut self, input: &Self::Input) -> Result<Vec<Self::Output>, Self::Error> {
+        Ok(self.try_convert(input))
+    }
+}
+
+fn apply_rule(
+    rule: &Rule,
+    mqtt_schema: &MqttSchema,
+    raised_alarms: &mut HashMap<String, bool>,
+    input: &MqttMessage,
+) -> Result<Vec<MqttMessage>, LocalConversionError> {
+    let payload: Value = serde_json::from_slice(input.payload_bytes())?;
+
+    let entity = expand_template(&rule.target.entity, &input.topic, &payload)?;
+    let entity: EntityTopicId = entity
+        .parse()
+        .map_err(|error| LocalConversionError::InvalidEntity { entity, error })?;
+    let target_type = expand_template(&rule.target.ty, &input.topic, &payload)?;
+    if target_type.is_empty() {
+        return Err(LocalConversionError::EmptyType {
+            template: rule.target.ty.clone(),
+        });
+    }
+    let channel = match rule.target.channel {
+        TargetChannel::Measurement => Channel::Measurement {
+            measurement_type: target_type,
+        },
+        TargetChannel::Event => Channel::Event {
+            event_type: target_type,
+        },
+        TargetChannel::Alarm => Channel::Alarm {
+            alarm_type: target_type,
+        },
+    };
+    let topic = mqtt_schema.topic_for(&entity, &channel);
+    if rule.source_topics().accept_topic(&topic) {
+        return Err(LocalConversionError::PublishingLoop(topic.name));
+    }
+
+    let transformed = if rule.fields.is_empty() {
+        payload
+    } else {
+        transform_fields(&rule.fields, rule.target.channel, &payload)?
+    };
+
+    let mut messages = vec![];
+    let message = MqttMessage::new(&topic, transformed.to_string());
+    if rule.target.channel == TargetChannel::Alarm {
+        messages.push(message.with_retain())
+    } else {
+        messages.push(message)
+    }
+
+    for alarm in rule.alarms.iter() {
+        let Some(value) = json_path(&transformed, &alarm.field).and_then(numeric_value) else {
+            continue;
+        };
+        let alarm_topic = mqtt_schema.topic_for(
+            &entity,
+            &Channel::Alarm {
+                alarm_type: alarm.alarm_type.clone(),
+            },
+        );
+        match crossed_threshold(alarm, value) {
+            Some(text) if raised_alarms.insert(alarm_topic.name.clone(), true) != Some(true) => {
+                let payload = json!({
+                    "severity": alarm.severity,
+                    "text": alarm.text.clone().unwrap_or(text),
+                });
+                messages.push(MqttMessage::new(&alarm_topic, payload.to_string()).with_retain());
+            }
+            None if raised_alarms.insert(alarm_topic.name.clone(), false) != Some(false) => {
+                messages.push(MqttMessage::new(&alarm_topic, "").with_retain());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Build the transformed payload from the selected fields of the source payload
+fn transform_fields(
+    fields: &[FieldRule],
+    channel: TargetChannel,
+    payload: &Value,
+) -> Result<Value, LocalConversionError> {
+    let mut transformed = Map::new();
+    for field in fields.iter() {
+        let value = json_path(payload, &field.from)
+            .ok_or_else(|| LocalConversionError::MissingField(field.from.clone()))?;
+        let mut value = scale(field, value)?;
+        if let (TargetChannel::Measurement, Some(unit)) = (channel, &field.unit) {
+            value = json!({ "value": value, "unit": unit });
+        }
+
+        match field.name().split_once('.') {
+            Some((group, name)) => {
+                let group = transformed
+                    .entry(group.to_string())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Value::Object(group) = group {
+                    group.insert(name.to_string(), value);
+                }
+            }
+            None => {
+                transformed.insert(field.name().to_string(), value);
+            }
+        }
+    }
+    Ok(Value::Object(transformed))
+}
+
+fn scale(field: &FieldRule, value: &Value) -> Result<Value, LocalConversionError> {
+    if field.scale == 1.0 && field.offset == 0.0 {
+        return Ok(value.clone());
+    }
+    value
+        .as_f64()
+        .map(|number| number * field.scale + field.offset)
+        .and_then(|number| serde_json::Number::from_f64(number).map(Value::Number))
+        .ok_or_else(|| LocalConversionError::NotANumber(field.from.clone()))
+}
+
+/// Return a description of the crossed threshold, if any
+fn crossed_threshold(alarm: &AlarmRule, value: f64) -> Option<String> {
+    match (alarm.above, alarm.below) {
+        (Some(threshold), _) if value > threshold => {
+            Some(format!("{} is {value}, above {threshold}", alarm.field))
+        }
+        (_, Some(threshold)) if value < threshold => {
+            Some(format!("{} is {value}, below {threshold}", alarm.field))
+        }
+        _ => None,
+    }
+}
+
+/// The numeric value of a measurement, given either as a number or as `{"value": number}`
+fn numeric_value(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.get("value").and_then(Value::as_f64))
+}
+
+/// The value at the given dot-separated path
+fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+/// Substitute the `${.topic[n]}` and `${.payload.x}` variables of a template
+fn expand_template(
+    template: &str,
+    topic: &Topic,
+    payload: &Value,
+) -> Result<String, LocalConversionError> {
+    let unknown_variable = || LocalConversionError::UnknownVariable {
+        template: template.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let variable = &rest[start + 2..];
+        let end = variable.find('}').ok_or_else(unknown_variable)?;
+        let value =
+            variable_value(&variable[..end], topic, payload).ok_or_else(unknown_variable)?;
+        expanded.push_str(&value);
+        rest = &variable[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn variable_value(variable: &str, topic: &Topic, payload: &Value) -> Option<String> {
+    if let Some(index) = variable
+        .strip_prefix(".topic[")
+        .and_then(|index| index.strip_suffix(']'))
+    {
+        let index: usize = index.parse().ok()?;
+        return topic.name.split('/').nth(index).map(str::to_string);
+    }
+
+    let value = json_path(payload, variable.strip_prefix(".payload.")?)?;
+    match value {
+        Value::String(text) => Some(text.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_json_diff::assert_json_eq;
+
+    fn converter(rules: &str) -> LocalConverter {
+        let config: LocalMapperConfig = toml::from_str(rules).unwrap();
+        LocalConverter::new(config, MqttSchema::default())
+    }
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    fn assert_message(message: &MqttMessage, topic: &str, payload: Value) {
+        assert_eq!(message.topic.name, topic);
+        assert_json_eq!(
+            serde_json::from_slice::<Value>(message.payload_bytes()).unwrap(),
+            payload
+        );
+    }
+
+    const MODBUS_RULES: &str = r#"
+[[rules]]
+name = "modbus"
+topic = "modbus/+/data"
+target = { entity = "device/${.topic[1]}//", channel = "measurement", type = "${.payload.kind}" }
+
+[[rules.fields]]
+from = "values.temp_dC"
+to = "temperature"
+scale = 0.1
+unit = "°C"
+
+[[rules.fields]]
+from = "values.pressure"
+to = "pump.pressure"
+
+[[rules.fields]]
+from = "values.flow"
+to = "pump.flow"
+
+[[rules.alarms]]
+field = "temperature"
+above = 30.0
+type = "temperature_high"
+"#;
+
+    #[test]
+    fn convert_messages_using_rules() {
+        let mut converter = converter(MODBUS_RULES);
+
+        let output = converter
+            .convert(&message(
+                "modbus/pump01/data",
+                r#"{"kind":"hydraulics","values":{"temp_dC":215,"pressure":3.2,"flow":12,"other":1}}"#,
+            ))
+            .unwrap();
+
+        // Along the measurement, the temperature alarm is cleared as its state is unknown
+        assert_eq!(output.len(), 2);
+        assert_message(
+            &output[0],
+            "te/device/pump01///m/hydraulics",
+            json!({
+                "temperature": {"value": 21.5, "unit": "°C"},
+                "pump": {"pressure": 3.2, "flow": 12}
+            }),
+        );
+    }
+
+    #[test]
+    fn raise_and_clear_alarms_on_thresholds() {
+        let mut converter = converter(MODBUS_RULES);
+        let payload = |temp: u32| {
+            format!(
+                r#"{{"kind":"hydraulics","values":{{"temp_dC":{temp},"pressure":3,"flow":1}}}}"#
+            )
+        };
+
+        // Below the threshold, the alarm possibly raised before a restart is cleared once
+        let output = converter
+            .convert(&message("modbus/pump01/data", &payload(250)))
+            .unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(
+            output[1].topic.name,
+            "te/device/pump01///a/temperature_high"
+        );
+        assert!(output[1].payload_bytes().is_empty());
+        let output = converter
+            .convert(&message("modbus/pump01/data", &payload(260)))
+            .unwrap();
+        assert_eq!(output.len(), 1);
+
+        // Above the threshold, the alarm is raised once
+        let output = converter
+            .convert(&message("modbus/pump01/data", &payload(320)))
+            .unwrap();
+        assert_eq!(output.len(), 2);
+        assert_message(
+            &output[1],
+            "te/device/pump01///a/temperature_high",
+            json!({"severity": "major", "text": "temperature is 32, above 30"}),
+        );
+        assert!(output[1].retain);
+        let output = converter
+            .convert(&message("modbus/pump01/data", &payload(330)))
+            .unwrap();
+        assert_eq!(output.len(), 1);
+
+        // Back to normal, the alarm is cleared
+        let output = converter
+            .convert(&message("modbus/pump01/data", &payload(290)))
+            .unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(
+            output[1].topic.name,
+            "te/device/pump01///a/temperature_high"
+        );
+        assert!(output[1].payload_bytes().is_empty());
+        assert!(output[1].retain);
+    }
+
+    #[test]
+    fn forward_the_payload_when_no_fields_are_selected() {
+        let mut converter = converter(
+            r#"
+[[rules]]
+name = "events"
+topic = "app/events"
+target = { channel = "event", type = "app" }
+"#,
+        );
+
+        let output = converter
+            .convert(&message("app/events", r#"{"text":"started"}"#))
+            .unwrap();
+        assert_message(
+            &output[0],
+            "te/device/main///e/app",
+            json!({"text": "started"}),
+        );
+    }
+
+    #[test]
+    fn report_conversion_errors() {
+        let mut converter = converter(MODBUS_RULES);
+
+        let output = converter
+            .convert(&message("modbus/pump01/data", r#"{"kind":"hydraulics"}"#))
+            .unwrap();
+        assert_eq!(output[0].topic.name, "te/errors");
+        assert_eq!(
+            output[0].payload_str().unwrap(),
+            "Rule modbus: Missing field values.temp_dC"
+        );
+
+        let output = converter
+            .convert(&message("modbus/pump01/data", r#"{"values":{}}"#))
+            .unwrap();
+        assert_eq!(
+            output[0].payload_str().unwrap(),
+            "Rule modbus: Unknown variable in template ${.payload.kind}"
+        );
+
+        let output = converter
+            .convert(&message(
+                "modbus/pump01/data",
+                r#"{"kind":"","values":{"temp_dC":215,"pressure":3,"flow":1}}"#,
+            ))
+            .unwrap();
+        assert_eq!(
+            output[0].payload_str().unwrap(),
+            "Rule modbus: The target type ${.payload.kind} expands to an empty type"
+        );
+    }
+
+    #[test]
+    fn reject_rules_publishing_on_their_own_source_topics() {
+        let mut converter = converter(
+            r#"
+[[rules]]
+name = "loop"
+topic = "te/+/+/+/+/m/+"
+target = { channel = "measurement", type = "copy" }
+"#,
+        );
+
+        let output = converter
+            .convert(&message("te/device/main///m/env", r#"{"temperature":20}"#))
+            .unwrap();
+        assert_eq!(output[0].topic.name, "te/errors");
+    }
+}
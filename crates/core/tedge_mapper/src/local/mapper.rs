@@ -0,0 +1,59 @@
+use crate::core::component::TEdgeComponent;
+use crate::core::mapper::start_basic_actors;
+use crate::local::config::LocalMapperConfig;
+use crate::local::config::LOCAL_MAPPER_RULES_FILE;
+use crate::local::converter::LocalConverter;
+use async_trait::async_trait;
+use std::path::Path;
+use tedge_actors::ConvertingActor;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::TEdgeConfig;
+use tracing::warn;
+
+const LOCAL_MAPPER_NAME: &str = "tedge-mapper-local";
+
+/// A mapper transforming local messages into thin-edge messages,
+/// using the rules defined in `/etc/tedge/mappers/local.toml`
+pub struct LocalMapper;
+
+#[async_trait]
+impl TEdgeComponent for LocalMapper {
+    fn session_name(&self) -> &str {
+        LOCAL_MAPPER_NAME
+    }
+
+    async fn start(
+        &self,
+        tedge_config: TEdgeConfig,
+        config_dir: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let (mut runtime, mut mqtt_actor) =
+            start_basic_actors(self.session_name(), &tedge_config).await?;
+        let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+
+        let rules_path = config_dir.join("mappers").join(LOCAL_MAPPER_RULES_FILE);
+        let config = LocalMapperConfig::from_file(&rules_path, &mqtt_schema)?;
+        if config.rules.is_empty() {
+            warn!(
+                "No transformation rules are defined in {}",
+                rules_path.display()
+            );
+        }
+
+        let topics = config.topics();
+        let local_converter = LocalConverter::new(config, mqtt_schema);
+        let mut local_converting_actor =
+            ConvertingActor::builder("LocalConverter", local_converter, topics);
+
+        local_converting_actor.add_input(&mut mqtt_actor);
+        local_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+
+        runtime.spawn(local_converting_actor).await?;
+        runtime.spawn(mqtt_actor).await?;
+        runtime.run_to_completion().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::core::component::TEdgeComponent;
+use crate::core::mapper::start_basic_actors;
+use async_trait::async_trait;
+use clock::WallClock;
+use otel_mapper_ext::OtelMapperBuilder;
+use otel_mapper_ext::OtelMapperConfig;
+use std::path::Path;
+use tedge_config::TEdgeConfig;
+use tedge_http_ext::HttpActor;
+
+const OTEL_MAPPER_NAME: &str = "tedge-mapper-otel";
+
+pub struct OtelMapper;
+
+#[async_trait]
+impl TEdgeComponent for OtelMapper {
+    fn session_name(&self) -> &str {
+        OTEL_MAPPER_NAME
+    }
+
+    async fn start(
+        &self,
+        tedge_config: TEdgeConfig,
+        config_dir: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let (mut runtime, mut mqtt_actor) =
+            start_basic_actors(self.session_name(), &tedge_config).await?;
+
+        let otel_config = OtelMapperConfig::from_tedge_config(config_dir, &tedge_config)?;
+        let mut http_actor = HttpActor::new().builder();
+        let otel_actor = OtelMapperBuilder::try_new(
+            otel_config,
+            Box::new(WallClock),
+            &mut mqtt_actor,
+            &mut http_actor,
+        )?;
+
+        runtime.spawn(otel_actor).await?;
+        runtime.spawn(http_actor).await?;
+        runtime.spawn(mqtt_actor).await?;
+        runtime.run_to_completion().await?;
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8Path;
+use clap::ValueEnum;
 use std::process::Command;
 
+use crate::UpdateStep;
+
 /// Additional flags passed to `tedge-write` process
 #[derive(Debug, PartialEq)]
 pub struct CopyOptions<'a> {
@@ -30,30 +33,21 @@ impl<'a> CopyOptions<'a> {
     ///
     /// Stdin and Stdout are UTF-8.
     pub fn copy(self) -> anyhow::Result<()> {
-        let output = self
-            .command()?
-            .output()
-            .context("Starting tedge-write process failed")?;
-
-        if !output.status.success() {
-            return Err(anyhow!(
-                String::from_utf8(output.stderr).expect("output should be utf-8")
-            ));
-        }
+        run(self.command()?)
+    }
 
-        Ok(())
+    /// Writes the file next to the destination, as the [UpdateStep::Stage] step of an update,
+    /// by spawning new tedge-write process.
+    ///
+    /// Unless the destination doesn't exist yet, the new file is given the permissions of the destination.
+    pub fn stage(self) -> anyhow::Result<()> {
+        let mut command = self.command()?;
+        command.arg("--step").arg("stage");
+        run(command)
     }
 
     pub fn command(&self) -> std::io::Result<Command> {
-        let is_sudo_installed = which::which_global("sudo").is_ok();
-
-        let mut command = if is_sudo_installed && self.sudo {
-            let mut command = Command::new("sudo");
-            command.arg(crate::TEDGE_WRITE_PATH);
-            command
-        } else {
-            Command::new(crate::TEDGE_WRITE_PATH)
-        };
+        let mut command = tedge_write_command(self.sudo);
 
         let from_reader = std::fs::File::open(self.from)?;
         command.stdin(from_reader).arg(self.to);
@@ -71,3 +65,59 @@ impl<'a> CopyOptions<'a> {
         Ok(command)
     }
 }
+
+/// Flags passed to `tedge-write` process to apply a step of an update
+#[derive(Debug, PartialEq)]
+pub struct UpdateOptions<'a> {
+    /// Path of the updated file
+    pub path: &'a Utf8Path,
+
+    /// Step to be applied, the [UpdateStep::Stage] step being applied with [CopyOptions::stage]
+    pub step: UpdateStep,
+
+    /// If tedge-write will be used with sudo
+    pub sudo: bool,
+}
+
+impl<'a> UpdateOptions<'a> {
+    /// Applies the update step by spawning new tedge-write process.
+    pub fn apply(self) -> anyhow::Result<()> {
+        run(self.command())
+    }
+
+    pub fn command(&self) -> Command {
+        let step = self
+            .step
+            .to_possible_value()
+            .expect("no update step is skipped");
+        let mut command = tedge_write_command(self.sudo);
+        command.arg(self.path).arg("--step").arg(step.get_name());
+        command
+    }
+}
+
+fn tedge_write_command(sudo: bool) -> Command {
+    let is_sudo_installed = which::which_global("sudo").is_ok();
+
+    if is_sudo_installed && sudo {
+        let mut command = Command::new("sudo");
+        command.arg(crate::TEDGE_WRITE_PATH);
+        command
+    } else {
+        Command::new(crate::TEDGE_WRITE_PATH)
+    }
+}
+
+fn run(mut command: Command) -> anyhow::Result<()> {
+    let output = command
+        .output()
+        .context("Starting tedge-write process failed")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            String::from_utf8(output.stderr).expect("output should be utf-8")
+        ));
+    }
+
+    Ok(())
+}
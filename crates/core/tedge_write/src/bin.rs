@@ -10,6 +10,9 @@ use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use clap::Parser;
 
+use crate::update;
+use crate::update::UpdateStep;
+
 /// A binary used for writing to files which `tedge` user does not have write permissions for, using
 /// sudo.
 #[derive(Debug, Clone, PartialEq, Eq, Parser)]
@@ -32,6 +35,13 @@ pub struct Args {
     /// Group which will become the new owner of the file.
     #[arg(long)]
     group: Option<Box<str>>,
+
+    /// Step of an update of several files, when all the new versions have to be written
+    /// before any file is replaced.
+    ///
+    /// The new version of the file is read from stdin only by the `stage` step.
+    #[arg(long, value_enum)]
+    step: Option<UpdateStep>,
 }
 
 pub fn run(args: Args) -> anyhow::Result<()> {
@@ -59,9 +69,28 @@ pub fn run(args: Args) -> anyhow::Result<()> {
         );
     }
 
-    let file_existed_before_write = target_filepath.is_file();
-
-    write_stdin_to_file_atomic(&target_filepath)?;
+    let (file_existed_before_write, written_filepath) = match args.step {
+        None => {
+            let file_existed_before_write = target_filepath.is_file();
+            write_stdin_to_file_atomic(&target_filepath)?;
+            (file_existed_before_write, target_filepath)
+        }
+        Some(UpdateStep::Stage) => {
+            let file_existed_before_write =
+                update::stage_file(&target_filepath, &mut io::stdin().lock()).with_context(
+                    || format!("Could not stage a new version of `{target_filepath}`"),
+                )?;
+            (
+                file_existed_before_write,
+                update::staged_path(&target_filepath),
+            )
+        }
+        Some(step) => {
+            return update::apply_step(&target_filepath, step).with_context(|| {
+                format!("Could not apply the {step:?} step on `{target_filepath}`")
+            });
+        }
+    };
 
     if file_existed_before_write {
         return Ok(());
@@ -69,7 +98,7 @@ pub fn run(args: Args) -> anyhow::Result<()> {
 
     if args.user.is_some() || args.group.is_some() {
         chown_by_user_and_group_name(
-            &target_filepath,
+            &written_filepath,
             args.user.as_deref(),
             args.group.as_deref(),
         )
@@ -79,7 +108,7 @@ pub fn run(args: Args) -> anyhow::Result<()> {
     if let Some(mode) = args.mode {
         let mode = u32::from_str_radix(&mode, 8).context("Parsing mode failed")?;
         let permissions = fs::Permissions::from_mode(mode);
-        fs::set_permissions(args.destination_path.as_std_path(), permissions)
+        fs::set_permissions(written_filepath.as_std_path(), permissions)
             .context("Could not set new permissions")?;
     }
 
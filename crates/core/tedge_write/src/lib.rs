@@ -23,7 +23,10 @@
 const TEDGE_WRITE_PATH: &str = "/usr/bin/tedge-write";
 
 pub mod bin;
+pub mod update;
 
 mod api;
 
 pub use api::CopyOptions;
+pub use api::UpdateOptions;
+pub use update::UpdateStep;
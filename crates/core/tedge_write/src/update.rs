@@ -0,0 +1,125 @@
+//! Update of a set of files, with all the new versions written before any file is replaced.
+//!
+//! The new version of each file is first *staged*, i.e. written next to the file it replaces,
+//! so a failure to write any of the new versions is detected before a single file is changed.
+//! The staged versions are then *committed*, i.e. moved into place, the current versions being kept as backups,
+//! so the whole update can be *rolled back*, till the staged and backup versions are *cleaned*.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+
+/// Suffix of the file name of the new version of a file, written next to the current version
+pub const STAGED_FILE_SUFFIX: &str = ".tedge-staged";
+
+/// Suffix of the file name of the previous version of a file, kept till the update is complete
+pub const BACKUP_FILE_SUFFIX: &str = ".tedge-backup";
+
+/// A step of the update of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum UpdateStep {
+    /// Write the new version of the file next to the current version
+    Stage,
+
+    /// Move the staged version into place, keeping the current version as a backup
+    Commit,
+
+    /// Move the current version aside, as a backup
+    Remove,
+
+    /// Restore the backup, removing the file if there was no previous version
+    Rollback,
+
+    /// Remove the staged and backup versions
+    Clean,
+}
+
+/// The path of the new version of a file, as written by the [UpdateStep::Stage] step
+pub fn staged_path(path: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{path}{STAGED_FILE_SUFFIX}"))
+}
+
+/// The path of the previous version of a file, as kept by the [UpdateStep::Commit] and [UpdateStep::Remove] steps
+pub fn backup_path(path: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{path}{BACKUP_FILE_SUFFIX}"))
+}
+
+/// Tell if a file is a staged or a backup version of another file
+pub fn is_update_file(file_name: &str) -> bool {
+    file_name.ends_with(STAGED_FILE_SUFFIX) || file_name.ends_with(BACKUP_FILE_SUFFIX)
+}
+
+/// Write the new version of a file next to the current version, returning true if there is a current version
+///
+/// The staged version is given the ownership and the permissions of the current version, if any.
+pub fn stage_file(path: &Utf8Path, content: &mut impl io::Read) -> io::Result<bool> {
+    let staged_path = staged_path(path);
+    let mut staged_file = fs::File::create(&staged_path)?;
+    io::copy(content, &mut staged_file)?;
+    staged_file.sync_all()?;
+
+    let Ok(current) = path.metadata() else {
+        return Ok(false);
+    };
+    let staged = staged_file.metadata()?;
+    if (current.uid(), current.gid()) != (staged.uid(), staged.gid()) {
+        nix::unistd::chown(
+            staged_path.as_std_path(),
+            Some(nix::unistd::Uid::from_raw(current.uid())),
+            Some(nix::unistd::Gid::from_raw(current.gid())),
+        )?;
+    }
+    fs::set_permissions(
+        &staged_path,
+        fs::Permissions::from_mode(current.permissions().mode()),
+    )?;
+    Ok(true)
+}
+
+/// Apply an update step on a file, but [UpdateStep::Stage] which requires the new content of the file
+pub fn apply_step(path: &Utf8Path, step: UpdateStep) -> io::Result<()> {
+    let backup_path = backup_path(path);
+    match step {
+        UpdateStep::Stage => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the new content of the file is required to stage a file",
+        )),
+        UpdateStep::Commit => {
+            remove_if_exists(&backup_path)?;
+            if path.exists() {
+                // a hard link keeps the previous version unchanged, ownership and permissions included
+                fs::hard_link(path, &backup_path)?;
+            }
+            fs::rename(staged_path(path), path)
+        }
+        UpdateStep::Remove => {
+            remove_if_exists(&backup_path)?;
+            if path.exists() {
+                fs::rename(path, &backup_path)?;
+            }
+            Ok(())
+        }
+        UpdateStep::Rollback => {
+            if backup_path.exists() {
+                fs::rename(&backup_path, path)
+            } else {
+                remove_if_exists(path)
+            }
+        }
+        UpdateStep::Clean => {
+            remove_if_exists(&staged_path(path))?;
+            remove_if_exists(&backup_path)
+        }
+    }
+}
+
+fn remove_if_exists(path: &Utf8Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -101,6 +101,79 @@ fn uses_sudo_only_if_installed() {
     assert_eq!(sudo_command.get_program(), "sudo");
 }
 
+#[test]
+fn stages_a_new_version_with_the_permissions_of_the_current_version() {
+    // Arrange
+    let (temp_dir, source_path) = setup_source_file();
+    let destination_path = temp_dir.path().join("destination.txt");
+    fs::write(&destination_path, "current contents").unwrap();
+    fs::set_permissions(&destination_path, Permissions::from_mode(0o640)).unwrap();
+
+    let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    command.pipe_stdin(&source_path).unwrap();
+    command.arg(&destination_path);
+    command.args(["--mode", "600", "--step", "stage"]);
+
+    // Act
+    command.assert().success();
+
+    // Assert
+    let staged_path = temp_dir.path().join("destination.txt.tedge-staged");
+    assert_eq!(fs::read_to_string(&staged_path).unwrap(), "file contents");
+    let staged_mode = staged_path.metadata().unwrap().permissions().mode();
+    assert_eq!(staged_mode & 0o777, 0o640);
+    assert_eq!(
+        fs::read_to_string(&destination_path).unwrap(),
+        "current contents"
+    );
+}
+
+#[test]
+fn commits_a_staged_version_till_rolled_back() {
+    // Arrange
+    let (temp_dir, source_path) = setup_source_file();
+    let updated_path = temp_dir.path().join("updated.txt");
+    let removed_path = temp_dir.path().join("removed.txt");
+    let added_path = temp_dir.path().join("added.txt");
+    fs::write(&updated_path, "updated: v1").unwrap();
+    fs::write(&removed_path, "removed: v1").unwrap();
+    for path in [&updated_path, &added_path] {
+        let mut stage = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        stage.pipe_stdin(&source_path).unwrap();
+        stage.arg(path).args(["--step", "stage"]);
+        stage.assert().success();
+    }
+
+    // Act
+    for (path, step) in [
+        (&updated_path, "commit"),
+        (&added_path, "commit"),
+        (&removed_path, "remove"),
+    ] {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command.arg(path).args(["--step", step]);
+        command.assert().success();
+    }
+
+    // Assert
+    assert_eq!(fs::read_to_string(&updated_path).unwrap(), "file contents");
+    assert_eq!(fs::read_to_string(&added_path).unwrap(), "file contents");
+    assert!(!removed_path.exists());
+
+    // Act
+    for path in [&updated_path, &added_path, &removed_path] {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command.arg(path).args(["--step", "rollback"]);
+        command.assert().success();
+    }
+
+    // Assert
+    assert_eq!(fs::read_to_string(&updated_path).unwrap(), "updated: v1");
+    assert_eq!(fs::read_to_string(&removed_path).unwrap(), "removed: v1");
+    assert!(!added_path.exists());
+    assert!(!temp_dir.path().join("updated.txt.tedge-backup").exists());
+}
+
 fn setup_source_file() -> (TempDir, PathBuf) {
     let temp_dir = tempfile::tempdir().unwrap();
 
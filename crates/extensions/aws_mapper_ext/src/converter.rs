@@ -7,6 +7,7 @@ use tedge_actors::Converter;
 use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_metrics::Counter;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 use tedge_utils::timestamp::TimeFormat;
@@ -23,6 +24,8 @@ pub struct AwsConverter {
     pub(crate) size_threshold: SizeThreshold,
     pub mqtt_schema: MqttSchema,
     pub time_format: TimeFormat,
+    messages_converted: Counter,
+    conversion_errors: Counter,
 }
 
 impl AwsConverter {
@@ -39,6 +42,8 @@ impl AwsConverter {
             size_threshold,
             mqtt_schema: mqtt_schema.clone(),
             time_format,
+            messages_converted: tedge_metrics::messages_converted("aws"),
+            conversion_errors: tedge_metrics::conversion_errors("aws"),
         }
     }
 
@@ -157,11 +162,15 @@ impl AwsConverter {
         &self,
         messages_or_err: Result<Vec<MqttMessage>, ConversionError>,
     ) -> Vec<MqttMessage> {
+        if messages_or_err.is_ok() {
+            self.messages_converted.inc();
+        }
         messages_or_err.unwrap_or_else(|error| vec![self.new_error_message(error)])
     }
 
     fn new_error_message(&self, error: ConversionError) -> MqttMessage {
         error!("Mapping error: {}", error);
+        self.conversion_errors.inc();
         MqttMessage::new(&self.mqtt_schema.error_topic(), error.to_string())
     }
 }
@@ -303,6 +312,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn converting_typed_values_preserves_units_quality_and_integers() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let input = r#"{
+            "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+            "packets": {"value": 12345678901, "unit": "packet"},
+            "errors": 3,
+            "door_open": true
+         }"#;
+
+        let expected_output = json!({
+            "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+            "packets": {"value": 12345678901_u64, "unit": "packet"},
+            "errors": 3,
+            "door_open": true
+        });
+
+        let output = converter.convert(&new_tedge_message(input)).unwrap();
+
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(&extract_first_message_payload(output))
+                .unwrap(),
+            expected_output
+        );
+    }
+
     #[test]
     fn converting_input_with_timestamp_produces_output_with_timestamp_given_add_timestamp_is_false()
     {
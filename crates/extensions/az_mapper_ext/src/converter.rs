@@ -10,6 +10,7 @@ use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_config::timestamp::TimeFormat;
+use tedge_metrics::Counter;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 
@@ -29,6 +30,8 @@ pub struct AzureConverter {
     pub(crate) size_threshold: SizeThreshold,
     pub(crate) mapper_config: MapperConfig,
     pub mqtt_schema: MqttSchema,
+    messages_converted: Counter,
+    conversion_errors: Counter,
 }
 
 impl AzureConverter {
@@ -50,6 +53,8 @@ impl AzureConverter {
             size_threshold,
             mapper_config,
             mqtt_schema: MqttSchema::default(),
+            messages_converted: tedge_metrics::messages_converted("az"),
+            conversion_errors: tedge_metrics::conversion_errors("az"),
         }
     }
 
@@ -123,11 +128,15 @@ impl AzureConverter {
         &self,
         messages_or_err: Result<Vec<MqttMessage>, ConversionError>,
     ) -> Vec<MqttMessage> {
+        if messages_or_err.is_ok() {
+            self.messages_converted.inc();
+        }
         messages_or_err.unwrap_or_else(|error| vec![self.new_error_message(error)])
     }
 
     fn new_error_message(&self, error: ConversionError) -> MqttMessage {
         error!("Mapping error: {}", error);
+        self.conversion_errors.inc();
         MqttMessage::new(&self.mapper_config.errors_topic, error.to_string())
     }
 }
@@ -257,6 +266,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn converting_typed_values_preserves_units_and_quality() {
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let input = r#"{
+            "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+            "door_open": true
+         }"#;
+
+        let expected_output = json!({
+            "temperature": {"value": 21.3, "unit": "°C", "quality": "good"},
+            "door_open": true
+        });
+
+        let output = converter.convert(&new_tedge_message(input)).unwrap();
+
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(&extract_first_message_payload(output))
+                .unwrap(),
+            expected_output
+        );
+    }
+
     #[test]
     fn converting_input_with_timestamp_produces_output_with_timestamp_given_add_timestamp_is_false()
     {
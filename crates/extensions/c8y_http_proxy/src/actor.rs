@@ -6,6 +6,7 @@ use crate::messages::C8YRestError;
 use crate::messages::C8YRestRequest;
 use crate::messages::C8YRestResult;
 use crate::messages::CreateEvent;
+use crate::messages::DeleteManagedObject;
 use crate::messages::DownloadFile;
 use crate::messages::EventId;
 use crate::messages::SoftwareListResponse;
@@ -121,6 +122,11 @@ impl Actor for C8YHttpProxyActor {
                     .download_file(request)
                     .await
                     .map(|response| response.into()),
+
+                C8YRestRequest::DeleteManagedObject(request) => self
+                    .delete_managed_object(request)
+                    .await
+                    .map(|response| response.into()),
             };
             self.peers.clients.send((client_id, result)).await?;
         }
@@ -239,7 +245,7 @@ impl C8YHttpProxyActor {
         let resp = self.peers.http.await_response(request).await?;
         match resp {
             Ok(response) => match response.status() {
-                StatusCode::OK | StatusCode::CREATED => Ok(Ok(response)),
+                StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(Ok(response)),
                 StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
                     self.try_request_with_fresh_token(build_request).await
                 }
@@ -348,6 +354,32 @@ impl C8YHttpProxyActor {
         Ok(())
     }
 
+    async fn delete_managed_object(
+        &mut self,
+        request: DeleteManagedObject,
+    ) -> Result<Unit, C8YRestError> {
+        let device_id = request.device_id;
+
+        if self.end_point.get_internal_id(device_id.clone()).is_err() {
+            self.get_and_set_internal_id(device_id.clone()).await?;
+        }
+
+        let build_request = |end_point: &C8yEndPoint| {
+            let internal_id = end_point
+                .get_internal_id(device_id.clone())
+                .map_err(|e| C8YRestError::CustomError(e.to_string()));
+            // Also delete the child devices and child additions, i.e. the services
+            let url = internal_id
+                .map(|id| format!("{}?cascade=true", end_point.get_url_for_managed_object(id)));
+            async { Ok::<_, C8YRestError>(HttpRequestBuilder::delete(url?)) }
+        };
+
+        let http_result = self.execute(device_id.clone(), build_request).await?;
+        http_result.error_for_status()?;
+        self.end_point.remove_internal_id(&device_id);
+        Ok(())
+    }
+
     async fn upload_log_binary(
         &mut self,
         request: UploadLogBinary,
@@ -7,7 +7,7 @@ use tedge_actors::ChannelError;
 use tedge_http_ext::HttpError;
 use tedge_utils::file::PermissionEntry;
 
-fan_in_message_type!(C8YRestRequest[GetJwtToken, GetFreshJwtToken, CreateEvent, SoftwareListResponse, UploadLogBinary, UploadFile, DownloadFile]: Debug, PartialEq, Eq);
+fan_in_message_type!(C8YRestRequest[GetJwtToken, GetFreshJwtToken, CreateEvent, SoftwareListResponse, UploadLogBinary, UploadFile, DownloadFile, DeleteManagedObject]: Debug, PartialEq, Eq);
 //HIPPO Rename EventId to String as there could be many other String responses as well and this macro doesn't allow another String variant
 fan_in_message_type!(C8YRestResponse[EventId, Url, Unit]: Debug);
 
@@ -93,6 +93,12 @@ pub struct DownloadFile {
     pub file_permissions: PermissionEntry,
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeleteManagedObject {
+    /// C8y's external ID of the device or service
+    pub device_id: String,
+}
+
 pub type EventId = String;
 
 pub type Unit = ();
@@ -356,6 +356,61 @@ async fn retry_software_list_once_with_fresh_internal_id() {
     .await;
 }
 
+#[tokio::test]
+async fn delete_managed_object_of_child_device() {
+    let c8y_host = "c8y.tenant.io";
+    let device_id = "device-001";
+    let child_id = "child-001";
+    let token = "JWT token";
+    let tmp_dir = "/tmp";
+
+    let (mut proxy, mut c8y) =
+        spawn_c8y_http_proxy(c8y_host.into(), device_id.into(), tmp_dir.into(), token).await;
+
+    // skip the internal id request of the main device
+    c8y.recv().await;
+    let c8y_response = HttpResponseBuilder::new()
+        .status(200)
+        .json(&InternalIdResponse::new("100", device_id))
+        .build()
+        .unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    let deletion = tokio::spawn(async move { proxy.delete_managed_object(child_id.into()).await });
+
+    // The proxy first requests the internal id of the child device
+    c8y.assert_recv(Some(
+        HttpRequestBuilder::get(format!(
+            "https://{c8y_host}/identity/externalIds/c8y_Serial/{child_id}"
+        ))
+        .bearer_auth(token)
+        .build()
+        .unwrap(),
+    ))
+    .await;
+    let c8y_response = HttpResponseBuilder::new()
+        .status(200)
+        .json(&InternalIdResponse::new("200", child_id))
+        .build()
+        .unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    // Then deletes the managed object of the child device along with its children
+    c8y.assert_recv(Some(
+        HttpRequestBuilder::delete(format!(
+            "https://{c8y_host}/inventory/managedObjects/200?cascade=true"
+        ))
+        .bearer_auth(token)
+        .build()
+        .unwrap(),
+    ))
+    .await;
+    let c8y_response = HttpResponseBuilder::new().status(204).build().unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    assert!(deletion.await.unwrap().is_ok());
+}
+
 #[tokio::test]
 async fn auto_retry_upload_log_binary_when_internal_id_expires() {
     let c8y_host = "c8y.tenant.io";
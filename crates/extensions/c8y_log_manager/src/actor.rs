@@ -116,13 +116,15 @@ impl LogManagerActor {
         self.mqtt_publisher.send(executing).await?;
 
         let log_path = log_manager::new_read_logs(
-            &self.plugin_config.files,
+            &self.plugin_config,
             &smartrest_request.log_type,
             smartrest_request.date_from,
+            smartrest_request.date_to,
             smartrest_request.lines,
             &smartrest_request.search_text,
             &self.config.tmp_dir,
-        )?;
+        )
+        .await?;
 
         let log_content = std::fs::read_to_string(&log_path)?;
 
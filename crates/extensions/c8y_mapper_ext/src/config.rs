@@ -10,8 +10,11 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use tedge_api::mqtt_topics::ChannelFilter::AlarmMetadata;
 use tedge_api::mqtt_topics::ChannelFilter::Command;
 use tedge_api::mqtt_topics::ChannelFilter::CommandMetadata;
+use tedge_api::mqtt_topics::ChannelFilter::EventMetadata;
+use tedge_api::mqtt_topics::ChannelFilter::MeasurementMetadata;
 use tedge_api::mqtt_topics::EntityFilter::AnyEntity;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
@@ -145,6 +148,7 @@ impl C8yMapperConfig {
             topics.add_all(mqtt_schema.topics(AnyEntity, Command(cmd.clone())));
             topics.add_all(mqtt_schema.topics(AnyEntity, CommandMetadata(cmd)));
         }
+        topics.add_all(Self::telemetry_metadata_topic_filter(&mqtt_schema));
 
         if capabilities.log_upload {
             topics.add_all(crate::operations::log_upload::log_upload_topic_filter(
@@ -213,6 +217,15 @@ impl C8yMapperConfig {
         Ok(topic_filter)
     }
 
+    /// The metadata of the measurements, events and alarms, used to enrich the telemetry data
+    pub fn telemetry_metadata_topic_filter(mqtt_schema: &MqttSchema) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for channel in [MeasurementMetadata, EventMetadata, AlarmMetadata] {
+            topics.add_all(mqtt_schema.topics(AnyEntity, channel));
+        }
+        topics
+    }
+
     /// List of all possible external topics that Cumulocity mapper addresses. For testing purpose.
     #[cfg(test)]
     pub fn default_external_topic_filter() -> TopicFilter {
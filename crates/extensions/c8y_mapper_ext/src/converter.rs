@@ -10,6 +10,8 @@ use crate::dynamic_discovery::DiscoverOp;
 use crate::error::ConversionError;
 use crate::json;
 use crate::operations::FtsDownloadOperationData;
+use crate::operations::CANCELLED_OPERATION_REASON;
+use crate::operations::QUEUED_OPERATION_EVENT_TYPE;
 use anyhow::anyhow;
 use anyhow::Context;
 use c8y_api::http_proxy::C8yEndPoint;
@@ -36,6 +38,7 @@ use c8y_api::smartrest::operations::Operations;
 use c8y_api::smartrest::operations::ResultFormat;
 use c8y_api::smartrest::smartrest_deserializer::AvailableChildDevices;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
+use c8y_api::smartrest::smartrest_serializer::create_event;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::request_pending_operations;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
@@ -84,9 +87,11 @@ use tedge_api::mqtt_topics::IdGenerator;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
 use tedge_api::pending_entity_store::PendingEntityData;
+use tedge_api::workflow::GenericCommandState;
 use tedge_api::DownloadInfo;
 use tedge_api::EntityStore;
 use tedge_config::TEdgeConfigError;
+use tedge_metrics::Counter;
 use tedge_mqtt_ext::Message;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
@@ -122,6 +127,9 @@ pub struct MapperConfig {
 impl CumulocityConverter {
     pub async fn convert(&mut self, input: &Message) -> Vec<Message> {
         let messages_or_err = self.try_convert(input).await;
+        if messages_or_err.is_ok() {
+            self.messages_converted.inc();
+        }
         self.wrap_errors(messages_or_err)
     }
 
@@ -138,6 +146,7 @@ impl CumulocityConverter {
 
     pub fn new_error_message(&self, error: ConversionError) -> Message {
         error!("Mapping error: {}", error);
+        self.conversion_errors.inc();
         Message::new(&self.get_mapper_config().errors_topic, error.to_string())
     }
 
@@ -211,6 +220,9 @@ pub struct CumulocityConverter {
     pub command_id: IdGenerator,
     // Keep active command IDs to avoid creation of multiple commands for an operation
     pub active_commands: HashSet<CmdId>,
+
+    messages_converted: Counter,
+    conversion_errors: Counter,
 }
 
 impl CumulocityConverter {
@@ -299,6 +311,8 @@ impl CumulocityConverter {
             pending_fts_download_operations: HashMap::new(),
             command_id,
             active_commands: HashSet::new(),
+            messages_converted: tedge_metrics::messages_converted("c8y"),
+            conversion_errors: tedge_metrics::conversion_errors("c8y"),
         })
     }
 
@@ -538,6 +552,24 @@ impl CumulocityConverter {
         Ok(mqtt_messages)
     }
 
+    /// Store the metadata of a measurement, event or alarm type,
+    /// used to enrich the subsequent measurements, events or alarms of that type
+    fn update_telemetry_metadata(
+        &mut self,
+        source: &EntityTopicId,
+        channel: &Channel,
+        message: &Message,
+    ) -> Result<Vec<Message>, ConversionError> {
+        let metadata = if message.payload_bytes().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice::<Value>(message.payload_bytes())?
+        };
+        self.entity_store
+            .update_telemetry_metadata(source, channel, metadata)?;
+        Ok(vec![])
+    }
+
     pub async fn process_health_status_message(
         &mut self,
         entity: &EntityTopicId,
@@ -1035,6 +1067,9 @@ impl CumulocityConverter {
     ) -> Result<Vec<Message>, ConversionError> {
         let mut registration_messages: Vec<Message> = vec![];
         match &channel {
+            Channel::EntityMetadata if message.payload_bytes().is_empty() => {
+                return self.try_deregister_entity(&source).await;
+            }
             Channel::EntityMetadata => {
                 if let Ok(register_message) = EntityRegistrationMessage::try_from(message) {
                     match self.entity_store.update(register_message.clone()) {
@@ -1062,6 +1097,10 @@ impl CumulocityConverter {
                     }
                 }
             }
+            _ if message.payload_bytes().is_empty() && self.entity_store.get(&source).is_none() => {
+                // A retained message is cleared for an unknown entity, e.g. after its deregistration
+                return Ok(vec![]);
+            }
             _ => {
                 // if device is unregistered register using auto-registration
                 if self.entity_store.get(&source).is_none() {
@@ -1104,6 +1143,34 @@ impl CumulocityConverter {
         Ok(registration_messages)
     }
 
+    /// Deregister an entity along with its descendants
+    ///
+    /// Returns the messages clearing the retained messages of the removed entities,
+    /// and deletes the managed object of the entity from Cumulocity.
+    async fn try_deregister_entity(
+        &mut self,
+        source: &EntityTopicId,
+    ) -> Result<Vec<Message>, ConversionError> {
+        let (removed_entities, clear_messages) = self.entity_store.deregister_entity(source)?;
+
+        if let Some(entity) = removed_entities.first() {
+            // The managed objects of the descendants are deleted along their ancestor
+            let external_id = entity.external_id.as_ref().to_string();
+            if let Err(err) = self
+                .http_proxy
+                .delete_managed_object(external_id.clone())
+                .await
+            {
+                error!("Failed to delete the managed object {external_id} of {source}: {err}");
+            }
+        }
+        for entity in removed_entities {
+            self.children.remove(entity.external_id.as_ref());
+        }
+
+        Ok(clear_messages)
+    }
+
     async fn try_convert_data_message(
         &mut self,
         source: EntityTopicId,
@@ -1127,14 +1194,24 @@ impl CumulocityConverter {
                 self.process_alarm_messages(&source, message, alarm_type)
             }
 
-            Channel::Command { cmd_id, .. } if message.payload_bytes().is_empty() => {
+            Channel::MeasurementMetadata { .. }
+            | Channel::EventMetadata { .. }
+            | Channel::AlarmMetadata { .. } => {
+                self.update_telemetry_metadata(&source, &channel, message)
+            }
+
+            Channel::Command { operation, cmd_id } if message.payload_bytes().is_empty() => {
                 // The command has been fully processed
                 self.active_commands.remove(cmd_id);
+                self.entity_store
+                    .remove_command(&source, operation.clone(), cmd_id.clone());
                 Ok(vec![])
             }
 
             Channel::CommandMetadata { operation } => {
                 self.validate_operation_supported(operation, &source)?;
+                self.entity_store
+                    .register_capability(&source, operation.clone());
                 match operation {
                     OperationType::Restart => self.register_restart_operation(&source).await,
                     OperationType::SoftwareList => {
@@ -1159,6 +1236,11 @@ impl CumulocityConverter {
 
             Channel::Command { operation, cmd_id } if self.command_id.is_generator_of(cmd_id) => {
                 self.active_commands.insert(cmd_id.clone());
+                self.entity_store
+                    .register_command(&source, operation.clone(), cmd_id.clone());
+                if is_queued_command(message) {
+                    return self.convert_queued_command(&source, operation);
+                }
                 match operation {
                     OperationType::Restart => {
                         self.publish_restart_operation_status(&source, cmd_id, message)
@@ -1191,6 +1273,13 @@ impl CumulocityConverter {
                 }
             }
 
+            Channel::Command { operation, cmd_id } => {
+                // A command not created by the mapper, still to be cleared on deregistration
+                self.entity_store
+                    .register_command(&source, operation.clone(), cmd_id.clone());
+                Ok(vec![])
+            }
+
             Channel::Health => self.process_health_status_message(&source, message).await,
 
             _ => Ok(vec![]),
@@ -1214,6 +1303,21 @@ impl CumulocityConverter {
         Ok(converted_messages)
     }
 
+    /// Raise an event telling why a queued command doesn't progress, the operation being left pending
+    fn convert_queued_command(
+        &self,
+        target: &EntityTopicId,
+        operation: &OperationType,
+    ) -> Result<Vec<Message>, ConversionError> {
+        let topic = self.smartrest_publish_topic_for_entity(target)?;
+        let text =
+            format!("{operation} operation queued, awaiting the completion of running operations");
+        Ok(vec![Message::new(
+            &topic,
+            create_event(QUEUED_OPERATION_EVENT_TYPE, &text),
+        )])
+    }
+
     fn validate_operation_supported(
         &self,
         op_type: &OperationType,
@@ -1387,6 +1491,13 @@ fn get_child_external_id(dir_path: &Path) -> Result<String, ConversionError> {
     }
 }
 
+fn is_queued_command(message: &Message) -> bool {
+    GenericCommandState::from_command_message(message)
+        .ok()
+        .flatten()
+        .is_some_and(|state| state.is_queued())
+}
+
 fn create_get_pending_operations_message() -> Result<Message, ConversionError> {
     let topic = C8yTopic::SmartRestResponse.to_topic()?;
     Ok(Message::new(&topic, request_pending_operations()))
@@ -1529,6 +1640,17 @@ impl CumulocityConverter {
                     Message::new(&topic, smartrest_set_operation),
                 ])
             }
+            CommandStatus::Cancelled => {
+                let smartrest_set_operation = fail_operation(
+                    CumulocitySupportedOperations::C8yRestartRequest,
+                    CANCELLED_OPERATION_REASON,
+                );
+
+                Ok(vec![
+                    command.clearing_message(&self.mqtt_schema),
+                    Message::new(&topic, smartrest_set_operation),
+                ])
+            }
             _ => {
                 // The other states are ignored
                 Ok(vec![])
@@ -1585,7 +1707,10 @@ impl CumulocityConverter {
             .ok_or_else(|| Error::UnknownEntity(target.to_string()))?;
 
         match response.status() {
-            CommandStatus::Init | CommandStatus::Scheduled | CommandStatus::Unknown => {
+            CommandStatus::Init
+            | CommandStatus::Scheduled
+            | CommandStatus::Queued
+            | CommandStatus::Unknown => {
                 // The command has not been processed yet
                 Ok(vec![])
             }
@@ -1608,6 +1733,18 @@ impl CumulocityConverter {
                 let smartrest_set_operation =
                     fail_operation(CumulocitySupportedOperations::C8ySoftwareUpdate, &reason);
 
+                Ok(vec![
+                    Message::new(&topic, smartrest_set_operation),
+                    response.clearing_message(&self.mqtt_schema),
+                    self.request_software_list(target),
+                ])
+            }
+            CommandStatus::Cancelled => {
+                let smartrest_set_operation = fail_operation(
+                    CumulocitySupportedOperations::C8ySoftwareUpdate,
+                    CANCELLED_OPERATION_REASON,
+                );
+
                 Ok(vec![
                     Message::new(&topic, smartrest_set_operation),
                     response.clearing_message(&self.mqtt_schema),
@@ -1654,8 +1791,11 @@ impl CumulocityConverter {
                 Ok(vec![response.clearing_message(&self.mqtt_schema)])
             }
 
+            CommandStatus::Cancelled => Ok(vec![response.clearing_message(&self.mqtt_schema)]),
+
             CommandStatus::Init
             | CommandStatus::Scheduled
+            | CommandStatus::Queued
             | CommandStatus::Executing
             | CommandStatus::Unknown => {
                 // C8Y doesn't expect any message to be published
@@ -1920,7 +2060,7 @@ pub(crate) mod tests {
                         },
                         "temp":{
                             "temp":{
-                                "value":1.0
+                                "value":1
                             }
                         },
                         "time":"2021-11-16T17:45:40.571760714+01:00",
@@ -1966,7 +2106,7 @@ pub(crate) mod tests {
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
             json!({
                 "externalSource":{"externalId":"nested_child","type":"c8y_Serial"},
-                "temp":{"temp":{"value":1.0}},
+                "temp":{"temp":{"value":1}},
                 "time":"2021-11-16T17:45:40.571760714+01:00",
                 "type":"ThinEdgeMeasurement"
             })
@@ -2023,7 +2163,7 @@ pub(crate) mod tests {
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
             json!({
                 "externalSource":{"externalId":"nested_service","type":"c8y_Serial"},
-                "temp":{"temp":{"value":1.0}},
+                "temp":{"temp":{"value":1}},
                 "time":"2021-11-16T17:45:40.571760714+01:00",
                 "type":"ThinEdgeMeasurement"
             })
@@ -2083,7 +2223,7 @@ pub(crate) mod tests {
                     "externalId":"test-device:device:child1:service:app1",
                     "type":"c8y_Serial"
                 },
-                "temp":{"temp":{"value":1.0}},
+                "temp":{"temp":{"value":1}},
                 "time":"2021-11-16T17:45:40.571760714+01:00",
                 "type":"m_type"})
             .to_string(),
@@ -2135,7 +2275,7 @@ pub(crate) mod tests {
                     "externalId":"test-device:device:main:service:appm",
                     "type":"c8y_Serial"
                 },
-                "temp":{"temp":{"value":1.0}},
+                "temp":{"temp":{"value":1}},
                 "time":"2021-11-16T17:45:40.571760714+01:00",
                 "type":"m_type"})
             .to_string(),
@@ -2194,7 +2334,7 @@ pub(crate) mod tests {
         );
         let expected_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
+            r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
         );
         assert_eq!(
             out_second_messages,
@@ -2223,7 +2363,7 @@ pub(crate) mod tests {
         );
         let expected_first_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
+            r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
         );
         assert_eq!(
             out_first_messages,
@@ -2248,7 +2388,7 @@ pub(crate) mod tests {
         );
         let expected_second_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"externalSource":{"externalId":"test-device:device:child2","type":"c8y_Serial"},"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
+            r#"{"externalSource":{"externalId":"test-device:device:child2","type":"c8y_Serial"},"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"ThinEdgeMeasurement"}"#,
         );
         assert_eq!(
             out_second_messages,
@@ -2270,7 +2410,7 @@ pub(crate) mod tests {
 
         let expected_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"test_type"}"#,
+            r#"{"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"test_type"}"#,
         );
 
         // Test the output messages contains SmartREST and C8Y JSON.
@@ -2294,7 +2434,7 @@ pub(crate) mod tests {
 
         let expected_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"type_in_payload"}"#,
+            r#"{"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"type_in_payload"}"#,
         );
 
         // Test the output messages contains SmartREST and C8Y JSON.
@@ -2323,7 +2463,7 @@ pub(crate) mod tests {
 
         let expected_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"externalSource":{"externalId":"test-device:device:child","type":"c8y_Serial"},"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"test_type"}"#,
+            r#"{"externalSource":{"externalId":"test-device:device:child","type":"c8y_Serial"},"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"test_type"}"#,
         );
 
         // Test the output messages contains SmartREST and C8Y JSON.
@@ -2357,7 +2497,7 @@ pub(crate) mod tests {
 
         let expected_c8y_json_message = Message::new(
             &Topic::new_unchecked("c8y/measurement/measurements/create"),
-            r#"{"externalSource":{"externalId":"test-device:device:child2","type":"c8y_Serial"},"temp":{"temp":{"value":1.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"type_in_payload"}"#,
+            r#"{"externalSource":{"externalId":"test-device:device:child2","type":"c8y_Serial"},"temp":{"temp":{"value":1}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"type_in_payload"}"#,
         );
 
         // Test the first output messages contains SmartREST and C8Y JSON.
@@ -2590,7 +2730,7 @@ pub(crate) mod tests {
         assert!(result[0]
             .payload_str()
             .unwrap()
-            .contains(r#"{"temperature0":{"temperature0":{"value":0.0}}"#));
+            .contains(r#"{"temperature0":{"temperature0":{"value":0}}"#));
         assert!(result[0]
             .payload_str()
             .unwrap()
@@ -2641,7 +2781,7 @@ pub(crate) mod tests {
 
         assert!(payload1.contains("101,test-device:device:child1,child1,thin-edge.io-child"));
         assert!(payload2 .contains(
-        r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temperature0":{"temperature0":{"value":0.0}},"#
+        r#"{"externalSource":{"externalId":"test-device:device:child1","type":"c8y_Serial"},"temperature0":{"temperature0":{"value":0}},"#
     ));
         assert!(payload2.contains(r#""type":"ThinEdgeMeasurement""#));
     }
@@ -3263,7 +3403,7 @@ pub(crate) mod tests {
                     json!({
                         "temperature":{
                             "temperature":{
-                                "value": 0.0
+                                "value": 0
                             }
                         },
                     })
@@ -3274,7 +3414,7 @@ pub(crate) mod tests {
                     json!({
                         "temperature":{
                             "temperature":{
-                                "value": 1.0
+                                "value": 1
                             }
                         },
                     })
@@ -3285,7 +3425,7 @@ pub(crate) mod tests {
                     json!({
                         "temperature":{
                             "temperature":{
-                                "value": 2.0
+                                "value": 2
                             }
                         },
                     })
@@ -5,6 +5,7 @@ use crate::converter::UploadOperationData;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
 use crate::operations::FtsDownloadOperationType;
+use crate::operations::CANCELLED_OPERATION_REASON;
 use anyhow::Context;
 use c8y_api::json_c8y_deserializer::C8yUploadConfigFile;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
@@ -160,6 +161,17 @@ impl CumulocityConverter {
                     .with_qos(QoS::AtLeastOnce);
                 vec![c8y_notification, clear_local_cmd]
             }
+            CommandStatus::Cancelled => {
+                let smartrest_operation_status = fail_operation(
+                    CumulocitySupportedOperations::C8yUploadConfigFile,
+                    CANCELLED_OPERATION_REASON,
+                );
+                let c8y_notification = Message::new(&smartrest_topic, smartrest_operation_status);
+                let clear_local_cmd = Message::new(&message.topic, "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce);
+                vec![c8y_notification, clear_local_cmd]
+            }
             _ => {
                 vec![] // Do nothing as other components might handle those states
             }
@@ -1,6 +1,7 @@
 use crate::converter::CumulocityConverter;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
+use crate::operations::CANCELLED_OPERATION_REASON;
 use c8y_api::json_c8y_deserializer::C8yDownloadConfigFile;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
@@ -86,6 +87,18 @@ impl CumulocityConverter {
 
                 vec![c8y_notification, clear_local_cmd]
             }
+            CommandStatus::Cancelled => {
+                let smartrest_operation_status = fail_operation(
+                    CumulocitySupportedOperations::C8yDownloadConfigFile,
+                    CANCELLED_OPERATION_REASON,
+                );
+                let c8y_notification = Message::new(&sm_topic, smartrest_operation_status);
+                let clear_local_cmd = Message::new(&message.topic, "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce);
+
+                vec![c8y_notification, clear_local_cmd]
+            }
             _ => {
                 vec![] // Do nothing as other components might handle those states
             }
@@ -1,6 +1,7 @@
 use crate::converter::CumulocityConverter;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
+use crate::operations::CANCELLED_OPERATION_REASON;
 use c8y_api::json_c8y_deserializer::C8yFirmware;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
@@ -59,6 +60,7 @@ impl CumulocityConverter {
             remote_url: firmware_request.url,
             name: firmware_request.name,
             version: firmware_request.version,
+            sha256: None,
         };
 
         // Command messages must be retained
@@ -131,6 +133,18 @@ impl CumulocityConverter {
 
                 vec![c8y_notification, clear_local_cmd]
             }
+            CommandStatus::Cancelled => {
+                let smartrest_operation_status = fail_operation(
+                    CumulocitySupportedOperations::C8yFirmware,
+                    CANCELLED_OPERATION_REASON,
+                );
+                let c8y_notification = Message::new(&sm_topic, smartrest_operation_status);
+                let clear_local_cmd = Message::new(&message.topic, "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce);
+
+                vec![c8y_notification, clear_local_cmd]
+            }
             _ => {
                 vec![] // Do nothing as other components might handle those states
             }
@@ -453,6 +467,40 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn handle_firmware_update_cancelled_cmd_for_main_device() {
+        let cfg_dir = TempTedgeDir::new();
+        let (mqtt, _http, _fs, _timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+        let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+        skip_init_messages(&mut mqtt).await;
+
+        // Simulate firmware_update command with "cancelled" state
+        mqtt.send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/firmware_update/c8y-mapper-1234"),
+            json!({
+                "status": "cancelled",
+                "cancelled_state": "executing",
+                "name": "myFirmware",
+                "version": "1.0",
+                "remoteUrl": "http://www.my.url",
+            })
+            .to_string(),
+        ))
+        .await
+        .expect("Send failed");
+
+        // Expect `502` smartrest message on `c8y/s/us` and the local command to be cleared
+        assert_received_contains_str(
+            &mut mqtt,
+            [
+                ("c8y/s/us", "502,c8y_Firmware,Operation cancelled"),
+                ("te/device/main///cmd/firmware_update/c8y-mapper-1234", ""),
+            ],
+        )
+        .await;
+    }
+
     #[tokio::test]
     async fn handle_firmware_update_successful_cmd_for_main_device() {
         let ttd = TempTedgeDir::new();
@@ -5,6 +5,7 @@ use crate::converter::CumulocityConverter;
 use crate::converter::UploadOperationData;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
+use crate::operations::CANCELLED_OPERATION_REASON;
 use anyhow::Context;
 use c8y_api::json_c8y_deserializer::C8yLogfileRequest;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
@@ -83,6 +84,8 @@ impl CumulocityConverter {
             date_to: log_request.date_to,
             search_text: Some(log_request.search_text).filter(|s| !s.is_empty()),
             lines: log_request.maximum_lines,
+            // the compression configured on the device for the log type, if any
+            compression: None,
         };
 
         // Command messages must be retained
@@ -163,6 +166,17 @@ impl CumulocityConverter {
                     .with_qos(QoS::AtLeastOnce);
                 vec![c8y_notification, clean_operation]
             }
+            CommandStatus::Cancelled => {
+                let smartrest_operation_status = fail_operation(
+                    CumulocitySupportedOperations::C8yLogFileRequest,
+                    CANCELLED_OPERATION_REASON,
+                );
+                let c8y_notification = Message::new(&smartrest_topic, smartrest_operation_status);
+                let clean_operation = Message::new(&message.topic, "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce);
+                vec![c8y_notification, clean_operation]
+            }
             _ => {
                 vec![] // Do nothing as other components might handle those states
             }
@@ -217,13 +231,19 @@ impl CumulocityConverter {
             .c8y_endpoint
             .get_url_for_event_binary_upload_unchecked(&event_response_id);
 
+        // Cumulocity provides no resumable upload of event binaries:
+        // an interrupted upload is retried from the beginning by the uploader
+        let content_type = match response.compression {
+            Some(_) => ContentType::ApplicationOctetStream,
+            None => ContentType::TextPlain,
+        };
         let upload_request = UploadRequest::new(
             self.auth_proxy
                 .proxy_url(binary_upload_event_url.clone())
                 .as_str(),
             &Utf8PathBuf::try_from(download_response.file_path).map_err(|e| e.into_io_error())?,
         )
-        .with_content_type(ContentType::TextPlain);
+        .with_content_type(content_type);
 
         self.uploader_sender
             .send((cmd_id.clone(), upload_request))
@@ -671,6 +691,7 @@ mod tests {
             request.1.url,
             "http://127.0.0.1:8001/c8y/event/events/dummy-event-id-1234/binaries"
         );
+        assert_eq!(request.1.content_type, ContentType::TextPlain);
 
         // Simulate Uploader returns a result
         ul.send((
@@ -694,6 +715,51 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn handle_log_upload_successful_cmd_with_compressed_logs() {
+        let ttd = TempTedgeDir::new();
+        let (mqtt, http, _fs, _timer, ul, dl) = spawn_c8y_mapper_actor(&ttd, true).await;
+        spawn_dummy_c8y_http_proxy(http);
+
+        let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+        let mut ul = ul.with_timeout(TEST_TIMEOUT_MS);
+        let mut dl = dl.with_timeout(TEST_TIMEOUT_MS);
+        skip_init_messages(&mut mqtt).await;
+
+        // Simulate log_upload command with "successful" state, the logs having been compressed by the agent
+        mqtt.send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/log_upload/c8y-mapper-1234"),
+            json!({
+            "status": "successful",
+            "tedgeUrl": "http://localhost:8888/tedge/file-transfer/test-device/log_upload/typeA-c8y-mapper-1234",
+            "type": "typeA",
+            "dateFrom": "2013-06-22T17:03:14.123+02:00",
+            "dateTo": "2013-06-23T18:03:14.123+02:00",
+            "lines": 1000,
+            "compression": "gzip"
+        })
+                .to_string(),
+        ))
+            .await
+            .expect("Send failed");
+
+        let download_request = dl.recv().await.expect("timeout");
+        dl.send((
+            download_request.0,
+            Ok(DownloadResponse {
+                url: download_request.1.url,
+                file_path: download_request.1.file_path,
+            }),
+        ))
+        .await
+        .unwrap();
+
+        // The compressed logs are uploaded as binary content
+        let request = ul.recv().await.expect("timeout");
+        assert_eq!(request.0, "c8y-mapper-1234");
+        assert_eq!(request.1.content_type, ContentType::ApplicationOctetStream);
+    }
+
     #[tokio::test]
     async fn handle_log_upload_successful_cmd_for_child_device() {
         let ttd = TempTedgeDir::new();
@@ -27,6 +27,16 @@ pub mod config_update;
 pub mod firmware_update;
 pub mod log_upload;
 
+/// The failure reason reported to Cumulocity for a command cancelled on the device
+///
+/// Cumulocity has no cancelled status: a cancelled command is reported as a failed operation.
+pub const CANCELLED_OPERATION_REASON: &str = "Operation cancelled";
+
+/// The type of the event raised on Cumulocity for a command queued on the device
+///
+/// Cumulocity has no queued status: a queued command is left pending, this event telling why it doesn't progress.
+pub const QUEUED_OPERATION_EVENT_TYPE: &str = "c8y_OperationQueued";
+
 /// Represents a pending download performed by the downloader from the FTS.
 ///
 /// Functions which download files from the tedge File Transfer Service as part of handling
@@ -2,17 +2,22 @@ use json_writer::JsonWriter;
 use json_writer::JsonWriterError;
 use tedge_api::entity_store::EntityMetadata;
 use tedge_api::entity_store::EntityType;
+use tedge_api::measurement::MeasurementValue;
 use tedge_api::measurement::MeasurementVisitor;
+use tedge_api::measurement::ScalarValue;
+use tedge_api::telemetry_metadata::MeasurementMetadata;
 use time::format_description;
 use time::OffsetDateTime;
 
 pub struct C8yJsonSerializer {
     json: JsonWriter,
-    is_within_group: bool,
+    // The group being visited, if any
+    group: Option<String>,
     timestamp_present: bool,
     default_timestamp: OffsetDateTime,
     type_present: bool,
     default_type: String,
+    metadata: Option<MeasurementMetadata>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -75,16 +80,17 @@ impl C8yJsonSerializer {
 
         Self {
             json,
-            is_within_group: false,
+            group: None,
             timestamp_present: false,
             default_timestamp,
             type_present: false,
             default_type,
+            metadata: entity.telemetry_metadata.measurement(m_type),
         }
     }
 
     fn end(&mut self) -> Result<(), C8yJsonSerializationError> {
-        if self.is_within_group {
+        if self.group.is_some() {
             return Err(MeasurementStreamError::UnexpectedEndOfData.into());
         }
 
@@ -102,10 +108,34 @@ impl C8yJsonSerializer {
         Ok(())
     }
 
-    fn write_value_obj(&mut self, value: f64) -> Result<(), C8yJsonSerializationError> {
+    /// Write a measurement series as expected by Cumulocity, i.e. a numeric value with an optional unit.
+    ///
+    /// Booleans are sent as `1` or `0`, and the quality is not sent,
+    /// as Cumulocity has no field for it.
+    fn write_value_obj(
+        &mut self,
+        key: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), C8yJsonSerializationError> {
         self.json.write_open_obj();
         self.json.write_key("value")?;
-        self.json.write_f64(value)?;
+        match value.value {
+            ScalarValue::Float(value) => self.json.write_f64(value)?,
+            ScalarValue::Integer(value) => self.json.write_i64(value)?,
+            ScalarValue::Boolean(value) => self.json.write_i64(value.into())?,
+        }
+        // The unit of the series is given by the measurement itself
+        // or else by the metadata of the measurement type, if any
+        let unit = value.unit.clone().or_else(|| {
+            self.metadata
+                .as_ref()
+                .and_then(|metadata| metadata.series(self.group.as_deref(), key))
+                .and_then(|series| series.unit)
+        });
+        if let Some(unit) = unit {
+            self.json.write_key("unit")?;
+            self.json.write_str(&unit)?;
+        }
         self.json.write_close_obj();
         Ok(())
     }
@@ -120,7 +150,7 @@ impl MeasurementVisitor for C8yJsonSerializer {
     type Error = C8yJsonSerializationError;
 
     fn visit_timestamp(&mut self, timestamp: OffsetDateTime) -> Result<(), Self::Error> {
-        if self.is_within_group {
+        if self.group.is_some() {
             return Err(MeasurementStreamError::UnexpectedTimestamp.into());
         }
 
@@ -137,7 +167,7 @@ impl MeasurementVisitor for C8yJsonSerializer {
     }
 
     fn visit_text_property(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
-        if self.is_within_group {
+        if self.group.is_some() {
             return Err(MeasurementStreamError::UnexpectedType.into());
         }
         match name {
@@ -160,6 +190,14 @@ impl MeasurementVisitor for C8yJsonSerializer {
     }
 
     fn visit_measurement(&mut self, key: &str, value: f64) -> Result<(), Self::Error> {
+        self.visit_typed_measurement(key, &ScalarValue::Float(value).into())
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        key: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
         match key {
             "type" => {
                 return Err(C8yJsonSerializationError::UnexpectedMeasurementName {
@@ -174,12 +212,12 @@ impl MeasurementVisitor for C8yJsonSerializer {
             _ => {
                 self.json.write_key(key)?;
 
-                if self.is_within_group {
-                    self.write_value_obj(value)?;
+                if self.group.is_some() {
+                    self.write_value_obj(key, value)?;
                 } else {
                     self.json.write_open_obj();
                     self.json.write_key(key)?;
-                    self.write_value_obj(value)?;
+                    self.write_value_obj(key, value)?;
                     self.json.write_close_obj();
                 }
             }
@@ -188,23 +226,23 @@ impl MeasurementVisitor for C8yJsonSerializer {
     }
 
     fn visit_start_group(&mut self, group: &str) -> Result<(), Self::Error> {
-        if self.is_within_group {
+        if self.group.is_some() {
             return Err(MeasurementStreamError::UnexpectedStartOfGroup.into());
         }
 
         self.json.write_key(group)?;
         self.json.write_open_obj();
-        self.is_within_group = true;
+        self.group = Some(group.to_string());
         Ok(())
     }
 
     fn visit_end_group(&mut self) -> Result<(), Self::Error> {
-        if !self.is_within_group {
+        if self.group.is_none() {
             return Err(MeasurementStreamError::UnexpectedEndOfGroup.into());
         }
 
         self.json.write_close_obj();
-        self.is_within_group = false;
+        self.group = None;
         Ok(())
     }
 }
@@ -215,6 +253,7 @@ mod tests {
     use assert_json_diff::*;
     use assert_matches::*;
     use serde_json::json;
+    use tedge_api::mqtt_topics::Channel;
 
     use super::*;
 
@@ -274,6 +313,113 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serialize_units_given_by_the_measurement_metadata() -> anyhow::Result<()> {
+        let timestamp = datetime!(2021-06-22 17:03:14.123456789 +05:00);
+
+        let mut entity = EntityMetadata::main_device("foo".to_string());
+        entity.telemetry_metadata.update(
+            &Channel::MeasurementMetadata {
+                measurement_type: "environment".to_string(),
+            },
+            json!({
+                "temperature": {"unit": "°C"},
+                "location": {"alti": {"unit": "m"}},
+            }),
+        );
+        let mut serializer = C8yJsonSerializer::new(timestamp, &entity, "environment");
+        serializer.visit_timestamp(timestamp)?;
+        serializer.visit_measurement("temperature", 25.5)?;
+        serializer.visit_measurement("pressure", 1013.0)?;
+        serializer.visit_start_group("location")?;
+        serializer.visit_measurement("alti", 2100.4)?;
+        serializer.visit_end_group()?;
+
+        let output = serializer.into_string()?;
+
+        let expected_output = json!({
+            "type": "environment",
+            "time": "2021-06-22T17:03:14.123456789+05:00",
+            "temperature": {
+                "temperature": {
+                    "value": 25.5,
+                    "unit": "°C"
+                }
+            },
+            "pressure": {
+                "pressure": {
+                    "value": 1013.0
+                }
+            },
+            "location": {
+                "alti": {
+                    "value": 2100.4,
+                    "unit": "m"
+                }
+            }
+        });
+
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(&output)?,
+            expected_output
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_typed_values() -> anyhow::Result<()> {
+        let timestamp = datetime!(2021-06-22 17:03:14.123456789 +05:00);
+
+        let mut entity = EntityMetadata::main_device("foo".to_string());
+        entity.telemetry_metadata.update(
+            &Channel::MeasurementMetadata {
+                measurement_type: "environment".to_string(),
+            },
+            json!({"temperature": {"unit": "°F"}}),
+        );
+        let mut serializer = C8yJsonSerializer::new(timestamp, &entity, "environment");
+        serializer.visit_timestamp(timestamp)?;
+        serializer.visit_typed_measurement(
+            "temperature",
+            &MeasurementValue {
+                value: ScalarValue::Float(21.3),
+                unit: Some("°C".to_string()),
+                quality: Some("good".to_string()),
+            },
+        )?;
+        serializer.visit_typed_measurement("door_open", &ScalarValue::Boolean(true).into())?;
+        serializer.visit_typed_measurement("packets", &ScalarValue::Integer(12345678901).into())?;
+
+        let output = serializer.into_string()?;
+
+        let expected_output = json!({
+            "type": "environment",
+            "time": "2021-06-22T17:03:14.123456789+05:00",
+            "temperature": {
+                "temperature": {
+                    "value": 21.3,
+                    "unit": "°C"
+                }
+            },
+            "door_open": {
+                "door_open": {
+                    "value": 1
+                }
+            },
+            "packets": {
+                "packets": {
+                    "value": 12345678901_i64
+                }
+            }
+        });
+
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(&output)?,
+            expected_output
+        );
+        Ok(())
+    }
+
     #[test]
     fn invalid_to_have_type_as_measurement() -> anyhow::Result<()> {
         let timestamp = datetime!(2021-06-22 17:03:14.123456789 +05:00);
@@ -13,6 +13,7 @@ use c8y_api::smartrest::topic::C8yTopic;
 use c8y_auth_proxy::url::Protocol;
 use c8y_http_proxy::messages::C8YRestRequest;
 use c8y_http_proxy::messages::C8YRestResult;
+use c8y_http_proxy::messages::DeleteManagedObject;
 use serde_json::json;
 use std::fs;
 use std::fs::File;
@@ -149,6 +150,94 @@ async fn child_device_registration_mapping() {
     .await;
 }
 
+#[tokio::test]
+async fn child_device_deregistration() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+    timer.send(Timeout::new(())).await.unwrap(); // Complete sync phase so that alarm mapping starts
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{ "@type": "child-device" }"#,
+    ))
+    .await
+    .unwrap();
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child2//"),
+        r#"{ "@type": "child-device", "@parent": "device/child1//" }"#,
+    ))
+    .await
+    .unwrap();
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child2///twin/foo"),
+        r#""bar""#,
+    ))
+    .await
+    .unwrap();
+    assert_received_contains_str(
+        &mut mqtt,
+        [
+            ("c8y/s/us", "101,test-device:device:child1"),
+            (
+                "c8y/s/us/test-device:device:child1",
+                "101,test-device:device:child2",
+            ),
+            (
+                "c8y/inventory/managedObjects/update/test-device:device:child2",
+                "bar",
+            ),
+        ],
+    )
+    .await;
+
+    // Deregister child1 by clearing its registration message
+    mqtt.send(MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain())
+        .await
+        .unwrap();
+
+    // The managed object of child1 is deleted, along with those of its descendants
+    let request = http.recv().await.unwrap();
+    assert_eq!(
+        request,
+        C8YRestRequest::DeleteManagedObject(DeleteManagedObject {
+            device_id: "test-device:device:child1".to_string()
+        })
+    );
+    http.send(Ok(c8y_http_proxy::messages::C8YRestResponse::Unit(())))
+        .await
+        .unwrap();
+
+    // The retained messages of child1 and its descendants are cleared
+    let mut cleared_topics = vec![];
+    for _ in 0..5 {
+        let message = mqtt.recv().await.unwrap();
+        assert!(message.payload_bytes().is_empty());
+        assert!(message.retain);
+        cleared_topics.push(message.topic.name);
+    }
+    assert_eq!(
+        cleared_topics,
+        vec![
+            "te/device/child1//",
+            "te/device/child1///status/health",
+            "te/device/child2//",
+            "te/device/child2///status/health",
+            "te/device/child2///twin/foo",
+        ]
+    );
+
+    // The clearing messages received back by the mapper are ignored
+    mqtt.send(
+        MqttMessage::new(&Topic::new_unchecked("te/device/child2///twin/foo"), "").with_retain(),
+    )
+    .await
+    .unwrap();
+    assert!(mqtt.recv().await.is_none());
+}
+
 #[tokio::test]
 async fn custom_topic_scheme_registration_mapping() {
     let cfg_dir = TempTedgeDir::new();
@@ -391,6 +480,42 @@ async fn mapper_publishes_software_update_status_onto_c8y_topic() {
     .await;
 }
 
+#[tokio::test]
+async fn mapper_raises_an_event_for_a_queued_software_update() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, http, _fs, _timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+    spawn_dummy_c8y_http_proxy(http);
+
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    // The agent queues a software update till running commands complete
+    let mqtt_schema = MqttSchema::default();
+    let device = EntityTopicId::default_main_device();
+    let request = SoftwareUpdateCommand::new(&device, "c8y-mapper-123".to_string());
+    let response = request.with_status(CommandStatus::Queued);
+    mqtt.send(response.command_message(&mqtt_schema))
+        .await
+        .expect("Send failed");
+
+    // The operation is left pending, an event telling why it doesn't progress
+    assert_received_contains_str(
+        &mut mqtt,
+        [(
+            "c8y/s/us",
+            "400,c8y_OperationQueued,\"software_update operation queued, awaiting the completion of running operations\"",
+        )],
+    )
+    .await;
+
+    // Once dequeued, the command is processed as usual
+    let response = response.with_status(CommandStatus::Executing);
+    mqtt.send(response.command_message(&mqtt_schema))
+        .await
+        .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "501,c8y_SoftwareUpdate")]).await;
+}
+
 #[tokio::test]
 async fn mapper_publishes_software_update_failed_status_onto_c8y_topic() {
     // Start SM Mapper
@@ -513,6 +638,40 @@ async fn c8y_mapper_alarm_mapping_to_smartrest() {
     .await;
 }
 
+#[tokio::test]
+async fn c8y_mapper_alarm_mapping_uses_the_alarm_metadata() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+    timer.send(Timeout::new(())).await.unwrap(); //Complete sync phase so that alarm mapping starts
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(
+        MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///a/temperature_alarm/meta"),
+            json!({ "severity": "major", "text": "Temperature high" }).to_string(),
+        )
+        .with_retain(),
+    )
+    .await
+    .unwrap();
+
+    // The severity and text given by the metadata are used when missing from the alarm
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///a/temperature_alarm"),
+        r#"{ "time": "2023-10-13T15:00:07.172674353Z" }"#,
+    ))
+    .await
+    .unwrap();
+
+    assert_received_contains_str(
+        &mut mqtt,
+        [("c8y/s/us", "302,temperature_alarm,Temperature high")],
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn c8y_mapper_child_alarm_mapping_to_smartrest() {
     let cfg_dir = TempTedgeDir::new();
@@ -2604,6 +2763,9 @@ pub(crate) async fn spawn_c8y_mapper_actor(
     ));
     topics.add_all(crate::operations::config_update::topic_filter(&mqtt_schema));
     topics.add_all(C8yMapperConfig::default_external_topic_filter());
+    topics.add_all(C8yMapperConfig::telemetry_metadata_topic_filter(
+        &mqtt_schema,
+    ));
 
     let config = C8yMapperConfig::new(
         config_dir.to_path_buf(),
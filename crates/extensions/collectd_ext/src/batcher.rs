@@ -122,4 +122,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn integer_values_are_kept_as_integers() -> anyhow::Result<()> {
+        let collectd_message = MqttMessage::new(
+            &Topic::new_unchecked("collectd/localhost/interface/rx_packets"),
+            "1700000000:12345678901",
+        );
+        let messages = CollectdMessage::parse_from(&collectd_message)?;
+
+        let output_topic = Topic::new_unchecked("te/device/main///m/");
+        let output = MessageBatch::thin_edge_json(&output_topic, messages)?;
+
+        assert_eq!(
+            output.payload_str()?,
+            r#"{"time":"2023-11-14T22:13:20Z","interface":{"rx_packets":12345678901}}"#
+        );
+
+        Ok(())
+    }
 }
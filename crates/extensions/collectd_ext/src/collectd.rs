@@ -1,5 +1,6 @@
 use batcher::Batchable;
 use tedge_api::measurement::MeasurementVisitor;
+use tedge_api::measurement::ScalarValue;
 use tedge_mqtt_ext::MqttMessage;
 use time::Duration;
 use time::OffsetDateTime;
@@ -9,7 +10,7 @@ pub struct CollectdMessage {
     pub metric_group_key: String,
     pub metric_key: String,
     pub timestamp: OffsetDateTime,
-    pub metric_value: f64,
+    pub metric_value: ScalarValue,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -32,11 +33,9 @@ impl CollectdMessage {
     where
         T: MeasurementVisitor,
     {
-        visitor.visit_grouped_measurement(
-            &self.metric_group_key,
-            &self.metric_key,
-            self.metric_value,
-        )
+        visitor.visit_start_group(&self.metric_group_key)?;
+        visitor.visit_typed_measurement(&self.metric_key, &self.metric_value.into())?;
+        visitor.visit_end_group()
     }
 
     #[cfg(test)]
@@ -50,7 +49,7 @@ impl CollectdMessage {
             metric_group_key: metric_group_key.to_string(),
             metric_key: metric_key.to_string(),
             timestamp,
-            metric_value,
+            metric_value: ScalarValue::Float(metric_value),
         }
     }
 
@@ -120,7 +119,7 @@ impl<'a> CollectdTopic<'a> {
 #[derive(Debug)]
 struct CollectdPayload {
     timestamp: f64,
-    metric_values: Vec<f64>,
+    metric_values: Vec<ScalarValue>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -156,7 +155,10 @@ impl CollectdPayload {
             .into_iter()
             .skip(1)
             .map(|m| {
-                m.parse::<f64>()
+                // Integer values, as those of collectd counters, are kept as integers
+                m.parse::<i64>()
+                    .map(ScalarValue::Integer)
+                    .or_else(|_| m.parse::<f64>().map(ScalarValue::Float))
                     .map_err(|_err| CollectdPayloadError::InvalidMeasurementValue(m.to_string()))
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -214,7 +216,7 @@ mod tests {
 
         assert_eq!(metric_key, "value");
         assert_eq!(*timestamp, datetime!(1973-11-29 21:33:09.0 UTC));
-        assert_eq!(*metric_value, 32.5);
+        assert_eq!(*metric_value, ScalarValue::Float(32.5));
     }
 
     #[test]
@@ -245,7 +247,7 @@ mod tests {
         assert_eq!(metric_group_key, "temperature");
         assert_eq!(metric_key, "value_val2");
         assert_eq!(*timestamp, datetime!(1973-11-29 21:33:09.0 UTC));
-        assert_eq!(*metric_value, 45.2);
+        assert_eq!(*metric_value, ScalarValue::Float(45.2));
     }
 
     #[test]
@@ -265,7 +267,7 @@ mod tests {
         assert_eq!(metric_group_key, "temperature");
         assert_eq!(metric_key, "value");
         assert_eq!(*timestamp, datetime!(1973-11-29 21:33:09.125 UTC));
-        assert_eq!(*metric_value, 32.5);
+        assert_eq!(*metric_value, ScalarValue::Float(32.5));
     }
 
     #[test]
@@ -341,7 +343,10 @@ mod tests {
         let result = CollectdPayload::parse_from(payload).unwrap();
 
         assert_eq!(result.timestamp, 123456789.0);
-        assert_eq!(result.metric_values, vec![1234.0, 5678.0]);
+        assert_eq!(
+            result.metric_values,
+            vec![ScalarValue::Integer(1234), ScalarValue::Integer(5678)]
+        );
     }
 
     #[test]
@@ -360,7 +365,10 @@ mod tests {
         let payload: String = format!("123456789:{}", u128::MAX);
         let collectd_payload = CollectdPayload::parse_from(payload.as_str()).unwrap();
 
-        assert_eq!(*collectd_payload.metric_values.index(0), u128::MAX as f64);
+        assert_eq!(
+            *collectd_payload.metric_values.index(0),
+            ScalarValue::Float(u128::MAX as f64)
+        );
     }
 
     #[test]
@@ -368,6 +376,9 @@ mod tests {
         let payload: String = format!("123456789:{}", i128::MIN);
         let collectd_payload = CollectdPayload::parse_from(payload.as_str()).unwrap();
 
-        assert_eq!(*collectd_payload.metric_values.index(0), i128::MIN as f64);
+        assert_eq!(
+            *collectd_payload.metric_values.index(0),
+            ScalarValue::Float(i128::MIN as f64)
+        );
     }
 }
@@ -0,0 +1,94 @@
+use crate::converter::OtelConverter;
+use crate::converter::OtlpRequest;
+use crate::error::ExportError;
+use crate::otlp::PROTOBUF_CONTENT_TYPE;
+use async_trait::async_trait;
+use log::error;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::ClientMessageBox;
+use tedge_actors::MessageReceiver;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::SimpleMessageBox;
+use tedge_http_ext::HttpRequest;
+use tedge_http_ext::HttpRequestBuilder;
+use tedge_http_ext::HttpResult;
+use tedge_metrics::Counter;
+use tedge_mqtt_ext::MqttMessage;
+use tokio::time::Instant;
+
+/// An actor converting the telemetry data received from MQTT,
+/// and exporting these data in batches to an OTLP/HTTP collector
+pub struct OtelMapperActor {
+    pub(crate) endpoint: String,
+    pub(crate) batch_max_size: usize,
+    pub(crate) batch_interval: Duration,
+    pub(crate) converter: OtelConverter,
+    pub(crate) messages: SimpleMessageBox<MqttMessage, NoMessage>,
+    pub(crate) http: ClientMessageBox<HttpRequest, HttpResult>,
+    pub(crate) dropped_records: Counter,
+}
+
+#[async_trait]
+impl Actor for OtelMapperActor {
+    fn name(&self) -> &str {
+        "OtelMapper"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let mut deadline = Instant::now() + self.batch_interval;
+        loop {
+            match tokio::time::timeout_at(deadline, self.messages.recv()).await {
+                Err(_) => {} // the batch interval is over
+                Ok(None) => break,
+                Ok(Some(message)) => {
+                    self.converter.convert(&message);
+                    if self.converter.pending_records() < self.batch_max_size {
+                        continue;
+                    }
+                }
+            }
+            self.export().await;
+            deadline = Instant::now() + self.batch_interval;
+        }
+
+        // Export what has been received before the shutdown
+        self.export().await;
+        Ok(())
+    }
+}
+
+impl OtelMapperActor {
+    /// Send all the pending data points and log records to the collector
+    ///
+    /// The data that cannot be exported are dropped, not to exhaust the memory of the device
+    /// while the collector is unreachable. These are counted by the `tedge_dropped_records_total` metric.
+    async fn export(&mut self) {
+        for request in self.converter.flush() {
+            let url = format!("{}{}", self.endpoint, request.path());
+            if let Err(err) = self.send(&url, &request).await {
+                let dropped = request.record_count();
+                self.dropped_records.inc_by(dropped as u64);
+                error!(
+                    "Failed to export telemetry data to {url}, dropping {dropped} records: {err}"
+                );
+            }
+        }
+    }
+
+    async fn send(&mut self, url: &str, request: &OtlpRequest) -> Result<(), ExportError> {
+        let http_request = HttpRequestBuilder::post(url)
+            .header("content-type", PROTOBUF_CONTENT_TYPE)
+            .body(request.encode_to_vec())
+            .build()?;
+        let response = self.http.await_response(http_request).await??;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ExportError::Rejected {
+                status: response.status().as_u16(),
+            })
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::error::OtelMapperError;
+use log::warn;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use tedge_api::mqtt_topics::ChannelFilter::AlarmMetadata;
+use tedge_api::mqtt_topics::ChannelFilter::EventMetadata;
+use tedge_api::mqtt_topics::ChannelFilter::MeasurementMetadata;
+use tedge_api::mqtt_topics::EntityFilter::AnyEntity;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::TEdgeConfig;
+use tedge_mqtt_ext::TopicFilter;
+
+const STATE_DIR_NAME: &str = ".tedge-mapper-otel";
+
+pub struct OtelMapperConfig {
+    /// The base URL of the OTLP/HTTP collector, e.g. `http://localhost:4318`
+    pub endpoint: String,
+    pub device_id: String,
+    pub device_topic_id: EntityTopicId,
+    pub default_service_type: String,
+    pub mqtt_schema: MqttSchema,
+    pub topics: TopicFilter,
+    /// The directory where the entity store is persisted
+    pub state_dir: PathBuf,
+    /// The number of data points and log records triggering an export
+    pub batch_max_size: usize,
+    /// The maximum delay before the pending data points and log records are exported
+    pub batch_interval: Duration,
+}
+
+impl OtelMapperConfig {
+    pub fn from_tedge_config(
+        config_dir: impl AsRef<Path>,
+        tedge_config: &TEdgeConfig,
+    ) -> Result<Self, OtelMapperError> {
+        let endpoint = Self::validate_endpoint(&tedge_config.otel.endpoint)?;
+        let device_id = tedge_config.device.id.try_read(tedge_config)?.to_string();
+        let device_topic_id = tedge_config.mqtt.device_topic_id.parse()?;
+        let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+
+        let mut topics = Self::telemetry_metadata_topic_filter(&mqtt_schema);
+        for topic in tedge_config.otel.topics.0.iter() {
+            if topics.add(topic).is_err() {
+                warn!("The configured topic '{topic}' is invalid and ignored.");
+            }
+        }
+
+        Ok(OtelMapperConfig {
+            endpoint,
+            device_id,
+            device_topic_id,
+            default_service_type: tedge_config.service.ty.clone(),
+            mqtt_schema,
+            topics,
+            state_dir: config_dir.as_ref().join(STATE_DIR_NAME),
+            batch_max_size: tedge_config.otel.batch.max_size as usize,
+            batch_interval: tedge_config.otel.batch.interval.duration(),
+        })
+    }
+
+    /// The metadata of the measurements, events and alarms, used to enrich the telemetry data
+    pub fn telemetry_metadata_topic_filter(mqtt_schema: &MqttSchema) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for channel in [MeasurementMetadata, EventMetadata, AlarmMetadata] {
+            topics.add_all(mqtt_schema.topics(AnyEntity, channel));
+        }
+        topics
+    }
+
+    fn validate_endpoint(endpoint: &str) -> Result<String, OtelMapperError> {
+        let endpoint = endpoint.trim_end_matches('/');
+        if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            Ok(endpoint.to_string())
+        } else {
+            Err(OtelMapperError::InvalidEndpoint {
+                endpoint: endpoint.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_are_http_urls() {
+        assert_eq!(
+            OtelMapperConfig::validate_endpoint("http://localhost:4318/").unwrap(),
+            "http://localhost:4318"
+        );
+        assert!(OtelMapperConfig::validate_endpoint("https://collector.example.com").is_ok());
+        assert!(OtelMapperConfig::validate_endpoint("localhost:4317").is_err());
+    }
+}
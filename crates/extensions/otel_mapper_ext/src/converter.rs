@@ -0,0 +1,898 @@
+//! Conversion of the thin-edge telemetry data into OTLP metrics and logs.
+//!
+//! - The measurements are converted into gauge data points, one metric per series,
+//!   the series of a group being named `<group>.<series>`.
+//! - The events and alarms are converted into log records,
+//!   the severity of an alarm being mapped to the OpenTelemetry severity levels.
+//! - The entities are tracked in an [EntityStore], from their registration and twin messages,
+//!   the telemetry data of each entity being attached to an OTLP resource describing this entity.
+//!
+//! The converted data points and log records are kept pending till the next [OtelConverter::flush].
+//!
+//! The alarms being retained, the broker sends them again each time the mapper subscribes:
+//! the alarms already exported before a restart are recorded in the state directory, not to be exported twice.
+use crate::config::OtelMapperConfig;
+use crate::error::ConversionError;
+use crate::error::OtelMapperError;
+use crate::otlp::*;
+use clock::Clock;
+use clock::Timestamp;
+use log::error;
+use log::warn;
+use prost::Message;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use tedge_api::alarm::ThinEdgeAlarm;
+use tedge_api::builder::ThinEdgeJsonBuilder;
+use tedge_api::data::ThinEdgeValue;
+use tedge_api::entity_store::EntityExternalId;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityTwinMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::event::ThinEdgeEvent;
+use tedge_api::measurement::ScalarValue;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::EntityStore;
+use tedge_metrics::Counter;
+use tedge_mqtt_ext::MqttMessage;
+
+const SCOPE_NAME: &str = "thin-edge.io";
+const DEFAULT_ALARM_SEVERITY: &str = "minor";
+const EXPORTED_ALARMS_FILE: &str = "exported-alarms.json";
+
+/// An OTLP request ready to be sent to a collector
+#[derive(Clone, Debug, PartialEq)]
+pub enum OtlpRequest {
+    Metrics(ExportMetricsServiceRequest),
+    Logs(ExportLogsServiceRequest),
+}
+
+impl OtlpRequest {
+    /// The URL path of the OTLP/HTTP service accepting this request
+    pub fn path(&self) -> &'static str {
+        match self {
+            OtlpRequest::Metrics(_) => METRICS_PATH,
+            OtlpRequest::Logs(_) => LOGS_PATH,
+        }
+    }
+
+    /// The number of data points or log records of the request
+    pub fn record_count(&self) -> usize {
+        match self {
+            OtlpRequest::Metrics(request) => request
+                .resource_metrics
+                .iter()
+                .flat_map(|resource| resource.scope_metrics.iter())
+                .flat_map(|scope| scope.metrics.iter())
+                .map(|metric| match &metric.data {
+                    Some(metric::Data::Gauge(gauge)) => gauge.data_points.len(),
+                    None => 0,
+                })
+                .sum(),
+            OtlpRequest::Logs(request) => request
+                .resource_logs
+                .iter()
+                .flat_map(|resource| resource.scope_logs.iter())
+                .map(|scope| scope.log_records.len())
+                .sum(),
+        }
+    }
+
+    /// Encode the request using protobuf
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        match self {
+            OtlpRequest::Metrics(request) => request.encode_to_vec(),
+            OtlpRequest::Logs(request) => request.encode_to_vec(),
+        }
+    }
+}
+
+/// The data points and log records pending for an entity
+#[derive(Default)]
+struct EntityBatch {
+    /// The metrics indexed by name and unit
+    metrics: BTreeMap<(String, String), Metric>,
+    log_records: Vec<LogRecord>,
+}
+
+impl EntityBatch {
+    fn add_data_point(&mut self, name: String, unit: String, data_point: NumberDataPoint) {
+        let metric = self
+            .metrics
+            .entry((name.clone(), unit.clone()))
+            .or_insert_with(|| Metric {
+                name,
+                description: String::new(),
+                unit,
+                data: Some(metric::Data::Gauge(Gauge::default())),
+            });
+        if let Some(metric::Data::Gauge(gauge)) = &mut metric.data {
+            gauge.data_points.push(data_point)
+        }
+    }
+}
+
+/// The latest payload of each alarm converted by the mapper, persisted across restarts
+struct ExportedAlarms {
+    path: PathBuf,
+    /// The alarm payloads, indexed by topic
+    payloads: BTreeMap<String, String>,
+}
+
+impl ExportedAlarms {
+    fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(EXPORTED_ALARMS_FILE);
+        let payloads = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+                warn!(
+                    "Ignoring the exported alarms recorded in {}: {err}",
+                    path.display()
+                );
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        ExportedAlarms { path, payloads }
+    }
+
+    /// Tell if the message is a retained alarm sent again by the broker, after being exported
+    fn is_replay(&self, message: &MqttMessage) -> bool {
+        message.retain
+            && self
+                .payloads
+                .get(&message.topic.name)
+                .is_some_and(|payload| payload.as_bytes() == message.payload_bytes())
+    }
+
+    /// Record the alarm as exported, a cleared alarm being forgotten
+    fn record(&mut self, topic: &str, payload: &str) {
+        if payload.is_empty() {
+            self.payloads.remove(topic);
+        } else {
+            self.payloads.insert(topic.to_string(), payload.to_string());
+        }
+        if let Err(err) = self.persist() {
+            error!(
+                "Failed to record the exported alarms in {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+
+    fn persist(&self) -> std::io::Result<()> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&self.payloads)?)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+pub struct OtelConverter {
+    mqtt_schema: MqttSchema,
+    entities: EntityStore,
+    exported_alarms: ExportedAlarms,
+    clock: Box<dyn Clock>,
+    /// The pending data, indexed by entity topic id
+    pending: BTreeMap<String, EntityBatch>,
+    pending_records: usize,
+    messages_converted: Counter,
+    conversion_errors: Counter,
+}
+
+impl OtelConverter {
+    pub fn try_new(
+        config: &OtelMapperConfig,
+        clock: Box<dyn Clock>,
+    ) -> Result<Self, OtelMapperError> {
+        let main_device = EntityRegistrationMessage {
+            topic_id: config.device_topic_id.clone(),
+            external_id: Some(config.device_id.as_str().into()),
+            r#type: EntityType::MainDevice,
+            parent: None,
+            other: Map::new(),
+        };
+        std::fs::create_dir_all(&config.state_dir)?;
+        let entities = EntityStore::with_main_device_and_default_service_type(
+            config.mqtt_schema.clone(),
+            main_device,
+            config.default_service_type.clone(),
+            default_external_id,
+            |external_id| Ok(external_id.into()),
+            // No telemetry data is cached, the data of unknown entities being rejected
+            0,
+            &config.state_dir,
+        )?;
+
+        Ok(OtelConverter {
+            mqtt_schema: config.mqtt_schema.clone(),
+            entities,
+            exported_alarms: ExportedAlarms::load(&config.state_dir),
+            clock,
+            pending: BTreeMap::new(),
+            pending_records: 0,
+            messages_converted: tedge_metrics::messages_converted("otel"),
+            conversion_errors: tedge_metrics::conversion_errors("otel"),
+        })
+    }
+
+    /// The number of data points and log records waiting to be exported
+    pub fn pending_records(&self) -> usize {
+        self.pending_records
+    }
+
+    /// Convert an MQTT message, adding the resulting data points and log records to the pending ones
+    ///
+    /// The conversion errors are logged, the invalid messages being ignored.
+    pub fn convert(&mut self, message: &MqttMessage) {
+        match self.try_convert(message) {
+            Ok(0) => {}
+            Ok(count) => {
+                self.pending_records += count;
+                self.messages_converted.inc();
+            }
+            Err(err) => {
+                self.conversion_errors.inc();
+                error!(
+                    "Failed to convert the message received on {}: {err}",
+                    message.topic.name
+                );
+            }
+        }
+    }
+
+    /// Return the OTLP requests for all the pending data points and log records
+    pub fn flush(&mut self) -> Vec<OtlpRequest> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_records = 0;
+
+        let scope = InstrumentationScope {
+            name: SCOPE_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let mut resource_metrics = vec![];
+        let mut resource_logs = vec![];
+        for (topic_id, batch) in pending {
+            let resource = self.resource(&topic_id);
+            if !batch.metrics.is_empty() {
+                resource_metrics.push(ResourceMetrics {
+                    resource: Some(resource.clone()),
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Some(scope.clone()),
+                        metrics: batch.metrics.into_values().collect(),
+                    }],
+                });
+            }
+            if !batch.log_records.is_empty() {
+                resource_logs.push(ResourceLogs {
+                    resource: Some(resource),
+                    scope_logs: vec![ScopeLogs {
+                        scope: Some(scope.clone()),
+                        log_records: batch.log_records,
+                    }],
+                });
+            }
+        }
+
+        let mut requests = vec![];
+        if !resource_metrics.is_empty() {
+            requests.push(OtlpRequest::Metrics(ExportMetricsServiceRequest {
+                resource_metrics,
+            }));
+        }
+        if !resource_logs.is_empty() {
+            requests.push(OtlpRequest::Logs(ExportLogsServiceRequest {
+                resource_logs,
+            }));
+        }
+        requests
+    }
+
+    /// Process a message, returning the number of data points and log records added to the batch
+    fn try_convert(&mut self, message: &MqttMessage) -> Result<usize, ConversionError> {
+        let Ok((entity, channel)) = self.mqtt_schema.entity_channel_of(&message.topic) else {
+            return Ok(0);
+        };
+        match channel {
+            Channel::EntityMetadata if message.payload_bytes().is_empty() => {
+                self.entities.deregister_entity(&entity)?;
+                Ok(0)
+            }
+            Channel::EntityMetadata => {
+                let registration = EntityRegistrationMessage::try_from(message).map_err(|()| {
+                    ConversionError::InvalidRegistration {
+                        topic: message.topic.name.clone(),
+                    }
+                })?;
+                self.entities.update(registration)?;
+                Ok(0)
+            }
+            Channel::EntityTwinData { fragment_key } => {
+                let fragment_value = if message.payload_bytes().is_empty() {
+                    JsonValue::Null
+                } else {
+                    serde_json::from_slice(message.payload_bytes())?
+                };
+                self.entities.update_twin_data(EntityTwinMessage::new(
+                    entity,
+                    fragment_key,
+                    fragment_value,
+                ))?;
+                Ok(0)
+            }
+            Channel::MeasurementMetadata { .. }
+            | Channel::EventMetadata { .. }
+            | Channel::AlarmMetadata { .. } => {
+                let metadata = if message.payload_bytes().is_empty() {
+                    JsonValue::Null
+                } else {
+                    serde_json::from_slice(message.payload_bytes())?
+                };
+                self.entities
+                    .update_telemetry_metadata(&entity, &channel, metadata)?;
+                Ok(0)
+            }
+            Channel::Measurement { measurement_type } => {
+                self.register_source(&entity)?;
+                self.convert_measurement(&entity, &measurement_type, message)
+            }
+            Channel::Event { event_type } => {
+                self.register_source(&entity)?;
+                self.convert_event(&entity, &event_type, message)
+            }
+            Channel::Alarm { .. } if self.exported_alarms.is_replay(message) => Ok(0),
+            Channel::Alarm { alarm_type } => {
+                self.register_source(&entity)?;
+                let count = self.convert_alarm(&entity, &alarm_type, message)?;
+                self.exported_alarms
+                    .record(&message.topic.name, message.payload_str()?);
+                Ok(count)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Auto-register the source of telemetry data, if using the default topic scheme
+    fn register_source(&mut self, entity: &EntityTopicId) -> Result<(), ConversionError> {
+        if self.entities.get(entity).is_none() {
+            self.entities.auto_register_entity(entity)?;
+        }
+        Ok(())
+    }
+
+    fn convert_measurement(
+        &mut self,
+        entity: &EntityTopicId,
+        measurement_type: &str,
+        message: &MqttMessage,
+    ) -> Result<usize, ConversionError> {
+        let mut builder = ThinEdgeJsonBuilder::default();
+        tedge_api::parser::parse_str(message.payload_str()?, &mut builder)?;
+        let measurement = builder.done()?;
+        let time = measurement.timestamp.unwrap_or_else(|| self.clock.now());
+        let metadata = self
+            .entities
+            .try_get(entity)?
+            .telemetry_metadata
+            .measurement(measurement_type)
+            .unwrap_or_default();
+
+        let mut series = vec![];
+        for value in measurement.values {
+            match value {
+                ThinEdgeValue::Single(single) => series.push((None, single.name, single.value)),
+                ThinEdgeValue::Multi(multi) => {
+                    for single in multi.values {
+                        series.push((Some(multi.name.clone()), single.name, single.value))
+                    }
+                }
+            }
+        }
+
+        let count = series.len();
+        let batch = self.pending.entry(entity.to_string()).or_default();
+        for (group, name, value) in series {
+            let unit = value
+                .unit
+                .or_else(|| metadata.series(group.as_deref(), &name)?.unit)
+                .unwrap_or_default();
+            let name = match group {
+                Some(group) => format!("{group}.{name}"),
+                None => name,
+            };
+            let data_point = NumberDataPoint {
+                attributes: vec![KeyValue::new(
+                    "tedge.measurement.type",
+                    AnyValue::string(measurement_type),
+                )],
+                start_time_unix_nano: 0,
+                time_unix_nano: unix_nanos(time),
+                value: Some(match value.value {
+                    ScalarValue::Integer(value) => number_data_point::Value::AsInt(value),
+                    value => number_data_point::Value::AsDouble(value.as_f64()),
+                }),
+            };
+            batch.add_data_point(name, unit, data_point);
+        }
+        Ok(count)
+    }
+
+    fn convert_event(
+        &mut self,
+        entity: &EntityTopicId,
+        event_type: &str,
+        message: &MqttMessage,
+    ) -> Result<usize, ConversionError> {
+        let event = ThinEdgeEvent::try_from(
+            event_type,
+            self.entities.try_get(entity)?,
+            message.payload_str()?,
+        )?;
+        let now = self.clock.now();
+
+        let mut attributes = vec![KeyValue::new(
+            "tedge.event.type",
+            AnyValue::string(&event.name),
+        )];
+        let (text, time) = match event.data {
+            Some(data) => {
+                attributes.extend(extra_attributes(data.extras));
+                (data.text, data.time)
+            }
+            None => (None, None),
+        };
+
+        let record = LogRecord {
+            time_unix_nano: unix_nanos(time.unwrap_or(now)),
+            observed_time_unix_nano: unix_nanos(now),
+            severity_number: SeverityNumber::Info as i32,
+            severity_text: String::new(),
+            body: Some(AnyValue::string(text.unwrap_or(event.name))),
+            attributes,
+        };
+        self.push_log_record(entity, record);
+        Ok(1)
+    }
+
+    fn convert_alarm(
+        &mut self,
+        entity: &EntityTopicId,
+        alarm_type: &str,
+        message: &MqttMessage,
+    ) -> Result<usize, ConversionError> {
+        let alarm = ThinEdgeAlarm::try_from(alarm_type, entity, message.payload_str()?)?;
+        let metadata = self
+            .entities
+            .try_get(entity)?
+            .telemetry_metadata
+            .alarm(&alarm.alarm_type)
+            .unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut attributes = vec![KeyValue::new(
+            "tedge.alarm.type",
+            AnyValue::string(&alarm.alarm_type),
+        )];
+        let record = match alarm.data {
+            Some(data) => {
+                let severity = data
+                    .severity
+                    .or(metadata.severity)
+                    .unwrap_or_else(|| DEFAULT_ALARM_SEVERITY.to_string());
+                let text = data.text.or(metadata.text).unwrap_or(alarm.alarm_type);
+                attributes.push(KeyValue::new(
+                    "tedge.alarm.status",
+                    AnyValue::string("raised"),
+                ));
+                attributes.extend(extra_attributes(data.extras));
+                LogRecord {
+                    time_unix_nano: unix_nanos(data.time.unwrap_or(now)),
+                    observed_time_unix_nano: unix_nanos(now),
+                    severity_number: alarm_severity_number(&severity) as i32,
+                    severity_text: severity,
+                    body: Some(AnyValue::string(text)),
+                    attributes,
+                }
+            }
+            None => {
+                let text = metadata.text.unwrap_or(alarm.alarm_type);
+                attributes.push(KeyValue::new(
+                    "tedge.alarm.status",
+                    AnyValue::string("cleared"),
+                ));
+                LogRecord {
+                    time_unix_nano: unix_nanos(now),
+                    observed_time_unix_nano: unix_nanos(now),
+                    severity_number: SeverityNumber::Info as i32,
+                    severity_text: String::new(),
+                    body: Some(AnyValue::string(text)),
+                    attributes,
+                }
+            }
+        };
+        self.push_log_record(entity, record);
+        Ok(1)
+    }
+
+    fn push_log_record(&mut self, entity: &EntityTopicId, record: LogRecord) {
+        self.pending
+            .entry(entity.to_string())
+            .or_default()
+            .log_records
+            .push(record)
+    }
+
+    /// The OTLP resource describing an entity
+    ///
+    /// - `tedge.topic_id` and `tedge.entity.type` identify the entity on the thin-edge MQTT bus.
+    /// - `device.id` is the external id of the entity if a device, or of its parent device if a service.
+    /// - `service.name` is the name of the entity if a service.
+    /// - The scalar properties given on registration are added as `tedge.property.<property>`,
+    ///   and the scalar twin data fragments as `tedge.twin.<fragment>`.
+    fn resource(&self, topic_id: &str) -> Resource {
+        let mut attributes = vec![KeyValue::new("tedge.topic_id", AnyValue::string(topic_id))];
+        let Some(entity) = topic_id
+            .parse::<EntityTopicId>()
+            .ok()
+            .and_then(|topic_id| self.entities.get(&topic_id))
+        else {
+            return Resource { attributes };
+        };
+
+        attributes.push(KeyValue::new(
+            "tedge.entity.type",
+            AnyValue::string(entity.r#type.as_str()),
+        ));
+        let device = match entity.r#type {
+            EntityType::Service => entity
+                .parent
+                .as_ref()
+                .and_then(|parent| self.entities.get(parent))
+                .unwrap_or(entity),
+            _ => entity,
+        };
+        attributes.push(KeyValue::new(
+            "device.id",
+            AnyValue::string(device.external_id.as_ref()),
+        ));
+        if entity.r#type == EntityType::Service {
+            let name = entity
+                .other
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .or(entity.topic_id.default_service_name())
+                .unwrap_or(entity.external_id.as_ref());
+            attributes.push(KeyValue::new("service.name", AnyValue::string(name)));
+        }
+        attributes.extend(scalar_attributes("tedge.property", &entity.other));
+        attributes.extend(scalar_attributes("tedge.twin", &entity.twin_data));
+
+        Resource { attributes }
+    }
+}
+
+/// The external id of an auto-registered entity: the device name of a device, or the topic id
+fn default_external_id(
+    topic_id: &EntityTopicId,
+    _main_device: &EntityExternalId,
+) -> EntityExternalId {
+    match (
+        topic_id.default_device_name(),
+        topic_id.default_service_name(),
+    ) {
+        (Some(device_name), None) => device_name.into(),
+        _ => topic_id.as_str().into(),
+    }
+}
+
+/// Map the thin-edge alarm severities to the OpenTelemetry severity levels
+fn alarm_severity_number(severity: &str) -> SeverityNumber {
+    match severity {
+        "critical" => SeverityNumber::Fatal,
+        "major" => SeverityNumber::Error,
+        "minor" => SeverityNumber::Warn2,
+        "warning" => SeverityNumber::Warn,
+        _ => SeverityNumber::Unspecified,
+    }
+}
+
+/// The custom fragments of an event or alarm, sorted by name
+fn extra_attributes(extras: impl IntoIterator<Item = (String, JsonValue)>) -> Vec<KeyValue> {
+    let extras: BTreeMap<String, JsonValue> = extras.into_iter().collect();
+    extras
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key, AnyValue::from_json(&value)))
+        .collect()
+}
+
+fn scalar_attributes<'a>(
+    prefix: &'a str,
+    properties: &'a Map<String, JsonValue>,
+) -> impl Iterator<Item = KeyValue> + 'a {
+    properties
+        .iter()
+        .filter(|(key, value)| {
+            !key.starts_with('@') && (value.is_string() || value.is_number() || value.is_boolean())
+        })
+        .map(move |(key, value)| {
+            KeyValue::new(format!("{prefix}.{key}"), AnyValue::from_json(value))
+        })
+}
+
+fn unix_nanos(time: Timestamp) -> u64 {
+    time.unix_timestamp_nanos().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tedge_mqtt_ext::Topic;
+    use tedge_mqtt_ext::TopicFilter;
+    use tedge_test_utils::fs::TempTedgeDir;
+    use time::macros::datetime;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        fn now(&self) -> Timestamp {
+            datetime!(2024-01-01 12:00 UTC)
+        }
+    }
+
+    fn converter(state_dir: &TempTedgeDir) -> OtelConverter {
+        let config = OtelMapperConfig {
+            endpoint: "http://localhost:4318".to_string(),
+            device_id: "gateway".to_string(),
+            device_topic_id: EntityTopicId::default_main_device(),
+            default_service_type: "service".to_string(),
+            mqtt_schema: MqttSchema::default(),
+            topics: TopicFilter::empty(),
+            state_dir: state_dir.path().to_path_buf(),
+            batch_max_size: 100,
+            batch_interval: Duration::from_secs(10),
+        };
+        OtelConverter::try_new(&config, Box::new(TestClock)).unwrap()
+    }
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    fn log_records(requests: &[OtlpRequest]) -> Vec<&LogRecord> {
+        requests
+            .iter()
+            .filter_map(|request| match request {
+                OtlpRequest::Logs(request) => Some(request),
+                OtlpRequest::Metrics(_) => None,
+            })
+            .flat_map(|request| request.resource_logs.iter())
+            .flat_map(|resource| resource.scope_logs.iter())
+            .flat_map(|scope| scope.log_records.iter())
+            .collect()
+    }
+
+    fn attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a any_value::Value> {
+        attributes
+            .iter()
+            .find(|attribute| attribute.key == key)?
+            .value
+            .as_ref()?
+            .value
+            .as_ref()
+    }
+
+    fn string(value: &str) -> any_value::Value {
+        any_value::Value::StringValue(value.to_string())
+    }
+
+    #[test]
+    fn convert_measurements_into_gauges() {
+        let state_dir = TempTedgeDir::new();
+        let mut converter = converter(&state_dir);
+
+        converter.convert(&message(
+            "te/device/main///m/environment/meta",
+            r#"{"temperature": {"unit": "°C"}}"#,
+        ));
+        converter.convert(&message(
+            "te/device/main///m/environment",
+            r#"{"time": "2024-01-01T11:00:00Z", "temperature": 21.5, "pump": {"rpm": 1200}}"#,
+        ));
+        assert_eq!(converter.pending_records(), 2);
+
+        let requests = converter.flush();
+        assert_eq!(converter.pending_records(), 0);
+        let [OtlpRequest::Metrics(request)] = &requests[..] else {
+            panic!("Expected a single metrics request, got {requests:?}");
+        };
+        let resource_metrics = &request.resource_metrics[0];
+        let resource = resource_metrics.resource.as_ref().unwrap();
+        assert_eq!(
+            attribute(&resource.attributes, "device.id"),
+            Some(&string("gateway"))
+        );
+
+        let metrics = &resource_metrics.scope_metrics[0].metrics;
+        let names: Vec<_> = metrics
+            .iter()
+            .map(|m| (m.name.as_str(), m.unit.as_str()))
+            .collect();
+        assert_eq!(names, vec![("pump.rpm", ""), ("temperature", "°C")]);
+
+        let Some(metric::Data::Gauge(gauge)) = &metrics[1].data else {
+            panic!("Expected a gauge");
+        };
+        let data_point = &gauge.data_points[0];
+        assert_eq!(
+            data_point.value,
+            Some(number_data_point::Value::AsDouble(21.5))
+        );
+        assert_eq!(
+            data_point.time_unix_nano,
+            unix_nanos(datetime!(2024-01-01 11:00 UTC))
+        );
+        assert_eq!(
+            attribute(&data_point.attributes, "tedge.measurement.type"),
+            Some(&string("environment"))
+        );
+    }
+
+    #[test]
+    fn convert_events_and_alarms_into_log_records() {
+        let state_dir = TempTedgeDir::new();
+        let mut converter = converter(&state_dir);
+
+        converter.convert(&message(
+            "te/device/child1///e/login",
+            r#"{"text": "A user logged in", "user": "alice"}"#,
+        ));
+        converter.convert(&message(
+            "te/device/child1///a/temperature_high",
+            r#"{"severity": "critical", "text": "Temperature is too high"}"#,
+        ));
+        converter.convert(&message("te/device/child1///a/temperature_high", ""));
+
+        let requests = converter.flush();
+        let [OtlpRequest::Logs(request)] = &requests[..] else {
+            panic!("Expected a single logs request, got {requests:?}");
+        };
+        let resource_logs = &request.resource_logs[0];
+        let resource = resource_logs.resource.as_ref().unwrap();
+        assert_eq!(
+            attribute(&resource.attributes, "device.id"),
+            Some(&string("child1"))
+        );
+        assert_eq!(
+            attribute(&resource.attributes, "tedge.entity.type"),
+            Some(&string("child-device"))
+        );
+
+        let records = &resource_logs.scope_logs[0].log_records;
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].body, Some(AnyValue::string("A user logged in")));
+        assert_eq!(
+            attribute(&records[0].attributes, "user"),
+            Some(&string("alice"))
+        );
+        assert_eq!(records[1].severity_number, SeverityNumber::Fatal as i32);
+        assert_eq!(records[1].severity_text, "critical");
+        assert_eq!(
+            attribute(&records[2].attributes, "tedge.alarm.status"),
+            Some(&string("cleared"))
+        );
+    }
+
+    #[test]
+    fn describe_services_with_their_registration_and_twin_data() {
+        let state_dir = TempTedgeDir::new();
+        let mut converter = converter(&state_dir);
+
+        converter.convert(&message(
+            "te/device/main/service/collector",
+            r#"{"@type": "service", "name": "collector", "type": "systemd"}"#,
+        ));
+        converter.convert(&message(
+            "te/device/main/service/collector/twin/version",
+            r#""1.2.0""#,
+        ));
+        converter.convert(&message(
+            "te/device/main/service/collector/m/stats",
+            r#"{"queue": 3}"#,
+        ));
+
+        let requests = converter.flush();
+        let [OtlpRequest::Metrics(request)] = &requests[..] else {
+            panic!("Expected a single metrics request, got {requests:?}");
+        };
+        let attributes = &request.resource_metrics[0]
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes;
+        assert_eq!(
+            attribute(attributes, "tedge.topic_id"),
+            Some(&string("device/main/service/collector"))
+        );
+        assert_eq!(attribute(attributes, "device.id"), Some(&string("gateway")));
+        assert_eq!(
+            attribute(attributes, "service.name"),
+            Some(&string("collector"))
+        );
+        assert_eq!(
+            attribute(attributes, "tedge.property.type"),
+            Some(&string("systemd"))
+        );
+        assert_eq!(
+            attribute(attributes, "tedge.twin.version"),
+            Some(&string("1.2.0"))
+        );
+    }
+
+    #[test]
+    fn retained_alarms_are_not_exported_again_on_restart() {
+        let state_dir = TempTedgeDir::new();
+        let raised = r#"{"severity": "major", "text": "Temperature is too high"}"#;
+        let mut mapper = converter(&state_dir);
+        mapper.convert(&message("te/device/main///a/temperature_high", raised));
+        mapper.convert(&message(
+            "te/device/main///a/door_open",
+            r#"{"text": "Door open"}"#,
+        ));
+        assert_eq!(log_records(&mapper.flush()).len(), 2);
+        drop(mapper);
+
+        // On restart, the retained alarms are sent again by the broker
+        let mut mapper = converter(&state_dir);
+        mapper.convert(&message("te/device/main///a/temperature_high", raised).with_retain());
+        mapper.convert(
+            &message(
+                "te/device/main///a/door_open",
+                r#"{"text": "Door open since 10 minutes"}"#,
+            )
+            .with_retain(),
+        );
+        mapper.convert(
+            &message(
+                "te/device/main///a/pressure_low",
+                r#"{"text": "Low pressure"}"#,
+            )
+            .with_retain(),
+        );
+        let requests = mapper.flush();
+        let records = log_records(&requests);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].body,
+            Some(AnyValue::string("Door open since 10 minutes"))
+        );
+        assert_eq!(records[1].body, Some(AnyValue::string("Low pressure")));
+
+        // An alarm raised again, once cleared, is exported
+        mapper.convert(&message("te/device/main///a/temperature_high", ""));
+        mapper.convert(&message("te/device/main///a/temperature_high", raised).with_retain());
+        assert_eq!(log_records(&mapper.flush()).len(), 2);
+    }
+
+    #[test]
+    fn count_the_records_of_a_request() {
+        let state_dir = TempTedgeDir::new();
+        let mut converter = converter(&state_dir);
+        converter.convert(&message(
+            "te/device/main///m/environment",
+            r#"{"temperature": 21.5, "pump": {"rpm": 1200, "pressure": 3.2}}"#,
+        ));
+        converter.convert(&message(
+            "te/device/child1///m/environment",
+            r#"{"temperature": 20}"#,
+        ));
+        converter.convert(&message("te/device/main///e/login", r#"{"text": "login"}"#));
+
+        let requests = converter.flush();
+        let counts: Vec<_> = requests.iter().map(OtlpRequest::record_count).collect();
+        assert_eq!(counts, vec![4, 1]);
+    }
+}
@@ -0,0 +1,65 @@
+use tedge_actors::ChannelError;
+use tedge_api::alarm::ThinEdgeAlarmDeserializerError;
+use tedge_api::builder::ThinEdgeJsonBuilderError;
+use tedge_api::entity_store;
+use tedge_api::event::error::ThinEdgeJsonDeserializerError;
+use tedge_api::parser::ThinEdgeJsonParserError;
+use tedge_http_ext::HttpError;
+use tedge_mqtt_ext::MqttError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConversionError {
+    #[error(transparent)]
+    MqttError(#[from] MqttError),
+
+    #[error(transparent)]
+    FromSerdeJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    FromThinEdgeJsonParser(#[from] ThinEdgeJsonParserError),
+
+    #[error(transparent)]
+    FromThinEdgeJsonBuilder(#[from] ThinEdgeJsonBuilderError),
+
+    #[error(transparent)]
+    FromEvent(#[from] ThinEdgeJsonDeserializerError),
+
+    #[error(transparent)]
+    FromAlarm(#[from] ThinEdgeAlarmDeserializerError),
+
+    #[error(transparent)]
+    FromEntityStore(#[from] entity_store::Error),
+
+    #[error("Invalid registration message received on {topic}")]
+    InvalidRegistration { topic: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OtelMapperError {
+    #[error(transparent)]
+    FromConfigRead(#[from] tedge_config::ReadError),
+
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromEntityStoreInit(#[from] entity_store::InitError),
+
+    #[error(transparent)]
+    FromTopicId(#[from] tedge_api::mqtt_topics::TopicIdError),
+
+    #[error("Invalid OTLP endpoint: '{endpoint}'")]
+    InvalidEndpoint { endpoint: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    FromHttp(#[from] HttpError),
+
+    #[error(transparent)]
+    FromChannel(#[from] ChannelError),
+
+    #[error("The collector responded with HTTP status {status}")]
+    Rejected { status: u16 },
+}
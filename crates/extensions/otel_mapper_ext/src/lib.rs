@@ -0,0 +1,89 @@
+//! A mapper exporting the thin-edge telemetry data to an OpenTelemetry collector.
+//!
+//! The measurements, events and alarms published over MQTT are converted into OTLP metrics and logs,
+//! and sent in batches to the collector using OTLP/HTTP with protobuf encoding.
+mod actor;
+mod config;
+pub mod converter;
+pub mod error;
+pub mod otlp;
+
+#[cfg(test)]
+mod tests;
+
+pub use actor::OtelMapperActor;
+pub use config::OtelMapperConfig;
+
+use crate::converter::OtelConverter;
+use crate::error::OtelMapperError;
+use clock::Clock;
+use std::convert::Infallible;
+use tedge_actors::Builder;
+use tedge_actors::ClientMessageBox;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_http_ext::HttpRequest;
+use tedge_http_ext::HttpResult;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+
+pub struct OtelMapperBuilder {
+    config: OtelMapperConfig,
+    converter: OtelConverter,
+    messages: SimpleMessageBoxBuilder<MqttMessage, NoMessage>,
+    http: ClientMessageBox<HttpRequest, HttpResult>,
+}
+
+impl OtelMapperBuilder {
+    pub fn try_new(
+        config: OtelMapperConfig,
+        clock: Box<dyn Clock>,
+        mqtt: &mut impl MessageSource<MqttMessage, TopicFilter>,
+        http: &mut impl ServiceProvider<HttpRequest, HttpResult, NoConfig>,
+    ) -> Result<Self, OtelMapperError> {
+        let converter = OtelConverter::try_new(&config, clock)?;
+        let messages = SimpleMessageBoxBuilder::new("OtelMapper", 16);
+        mqtt.register_peer(config.topics.clone(), messages.get_sender());
+        let http = ClientMessageBox::new("OtelMapper => HTTP", http);
+
+        Ok(OtelMapperBuilder {
+            config,
+            converter,
+            messages,
+            http,
+        })
+    }
+}
+
+impl RuntimeRequestSink for OtelMapperBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.messages.get_signal_sender()
+    }
+}
+
+impl Builder<OtelMapperActor> for OtelMapperBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<OtelMapperActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> OtelMapperActor {
+        OtelMapperActor {
+            endpoint: self.config.endpoint,
+            batch_max_size: self.config.batch_max_size,
+            batch_interval: self.config.batch_interval,
+            converter: self.converter,
+            messages: self.messages.build(),
+            http: self.http,
+            dropped_records: tedge_metrics::dropped_records("otel"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::otlp::any_value;
+use crate::otlp::ExportLogsServiceRequest;
+use crate::otlp::ExportMetricsServiceRequest;
+use crate::OtelMapperBuilder;
+use crate::OtelMapperConfig;
+use clock::WallClock;
+use prost::Message;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::NoMessage;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_http_ext::test_helpers::FakeHttpServerBox;
+use tedge_http_ext::test_helpers::HttpResponseBuilder;
+use tedge_http_ext::HttpRequest;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+use tedge_test_utils::fs::TempTedgeDir;
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Spawn an OTLP mapper along a fake collector and a box to send it MQTT messages
+fn spawn_otel_mapper(
+    state_dir: &TempTedgeDir,
+    batch_max_size: usize,
+    batch_interval: Duration,
+) -> (SimpleMessageBox<NoMessage, MqttMessage>, FakeHttpServerBox) {
+    let config = OtelMapperConfig {
+        endpoint: "http://collector:4318".to_string(),
+        device_id: "gateway".to_string(),
+        device_topic_id: EntityTopicId::default_main_device(),
+        default_service_type: "service".to_string(),
+        mqtt_schema: MqttSchema::default(),
+        topics: TopicFilter::new_unchecked("te/#"),
+        state_dir: state_dir.path().to_path_buf(),
+        batch_max_size,
+        batch_interval,
+    };
+    let mut mqtt: SimpleMessageBoxBuilder<NoMessage, MqttMessage> =
+        SimpleMessageBoxBuilder::new("MQTT", 16);
+    let mut collector = FakeHttpServerBox::builder();
+
+    let mapper =
+        OtelMapperBuilder::try_new(config, Box::new(WallClock), &mut mqtt, &mut collector).unwrap();
+    let actor = mapper.build();
+    tokio::spawn(async move { actor.run().await });
+
+    (mqtt.build(), collector.build())
+}
+
+/// Receive the next OTLP request, checking its URL and content type, and respond with success
+async fn recv_otlp_request(collector: &mut FakeHttpServerBox, path: &str) -> Vec<u8> {
+    let request: HttpRequest = tokio::time::timeout(TEST_TIMEOUT, collector.recv())
+        .await
+        .expect("No request received by the collector")
+        .unwrap();
+    assert_eq!(request.method(), "POST");
+    assert_eq!(
+        request.uri().to_string(),
+        format!("http://collector:4318{path}")
+    );
+    assert_eq!(request.headers()["content-type"], "application/x-protobuf");
+    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+
+    let response = HttpResponseBuilder::new().status(200).build().unwrap();
+    collector.send(Ok(response)).await.unwrap();
+    body.to_vec()
+}
+
+#[tokio::test]
+async fn export_metrics_when_the_batch_is_full() {
+    let state_dir = TempTedgeDir::new();
+    let (mut mqtt, mut collector) = spawn_otel_mapper(&state_dir, 2, Duration::from_secs(3600));
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/environment"),
+        r#"{"temperature": 21.5}"#,
+    ))
+    .await
+    .unwrap();
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/environment"),
+        r#"{"temperature": 22.0}"#,
+    ))
+    .await
+    .unwrap();
+
+    let body = recv_otlp_request(&mut collector, "/v1/metrics").await;
+    let request = ExportMetricsServiceRequest::decode(body.as_slice()).unwrap();
+    let metrics = &request.resource_metrics[0].scope_metrics[0].metrics;
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(metrics[0].name, "temperature");
+    let Some(crate::otlp::metric::Data::Gauge(gauge)) = &metrics[0].data else {
+        panic!("Expected a gauge");
+    };
+    assert_eq!(gauge.data_points.len(), 2);
+}
+
+#[tokio::test]
+async fn export_logs_at_the_end_of_the_batch_interval() {
+    let state_dir = TempTedgeDir::new();
+    let (mut mqtt, mut collector) = spawn_otel_mapper(&state_dir, 100, Duration::from_millis(100));
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///e/login"),
+        r#"{"text": "A user logged in"}"#,
+    ))
+    .await
+    .unwrap();
+
+    let body = recv_otlp_request(&mut collector, "/v1/logs").await;
+    let request = ExportLogsServiceRequest::decode(body.as_slice()).unwrap();
+    let records = &request.resource_logs[0].scope_logs[0].log_records;
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].body.as_ref().unwrap().value,
+        Some(any_value::Value::StringValue(
+            "A user logged in".to_string()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn count_the_records_dropped_when_the_collector_rejects_them() {
+    let state_dir = TempTedgeDir::new();
+    let (mut mqtt, mut collector) = spawn_otel_mapper(&state_dir, 2, Duration::from_secs(3600));
+    let dropped_records = tedge_metrics::dropped_records("otel");
+    let dropped_before = dropped_records.get();
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/environment"),
+        r#"{"temperature": 21.5, "humidity": 40}"#,
+    ))
+    .await
+    .unwrap();
+
+    let _request: HttpRequest = tokio::time::timeout(TEST_TIMEOUT, collector.recv())
+        .await
+        .expect("No request received by the collector")
+        .unwrap();
+    let response = HttpResponseBuilder::new().status(503).build().unwrap();
+    collector.send(Ok(response)).await.unwrap();
+
+    tokio::time::timeout(TEST_TIMEOUT, async {
+        while dropped_records.get() < dropped_before + 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The dropped records are not counted");
+}
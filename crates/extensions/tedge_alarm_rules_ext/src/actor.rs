@@ -0,0 +1,122 @@
+use crate::engine::AlarmRulesEngine;
+use crate::rules::AlarmRules;
+use crate::AlarmRulesConfig;
+use async_trait::async_trait;
+use log::error;
+use log::info;
+use std::time::Duration;
+use tedge_actors::fan_in_message_type;
+use tedge_actors::Actor;
+use tedge_actors::ChannelError;
+use tedge_actors::LoggingSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_file_system_ext::FsWatchEvent;
+use tedge_mqtt_ext::MqttMessage;
+use time::OffsetDateTime;
+
+/// How often the rules that must be violated for some duration are checked
+const TICK: Duration = Duration::from_secs(1);
+
+fan_in_message_type!(AlarmRulesInput[MqttMessage, FsWatchEvent] : Debug);
+
+pub struct AlarmRulesActor {
+    config: AlarmRulesConfig,
+    engine: AlarmRulesEngine,
+    messages: SimpleMessageBox<AlarmRulesInput, NoMessage>,
+    mqtt_publisher: LoggingSender<MqttMessage>,
+}
+
+#[async_trait]
+impl Actor for AlarmRulesActor {
+    fn name(&self) -> &str {
+        "AlarmRules"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        self.reload_rules().await?;
+
+        loop {
+            match tokio::time::timeout(TICK, self.messages.recv()).await {
+                Err(_) => {}       // no message received since the last tick
+                Ok(None) => break, // input channel closed
+                Ok(Some(AlarmRulesInput::MqttMessage(message))) => {
+                    let alarms = self
+                        .engine
+                        .process_measurement(OffsetDateTime::now_utc(), &message);
+                    self.publish(alarms).await?;
+                }
+                Ok(Some(AlarmRulesInput::FsWatchEvent(event))) => {
+                    self.process_file_watch_event(event).await?;
+                }
+            }
+            let alarms = self.engine.tick(OffsetDateTime::now_utc());
+            self.publish(alarms).await?;
+        }
+        Ok(())
+    }
+}
+
+impl AlarmRulesActor {
+    pub fn new(
+        config: AlarmRulesConfig,
+        messages: SimpleMessageBox<AlarmRulesInput, NoMessage>,
+        mqtt_publisher: LoggingSender<MqttMessage>,
+    ) -> Self {
+        let engine = AlarmRulesEngine::new(
+            config.mqtt_schema.clone(),
+            config.measurement_topics.clone(),
+        );
+        AlarmRulesActor {
+            config,
+            engine,
+            messages,
+            mqtt_publisher,
+        }
+    }
+
+    async fn process_file_watch_event(&mut self, event: FsWatchEvent) -> Result<(), ChannelError> {
+        let path = match event {
+            FsWatchEvent::Modified(path) => path,
+            FsWatchEvent::FileDeleted(path) => path,
+            // Creating a file also emits `FsWatchEvent::Modified`
+            FsWatchEvent::FileCreated(_) => return Ok(()),
+            FsWatchEvent::DirectoryDeleted(_) => return Ok(()),
+            FsWatchEvent::DirectoryCreated(_) => return Ok(()),
+        };
+
+        if path.file_name() == self.config.rules_path.file_name() {
+            self.reload_rules().await?;
+        }
+        Ok(())
+    }
+
+    /// Reload the rules, keeping the previous rules if the new ones are invalid
+    async fn reload_rules(&mut self) -> Result<(), ChannelError> {
+        match AlarmRules::from_file(&self.config.rules_path) {
+            Ok(rules) => {
+                info!(
+                    "Loaded {} alarm rules from {}",
+                    rules.rules.len(),
+                    self.config.rules_path.display()
+                );
+                let cleared_alarms = self.engine.reload(rules);
+                self.publish(cleared_alarms).await
+            }
+            Err(err) => {
+                error!("The alarm rules are not updated: {err}");
+                Ok(())
+            }
+        }
+    }
+
+    async fn publish(&mut self, messages: Vec<MqttMessage>) -> Result<(), ChannelError> {
+        for message in messages {
+            self.mqtt_publisher.send(message).await?;
+        }
+        Ok(())
+    }
+}
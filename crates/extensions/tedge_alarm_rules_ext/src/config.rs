@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::TopicFilter;
+
+pub const DEFAULT_PLUGIN_CONFIG_FILE_NAME: &str = "tedge-alarm-rules.toml";
+pub const DEFAULT_PLUGIN_CONFIG_DIR_NAME: &str = "plugins/";
+
+/// Configuration of the Alarm Rules actor
+#[derive(Clone, Debug)]
+pub struct AlarmRulesConfig {
+    pub mqtt_schema: MqttSchema,
+    pub rules_dir: PathBuf,
+    pub rules_path: PathBuf,
+    pub measurement_topics: TopicFilter,
+}
+
+impl AlarmRulesConfig {
+    pub fn new(config_dir: PathBuf, mqtt_schema: MqttSchema) -> Self {
+        let rules_dir = config_dir.join(DEFAULT_PLUGIN_CONFIG_DIR_NAME);
+        let rules_path = rules_dir.join(DEFAULT_PLUGIN_CONFIG_FILE_NAME);
+        let measurement_topics =
+            mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::Measurement);
+
+        AlarmRulesConfig {
+            mqtt_schema,
+            rules_dir,
+            rules_path,
+            measurement_topics,
+        }
+    }
+}
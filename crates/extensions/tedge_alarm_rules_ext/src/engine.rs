@@ -0,0 +1,526 @@
+use crate::rules::AlarmRule;
+use crate::rules::AlarmRules;
+use serde_json::json;
+use std::collections::HashMap;
+use tedge_api::builder::ThinEdgeJsonBuilder;
+use tedge_api::data::ThinEdgeJson;
+use tedge_api::data::ThinEdgeValue;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Evaluate the alarm rules on the measurements, raising and clearing the alarms
+///
+/// An alarm is raised on the entity of a measurement when the rule has been violated for the rule duration,
+/// and cleared as soon as the rule is no more violated.
+///
+/// The durations and the rates are measured using the time the measurements are received, not their timestamps,
+/// so a single clock is used even if the clocks of the devices publishing measurements are not in sync.
+pub struct AlarmRulesEngine {
+    mqtt_schema: MqttSchema,
+    measurement_topics: TopicFilter,
+    rules: Vec<(AlarmRule, TopicFilter)>,
+    states: HashMap<String, SeriesState>,
+}
+
+/// The state of an alarm rule, for a specific entity
+struct SeriesState {
+    alarm_type: String,
+    alarm_topic: Topic,
+
+    /// Whether the alarm is currently raised, `None` if unknown
+    ///
+    /// The state is unknown on start, the alarm being possibly raised before a restart.
+    raised: Option<bool>,
+
+    /// The last value received for the series
+    last: Option<(OffsetDateTime, f64)>,
+
+    /// Since when the rule is violated, along the last violation
+    abnormal: Option<(OffsetDateTime, Violation)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Violation {
+    Above { threshold: f64, value: f64 },
+    Below { threshold: f64, value: f64 },
+    Rate { max_rate: f64, rate: f64 },
+}
+
+impl AlarmRulesEngine {
+    pub fn new(mqtt_schema: MqttSchema, measurement_topics: TopicFilter) -> Self {
+        AlarmRulesEngine {
+            mqtt_schema,
+            measurement_topics,
+            rules: vec![],
+            states: HashMap::new(),
+        }
+    }
+
+    /// Replace the current rules
+    ///
+    /// Return the messages clearing the alarms raised by rules that no more exist.
+    pub fn reload(&mut self, rules: AlarmRules) -> Vec<MqttMessage> {
+        self.rules = rules
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let topics = rule.topics(&self.measurement_topics);
+                (rule, topics)
+            })
+            .collect();
+
+        let mut messages = vec![];
+        let rules = &self.rules;
+        self.states.retain(|_, state| {
+            if rules
+                .iter()
+                .any(|(rule, _)| rule.alarm_type == state.alarm_type)
+            {
+                return true;
+            }
+            if state.raised != Some(false) {
+                messages.push(clear_message(&state.alarm_topic));
+            }
+            false
+        });
+        messages
+    }
+
+    /// Evaluate the rules watching the series of a measurement, received at the given time
+    ///
+    /// Return the messages raising or clearing alarms.
+    pub fn process_measurement(
+        &mut self,
+        now: OffsetDateTime,
+        message: &MqttMessage,
+    ) -> Vec<MqttMessage> {
+        if !self.rules.iter().any(|(_, topics)| topics.accept(message)) {
+            return vec![];
+        }
+        let Ok((entity, Channel::Measurement { .. })) =
+            self.mqtt_schema.entity_channel_of(&message.topic)
+        else {
+            return vec![];
+        };
+        let Some(measurement) = parse_measurement(message) else {
+            return vec![];
+        };
+        let mut messages = vec![];
+        for (rule, topics) in self.rules.iter() {
+            if !topics.accept(message) {
+                continue;
+            }
+            let Some(value) = series_value(&measurement, rule) else {
+                continue;
+            };
+            let alarm_topic = self.mqtt_schema.topic_for(
+                &entity,
+                &Channel::Alarm {
+                    alarm_type: rule.alarm_type.clone(),
+                },
+            );
+            let state = self
+                .states
+                .entry(alarm_topic.name.clone())
+                .or_insert_with(|| SeriesState::new(rule, alarm_topic));
+            messages.extend(state.update(rule, now, value));
+        }
+        messages
+    }
+
+    /// Raise the alarms of the rules violated for long enough at the given time
+    pub fn tick(&mut self, now: OffsetDateTime) -> Vec<MqttMessage> {
+        let mut messages = vec![];
+        for state in self.states.values_mut() {
+            if let Some((rule, _)) = self
+                .rules
+                .iter()
+                .find(|(rule, _)| rule.alarm_type == state.alarm_type)
+            {
+                messages.extend(state.check_duration(rule, now));
+            }
+        }
+        messages
+    }
+}
+
+impl SeriesState {
+    fn new(rule: &AlarmRule, alarm_topic: Topic) -> Self {
+        SeriesState {
+            alarm_type: rule.alarm_type.clone(),
+            alarm_topic,
+            raised: None,
+            last: None,
+            abnormal: None,
+        }
+    }
+
+    fn update(
+        &mut self,
+        rule: &AlarmRule,
+        time: OffsetDateTime,
+        value: f64,
+    ) -> Option<MqttMessage> {
+        let violation = Violation::check(rule, self.last, time, value);
+        self.last = Some((time, value));
+
+        match violation {
+            Some(violation) => {
+                let since = self.abnormal.map_or(time, |(since, _)| since);
+                self.abnormal = Some((since, violation));
+                self.check_duration(rule, time)
+            }
+            None => {
+                self.abnormal = None;
+                if self.raised == Some(false) {
+                    return None;
+                }
+                self.raised = Some(false);
+                Some(clear_message(&self.alarm_topic))
+            }
+        }
+    }
+
+    fn check_duration(&mut self, rule: &AlarmRule, now: OffsetDateTime) -> Option<MqttMessage> {
+        let (since, violation) = self.abnormal?;
+        if self.raised == Some(true) || now - since < rule.duration() {
+            return None;
+        }
+        self.raised = Some(true);
+        Some(raise_message(&self.alarm_topic, rule, since, violation))
+    }
+}
+
+impl Violation {
+    fn check(
+        rule: &AlarmRule,
+        last: Option<(OffsetDateTime, f64)>,
+        time: OffsetDateTime,
+        value: f64,
+    ) -> Option<Violation> {
+        if let Some(threshold) = rule.above.filter(|threshold| value > *threshold) {
+            return Some(Violation::Above { threshold, value });
+        }
+        if let Some(threshold) = rule.below.filter(|threshold| value < *threshold) {
+            return Some(Violation::Below { threshold, value });
+        }
+        if let (Some(max_rate), Some((last_time, last_value))) = (rule.max_rate, last) {
+            let elapsed = (time - last_time).as_seconds_f64();
+            if elapsed > 0.0 {
+                let rate = (value - last_value).abs() / elapsed;
+                if rate > max_rate {
+                    return Some(Violation::Rate { max_rate, rate });
+                }
+            }
+        }
+        None
+    }
+
+    fn text(&self, measurement: &str) -> String {
+        match self {
+            Violation::Above { threshold, value } => {
+                format!("{measurement} is above {threshold}: {value}")
+            }
+            Violation::Below { threshold, value } => {
+                format!("{measurement} is below {threshold}: {value}")
+            }
+            Violation::Rate { max_rate, rate } => {
+                format!("{measurement} changes faster than {max_rate} per second: {rate:.3}")
+            }
+        }
+    }
+}
+
+fn parse_measurement(message: &MqttMessage) -> Option<ThinEdgeJson> {
+    let payload = message.payload_str().ok()?;
+    let mut builder = ThinEdgeJsonBuilder::default();
+    tedge_api::parser::parse_str(payload, &mut builder).ok()?;
+    builder.done().ok()
+}
+
+fn series_value(measurement: &ThinEdgeJson, rule: &AlarmRule) -> Option<f64> {
+    let value = match rule.series() {
+        (None, name) => measurement.values.iter().find_map(|value| match value {
+            ThinEdgeValue::Single(single) if single.name == name => Some(&single.value),
+            _ => None,
+        }),
+        (Some(group), name) => measurement.values.iter().find_map(|value| match value {
+            ThinEdgeValue::Multi(multi) if multi.name == group => multi
+                .values
+                .iter()
+                .find(|single| single.name == name)
+                .map(|single| &single.value),
+            _ => None,
+        }),
+    };
+    value.map(|value| value.value.as_f64())
+}
+
+fn raise_message(
+    alarm_topic: &Topic,
+    rule: &AlarmRule,
+    since: OffsetDateTime,
+    violation: Violation,
+) -> MqttMessage {
+    let text = rule
+        .text
+        .clone()
+        .unwrap_or_else(|| violation.text(&rule.measurement));
+    let time = since.format(&Rfc3339).unwrap_or_else(|_| since.to_string());
+    let payload = json!({
+        "text": text,
+        "severity": rule.severity,
+        "time": time,
+    });
+    MqttMessage::new(alarm_topic, payload.to_string())
+        .with_retain()
+        .with_qos(QoS::AtLeastOnce)
+}
+
+fn clear_message(alarm_topic: &Topic) -> MqttMessage {
+    MqttMessage::new(alarm_topic, "")
+        .with_retain()
+        .with_qos(QoS::AtLeastOnce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn engine(rules: &str) -> AlarmRulesEngine {
+        let schema = MqttSchema::default();
+        let topics = TopicFilter::new_unchecked("te/+/+/+/+/m/+");
+        let mut engine = AlarmRulesEngine::new(schema, topics);
+        engine.reload(toml::from_str(rules).unwrap());
+        engine
+    }
+
+    fn at(secs: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(secs).unwrap()
+    }
+
+    fn measurement(topic: &str, payload: Value) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload.to_string())
+    }
+
+    fn alarm(message: &MqttMessage) -> (String, Value) {
+        let payload = if message.payload_bytes().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(message.payload_bytes()).unwrap()
+        };
+        (message.topic.name.clone(), payload)
+    }
+
+    #[test]
+    fn raise_and_clear_threshold_alarms() {
+        let mut engine = engine(
+            r#"
+[[rules]]
+type = "temperature_high"
+measurement = "temperature"
+above = 30.0
+"#,
+        );
+        let topic = "te/device/child01///m/environment";
+
+        // The state of the alarm is unknown on start, so the alarm is cleared
+        let messages =
+            engine.process_measurement(at(1), &measurement(topic, json!({"temperature": 20.0})));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/child01///a/temperature_high".to_string(),
+                Value::Null
+            )]
+        );
+        assert!(messages[0].retain);
+
+        let messages =
+            engine.process_measurement(at(2), &measurement(topic, json!({"temperature": 31.5})));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/child01///a/temperature_high".to_string(),
+                json!({
+                    "text": "temperature is above 30: 31.5",
+                    "severity": "major",
+                    "time": "1970-01-01T00:00:02Z"
+                })
+            )]
+        );
+
+        // The alarm is raised only once
+        assert!(engine
+            .process_measurement(at(3), &measurement(topic, json!({"temperature": 32.0})))
+            .is_empty());
+
+        let messages =
+            engine.process_measurement(at(4), &measurement(topic, json!({"temperature": 29.0})));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/child01///a/temperature_high".to_string(),
+                Value::Null
+            )]
+        );
+        assert!(engine
+            .process_measurement(at(5), &measurement(topic, json!({"temperature": 28.0})))
+            .is_empty());
+    }
+
+    #[test]
+    fn raise_alarms_once_out_of_range_for_the_rule_duration() {
+        let mut engine = engine(
+            r#"
+[[rules]]
+type = "pressure_low"
+topic = "te/device/main///m/+"
+measurement = "pump.pressure"
+below = 2.0
+duration = 10
+severity = "critical"
+text = "Low pump pressure"
+"#,
+        );
+        let topic = "te/device/main///m/pump";
+
+        engine.process_measurement(
+            at(0),
+            &measurement(topic, json!({"pump": {"pressure": 3.0}})),
+        );
+        assert!(engine
+            .process_measurement(
+                at(1),
+                &measurement(topic, json!({"pump": {"pressure": 1.5}}))
+            )
+            .is_empty());
+        assert!(engine.tick(at(10)).is_empty());
+
+        let messages = engine.tick(at(11));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/main///a/pressure_low".to_string(),
+                json!({
+                    "text": "Low pump pressure",
+                    "severity": "critical",
+                    "time": "1970-01-01T00:00:01Z"
+                })
+            )]
+        );
+        assert!(engine.tick(at(12)).is_empty());
+
+        // The rule doesn't apply to other devices
+        assert!(engine
+            .process_measurement(
+                at(12),
+                &measurement(
+                    "te/device/child///m/pump",
+                    json!({"pump": {"pressure": 0.0}})
+                )
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn raise_alarms_on_fast_changes() {
+        let mut engine = engine(
+            r#"
+[[rules]]
+type = "temperature_rise"
+measurement = "temperature"
+max_rate = 0.5
+"#,
+        );
+        let topic = "te/device/main///m/";
+
+        engine.process_measurement(at(0), &measurement(topic, json!({"temperature": 20.0})));
+        assert!(engine
+            .process_measurement(at(10), &measurement(topic, json!({"temperature": 24.0})))
+            .is_empty());
+
+        let messages =
+            engine.process_measurement(at(12), &measurement(topic, json!({"temperature": 26.0})));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/main///a/temperature_rise".to_string(),
+                json!({
+                    "text": "temperature changes faster than 0.5 per second: 1.000",
+                    "severity": "major",
+                    "time": "1970-01-01T00:00:12Z"
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn measurement_timestamps_are_ignored() {
+        let mut engine = engine(
+            r#"
+[[rules]]
+type = "temperature_high"
+measurement = "temperature"
+above = 30.0
+duration = 10
+"#,
+        );
+        let topic = "te/device/main///m/";
+
+        // A measurement published by a device which clock is late
+        assert!(engine
+            .process_measurement(
+                at(100),
+                &measurement(topic, json!({"time": 0, "temperature": 35.0}))
+            )
+            .is_empty());
+        assert!(engine.tick(at(101)).is_empty());
+
+        let messages = engine.tick(at(110));
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/main///a/temperature_high".to_string(),
+                json!({
+                    "text": "temperature is above 30: 35",
+                    "severity": "major",
+                    "time": "1970-01-01T00:01:40Z"
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn clear_alarms_of_removed_rules() {
+        let mut engine = engine(
+            r#"
+[[rules]]
+type = "temperature_high"
+measurement = "temperature"
+above = 30.0
+"#,
+        );
+        let topic = "te/device/main///m/";
+        engine.process_measurement(at(1), &measurement(topic, json!({"temperature": 35.0})));
+
+        let messages = engine.reload(AlarmRules::default());
+        assert_eq!(
+            messages.iter().map(alarm).collect::<Vec<_>>(),
+            vec![(
+                "te/device/main///a/temperature_high".to_string(),
+                Value::Null
+            )]
+        );
+        assert!(engine
+            .process_measurement(at(2), &measurement(topic, json!({"temperature": 35.0})))
+            .is_empty());
+    }
+}
@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AlarmRulesError {
+    #[error("Failed to read the alarm rules from {path}: {error}")]
+    ReadError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error("Failed to parse the alarm rules from {path}: {error}")]
+    ParseError {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+
+    #[error("Invalid alarm rule {alarm_type}: {reason}")]
+    InvalidRule { alarm_type: String, reason: String },
+}
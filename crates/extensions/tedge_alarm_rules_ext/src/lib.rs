@@ -0,0 +1,107 @@
+//! Raise and clear alarms on the device, watching measurement series.
+//!
+//! The rules are defined in `/etc/tedge/plugins/tedge-alarm-rules.toml` and reloaded on change.
+//! A rule raises an alarm on the entity of a measurement when the value of a series:
+//! - is above or below a threshold,
+//! - changes faster than a rate,
+//! - and optionally, stays so for a given duration.
+//!
+//! The alarms are published on the regular alarm topics (`te/<entity>/a/<type>`),
+//! and are cleared as soon as the series is back to normal.
+mod actor;
+mod config;
+mod engine;
+mod error;
+mod rules;
+
+#[cfg(test)]
+mod tests;
+
+pub use actor::*;
+pub use config::*;
+pub use engine::AlarmRulesEngine;
+pub use error::AlarmRulesError;
+pub use rules::AlarmRule;
+pub use rules::AlarmRules;
+use std::path::PathBuf;
+use tedge_actors::adapt;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::LinkError;
+use tedge_actors::LoggingSender;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_file_system_ext::FsWatchEvent;
+use tedge_mqtt_ext::*;
+use tedge_utils::file::create_directory_with_defaults;
+use tedge_utils::file::FileError;
+
+/// This is an actor builder.
+pub struct AlarmRulesBuilder {
+    config: AlarmRulesConfig,
+    box_builder: SimpleMessageBoxBuilder<AlarmRulesInput, NoMessage>,
+    mqtt_publisher: DynSender<MqttMessage>,
+}
+
+impl AlarmRulesBuilder {
+    pub fn try_new(
+        config: AlarmRulesConfig,
+        mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+        fs_notify: &mut impl MessageSource<FsWatchEvent, PathBuf>,
+    ) -> Result<Self, FileError> {
+        // The rules directory must exist to be watched
+        create_directory_with_defaults(&config.rules_dir)?;
+
+        let box_builder = SimpleMessageBoxBuilder::new("Alarm Rules", 16);
+        let mqtt_publisher = mqtt.connect_consumer(
+            Self::subscriptions(&config),
+            adapt(&box_builder.get_sender()),
+        );
+        fs_notify.register_peer(
+            Self::watched_directory(&config),
+            adapt(&box_builder.get_sender()),
+        );
+
+        Ok(Self {
+            config,
+            box_builder,
+            mqtt_publisher,
+        })
+    }
+
+    /// List of MQTT topic filters the alarm rules actor has to subscribe to
+    fn subscriptions(config: &AlarmRulesConfig) -> TopicFilter {
+        config.measurement_topics.clone()
+    }
+
+    /// Directory watched by the alarm rules actor for rule changes
+    fn watched_directory(config: &AlarmRulesConfig) -> PathBuf {
+        config.rules_dir.clone()
+    }
+}
+
+impl RuntimeRequestSink for AlarmRulesBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.box_builder.get_signal_sender()
+    }
+}
+
+impl Builder<AlarmRulesActor> for AlarmRulesBuilder {
+    type Error = LinkError;
+
+    fn try_build(self) -> Result<AlarmRulesActor, Self::Error> {
+        let mqtt_publisher = LoggingSender::new("Tedge-Alarm-Rules".into(), self.mqtt_publisher);
+        let message_box = self.box_builder.build();
+
+        Ok(AlarmRulesActor::new(
+            self.config,
+            message_box,
+            mqtt_publisher,
+        ))
+    }
+}
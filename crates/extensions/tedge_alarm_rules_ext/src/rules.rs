@@ -0,0 +1,240 @@
+use crate::error::AlarmRulesError;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use tedge_mqtt_ext::TopicFilter;
+
+/// The alarm rules, as defined in `/etc/tedge/plugins/tedge-alarm-rules.toml`
+///
+/// ```toml
+/// [[rules]]
+/// type = "temperature_high"
+/// topic = "te/+/+/+/+/m/environment"
+/// measurement = "temperature"
+/// above = 30.0
+/// duration = 60
+/// severity = "major"
+///
+/// [[rules]]
+/// type = "pressure_drop"
+/// measurement = "pump.pressure"
+/// max_rate = 0.5
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmRules {
+    #[serde(default)]
+    pub rules: Vec<AlarmRule>,
+}
+
+/// A rule raising an alarm when a measurement series is out of range or changes too fast,
+/// and clearing this alarm once the series is back to normal.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmRule {
+    /// The type of the alarm raised by this rule
+    #[serde(rename = "type")]
+    pub alarm_type: String,
+
+    /// The topic filter of the watched measurements, defaulting to all the measurements
+    pub topic: Option<String>,
+
+    /// The name of the watched series, given as `group.name` for a series that is part of a group
+    pub measurement: String,
+
+    /// The alarm is raised when the value is above this threshold
+    pub above: Option<f64>,
+
+    /// The alarm is raised when the value is below this threshold
+    pub below: Option<f64>,
+
+    /// The alarm is raised when the value changes faster than this rate, in units per second
+    pub max_rate: Option<f64>,
+
+    /// How long, in seconds, the series must be abnormal before the alarm is raised
+    #[serde(default)]
+    pub duration: u64,
+
+    #[serde(default = "major")]
+    pub severity: String,
+
+    /// The text of the alarm, defaulting to a description of the violated condition
+    pub text: Option<String>,
+}
+
+impl AlarmRules {
+    /// Load the rules from the given file, no rules being defined if there is no such file
+    pub fn from_file(path: &Path) -> Result<Self, AlarmRulesError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(AlarmRules::default())
+            }
+            Err(error) => {
+                return Err(AlarmRulesError::ReadError {
+                    path: path.to_path_buf(),
+                    error,
+                })
+            }
+        };
+        let rules: AlarmRules =
+            toml::from_str(&content).map_err(|error| AlarmRulesError::ParseError {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    fn validate(&self) -> Result<(), AlarmRulesError> {
+        let mut alarm_types = HashSet::new();
+        for rule in self.rules.iter() {
+            rule.validate()?;
+            if !alarm_types.insert(&rule.alarm_type) {
+                return Err(rule.invalid("several rules are defined for this alarm type"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AlarmRule {
+    /// The topics of the measurements watched by this rule
+    pub fn topics(&self, default_topics: &TopicFilter) -> TopicFilter {
+        match &self.topic {
+            Some(topic) => TopicFilter::new_unchecked(topic),
+            None => default_topics.clone(),
+        }
+    }
+
+    /// The group and name of the watched series
+    pub fn series(&self) -> (Option<&str>, &str) {
+        match self.measurement.split_once('.') {
+            Some((group, name)) => (Some(group), name),
+            None => (None, &self.measurement),
+        }
+    }
+
+    /// How long the series must be abnormal before the alarm is raised
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration)
+    }
+
+    fn validate(&self) -> Result<(), AlarmRulesError> {
+        if self.alarm_type.is_empty() || self.alarm_type.contains(['/', '+', '#']) {
+            return Err(self.invalid("the alarm type must be a non-empty topic level"));
+        }
+        if let Some(topic) = &self.topic {
+            if TopicFilter::new(topic).is_err() {
+                return Err(self.invalid(&format!("invalid topic filter: {topic}")));
+            }
+        }
+        if self.above.is_none() && self.below.is_none() && self.max_rate.is_none() {
+            return Err(self.invalid("at least one of `above`, `below` or `max_rate` is required"));
+        }
+        if let (Some(above), Some(below)) = (self.above, self.below) {
+            if below > above {
+                return Err(self.invalid("`below` is greater than `above`"));
+            }
+        }
+        if self.max_rate.is_some_and(|rate| rate < 0.0) {
+            return Err(self.invalid("`max_rate` must be positive"));
+        }
+        Ok(())
+    }
+
+    fn invalid(&self, reason: &str) -> AlarmRulesError {
+        AlarmRulesError::InvalidRule {
+            alarm_type: self.alarm_type.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+fn major() -> String {
+    "major".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    fn parse(content: &str) -> Result<AlarmRules, AlarmRulesError> {
+        let rules: AlarmRules = toml::from_str(content).unwrap();
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    #[test]
+    fn parse_rules() {
+        let rules = parse(
+            r#"
+[[rules]]
+type = "temperature_high"
+topic = "te/+/+/+/+/m/environment"
+measurement = "temperature"
+above = 30.0
+duration = 60
+
+[[rules]]
+type = "pressure_drop"
+measurement = "pump.pressure"
+max_rate = 0.5
+severity = "critical"
+"#,
+        )
+        .unwrap();
+
+        let temperature = &rules.rules[0];
+        assert_eq!(temperature.series(), (None, "temperature"));
+        assert_eq!(temperature.duration(), Duration::from_secs(60));
+        assert_eq!(temperature.severity, "major");
+
+        let pressure = &rules.rules[1];
+        assert_eq!(pressure.series(), (Some("pump"), "pressure"));
+        assert_eq!(pressure.duration(), Duration::ZERO);
+        assert_eq!(
+            pressure.topics(&TopicFilter::new_unchecked("te/+/+/+/+/m/+")),
+            TopicFilter::new_unchecked("te/+/+/+/+/m/+")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        assert_matches!(
+            parse(
+                r#"
+[[rules]]
+type = "no_condition"
+measurement = "temperature"
+"#
+            ),
+            Err(AlarmRulesError::InvalidRule { alarm_type, .. }) if alarm_type == "no_condition"
+        );
+
+        assert_matches!(
+            parse(
+                r#"
+[[rules]]
+type = "duplicated"
+measurement = "temperature"
+above = 30.0
+
+[[rules]]
+type = "duplicated"
+measurement = "humidity"
+above = 80.0
+"#
+            ),
+            Err(AlarmRulesError::InvalidRule { alarm_type, .. }) if alarm_type == "duplicated"
+        );
+    }
+
+    #[test]
+    fn a_missing_rules_file_defines_no_rules() {
+        let rules = AlarmRules::from_file(Path::new("/some/unknown/rules.toml")).unwrap();
+        assert!(rules.rules.is_empty());
+    }
+}
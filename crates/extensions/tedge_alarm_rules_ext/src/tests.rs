@@ -0,0 +1,139 @@
+use crate::AlarmRulesBuilder;
+use crate::AlarmRulesConfig;
+use serde_json::json;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+use tedge_actors::test_helpers::MessageReceiverExt;
+use tedge_actors::test_helpers::TimedMessageBox;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::MessageReceiver;
+use tedge_actors::NoMessage;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_file_system_ext::FsWatchEvent;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_test_utils::fs::TempTedgeDir;
+
+type MqttMessageBox = TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>;
+
+const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
+
+const TEMPERATURE_RULE: &str = r#"
+[[rules]]
+type = "temperature_high"
+measurement = "temperature"
+above = 30.0
+"#;
+
+const HUMIDITY_RULE: &str = r#"
+[[rules]]
+type = "humidity_high"
+measurement = "humidity"
+above = 80.0
+"#;
+
+/// Spawn an alarm rules actor and return 2 boxes to exchange MQTT messages and file events with it
+async fn spawn_alarm_rules_actor(
+    config_dir: &Path,
+) -> (MqttMessageBox, SimpleMessageBox<NoMessage, FsWatchEvent>) {
+    let config = AlarmRulesConfig::new(config_dir.to_path_buf(), MqttSchema::default());
+
+    let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+        SimpleMessageBoxBuilder::new("MQTT", 5);
+    let mut fs_watcher_builder: SimpleMessageBoxBuilder<NoMessage, FsWatchEvent> =
+        SimpleMessageBoxBuilder::new("FS", 5);
+
+    let actor = AlarmRulesBuilder::try_new(config, &mut mqtt_builder, &mut fs_watcher_builder)
+        .unwrap()
+        .build();
+    tokio::spawn(async move { actor.run().await });
+
+    (
+        mqtt_builder.build().with_timeout(TEST_TIMEOUT_MS),
+        fs_watcher_builder.build(),
+    )
+}
+
+fn measurement(payload: Value) -> MqttMessage {
+    MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/environment"),
+        payload.to_string(),
+    )
+}
+
+fn alarm_payload(message: &MqttMessage) -> Value {
+    serde_json::from_slice(message.payload_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn raise_alarms_on_threshold_crossing() -> Result<(), anyhow::Error> {
+    let tempdir = TempTedgeDir::new();
+    tempdir
+        .dir("plugins")
+        .file("tedge-alarm-rules.toml")
+        .with_raw_content(TEMPERATURE_RULE);
+    let (mut mqtt, _fs) = spawn_alarm_rules_actor(tempdir.path()).await;
+
+    mqtt.send(measurement(
+        json!({"time": "2024-06-20T12:00:00Z", "temperature": 35.0}),
+    ))
+    .await?;
+
+    let alarm = mqtt.recv().await.expect("an alarm");
+    assert_eq!(alarm.topic.name, "te/device/main///a/temperature_high");
+    assert!(alarm.retain);
+    assert_eq!(
+        alarm_payload(&alarm),
+        json!({
+            "text": "temperature is above 30: 35",
+            "severity": "major",
+            "time": "2024-06-20T12:00:00Z"
+        })
+    );
+
+    mqtt.send(measurement(json!({"temperature": 25.0}))).await?;
+    assert_eq!(
+        mqtt.recv().await,
+        Some(
+            MqttMessage::new(
+                &Topic::new_unchecked("te/device/main///a/temperature_high"),
+                ""
+            )
+            .with_retain()
+            .with_qos(tedge_mqtt_ext::QoS::AtLeastOnce)
+        )
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_rules_on_change() -> Result<(), anyhow::Error> {
+    let tempdir = TempTedgeDir::new();
+    let (mut mqtt, mut fs) = spawn_alarm_rules_actor(tempdir.path()).await;
+
+    // No rules are defined yet: the measurement is ignored
+    mqtt.send(measurement(json!({"humidity": 95.0}))).await?;
+
+    let rules_path = tempdir
+        .path()
+        .join("plugins")
+        .join("tedge-alarm-rules.toml");
+    std::fs::write(&rules_path, HUMIDITY_RULE)?;
+    fs.send(FsWatchEvent::Modified(rules_path)).await?;
+
+    mqtt.send(measurement(json!({"humidity": 96.0}))).await?;
+    let alarm = mqtt.recv().await.expect("an alarm");
+    assert_eq!(alarm.topic.name, "te/device/main///a/humidity_high");
+    assert_eq!(
+        alarm_payload(&alarm)["text"],
+        json!("humidity is above 80: 96")
+    );
+
+    Ok(())
+}
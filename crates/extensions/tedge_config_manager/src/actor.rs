@@ -4,8 +4,10 @@ use camino::Utf8PathBuf;
 use log::debug;
 use log::error;
 use log::info;
+use log::warn;
 use serde_json::json;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use tedge_actors::fan_in_message_type;
 use tedge_actors::Actor;
 use tedge_actors::ChannelError;
@@ -27,11 +29,19 @@ use tedge_mqtt_ext::Topic;
 use tedge_uploader_ext::UploadRequest;
 use tedge_uploader_ext::UploadResult;
 use tedge_write::CopyOptions;
+use tedge_write::UpdateOptions;
+use tedge_write::UpdateStep;
 
 use crate::TedgeWriteStatus;
 
+use super::archive::create_archive;
+use super::archive::unpack_archive;
+use super::config::FileEntry;
+use super::config::FileEntryKind;
 use super::config::PluginConfig;
 use super::error::ConfigManagementError;
+use super::hook::run_hook;
+use super::hook::HOOK_TIMEOUT;
 use super::ConfigManagerConfig;
 use super::DEFAULT_PLUGIN_CONFIG_FILE_NAME;
 
@@ -43,7 +53,10 @@ pub type ConfigDownloadResult = (MqttTopic, DownloadResult);
 pub type ConfigUploadRequest = (MqttTopic, UploadRequest);
 pub type ConfigUploadResult = (MqttTopic, UploadResult);
 
-fan_in_message_type!(ConfigInput[MqttMessage, FsWatchEvent, ConfigDownloadResult, ConfigUploadResult] : Debug);
+/// The outcome of a configuration update, i.e. the path of the deployed configuration or the reason of the failure
+pub type ConfigApplyResult = (MqttTopic, Result<Utf8PathBuf, String>);
+
+fan_in_message_type!(ConfigInput[MqttMessage, FsWatchEvent, ConfigDownloadResult, ConfigUploadResult, ConfigApplyResult] : Debug);
 fan_in_message_type!(ConfigOutput[MqttMessage, ConfigDownloadRequest, ConfigUploadRequest]: Debug);
 
 pub struct ConfigManagerActor {
@@ -54,6 +67,7 @@ pub struct ConfigManagerActor {
     mqtt_publisher: LoggingSender<MqttMessage>,
     download_sender: DynSender<ConfigDownloadRequest>,
     upload_sender: DynSender<ConfigUploadRequest>,
+    apply_sender: DynSender<ConfigApplyResult>,
 }
 
 #[async_trait]
@@ -75,6 +89,9 @@ impl Actor for ConfigManagerActor {
                 ConfigInput::ConfigUploadResult((topic, result)) => {
                     self.process_uploaded_config(&topic, result).await
                 }
+                ConfigInput::ConfigApplyResult((topic, result)) => {
+                    self.process_applied_config(&topic, result).await
+                }
             };
 
             if let Err(err) = result {
@@ -94,6 +111,7 @@ impl ConfigManagerActor {
         mqtt_publisher: LoggingSender<MqttMessage>,
         download_sender: DynSender<ConfigDownloadRequest>,
         upload_sender: DynSender<ConfigUploadRequest>,
+        apply_sender: DynSender<ConfigApplyResult>,
     ) -> Self {
         ConfigManagerActor {
             config,
@@ -103,12 +121,16 @@ impl ConfigManagerActor {
             mqtt_publisher,
             download_sender,
             upload_sender,
+            apply_sender,
         }
     }
 
     async fn process_mqtt_message(&mut self, message: MqttMessage) -> Result<(), ChannelError> {
         match ConfigOperation::request_from_message(&self.config, &message) {
             Ok(Some(ConfigOperation::Snapshot(request))) => match request.status {
+                CommandStatus::Init if self.config.scheduled_by_agent => {
+                    info!("Config Snapshot received, awaiting to be scheduled: {request:?}");
+                }
                 CommandStatus::Init => {
                     info!("Config Snapshot received: {request:?}");
                     self.start_executing_config_request(
@@ -122,12 +144,19 @@ impl ConfigManagerActor {
                     self.handle_config_snapshot_request(&message.topic, request)
                         .await?;
                 }
+                CommandStatus::Cancelled => {
+                    self.cancel_config_request(&message.topic);
+                }
                 CommandStatus::Scheduled
+                | CommandStatus::Queued
                 | CommandStatus::Unknown
                 | CommandStatus::Successful
                 | CommandStatus::Failed { .. } => {}
             },
             Ok(Some(ConfigOperation::Update(request))) => match request.status {
+                CommandStatus::Init if self.config.scheduled_by_agent => {
+                    info!("Config Update received, awaiting to be scheduled: {request:?}");
+                }
                 CommandStatus::Init => {
                     info!("Config Update received: {request:?}");
                     self.start_executing_config_request(
@@ -141,7 +170,11 @@ impl ConfigManagerActor {
                     self.handle_config_update_request(&message.topic, request)
                         .await?;
                 }
+                CommandStatus::Cancelled => {
+                    self.cancel_config_request(&message.topic);
+                }
                 CommandStatus::Scheduled
+                | CommandStatus::Queued
                 | CommandStatus::Unknown
                 | CommandStatus::Successful
                 | CommandStatus::Failed { .. } => {}
@@ -160,6 +193,24 @@ impl ConfigManagerActor {
         Ok(())
     }
 
+    /// Abandons the request in progress for a cancelled command, if any,
+    /// so the late outcome of its download or upload is ignored.
+    fn cancel_config_request(&mut self, topic: &Topic) {
+        if self.pending_operations.remove(&topic.name).is_some() {
+            info!("Config request cancelled: {}", topic.name);
+        }
+    }
+
+    /// Removes the file transferred on behalf of a cancelled request, if temporary
+    fn remove_cancelled_transfer(&self, topic: &str, file_path: &Utf8Path) {
+        warn!("Ignoring the outcome of the cancelled config request: {topic}");
+        if file_path.starts_with(self.config.tmp_path.as_ref()) {
+            if let Err(err) = std::fs::remove_file(file_path) {
+                warn!("Failed to remove the temporary file {file_path}: {err}");
+            }
+        }
+    }
+
     async fn start_executing_config_request(
         &mut self,
         topic: &Topic,
@@ -208,8 +259,22 @@ impl ConfigManagerActor {
             .plugin_config
             .get_file_entry_from_type(&request.config_type)?;
 
-        let upload_request =
-            UploadRequest::new(&request.tedge_url, Utf8Path::new(&file_entry.path));
+        // the files of a directory or glob entry are uploaded as a tar archive
+        let upload_path = match file_entry.kind() {
+            FileEntryKind::File => Utf8PathBuf::from(&file_entry.path),
+            FileEntryKind::Directory | FileEntryKind::Glob => {
+                let archive_format = file_entry.archive_format;
+                let archive_path = Utf8PathBuf::from(format!(
+                    "{}.{}",
+                    self.temp_path(&file_entry.config_type),
+                    archive_format.file_extension()
+                ));
+                create_archive(&file_entry.list_files()?, &archive_path, archive_format)?;
+                archive_path
+            }
+        };
+
+        let upload_request = UploadRequest::new(&request.tedge_url, &upload_path);
 
         info!(
             "Awaiting upload of config type: {} to url: {}",
@@ -233,7 +298,16 @@ impl ConfigManagerActor {
             let topic = Topic::new_unchecked(topic);
             match result {
                 Ok(response) => {
-                    request.successful(response.file_path.as_str());
+                    match self
+                        .plugin_config
+                        .get_file_entry_from_type(&request.config_type)
+                    {
+                        Ok(file_entry) if file_entry.kind() != FileEntryKind::File => {
+                            let _ = std::fs::remove_file(&response.file_path);
+                            request.successful(&file_entry.path);
+                        }
+                        _ => request.successful(response.file_path.as_str()),
+                    }
                     info!(
                         "Config Snapshot request processed for config type: {}.",
                         request.config_type
@@ -252,6 +326,8 @@ impl ConfigManagerActor {
                         .await?;
                 }
             }
+        } else if let Ok(response) = result {
+            self.remove_cancelled_transfer(topic, &response.file_path);
         }
 
         Ok(())
@@ -292,7 +368,7 @@ impl ConfigManagerActor {
 
         // because we might not have permissions to write to destination, save in tmpdir and then
         // move to destination later
-        let temp_path = &self.config.tmp_path.join(&file_entry.config_type);
+        let temp_path = &self.temp_path(&file_entry.config_type);
 
         let Some(tedge_url) = &request.tedge_url else {
             debug!("tedge_url not present in config update payload, ignoring");
@@ -321,6 +397,11 @@ impl ConfigManagerActor {
     ) -> Result<(), ConfigManagementError> {
         let Some(ConfigOperation::Update(mut request)) = self.pending_operations.remove(topic)
         else {
+            if let Ok(response) = result {
+                if let Some(file_path) = Utf8Path::from_path(&response.file_path) {
+                    self.remove_cancelled_transfer(topic, file_path);
+                }
+            }
             return Ok(());
         };
 
@@ -339,14 +420,16 @@ impl ConfigManagerActor {
         };
 
         // new config was downloaded into tmpdir, we need to write it into destination using tedge-write
-        let from = Utf8Path::from_path(response.file_path.as_path()).unwrap();
-
-        let deployed_to_path = match self.deploy_config_file(from, &request.config_type) {
-            Ok(path) => path,
+        let from = Utf8Path::from_path(response.file_path.as_path())
+            .unwrap()
+            .to_owned();
+        let file_entry = match self
+            .plugin_config
+            .get_file_entry_from_type(&request.config_type)
+        {
+            Ok(file_entry) => file_entry.clone(),
             Err(err) => {
-                let error_message =
-                    format!("config-manager failed writing updated configuration file: {err}",);
-
+                let error_message = err.to_string();
                 request.failed(&error_message);
                 error!("{}", error_message);
                 self.publish_command_status(&topic, &ConfigOperation::Update(request))
@@ -355,57 +438,61 @@ impl ConfigManagerActor {
             }
         };
 
-        request.successful(deployed_to_path);
-        info!(
-            "Config Update request processed for config type: {}.",
-            request.config_type
-        );
-        self.publish_command_status(&topic, &ConfigOperation::Update(request))
-            .await?;
+        // the hooks can take a while to complete, so the new version is applied by a dedicated task
+        // while the actor keeps processing the other requests
+        let deployer = ConfigDeployer {
+            use_tedge_write: self.config.use_tedge_write,
+            file_entry,
+        };
+        let mut apply_sender = self.apply_sender.clone();
+        let topic = topic.name;
+        self.pending_operations
+            .insert(topic.clone(), ConfigOperation::Update(request));
+        tokio::spawn(async move {
+            let result = deployer.apply_config_file(&from).await;
+            if let Err(err) = apply_sender.send((topic, result)).await {
+                error!("Failed to send the outcome of a configuration update: {err}");
+            }
+        });
 
         Ok(())
     }
 
-    /// Deploys the new version of the configuration file and returns the path under which it was
-    /// deployed.
-    ///
-    /// This function ensures that the configuration file under `dest` is overwritten by a new
-    /// version currently stored in a temporary directory under `src`. Depending on if
-    /// `use_tedge_write` is used, either a new `tedge-write` process is spawned, or a file is
-    /// copied directly.
-    fn deploy_config_file(
-        &self,
-        from: &Utf8Path,
-        config_type: &str,
-    ) -> Result<Utf8PathBuf, ConfigManagementError> {
-        let file_entry = self.plugin_config.get_file_entry_from_type(config_type)?;
-
-        let mode = file_entry.file_permissions.mode;
-        let user = file_entry.file_permissions.user.as_deref();
-        let group = file_entry.file_permissions.group.as_deref();
-
-        let to = Utf8PathBuf::from(&file_entry.path);
+    async fn process_applied_config(
+        &mut self,
+        topic: &str,
+        result: Result<Utf8PathBuf, String>,
+    ) -> Result<(), ChannelError> {
+        let Some(ConfigOperation::Update(mut request)) = self.pending_operations.remove(topic)
+        else {
+            warn!("Configuration updated on behalf of a cancelled config request: {topic}");
+            return Ok(());
+        };
 
-        match self.config.use_tedge_write {
-            TedgeWriteStatus::Disabled => {
-                let src_file = std::fs::File::open(from)?;
-                tedge_utils::fs::atomically_write_file_sync(&to, src_file)?;
+        match result {
+            Ok(deployed_to_path) => {
+                request.successful(deployed_to_path);
+                info!(
+                    "Config Update request processed for config type: {}.",
+                    request.config_type
+                );
             }
-
-            TedgeWriteStatus::Enabled { sudo } => {
-                let options = CopyOptions {
-                    from,
-                    to: to.as_path(),
-                    sudo,
-                    mode,
-                    user,
-                    group,
-                };
-                options.copy()?;
+            Err(error_message) => {
+                request.failed(&error_message);
+                error!("{}", error_message);
             }
         }
+        self.publish_command_status(
+            &Topic::new_unchecked(topic),
+            &ConfigOperation::Update(request),
+        )
+        .await
+    }
 
-        Ok(to)
+    /// The path of the temporary file used to transfer a configuration
+    fn temp_path(&self, config_type: &str) -> Utf8PathBuf {
+        // the config type defaults to the path of the configuration
+        self.config.tmp_path.join(config_type.replace('/', "_"))
     }
 
     async fn process_file_watch_events(&mut self, event: FsWatchEvent) -> Result<(), ChannelError> {
@@ -469,6 +556,209 @@ impl ConfigManagerActor {
     }
 }
 
+/// Applies a new version of a configuration file, running the `validate` and `post_apply` hooks of its entry.
+///
+/// The hooks taking up to [HOOK_TIMEOUT] to complete, a new version is applied by a task spawned for each update,
+/// which sends the outcome back to the actor, as done by the downloader and the uploader.
+struct ConfigDeployer {
+    use_tedge_write: TedgeWriteStatus,
+    file_entry: FileEntry,
+}
+
+impl ConfigDeployer {
+    /// Validates, deploys and applies a new version of a configuration file,
+    /// returning the path under which it was deployed or the reason of the failure.
+    ///
+    /// The new version is checked by the `validate` command of the file entry before being deployed.
+    /// If the `post_apply` command fails once the new version is deployed,
+    /// the previous version of the file is restored and the `post_apply` command run again.
+    ///
+    /// Once the update completes, successfully or not, the unpacked archive is removed.
+    async fn apply_config_file(&self, from: &Utf8Path) -> Result<Utf8PathBuf, String> {
+        let result = self.apply_new_version(from).await;
+
+        let unpacked_dir = Utf8PathBuf::from(format!("{from}.d"));
+        if let Err(err) = std::fs::remove_dir_all(&unpacked_dir) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove the temporary directory {unpacked_dir}: {err}");
+            }
+        }
+
+        result
+    }
+
+    async fn apply_new_version(&self, from: &Utf8Path) -> Result<Utf8PathBuf, String> {
+        let file_entry = &self.file_entry;
+
+        // the files of a directory or glob entry are received as an archive,
+        // which is unpacked and checked before any file is deployed
+        let new_version = match file_entry.kind() {
+            FileEntryKind::File => from.to_owned(),
+            FileEntryKind::Directory | FileEntryKind::Glob => {
+                let unpacked_dir = Utf8PathBuf::from(format!("{from}.d"));
+                unpack_archive(from, &unpacked_dir, |name| {
+                    file_entry.accepts_file_name(name)
+                })
+                .map_err(|err| {
+                    format!("config-manager failed to unpack the configuration archive: {err}")
+                })?;
+                unpacked_dir
+            }
+        };
+
+        if let Some(validate) = &file_entry.validate {
+            run_hook(validate, &new_version, HOOK_TIMEOUT)
+                .await
+                .map_err(|err| {
+                    format!("config-manager rejected the new configuration as invalid: {err}")
+                })?;
+        }
+
+        let updates = file_updates(&new_version, file_entry).map_err(|err| {
+            format!("config-manager failed to list the configuration files to update: {err}")
+        })?;
+        let result = self.deploy_config_files(&updates).await;
+
+        // once the update completes, the staged and previous versions of the files are removed
+        for update in &updates {
+            if let Err(err) = self.update_config_file(&update.target, UpdateStep::Clean) {
+                warn!(
+                    "Failed to remove the temporary versions of {}: {err}",
+                    update.target
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Deploys the new versions of the configuration files and returns the path under which they were deployed.
+    ///
+    /// The new versions of all the files are first written next to the files they replace,
+    /// and only then moved into place, the current versions being kept aside.
+    /// Hence, no file is changed if any of the new versions cannot be written,
+    /// and the previous configuration can be restored if a file cannot be moved into place
+    /// or if the `post_apply` command fails.
+    async fn deploy_config_files(&self, updates: &[FileUpdate]) -> Result<Utf8PathBuf, String> {
+        let file_entry = &self.file_entry;
+        let deployed_to_path = match file_entry.target_dir() {
+            None => Utf8PathBuf::from(&file_entry.path),
+            Some(target_dir) => target_dir.to_owned(),
+        };
+
+        for update in updates {
+            if let Some(new_version) = &update.new_version {
+                self.stage_config_file(new_version, &update.target)
+                    .map_err(|err| {
+                        format!("config-manager failed writing updated configuration file: {err}")
+                    })?;
+            }
+        }
+
+        for (count, update) in updates.iter().enumerate() {
+            let step = match update.new_version {
+                Some(_) => UpdateStep::Commit,
+                None => UpdateStep::Remove,
+            };
+            if let Err(err) = self.update_config_file(&update.target, step) {
+                let err =
+                    format!("config-manager failed writing updated configuration file: {err}");
+                return match self.rollback_config_files(&updates[..count]) {
+                    Ok(()) => Err(format!(
+                        "{err}. The previous configuration has been restored"
+                    )),
+                    Err(rollback_err) => Err(format!(
+                        "{err}. The previous configuration could not be restored: {rollback_err}"
+                    )),
+                };
+            }
+        }
+
+        let Some(post_apply) = &file_entry.post_apply else {
+            return Ok(deployed_to_path);
+        };
+        let Err(err) = run_hook(post_apply, &deployed_to_path, HOOK_TIMEOUT).await else {
+            return Ok(deployed_to_path);
+        };
+
+        let rollback = match self.rollback_config_files(updates) {
+            Ok(()) => run_hook(post_apply, &deployed_to_path, HOOK_TIMEOUT)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match rollback {
+            Ok(()) => Err(format!(
+                "config-manager failed to apply the new configuration: {err}. The previous configuration has been restored"
+            )),
+            Err(rollback_err) => Err(format!(
+                "config-manager failed to apply the new configuration: {err}. The previous configuration could not be restored: {rollback_err}"
+            )),
+        }
+    }
+
+    /// Restores the previous versions of updated configuration files, removing the files that have been added
+    fn rollback_config_files(&self, updates: &[FileUpdate]) -> Result<(), ConfigManagementError> {
+        for update in updates {
+            self.update_config_file(&update.target, UpdateStep::Rollback)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the new version of a configuration file next to the file it replaces.
+    ///
+    /// Depending on if `use_tedge_write` is used, either a new `tedge-write` process is spawned,
+    /// or the file is written directly.
+    fn stage_config_file(
+        &self,
+        from: &Utf8Path,
+        to: &Utf8Path,
+    ) -> Result<(), ConfigManagementError> {
+        let mode = self.file_entry.file_permissions.mode;
+        let user = self.file_entry.file_permissions.user.as_deref();
+        let group = self.file_entry.file_permissions.group.as_deref();
+
+        match self.use_tedge_write {
+            TedgeWriteStatus::Disabled => {
+                let mut src_file = std::fs::File::open(from)?;
+                tedge_write::update::stage_file(to, &mut src_file)?;
+            }
+
+            TedgeWriteStatus::Enabled { sudo } => {
+                let options = CopyOptions {
+                    from,
+                    to,
+                    sudo,
+                    mode,
+                    user,
+                    group,
+                };
+                options.stage()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies a step of the update of a configuration file,
+    /// using `tedge-write` if enabled, as for writing the new version of the file.
+    fn update_config_file(
+        &self,
+        path: &Utf8Path,
+        step: UpdateStep,
+    ) -> Result<(), ConfigManagementError> {
+        match self.use_tedge_write {
+            TedgeWriteStatus::Disabled => tedge_write::update::apply_step(path, step)?,
+            TedgeWriteStatus::Enabled { sudo } => {
+                let options = UpdateOptions { path, step, sudo };
+                options.apply()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ConfigOperation {
     Snapshot(ConfigSnapshotCmdPayload),
@@ -506,3 +796,50 @@ impl ConfigOperation {
         }
     }
 }
+
+/// The update of a configuration file: either replaced by a new version or removed
+struct FileUpdate {
+    target: Utf8PathBuf,
+    new_version: Option<Utf8PathBuf>,
+}
+
+/// The files to be updated to deploy a new version of a file entry
+///
+/// For a directory or glob entry, `new_version` is a directory with the new versions of the files,
+/// each of them being deployed into the target directory of the entry.
+/// The files of the entry that are not part of the new version are removed,
+/// so the entry is made of exactly the files of `new_version`.
+fn file_updates(
+    new_version: &Utf8Path,
+    file_entry: &FileEntry,
+) -> Result<Vec<FileUpdate>, std::io::Error> {
+    let Some(target_dir) = file_entry.target_dir() else {
+        return Ok(vec![FileUpdate {
+            target: Utf8PathBuf::from(&file_entry.path),
+            new_version: Some(new_version.to_owned()),
+        }]);
+    };
+
+    let mut updates = vec![];
+    let mut new_files = HashSet::new();
+    for entry in new_version.read_dir_utf8()? {
+        let entry = entry?;
+        new_files.insert(entry.file_name().to_string());
+        updates.push(FileUpdate {
+            target: target_dir.join(entry.file_name()),
+            new_version: Some(entry.into_path()),
+        });
+    }
+    for file in file_entry.list_files()? {
+        if file
+            .file_name()
+            .is_some_and(|name| !new_files.contains(name))
+        {
+            updates.push(FileUpdate {
+                target: file,
+                new_version: None,
+            });
+        }
+    }
+    Ok(updates)
+}
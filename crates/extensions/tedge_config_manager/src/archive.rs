@@ -0,0 +1,275 @@
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Component;
+use std::path::Path;
+
+use super::error::ConfigManagementError;
+
+/// The format of the archives used to transfer the files of a directory or glob entry
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    #[default]
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    /// Detect the format of an archive from its first bytes, the tar format having no magic number at the start
+    fn detect(archive: &Utf8Path) -> Result<Self, ConfigManagementError> {
+        let mut magic = [0; 4];
+        let len = File::open(archive)?.read(&mut magic)?;
+        match &magic[..len] {
+            b"PK\x03\x04" | b"PK\x05\x06" => Ok(ArchiveFormat::Zip),
+            _ => Ok(ArchiveFormat::Tar),
+        }
+    }
+}
+
+/// Create an archive of a set of files, stored under their file names
+pub fn create_archive(
+    files: &[Utf8PathBuf],
+    archive: &Utf8Path,
+    format: ArchiveFormat,
+) -> Result<(), ConfigManagementError> {
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(File::create(archive)?);
+            builder.follow_symlinks(false);
+            for file in files {
+                let Some(file_name) = file.file_name() else {
+                    continue;
+                };
+                builder.append_path_with_name(file, file_name)?;
+            }
+            builder.into_inner()?.sync_all()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(File::create(archive)?);
+            for file in files {
+                let Some(file_name) = file.file_name() else {
+                    continue;
+                };
+                let metadata = file.symlink_metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                let options = zip::write::FileOptions::default().unix_permissions(
+                    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()),
+                );
+                writer.start_file(file_name, options)?;
+                std::io::copy(&mut File::open(file)?, &mut writer)?;
+            }
+            writer.finish()?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// Unpack a tar or zip archive into a directory, returning the paths of the unpacked files
+///
+/// The directory is cleared before unpacking the archive.
+/// The archive is rejected if it contains anything else than regular files with plain file names
+/// accepted by the given predicate: no directories, links, absolute paths or paths with a `..`.
+pub fn unpack_archive(
+    archive: &Utf8Path,
+    dir: &Utf8Path,
+    accept: impl Fn(&str) -> bool,
+) -> Result<Vec<Utf8PathBuf>, ConfigManagementError> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let mut files = vec![];
+    match ArchiveFormat::detect(archive)? {
+        ArchiveFormat::Tar => {
+            let mut archive = tar::Archive::new(File::open(archive)?);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+                let file_name =
+                    accepted_file_name(&path, entry.header().entry_type().is_file(), &accept)?;
+
+                let target = dir.join(file_name);
+                entry.unpack(&target)?;
+                files.push(target);
+            }
+        }
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(archive)?)?;
+            for index in 0..archive.len() {
+                let mut entry = archive.by_index(index)?;
+                let path = Path::new(entry.name()).to_owned();
+                // zip archives flag symbolic links only with their unix mode
+                let is_link = entry
+                    .unix_mode()
+                    .is_some_and(|mode| mode & 0o170000 == 0o120000);
+                let file_name = accepted_file_name(&path, entry.is_file() && !is_link, &accept)?;
+
+                let target = dir.join(file_name);
+                std::io::copy(&mut entry, &mut File::create(&target)?)?;
+                files.push(target);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The name of an archive entry, if a regular file with a plain file name accepted by the predicate
+fn accepted_file_name(
+    path: &Path,
+    is_file: bool,
+    accept: impl Fn(&str) -> bool,
+) -> Result<String, ConfigManagementError> {
+    let file_name = match path.components().collect::<Vec<_>>().as_slice() {
+        [Component::Normal(name)] => name.to_str().map(str::to_string),
+        _ => None,
+    };
+    match file_name {
+        Some(name) if is_file && accept(&name) => Ok(name),
+        _ => Err(ConfigManagementError::InvalidArchiveEntry {
+            path: path.display().to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    #[test]
+    fn unpack_the_files_of_an_archive() {
+        let ttd = TempTedgeDir::new();
+        ttd.file("a.conf").with_raw_content("a = 1");
+        ttd.file("b.conf").with_raw_content("b = 2");
+        let root = Utf8Path::from_path(ttd.path()).unwrap();
+        let archive = root.join("archive.tar");
+
+        create_archive(
+            &[root.join("a.conf"), root.join("b.conf")],
+            &archive,
+            ArchiveFormat::Tar,
+        )
+        .unwrap();
+        let files = unpack_archive(&archive, &root.join("unpacked"), |_| true).unwrap();
+
+        assert_eq!(
+            files,
+            vec![root.join("unpacked/a.conf"), root.join("unpacked/b.conf")]
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("unpacked/b.conf")).unwrap(),
+            "b = 2"
+        );
+    }
+
+    #[test]
+    fn unpack_the_files_of_a_zip_archive() {
+        let ttd = TempTedgeDir::new();
+        ttd.file("a.conf").with_raw_content("a = 1");
+        ttd.file("b.conf").with_raw_content("b = 2");
+        let root = Utf8Path::from_path(ttd.path()).unwrap();
+        let archive = root.join("archive.zip");
+
+        create_archive(
+            &[root.join("a.conf"), root.join("b.conf")],
+            &archive,
+            ArchiveFormat::Zip,
+        )
+        .unwrap();
+        let files = unpack_archive(&archive, &root.join("unpacked"), |_| true).unwrap();
+
+        assert_eq!(
+            files,
+            vec![root.join("unpacked/a.conf"), root.join("unpacked/b.conf")]
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("unpacked/b.conf")).unwrap(),
+            "b = 2"
+        );
+    }
+
+    #[test]
+    fn reject_zip_archive_links() {
+        let ttd = TempTedgeDir::new();
+        let root = Utf8Path::from_path(ttd.path()).unwrap();
+        let archive = root.join("archive.zip");
+
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        writer
+            .add_symlink("a.conf", "/etc/shadow", zip::write::FileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let err = unpack_archive(&archive, &root.join("unpacked"), |_| true).unwrap_err();
+
+        assert!(matches!(
+            err,
+            ConfigManagementError::InvalidArchiveEntry { .. }
+        ));
+    }
+
+    #[test]
+    fn reject_archive_entries_not_accepted_by_the_predicate() {
+        let ttd = TempTedgeDir::new();
+        ttd.file("a.conf").with_raw_content("a = 1");
+        ttd.file("b.txt").with_raw_content("b = 2");
+        let root = Utf8Path::from_path(ttd.path()).unwrap();
+        let archive = root.join("archive.tar");
+
+        create_archive(
+            &[root.join("a.conf"), root.join("b.txt")],
+            &archive,
+            ArchiveFormat::Tar,
+        )
+        .unwrap();
+        let err = unpack_archive(&archive, &root.join("unpacked"), |name| {
+            name.ends_with(".conf")
+        })
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The archive entry \"b.txt\" is not a file of the configuration."
+        );
+    }
+
+    #[test]
+    fn reject_archive_entries_outside_of_the_target_directory() {
+        let ttd = TempTedgeDir::new();
+        let root = Utf8Path::from_path(ttd.path()).unwrap();
+        let archive = root.join("archive.tar");
+
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        let name = b"../escaped.conf";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_cksum();
+        builder.append(&header, "a = 1".as_bytes()).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let err = unpack_archive(&archive, &root.join("unpacked"), |_| true).unwrap_err();
+
+        assert!(matches!(
+            err,
+            ConfigManagementError::InvalidArchiveEntry { .. }
+        ));
+        assert!(!root.join("escaped.conf").exists());
+    }
+}
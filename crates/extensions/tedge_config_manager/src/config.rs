@@ -1,4 +1,5 @@
 use camino::Utf8Path;
+use camino::Utf8PathBuf;
 use log::error;
 use log::info;
 use log::warn;
@@ -20,6 +21,7 @@ use tedge_config::ReadError;
 use tedge_mqtt_ext::TopicFilter;
 use tedge_utils::file::PermissionEntry;
 
+use super::archive::ArchiveFormat;
 use super::error::InvalidConfigTypeError;
 
 pub const DEFAULT_PLUGIN_CONFIG_FILE_NAME: &str = "tedge-configuration-plugin.toml";
@@ -41,6 +43,10 @@ pub struct ConfigManagerConfig {
     pub use_tedge_write: TedgeWriteStatus,
 
     pub config_update_enabled: bool,
+
+    /// If enabled, the config_snapshot and config_update commands are moved to executing by the agent,
+    /// once admitted given their schedule and the concurrency limits, and not by the config manager.
+    pub scheduled_by_agent: bool,
 }
 
 pub struct ConfigManagerOptions {
@@ -50,6 +56,7 @@ pub struct ConfigManagerOptions {
     pub tmp_path: Arc<Utf8Path>,
     pub is_sudo_enabled: bool,
     pub config_update_enabled: bool,
+    pub scheduled_by_agent: bool,
 }
 
 impl ConfigManagerConfig {
@@ -93,6 +100,7 @@ impl ConfigManagerConfig {
                 sudo: cliopts.is_sudo_enabled,
             },
             config_update_enabled: cliopts.config_update_enabled,
+            scheduled_by_agent: cliopts.scheduled_by_agent,
         })
     }
 }
@@ -112,6 +120,9 @@ pub struct RawFileEntry {
     user: Option<String>,
     group: Option<String>,
     mode: Option<u32>,
+    validate: Option<String>,
+    post_apply: Option<String>,
+    archive: Option<ArchiveFormat>,
 }
 
 #[derive(Debug, Eq, PartialEq, Default, Clone)]
@@ -124,6 +135,15 @@ pub struct FileEntry {
     pub path: String,
     pub config_type: String,
     pub file_permissions: PermissionEntry,
+
+    /// Command checking a new version of the file before it is deployed
+    pub validate: Option<String>,
+
+    /// Command run once a new version of the file has been deployed
+    pub post_apply: Option<String>,
+
+    /// Format of the snapshot archives of a directory or glob entry
+    pub archive_format: ArchiveFormat,
 }
 
 impl Hash for FileEntry {
@@ -144,6 +164,21 @@ impl Borrow<str> for FileEntry {
     }
 }
 
+/// The kind of configuration referenced by a file entry, as given by the syntax of its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileEntryKind {
+    /// A single file, e.g. `/etc/mosquitto/mosquitto.conf`
+    File,
+
+    /// All the files of a directory, when the path ends with a `/`, e.g. `/etc/nginx/conf.d/`
+    Directory,
+
+    /// All the files matching a glob pattern, e.g. `/etc/systemd/system/tedge-*.service`
+    Glob,
+}
+
+const GLOB_CHARACTERS: [char; 3] = ['*', '?', '['];
+
 impl FileEntry {
     pub fn new(
         path: String,
@@ -156,6 +191,87 @@ impl FileEntry {
             path,
             config_type,
             file_permissions: PermissionEntry { user, group, mode },
+            validate: None,
+            post_apply: None,
+            archive_format: ArchiveFormat::default(),
+        }
+    }
+
+    pub fn kind(&self) -> FileEntryKind {
+        let path = Utf8Path::new(&self.path);
+        if path
+            .file_name()
+            .is_some_and(|name| name.contains(GLOB_CHARACTERS))
+        {
+            FileEntryKind::Glob
+        } else if self.path.ends_with('/') {
+            FileEntryKind::Directory
+        } else {
+            FileEntryKind::File
+        }
+    }
+
+    /// The directory where are stored the files of a directory or glob entry
+    ///
+    /// The configuration of such an entry is transferred as an archive of these files.
+    pub fn target_dir(&self) -> Option<&Utf8Path> {
+        let path = Utf8Path::new(&self.path);
+        match self.kind() {
+            FileEntryKind::File => None,
+            FileEntryKind::Directory => Some(path),
+            FileEntryKind::Glob => path.parent(),
+        }
+    }
+
+    /// Tell if a file of the target directory belongs to a directory or glob entry
+    ///
+    /// The new and previous versions of the files, kept aside while an update is in progress, are ignored.
+    pub fn accepts_file_name(&self, file_name: &str) -> bool {
+        if tedge_write::update::is_update_file(file_name) {
+            return false;
+        }
+        match self.kind() {
+            FileEntryKind::File => false,
+            FileEntryKind::Directory => true,
+            FileEntryKind::Glob => Utf8Path::new(&self.path)
+                .file_name()
+                .and_then(|pattern| glob::Pattern::new(pattern).ok())
+                .is_some_and(|pattern| pattern.matches(file_name)),
+        }
+    }
+
+    /// List the regular files currently stored on the device for a directory or glob entry
+    pub fn list_files(&self) -> Result<Vec<Utf8PathBuf>, std::io::Error> {
+        let Some(target_dir) = self.target_dir() else {
+            return Ok(vec![]);
+        };
+        if !target_dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut files = vec![];
+        for entry in target_dir.read_dir_utf8()? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && self.accepts_file_name(entry.file_name()) {
+                files.push(entry.into_path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn with_hooks(self, validate: Option<String>, post_apply: Option<String>) -> Self {
+        Self {
+            validate,
+            post_apply,
+            ..self
+        }
+    }
+
+    pub fn with_archive_format(self, archive_format: ArchiveFormat) -> Self {
+        Self {
+            archive_format,
+            ..self
         }
     }
 }
@@ -222,13 +338,25 @@ impl PluginConfig {
                 return original_plugin_config;
             }
 
+            if let Some(parent) = Utf8Path::new(&raw_entry.path).parent() {
+                if parent.as_str().contains(GLOB_CHARACTERS) {
+                    error!(
+                        "The config path '{}' contains glob characters outside of its file name.",
+                        raw_entry.path
+                    );
+                    return original_plugin_config;
+                }
+            }
+
             let entry = FileEntry::new(
                 raw_entry.path,
                 config_type.clone(),
                 raw_entry.user,
                 raw_entry.group,
                 raw_entry.mode,
-            );
+            )
+            .with_hooks(raw_entry.validate, raw_entry.post_apply)
+            .with_archive_format(raw_entry.archive.unwrap_or_default());
 
             if !self.files.insert(entry) {
                 error!("The config file has the duplicated type '{}'.", config_type);
@@ -26,6 +26,12 @@ pub enum ConfigManagementError {
     #[error("Received unexpected message on topic")]
     InvalidTopicError,
 
+    #[error(transparent)]
+    FromZipError(#[from] zip::result::ZipError),
+
+    #[error("The archive entry {path:?} is not a file of the configuration.")]
+    InvalidArchiveEntry { path: String },
+
     #[error("Directory {path} is not found.")]
     DirectoryNotFound { path: std::path::PathBuf },
 
@@ -46,3 +52,28 @@ impl From<ConfigManagementError> for RuntimeError {
 pub struct InvalidConfigTypeError {
     pub config_type: String,
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum HookError {
+    #[error("Invalid command {command:?}: {reason}")]
+    InvalidCommand { command: String, reason: String },
+
+    #[error("Failed to execute {command:?}: {error}")]
+    ExecutionFailed {
+        command: String,
+        error: std::io::Error,
+    },
+
+    #[error("Command {command:?} failed with {status}: {stderr}")]
+    CommandFailed {
+        command: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+
+    #[error("Command {command:?} has been killed after {timeout:?}")]
+    Timeout {
+        command: String,
+        timeout: std::time::Duration,
+    },
+}
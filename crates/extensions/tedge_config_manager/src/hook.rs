@@ -0,0 +1,115 @@
+use camino::Utf8Path;
+use log::info;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use super::error::HookError;
+
+/// The placeholder replaced by the path of the configuration file in hook commands
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// The time given to a hook command to complete, before being killed
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Run a hook command on a configuration file.
+///
+/// The command line is split as a shell would do,
+/// then any `{file}` placeholder in the arguments is replaced by the path of the file.
+/// The command is not run by a shell: pipes and redirections are not supported.
+/// The command is killed if not completed within the given timeout.
+pub async fn run_hook(
+    command_line: &str,
+    file: &Utf8Path,
+    timeout: Duration,
+) -> Result<(), HookError> {
+    let args = shell_words::split(command_line).map_err(|err| HookError::InvalidCommand {
+        command: command_line.to_string(),
+        reason: err.to_string(),
+    })?;
+    let mut args = args
+        .into_iter()
+        .map(|arg| arg.replace(FILE_PLACEHOLDER, file.as_str()));
+    let Some(program) = args.next() else {
+        return Err(HookError::InvalidCommand {
+            command: command_line.to_string(),
+            reason: "command line is empty".to_string(),
+        });
+    };
+
+    info!("Running {command_line:?} on {file}");
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| HookError::ExecutionFailed {
+            command: command_line.to_string(),
+            error,
+        })?;
+
+    // on timeout, the child process is killed when dropped
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| HookError::Timeout {
+            command: command_line.to_string(),
+            timeout,
+        })?
+        .map_err(|error| HookError::ExecutionFailed {
+            command: command_line.to_string(),
+            error,
+        })?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(HookError::CommandFailed {
+            command: command_line.to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_file_placeholder_is_replaced_by_the_file_path() {
+        let file = Utf8Path::new("/tmp/some file.conf");
+        assert!(run_hook(
+            r#"test "{file}" = "/tmp/some file.conf""#,
+            file,
+            HOOK_TIMEOUT
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_failing_command_is_reported_with_its_stderr() {
+        let file = Utf8Path::new("/tmp/missing.conf");
+        let err = run_hook("ls {file}", file, HOOK_TIMEOUT).await.unwrap_err();
+        assert!(matches!(err, HookError::CommandFailed { .. }));
+        assert!(err.to_string().contains("/tmp/missing.conf"));
+    }
+
+    #[tokio::test]
+    async fn an_empty_command_is_rejected() {
+        let file = Utf8Path::new("/tmp/some.conf");
+        let err = run_hook("  ", file, HOOK_TIMEOUT).await.unwrap_err();
+        assert!(matches!(err, HookError::InvalidCommand { .. }));
+    }
+
+    #[tokio::test]
+    async fn a_command_not_completed_in_time_is_killed() {
+        let file = Utf8Path::new("/tmp/some.conf");
+        let err = run_hook("sleep 10", file, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, HookError::Timeout { .. }));
+    }
+}
@@ -1,6 +1,8 @@
 mod actor;
+mod archive;
 mod config;
 mod error;
+mod hook;
 
 #[cfg(test)]
 mod tests;
@@ -38,6 +40,7 @@ pub struct ConfigManagerBuilder {
     mqtt_publisher: DynSender<MqttMessage>,
     download_sender: DynSender<ConfigDownloadRequest>,
     upload_sender: DynSender<ConfigUploadRequest>,
+    apply_sender: DynSender<ConfigApplyResult>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
 }
 
@@ -73,6 +76,8 @@ impl ConfigManagerBuilder {
 
         let upload_sender = uploader_actor.connect_consumer(NoConfig, events_sender.clone().into());
 
+        let apply_sender = events_sender.clone().into();
+
         fs_notify.register_peer(
             ConfigManagerBuilder::watched_directory(&config),
             events_sender.into(),
@@ -85,6 +90,7 @@ impl ConfigManagerBuilder {
             mqtt_publisher,
             download_sender,
             upload_sender,
+            apply_sender,
             signal_sender,
         })
     }
@@ -160,6 +166,7 @@ impl Builder<ConfigManagerActor> for ConfigManagerBuilder {
             mqtt_publisher,
             self.download_sender,
             self.upload_sender,
+            self.apply_sender,
         ))
     }
 }
@@ -23,6 +23,7 @@ use crate::actor::ConfigDownloadRequest;
 use crate::actor::ConfigDownloadResult;
 use crate::actor::ConfigUploadRequest;
 use crate::actor::ConfigUploadResult;
+use crate::archive::ArchiveFormat;
 use crate::ConfigManagerBuilder;
 use crate::ConfigManagerConfig;
 use crate::TedgeWriteStatus;
@@ -61,17 +62,8 @@ fn prepare() -> Result<TempTedgeDir, anyhow::Error> {
     Ok(tempdir)
 }
 
-#[allow(clippy::type_complexity)]
-async fn new_config_manager_builder(
-    temp_dir: &Path,
-) -> (
-    ConfigManagerBuilder,
-    MqttMessageBox,
-    SimpleMessageBox<NoMessage, FsWatchEvent>,
-    DownloaderMessageBox,
-    UploaderMessageBox,
-) {
-    let config = ConfigManagerConfig {
+fn test_config(temp_dir: &Path) -> ConfigManagerConfig {
+    ConfigManagerConfig {
         config_dir: temp_dir.to_path_buf(),
         plugin_config_dir: temp_dir.to_path_buf(),
         plugin_config_path: temp_dir.join("tedge-configuration-plugin.toml"),
@@ -86,8 +78,20 @@ async fn new_config_manager_builder(
         config_snapshot_topic: TopicFilter::new_unchecked("te/device/main///cmd/config_snapshot/+"),
         config_update_topic: TopicFilter::new_unchecked("te/device/main///cmd/config_update/+"),
         config_update_enabled: true,
-    };
+        scheduled_by_agent: false,
+    }
+}
 
+#[allow(clippy::type_complexity)]
+async fn new_config_manager_builder(
+    config: ConfigManagerConfig,
+) -> (
+    ConfigManagerBuilder,
+    MqttMessageBox,
+    SimpleMessageBox<NoMessage, FsWatchEvent>,
+    DownloaderMessageBox,
+    UploaderMessageBox,
+) {
     let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
         SimpleMessageBoxBuilder::new("MQTT", 5);
     let mut fs_watcher_builder: SimpleMessageBoxBuilder<NoMessage, FsWatchEvent> =
@@ -126,8 +130,18 @@ async fn spawn_config_manager_actor(
     DownloaderMessageBox,
     UploaderMessageBox,
 ) {
-    let (actor_builder, mqtt, fs, downloader, uploader) =
-        new_config_manager_builder(temp_dir).await;
+    spawn_config_manager_actor_with_config(test_config(temp_dir)).await
+}
+
+async fn spawn_config_manager_actor_with_config(
+    config: ConfigManagerConfig,
+) -> (
+    MqttMessageBox,
+    SimpleMessageBox<NoMessage, FsWatchEvent>,
+    DownloaderMessageBox,
+    UploaderMessageBox,
+) {
+    let (actor_builder, mqtt, fs, downloader, uploader) = new_config_manager_builder(config).await;
     let actor = actor_builder.build();
     tokio::spawn(async move { actor.run().await });
     (mqtt, fs, downloader, uploader)
@@ -300,6 +314,93 @@ async fn config_manager_download_update() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn config_manager_ignores_the_download_of_a_cancelled_update() -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let config = ConfigManagerConfig {
+        tmp_path: Arc::from(Utf8Path::from_path(tempdir.path()).unwrap()),
+        ..test_config(tempdir.path())
+    };
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor_with_config(config).await;
+
+    let config_topic = Topic::new_unchecked("te/device/main///cmd/config_update/1234");
+
+    // Let's ignore the reload messages sent on start
+    mqtt.skip(2).await;
+
+    // When a config update request is being executed
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/type_two-1234","remoteUrl":"http://www.remote.url","type":"type_two"}"#;
+    mqtt.send(MqttMessage::new(&config_topic, executing_request).with_retain())
+        .await?;
+    let (topic, download_request) = downloader.recv().await.unwrap();
+
+    // And the command is cancelled, while the new configuration is downloaded
+    let cancelled_request = r#"{"status":"cancelled","cancelled_state":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/type_two-1234","remoteUrl":"http://www.remote.url","type":"type_two"}"#;
+    mqtt.send(MqttMessage::new(&config_topic, cancelled_request).with_retain())
+        .await?;
+
+    // Then the late outcome of the download is ignored
+    std::fs::write(&download_request.file_path, "new content").unwrap();
+    let download_response =
+        DownloadResponse::new(&download_request.url, &download_request.file_path);
+    downloader.send((topic, Ok(download_response))).await?;
+    assert!(mqtt.recv().await.is_none());
+
+    // The new configuration is not deployed and the downloaded file is removed
+    assert_eq!(
+        std::fs::read_to_string(tempdir.path().join("file_b"))?,
+        "Some content"
+    );
+    assert!(!download_request.file_path.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_update_executed_only_once_moved_to_executing_by_the_agent(
+) -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let config = ConfigManagerConfig {
+        scheduled_by_agent: true,
+        ..test_config(tempdir.path())
+    };
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor_with_config(config).await;
+
+    let config_topic = Topic::new_unchecked("te/device/main///cmd/config_update/1234");
+
+    // Let's ignore the reload messages sent on start
+    mqtt.skip(2).await;
+
+    // When a config update request is received
+    let update_request = r#"
+        {
+            "status": "init",
+            "tedgeUrl": "http://127.0.0.1:3000/tedge/file-transfer/main/config_update/type_two-1234",
+            "remoteUrl": "http://www.remote.url",
+            "type": "type_two"
+        }"#;
+    mqtt.send(MqttMessage::new(&config_topic, update_request).with_retain())
+        .await?;
+
+    // The config manager leaves to the agent the decision to start the command
+    assert!(mqtt.recv().await.is_none());
+
+    // Once moved to executing by the agent, the command is processed
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/type_two-1234","remoteUrl":"http://www.remote.url","type":"type_two"}"#;
+    mqtt.send(MqttMessage::new(&config_topic, executing_request).with_retain())
+        .await?;
+    let (topic, download_request) = downloader.recv().await.unwrap();
+    assert_eq!(Topic::new_unchecked(&topic), config_topic);
+    assert_eq!(
+        download_request.url,
+        "http://127.0.0.1:3000/tedge/file-transfer/main/config_update/type_two-1234"
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn request_config_snapshot_that_does_not_exist() -> Result<(), anyhow::Error> {
     let tempdir = prepare()?;
@@ -400,3 +501,371 @@ async fn send_incorrect_payload() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+/// Prepare a config manager with config files deployed using a validate and a post-apply commands
+///
+/// A new version of a file is only valid if it is not empty, and can only be applied if it starts with `ok`.
+fn prepare_with_hooks() -> TempTedgeDir {
+    let tempdir = TempTedgeDir::new();
+    let tempdir_path = tempdir.path().to_str().unwrap();
+
+    tempdir.file("validated_file").with_raw_content("ok: v1");
+    tempdir.file("applied_file").with_raw_content("ok: v1");
+
+    tempdir
+        .file("tedge-configuration-plugin.toml")
+        .with_raw_content(&format!(
+            r#"files = [
+            {{ path = "{tempdir_path}/validated_file", type = "validated_type", validate = "test -s {{file}}" }},
+            {{ path = "{tempdir_path}/applied_file", type = "applied_type", post_apply = "grep -q ^ok {{file}}" }},
+        ]"#
+        ));
+
+    tempdir
+}
+
+/// Push a new version of a config file to the config manager, returning the payload of the final status message
+async fn update_config_file(
+    mqtt: &mut MqttMessageBox,
+    downloader: &mut DownloaderMessageBox,
+    config_type: &str,
+    new_content: &str,
+) -> serde_json::Value {
+    let config_topic = Topic::new_unchecked("te/device/main///cmd/config_update/1234");
+    let executing_request = format!(
+        r#"{{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/{config_type}-1234","remoteUrl":"http://www.remote.url","type":"{config_type}"}}"#
+    );
+    mqtt.send(MqttMessage::new(&config_topic, executing_request).with_retain())
+        .await
+        .unwrap();
+
+    let (topic, download_request) = downloader.recv().await.unwrap();
+    std::fs::write(&download_request.file_path, new_content).unwrap();
+    let download_response =
+        DownloadResponse::new(&download_request.url, &download_request.file_path);
+    downloader
+        .send((topic, Ok(download_response)))
+        .await
+        .unwrap();
+
+    let status_message = mqtt.recv().await.unwrap();
+    serde_json::from_str(status_message.payload_str().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn config_update_rejected_by_the_validate_command() -> Result<(), anyhow::Error> {
+    let tempdir = prepare_with_hooks();
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let status = update_config_file(&mut mqtt, &mut downloader, "validated_type", "").await;
+
+    assert_eq!(status["status"], "failed");
+    assert!(status["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("config-manager rejected the new configuration as invalid"));
+    assert_eq!(
+        std::fs::read_to_string(tempdir.path().join("validated_file"))?,
+        "ok: v1"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_update_rolled_back_when_the_post_apply_command_fails() -> Result<(), anyhow::Error>
+{
+    let tempdir = prepare_with_hooks();
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let status = update_config_file(&mut mqtt, &mut downloader, "applied_type", "ko: v2").await;
+
+    assert_eq!(status["status"], "failed");
+    assert!(status["reason"]
+        .as_str()
+        .unwrap()
+        .ends_with("The previous configuration has been restored"));
+    assert_eq!(
+        std::fs::read_to_string(tempdir.path().join("applied_file"))?,
+        "ok: v1"
+    );
+    assert!(!tempdir.path().join("applied_file.tedge-backup").exists());
+
+    let status = update_config_file(&mut mqtt, &mut downloader, "applied_type", "ok: v2").await;
+
+    assert_eq!(status["status"], "successful");
+    assert_eq!(
+        std::fs::read_to_string(tempdir.path().join("applied_file"))?,
+        "ok: v2"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_requests_processed_while_a_hook_is_running() -> Result<(), anyhow::Error> {
+    let tempdir = TempTedgeDir::new();
+    let tempdir_path = tempdir.path().to_str().unwrap();
+    tempdir.file("slow_file").with_raw_content("v1");
+    tempdir.file("other_file").with_raw_content("other");
+    tempdir
+        .file("tedge-configuration-plugin.toml")
+        .with_raw_content(&format!(
+            r#"files = [
+            {{ path = "{tempdir_path}/slow_file", type = "slow_type", post_apply = "sh -c 'while [ ! -e {tempdir_path}/release ]; do sleep 0.1; done'" }},
+            {{ path = "{tempdir_path}/other_file", type = "other_type" }},
+        ]"#
+        ));
+    let (mut mqtt, _fs, mut downloader, mut uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    // An update which post-apply command doesn't complete till released
+    let update_topic = Topic::new_unchecked("te/device/main///cmd/config_update/1234");
+    let update_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/slow_type-1234","remoteUrl":"http://www.remote.url","type":"slow_type"}"#;
+    mqtt.send(MqttMessage::new(&update_topic, update_request).with_retain())
+        .await?;
+    let (topic, download_request) = downloader.recv().await.unwrap();
+    std::fs::write(&download_request.file_path, "v2")?;
+    let download_response =
+        DownloadResponse::new(&download_request.url, &download_request.file_path);
+    downloader.send((topic, Ok(download_response))).await?;
+
+    // Other requests are processed in the meantime
+    let snapshot_topic = Topic::new_unchecked("te/device/main///cmd/config_snapshot/5678");
+    let snapshot_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config-snapshot/other_type-5678","type":"other_type"}"#;
+    mqtt.send(MqttMessage::new(&snapshot_topic, snapshot_request).with_retain())
+        .await?;
+    let (topic, upload_request) = uploader.recv().await.unwrap();
+    assert_eq!(Topic::new_unchecked(&topic), snapshot_topic);
+
+    // The update completes once the post-apply command is released
+    tempdir.file("release");
+    let status_message = mqtt.recv().await.unwrap();
+    assert_eq!(status_message.topic, update_topic);
+    let status: serde_json::Value = serde_json::from_str(status_message.payload_str()?)?;
+    assert_eq!(status["status"], "successful");
+    assert_eq!(
+        std::fs::read_to_string(tempdir.path().join("slow_file"))?,
+        "v2"
+    );
+
+    let upload_response = UploadResponse::new(&upload_request.url, upload_request.file_path);
+    uploader.send((topic, Ok(upload_response))).await?;
+    let status_message = mqtt.recv().await.unwrap();
+    assert_eq!(status_message.topic, snapshot_topic);
+
+    Ok(())
+}
+
+/// Prepare a config manager with a directory entry and a glob entry
+fn prepare_with_directories() -> TempTedgeDir {
+    let tempdir = TempTedgeDir::new();
+    let tempdir_path = tempdir.path().to_str().unwrap();
+
+    let conf_dir = tempdir.dir("conf.d");
+    conf_dir.file("a.conf").with_raw_content("a = 1");
+    conf_dir.file("b.conf").with_raw_content("b = 1");
+    conf_dir
+        .file("README")
+        .with_raw_content("Not a config file");
+
+    tempdir
+        .file("tedge-configuration-plugin.toml")
+        .with_raw_content(&format!(
+            r#"files = [
+            {{ path = "{tempdir_path}/conf.d/", type = "conf_dir" }},
+            {{ path = "{tempdir_path}/conf.d/*.conf", type = "conf_glob" }},
+        ]"#
+        ));
+
+    tempdir
+}
+
+#[tokio::test]
+async fn config_manager_uploads_an_archive_of_a_glob_entry() -> Result<(), anyhow::Error> {
+    let tempdir = prepare_with_directories();
+    let (mut mqtt, _fs, _downloader, mut uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let config_topic = Topic::new_unchecked("te/device/main///cmd/config_snapshot/1234");
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config-snapshot/conf_glob-1234","type":"conf_glob"}"#;
+    mqtt.send(MqttMessage::new(&config_topic, executing_request).with_retain())
+        .await?;
+
+    // The files matching the glob pattern are uploaded as a tar archive
+    let (topic, upload_request) = uploader.recv().await.unwrap();
+    let mut archive = tar::Archive::new(std::fs::File::open(&upload_request.file_path)?);
+    let mut archived_files = archive
+        .entries()?
+        .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+        .collect::<Vec<_>>();
+    archived_files.sort();
+    assert_eq!(archived_files, vec!["a.conf", "b.conf"]);
+
+    let upload_response = UploadResponse::new(&upload_request.url, upload_request.file_path);
+    uploader.send((topic, Ok(upload_response))).await?;
+
+    // The path of the entry is reported on success
+    let status_message = mqtt.recv().await.unwrap();
+    let status: serde_json::Value = serde_json::from_str(status_message.payload_str()?)?;
+    assert_eq!(status["status"], "successful");
+    assert_eq!(
+        status["path"],
+        format!("{}/conf.d/*.conf", tempdir.path().display())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_manager_unpacks_an_archive_into_a_directory() -> Result<(), anyhow::Error> {
+    let tempdir = prepare_with_directories();
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let status = update_config_archive(
+        &tempdir,
+        &mut mqtt,
+        &mut downloader,
+        "conf_dir",
+        &[("b.conf", "b = 2"), ("c.conf", "c = 2")],
+        ArchiveFormat::Tar,
+    )
+    .await;
+    assert_eq!(status["status"], "successful");
+
+    // The directory is made of exactly the files of the archive
+    let conf_dir = tempdir.path().join("conf.d");
+    assert!(!conf_dir.join("a.conf").exists());
+    assert!(!conf_dir.join("README").exists());
+    assert_eq!(std::fs::read_to_string(conf_dir.join("b.conf"))?, "b = 2");
+    assert_eq!(std::fs::read_to_string(conf_dir.join("c.conf"))?, "c = 2");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_manager_unpacks_a_zip_archive_into_a_directory() -> Result<(), anyhow::Error> {
+    let tempdir = prepare_with_directories();
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let status = update_config_archive(
+        &tempdir,
+        &mut mqtt,
+        &mut downloader,
+        "conf_glob",
+        &[("b.conf", "b = 2")],
+        ArchiveFormat::Zip,
+    )
+    .await;
+    assert_eq!(status["status"], "successful");
+
+    // The files matching the pattern are made of exactly the files of the archive
+    let conf_dir = tempdir.path().join("conf.d");
+    assert!(!conf_dir.join("a.conf").exists());
+    assert!(conf_dir.join("README").exists());
+    assert_eq!(std::fs::read_to_string(conf_dir.join("b.conf"))?, "b = 2");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_update_of_a_glob_entry_rolled_back_when_the_post_apply_command_fails(
+) -> Result<(), anyhow::Error> {
+    let tempdir = prepare_with_directories();
+    let tempdir_path = tempdir.path().to_str().unwrap();
+    std::fs::write(
+        tempdir.path().join("tedge-configuration-plugin.toml"),
+        format!(
+            r#"files = [
+            {{ path = "{tempdir_path}/conf.d/*.conf", type = "conf_glob", post_apply = "test ! -e {{file}}/bad.conf" }},
+        ]"#
+        ),
+    )?;
+    let (mut mqtt, _fs, mut downloader, _uploader) =
+        spawn_config_manager_actor(tempdir.path()).await;
+    mqtt.skip(2).await;
+
+    let status = update_config_archive(
+        &tempdir,
+        &mut mqtt,
+        &mut downloader,
+        "conf_glob",
+        &[("a.conf", "a = 2"), ("bad.conf", "bad = 2")],
+        ArchiveFormat::Tar,
+    )
+    .await;
+    assert_eq!(status["status"], "failed");
+    assert!(status["reason"]
+        .as_str()
+        .unwrap()
+        .ends_with("The previous configuration has been restored"));
+
+    // The previous files are restored and the new ones removed
+    let conf_dir = tempdir.path().join("conf.d");
+    assert_eq!(std::fs::read_to_string(conf_dir.join("a.conf"))?, "a = 1");
+    assert_eq!(std::fs::read_to_string(conf_dir.join("b.conf"))?, "b = 1");
+    assert!(!conf_dir.join("bad.conf").exists());
+    assert!(conf_dir.join("README").exists());
+
+    // The unpacked archive, the staged and the previous versions are removed
+    assert!(!std::env::temp_dir().join("conf_glob.d").exists());
+    assert_eq!(
+        std::fs::read_dir(&conf_dir)?.count(),
+        3,
+        "Expected only a.conf, b.conf and README"
+    );
+
+    Ok(())
+}
+
+/// Push an archive of new config files to the config manager, returning the payload of the final status message
+async fn update_config_archive(
+    tempdir: &TempTedgeDir,
+    mqtt: &mut MqttMessageBox,
+    downloader: &mut DownloaderMessageBox,
+    config_type: &str,
+    new_files: &[(&str, &str)],
+    format: ArchiveFormat,
+) -> serde_json::Value {
+    let new_dir = tempdir.dir("new");
+    let mut new_file_paths = vec![];
+    for (name, content) in new_files {
+        new_dir.file(name).with_raw_content(content);
+        new_file_paths.push(new_dir.utf8_path().join(name));
+    }
+
+    let config_topic = Topic::new_unchecked("te/device/main///cmd/config_update/1234");
+    let executing_request = format!(
+        r#"{{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/config_update/{config_type}-1234","remoteUrl":"http://www.remote.url","type":"{config_type}"}}"#
+    );
+    mqtt.send(MqttMessage::new(&config_topic, executing_request).with_retain())
+        .await
+        .unwrap();
+
+    let (topic, download_request) = downloader.recv().await.unwrap();
+    crate::archive::create_archive(
+        &new_file_paths,
+        Utf8Path::from_path(&download_request.file_path).unwrap(),
+        format,
+    )
+    .unwrap();
+    let download_response =
+        DownloadResponse::new(&download_request.url, &download_request.file_path);
+    downloader
+        .send((topic, Ok(download_response)))
+        .await
+        .unwrap();
+
+    let status_message = mqtt.recv().await.unwrap();
+    serde_json::from_str(status_message.payload_str().unwrap()).unwrap()
+}
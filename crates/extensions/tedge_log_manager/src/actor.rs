@@ -1,7 +1,11 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
 
 use async_trait::async_trait;
 use camino::Utf8Path;
+use flate2::write::GzEncoder;
 use log::debug;
 use log::error;
 use log::info;
@@ -19,6 +23,7 @@ use tedge_actors::RuntimeError;
 use tedge_actors::Sender;
 use tedge_actors::SimpleMessageBox;
 use tedge_api::messages::CommandStatus;
+use tedge_api::messages::LogCompression;
 use tedge_api::messages::LogUploadCmdPayload;
 use tedge_api::Jsonify;
 use tedge_file_system_ext::FsWatchEvent;
@@ -33,6 +38,12 @@ use super::DEFAULT_PLUGIN_CONFIG_FILE_NAME;
 
 type MqttTopic = String;
 
+/// Size of the chunks used to upload a log file, so an interrupted upload can be resumed
+const LOG_UPLOAD_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Path prefix of the urls served by the file-transfer service, which supports resumable uploads
+const FILE_TRANSFER_PATH: &str = "tedge/file-transfer/";
+
 pub type LogUploadRequest = (MqttTopic, UploadRequest);
 pub type LogUploadResult = (MqttTopic, UploadResult);
 
@@ -96,6 +107,9 @@ impl LogManagerActor {
         if self.config.logfile_request_topic.accept(&message) {
             match request_from_message(&message) {
                 Ok(Some(request)) => match request.status {
+                    CommandStatus::Init if self.config.scheduled_by_agent => {
+                        info!("Log request received, awaiting to be scheduled: {request:?}");
+                    }
                     CommandStatus::Init => {
                         info!("Log request received: {request:?}");
                         self.start_executing_logfile_request(&message.topic, request)
@@ -106,7 +120,17 @@ impl LogManagerActor {
                         self.handle_logfile_request_operation(&message.topic, request)
                             .await?;
                     }
+                    CommandStatus::Cancelled => {
+                        if self
+                            .pending_operations
+                            .remove(&message.topic.name)
+                            .is_some()
+                        {
+                            info!("Log request cancelled: {}", message.topic.name);
+                        }
+                    }
                     CommandStatus::Scheduled
+                    | CommandStatus::Queued
                     | CommandStatus::Unknown
                     | CommandStatus::Successful
                     | CommandStatus::Failed { .. } => {}
@@ -140,7 +164,7 @@ impl LogManagerActor {
         topic: &Topic,
         mut request: LogUploadCmdPayload,
     ) -> Result<(), ChannelError> {
-        if let Err(error) = self.generate_and_upload_logfile(topic, &request).await {
+        if let Err(error) = self.generate_and_upload_logfile(topic, &mut request).await {
             let error_message = format!("Failed to initiate log file upload: {error}");
             request.failed(&error_message);
             self.publish_command_status(topic, &request).await?;
@@ -154,24 +178,39 @@ impl LogManagerActor {
     }
 
     /// Generates the required logfile and starts its upload via the uploader actor.
+    ///
+    /// The compression configured for the log type is applied when none is given by the request,
+    /// and recorded in the request so the consumer of the log file knows its format.
     async fn generate_and_upload_logfile(
         &mut self,
         topic: &Topic,
-        request: &LogUploadCmdPayload,
+        request: &mut LogUploadCmdPayload,
     ) -> Result<(), LogManagementError> {
         let log_path = log_manager::new_read_logs(
-            &self.plugin_config.files,
+            &self.plugin_config,
             &request.log_type,
             request.date_from,
+            request.date_to,
             request.lines.to_owned(),
             &request.search_text,
             &self.config.tmp_dir,
-        )?;
+        )
+        .await?;
+        request.compression = request
+            .compression
+            .or_else(|| self.plugin_config.compression(&request.log_type));
+        let log_path = match request.compression {
+            Some(compression) => compress_log_file(&log_path, compression)?,
+            None => log_path,
+        };
 
-        let upload_request = UploadRequest::new(
+        let mut upload_request = UploadRequest::new(
             &request.tedge_url,
             Utf8Path::from_path(log_path.as_path()).unwrap(),
         );
+        if is_file_transfer_url(&request.tedge_url) {
+            upload_request = upload_request.with_chunk_size(LOG_UPLOAD_CHUNK_SIZE);
+        }
 
         info!(
             "Awaiting upload of log type: {} to url: {}",
@@ -191,7 +230,16 @@ impl LogManagerActor {
         result: UploadResult,
     ) -> Result<(), LogManagementError> {
         let Some(mut request) = self.pending_operations.remove(topic) else {
+            // The command has been cancelled meanwhile: the log file is no more of any use
             warn!("Ignoring unexpected log_upload result: {topic}");
+            if let Ok(response) = result {
+                if let Err(err) = std::fs::remove_file(&response.file_path) {
+                    warn!(
+                        "Failed to remove temporary file {}: {}",
+                        response.file_path, err
+                    )
+                }
+            }
             return Ok(());
         };
 
@@ -278,6 +326,46 @@ impl LogManagerActor {
     }
 }
 
+/// Compress a log file, returning the path of the compressed file that replaces the original one
+fn compress_log_file(
+    log_path: &Path,
+    compression: LogCompression,
+) -> Result<PathBuf, LogManagementError> {
+    let compressed_path = PathBuf::from(format!(
+        "{}.{}",
+        log_path.display(),
+        compression.file_extension()
+    ));
+
+    let mut log_file = File::open(log_path)?;
+    let compressed_file = File::create(&compressed_path)?;
+    match compression {
+        LogCompression::Gzip => {
+            let mut encoder = GzEncoder::new(compressed_file, flate2::Compression::default());
+            std::io::copy(&mut log_file, &mut encoder)?;
+            encoder.finish()?;
+        }
+        LogCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(compressed_file, 0)?;
+            std::io::copy(&mut log_file, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+
+    std::fs::remove_file(log_path)?;
+    Ok(compressed_path)
+}
+
+/// Tell if the url targets the file-transfer service, the only server known to support resumable uploads
+///
+/// Other servers are not probed: the status request of a resumable upload is a PUT,
+/// that would create an empty file on a server not supporting partial updates.
+fn is_file_transfer_url(url: &str) -> bool {
+    url.split_once("://")
+        .and_then(|(_, url)| url.split_once('/'))
+        .is_some_and(|(_, path)| path.starts_with(FILE_TRANSFER_PATH))
+}
+
 fn request_from_message(
     message: &MqttMessage,
 ) -> Result<Option<LogUploadCmdPayload>, LogManagementError> {
@@ -21,6 +21,10 @@ pub struct LogManagerConfig {
     pub plugin_config_path: PathBuf,
     pub logtype_reload_topic: Topic,
     pub logfile_request_topic: TopicFilter,
+
+    /// If enabled, the log_upload commands are moved to executing by the agent,
+    /// once admitted given their schedule and the concurrency limits, and not by the log manager.
+    pub scheduled_by_agent: bool,
 }
 
 pub struct LogManagerOptions {
@@ -28,6 +32,7 @@ pub struct LogManagerOptions {
     pub tmp_dir: PathBuf,
     pub mqtt_schema: MqttSchema,
     pub mqtt_device_topic_id: EntityTopicId,
+    pub scheduled_by_agent: bool,
 }
 
 impl LogManagerConfig {
@@ -59,6 +64,7 @@ impl LogManagerConfig {
             plugin_config_path,
             logtype_reload_topic,
             logfile_request_topic,
+            scheduled_by_agent: cliopts.scheduled_by_agent,
         })
     }
 }
@@ -5,6 +5,8 @@ use crate::LogUploadResult;
 use crate::Topic;
 use filetime::set_file_mtime;
 use filetime::FileTime;
+use flate2::read::GzDecoder;
+use std::io::Read;
 use std::path::Path;
 use std::time::Duration;
 use tedge_actors::test_helpers::MessageReceiverExt;
@@ -76,26 +78,29 @@ fn prepare() -> Result<TempTedgeDir, anyhow::Error> {
     Ok(tempdir)
 }
 
+fn test_config(temp_dir: &Path) -> LogManagerConfig {
+    LogManagerConfig {
+        config_dir: temp_dir.to_path_buf(),
+        tmp_dir: temp_dir.to_path_buf(),
+        plugin_config_dir: temp_dir.to_path_buf(),
+        plugin_config_path: temp_dir.join("tedge-log-plugin.toml"),
+        logtype_reload_topic: Topic::new_unchecked("te/device/main///cmd/log_upload"),
+        logfile_request_topic: TopicFilter::new_unchecked("te/device/main///cmd/log_upload/+"),
+        scheduled_by_agent: false,
+    }
+}
+
 /// Create a log manager actor builder
 /// along two boxes to exchange MQTT and HTTP messages with the log actor
 #[allow(clippy::type_complexity)]
 async fn new_log_manager_builder(
-    temp_dir: &Path,
+    config: LogManagerConfig,
 ) -> (
     LogManagerBuilder,
     TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
     SimpleMessageBox<NoMessage, FsWatchEvent>,
     UploaderMessageBox,
 ) {
-    let config = LogManagerConfig {
-        config_dir: temp_dir.to_path_buf(),
-        tmp_dir: temp_dir.to_path_buf(),
-        plugin_config_dir: temp_dir.to_path_buf(),
-        plugin_config_path: temp_dir.join("tedge-log-plugin.toml"),
-        logtype_reload_topic: Topic::new_unchecked("te/device/main///cmd/log_upload"),
-        logfile_request_topic: TopicFilter::new_unchecked("te/device/main///cmd/log_upload/+"),
-    };
-
     let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
         SimpleMessageBoxBuilder::new("MQTT", 5);
     let mut fs_watcher_builder: SimpleMessageBoxBuilder<NoMessage, FsWatchEvent> =
@@ -128,7 +133,17 @@ async fn spawn_log_manager_actor(
     SimpleMessageBox<NoMessage, FsWatchEvent>,
     UploaderMessageBox,
 ) {
-    let (actor_builder, mqtt, fs, uploader) = new_log_manager_builder(temp_dir).await;
+    spawn_log_manager_actor_with_config(test_config(temp_dir)).await
+}
+
+async fn spawn_log_manager_actor_with_config(
+    config: LogManagerConfig,
+) -> (
+    MqttMessageBox,
+    SimpleMessageBox<NoMessage, FsWatchEvent>,
+    UploaderMessageBox,
+) {
+    let (actor_builder, mqtt, fs, uploader) = new_log_manager_builder(config).await;
     let actor = actor_builder.build();
     tokio::spawn(async move { actor.run().await });
     (mqtt, fs, uploader)
@@ -230,6 +245,205 @@ async fn log_manager_upload_log_files_on_request() -> Result<(), anyhow::Error>
     Ok(())
 }
 
+#[tokio::test]
+async fn log_request_executed_only_once_moved_to_executing_by_the_agent(
+) -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let config = LogManagerConfig {
+        scheduled_by_agent: true,
+        ..test_config(tempdir.path())
+    };
+    let (mut mqtt, _fs, mut uploader) = spawn_log_manager_actor_with_config(config).await;
+
+    let logfile_topic = Topic::new_unchecked("te/device/main///cmd/log_upload/1234");
+
+    // Let's ignore the init message sent on start
+    mqtt.skip(1).await;
+
+    // When a log request is received
+    let log_request = r#"
+        {
+            "status": "init",
+            "tedgeUrl": "http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234",
+            "type": "type_two",
+            "dateFrom": "1970-01-01T00:00:00+00:00",
+            "dateTo": "1970-01-01T00:00:30+00:00",
+            "lines": 1000
+        }"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, log_request).with_retain())
+        .await?;
+
+    // The log manager leaves to the agent the decision to start the command
+    assert!(mqtt.recv().await.is_none());
+
+    // Once moved to executing by the agent, the command is processed
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234","type":"type_two","dateFrom":"1970-01-01T00:00:00Z","dateTo":"1970-01-01T00:00:30Z","lines":1000}"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, executing_request).with_retain())
+        .await?;
+    let (topic, upload_request) = uploader.recv().await.unwrap();
+    assert_eq!(Topic::new_unchecked(&topic), logfile_topic);
+    assert_eq!(
+        upload_request.url,
+        "http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn log_manager_ignores_the_upload_of_a_cancelled_request() -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let (mut mqtt, _fs, mut uploader) = spawn_log_manager_actor(tempdir.path()).await;
+
+    let logfile_topic = Topic::new_unchecked("te/device/main///cmd/log_upload/1234");
+
+    // Let's ignore the init message sent on start
+    mqtt.skip(1).await;
+
+    // When a log request is being executed
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234","type":"type_two","dateFrom":"1970-01-01T00:00:00Z","dateTo":"1970-01-01T00:00:30Z","lines":1000}"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, executing_request).with_retain())
+        .await?;
+    let (topic, upload_request) = uploader.recv().await.unwrap();
+    assert!(upload_request.file_path.exists());
+
+    // And the command is cancelled, while the log file is uploaded
+    let cancelled_request = r#"{"status":"cancelled","cancelled_state":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234","type":"type_two","dateFrom":"1970-01-01T00:00:00Z","dateTo":"1970-01-01T00:00:30Z","lines":1000}"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, cancelled_request).with_retain())
+        .await?;
+
+    // Then the late outcome of the upload is ignored
+    let upload_response =
+        UploadResponse::new(&upload_request.url, upload_request.file_path.clone());
+    uploader.send((topic, Ok(upload_response))).await?;
+    assert!(mqtt.recv().await.is_none());
+
+    // And the log file removed
+    assert!(!upload_request.file_path.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn log_manager_upload_compressed_log_files_on_request() -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let (mut mqtt, _fs, mut uploader) = spawn_log_manager_actor(tempdir.path()).await;
+
+    let logfile_topic = Topic::new_unchecked("te/device/main///cmd/log_upload/1234");
+
+    // Let's ignore the init message sent on start
+    mqtt.skip(1).await;
+
+    // When a log request asking for gzip compression is received
+    let log_request = r#"
+        {
+            "status": "executing",
+            "tedgeUrl": "http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234",
+            "type": "type_two",
+            "dateFrom": "1970-01-01T00:00:00+00:00",
+            "dateTo": "1970-01-01T00:00:30+00:00",
+            "lines": 1000,
+            "compression": "gzip"
+        }"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, log_request).with_retain())
+        .await?;
+
+    // The log file is compressed before being uploaded chunk by chunk
+    let (_, upload_request) = uploader.recv().await.unwrap();
+    assert_eq!(upload_request.file_path.extension(), Some("gz"));
+    assert!(upload_request.chunk_size.is_some());
+
+    let mut content = String::new();
+    GzDecoder::new(std::fs::File::open(&upload_request.file_path)?).read_to_string(&mut content)?;
+    assert!(content.contains("Some content"));
+
+    // The uncompressed log file is removed
+    let log_path = upload_request.file_path.with_extension("");
+    assert!(!log_path.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn log_manager_compresses_the_log_files_as_configured_for_their_type(
+) -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let tempdir_path = tempdir.path().to_str().unwrap();
+    std::fs::remove_file(tempdir.path().join("tedge-log-plugin.toml"))?;
+    tempdir
+        .file("tedge-log-plugin.toml")
+        .with_raw_content(&format!(
+            r#"files = [
+            {{ type = "type_two", path = "{tempdir_path}/file_c", compression = "zstd" }},
+        ]"#
+        ));
+    let (mut mqtt, _fs, mut uploader) = spawn_log_manager_actor(tempdir.path()).await;
+
+    let logfile_topic = Topic::new_unchecked("te/device/main///cmd/log_upload/1234");
+
+    // Let's ignore the init message sent on start
+    mqtt.skip(1).await;
+
+    // When a log request with no compression is received for a log type configured to be compressed
+    let executing_request = r#"{"status":"executing","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234","type":"type_two","dateFrom":"1970-01-01T00:00:00Z","dateTo":"1970-01-01T00:00:30Z","lines":1000}"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, executing_request).with_retain())
+        .await?;
+
+    // The log file is compressed as configured
+    let (topic, upload_request) = uploader.recv().await.unwrap();
+    assert_eq!(upload_request.file_path.extension(), Some("zst"));
+    let content = zstd::decode_all(std::fs::File::open(&upload_request.file_path)?)?;
+    assert!(String::from_utf8(content)?.contains("Some content"));
+
+    // And the compression is given along the successful status
+    let upload_response = UploadResponse::new(&upload_request.url, upload_request.file_path);
+    uploader.send((topic, Ok(upload_response))).await?;
+    assert_eq!(
+            mqtt.recv().await,
+            Some(MqttMessage::new(
+                &logfile_topic,
+                r#"{"status":"successful","tedgeUrl":"http://127.0.0.1:3000/tedge/file-transfer/main/log_upload/type_two-1234","type":"type_two","dateFrom":"1970-01-01T00:00:00Z","dateTo":"1970-01-01T00:00:30Z","lines":1000,"compression":"zstd"}"#
+            ).with_retain())
+        );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn log_files_are_uploaded_at_once_to_a_server_other_than_the_file_transfer_service(
+) -> Result<(), anyhow::Error> {
+    let tempdir = prepare()?;
+    let (mut mqtt, _fs, mut uploader) = spawn_log_manager_actor(tempdir.path()).await;
+
+    let logfile_topic = Topic::new_unchecked("te/device/main///cmd/log_upload/1234");
+
+    // Let's ignore the init message sent on start
+    mqtt.skip(1).await;
+
+    // When a log request targets a server that is not known to support resumable uploads
+    let log_request = r#"
+        {
+            "status": "executing",
+            "tedgeUrl": "http://example.com/uploads/type_two-1234",
+            "type": "type_two",
+            "dateFrom": "1970-01-01T00:00:00+00:00",
+            "dateTo": "1970-01-01T00:00:30+00:00",
+            "lines": 1000
+        }"#;
+    mqtt.send(MqttMessage::new(&logfile_topic, log_request).with_retain())
+        .await?;
+
+    // The log file is uploaded with a single request
+    let (_, upload_request) = uploader.recv().await.unwrap();
+    assert_eq!(
+        upload_request.url,
+        "http://example.com/uploads/type_two-1234"
+    );
+    assert!(upload_request.chunk_size.is_none());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn request_logtype_that_does_not_exist() -> Result<(), anyhow::Error> {
     let tempdir = prepare()?;
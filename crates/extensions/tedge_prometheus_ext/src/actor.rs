@@ -0,0 +1,56 @@
+use crate::collector::TelemetryCollector;
+use crate::server::metrics_server;
+use crate::MetricsError;
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use log::info;
+use rustls::ServerConfig;
+use std::net::TcpListener;
+use tedge_actors::Actor;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_mqtt_ext::MqttMessage;
+
+/// Serve the Prometheus metrics endpoint, collecting the telemetry data when connected to MQTT
+pub struct PrometheusExporterActor {
+    pub(crate) listener: TcpListener,
+    pub(crate) rustls_config: Option<ServerConfig>,
+    pub(crate) signal_receiver: mpsc::Receiver<RuntimeRequest>,
+    pub(crate) telemetry: Option<(TelemetryCollector, mpsc::Receiver<MqttMessage>)>,
+}
+
+#[async_trait]
+impl Actor for PrometheusExporterActor {
+    fn name(&self) -> &str {
+        "PrometheusExporter"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let server = metrics_server(self.listener, self.rustls_config);
+        let collector = collect_telemetry(self.telemetry);
+
+        tokio::select! {
+            result = server => {
+                info!("Done");
+                Ok(result.map_err(MetricsError::FromIo)?)
+            }
+            _ = collector => Ok(()),
+            Some(RuntimeRequest::Shutdown) = self.signal_receiver.next() => {
+                info!("Shutdown");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Update the telemetry metrics until the MQTT connection is closed
+async fn collect_telemetry(telemetry: Option<(TelemetryCollector, mpsc::Receiver<MqttMessage>)>) {
+    let Some((mut collector, mut mqtt_input)) = telemetry else {
+        return futures::future::pending().await;
+    };
+    while let Some(message) = mqtt_input.next().await {
+        collector.process_message(&message);
+    }
+    info!("MQTT connection closed");
+}
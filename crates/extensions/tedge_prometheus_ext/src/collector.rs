@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use tedge_api::builder::ThinEdgeJsonBuilder;
+use tedge_api::data::ThinEdgeValue;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+
+/// Update the telemetry metrics from the measurements and commands published over MQTT
+///
+/// - The latest value of each measurement series is exported as a `tedge_measurement` gauge.
+/// - Each command status transition is counted by the `tedge_commands_total` counter.
+pub struct TelemetryCollector {
+    mqtt_schema: MqttSchema,
+    /// The latest status of each command, used to count the status transitions only once
+    command_status: HashMap<String, String>,
+}
+
+impl TelemetryCollector {
+    pub fn new(mqtt_schema: MqttSchema) -> Self {
+        TelemetryCollector {
+            mqtt_schema,
+            command_status: HashMap::new(),
+        }
+    }
+
+    /// The MQTT topics the collector has to subscribe to
+    pub fn subscriptions(mqtt_schema: &MqttSchema) -> TopicFilter {
+        let mut topics = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::Measurement);
+        topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::AnyCommand));
+        topics
+    }
+
+    pub fn process_message(&mut self, message: &MqttMessage) {
+        let Ok((entity, channel)) = self.mqtt_schema.entity_channel_of(&message.topic) else {
+            return;
+        };
+        match channel {
+            Channel::Measurement { measurement_type } => {
+                self.process_measurement(entity.as_str(), &measurement_type, message)
+            }
+            Channel::Command { operation, .. } => {
+                self.process_command(&operation.to_string(), message)
+            }
+            _ => {}
+        }
+    }
+
+    fn process_measurement(&mut self, entity: &str, measurement_type: &str, message: &MqttMessage) {
+        let Ok(payload) = message.payload_str() else {
+            return;
+        };
+        let mut builder = ThinEdgeJsonBuilder::default();
+        if tedge_api::parser::parse_str(payload, &mut builder).is_err() {
+            return;
+        }
+        let Ok(measurement) = builder.done() else {
+            return;
+        };
+
+        for value in measurement.values {
+            match value {
+                ThinEdgeValue::Single(single) => {
+                    tedge_metrics::measurement(entity, measurement_type, &single.name)
+                        .set(single.value.value.as_f64());
+                }
+                ThinEdgeValue::Multi(multi) => {
+                    for single in multi.values {
+                        let series = format!("{}.{}", multi.name, single.name);
+                        tedge_metrics::measurement(entity, measurement_type, &series)
+                            .set(single.value.value.as_f64());
+                    }
+                }
+            }
+        }
+    }
+
+    fn process_command(&mut self, operation: &str, message: &MqttMessage) {
+        let command = message.topic.name.clone();
+        if message.payload_bytes().is_empty() {
+            // The command has been cleared
+            self.command_status.remove(&command);
+            return;
+        }
+
+        let Some(status) = serde_json::from_slice::<serde_json::Value>(message.payload_bytes())
+            .ok()
+            .and_then(|payload| payload.get("status")?.as_str().map(str::to_string))
+        else {
+            return;
+        };
+        if self.command_status.get(&command) != Some(&status) {
+            tedge_metrics::commands(operation, &status).inc();
+            self.command_status.insert(command, status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tedge_mqtt_ext::Topic;
+
+    fn message(topic: &str, payload: serde_json::Value) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload.to_string())
+    }
+
+    #[test]
+    fn export_the_latest_value_of_each_measurement_series() {
+        let mut collector = TelemetryCollector::new(MqttSchema::default());
+
+        collector.process_message(&message(
+            "te/device/child-a///m/environment",
+            json!({"temperature": 21.5, "pump": {"pressure": 3.0}}),
+        ));
+        collector.process_message(&message(
+            "te/device/child-a///m/environment",
+            json!({"temperature": 22.5}),
+        ));
+
+        assert_eq!(
+            tedge_metrics::measurement("device/child-a//", "environment", "temperature").get(),
+            22.5
+        );
+        assert_eq!(
+            tedge_metrics::measurement("device/child-a//", "environment", "pump.pressure").get(),
+            3.0
+        );
+    }
+
+    #[test]
+    fn count_command_status_transitions_once() {
+        let mut collector = TelemetryCollector::new(MqttSchema::default());
+        let topic = "te/device/child-b///cmd/restart/c8y-mapper-1";
+
+        collector.process_message(&message(topic, json!({"status": "init"})));
+        collector.process_message(&message(topic, json!({"status": "executing"})));
+        // A status published twice is counted once
+        collector.process_message(&message(topic, json!({"status": "executing"})));
+        collector.process_message(&message(topic, json!({"status": "successful"})));
+        collector.process_message(&MqttMessage::new(&Topic::new_unchecked(topic), ""));
+
+        assert_eq!(tedge_metrics::commands("restart", "executing").get(), 1);
+        assert_eq!(tedge_metrics::commands("restart", "successful").get(), 1);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::MetricsError;
+use camino::Utf8PathBuf;
+use std::net::SocketAddr;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::OptionalConfig;
+use tedge_config::TEdgeConfig;
+
+/// Configuration of the Prometheus metrics endpoint of a service
+#[derive(Clone, Debug)]
+pub struct PrometheusExporterConfig {
+    pub bind_address: SocketAddr,
+    pub cert_path: OptionalConfig<Utf8PathBuf>,
+    pub key_path: OptionalConfig<Utf8PathBuf>,
+    pub ca_path: OptionalConfig<Utf8PathBuf>,
+    pub mqtt_schema: MqttSchema,
+}
+
+impl PrometheusExporterConfig {
+    /// Return the configuration of the metrics endpoint of the given service
+    ///
+    /// Return `None` if no port is configured for this service in `metrics.ports`.
+    pub fn from_tedge_config(
+        service_name: &str,
+        tedge_config: &TEdgeConfig,
+    ) -> Result<Option<Self>, MetricsError> {
+        let Some(port) = port_of(service_name, &tedge_config.metrics.ports.0)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(PrometheusExporterConfig {
+            bind_address: SocketAddr::new(tedge_config.metrics.bind.address, port),
+            cert_path: tedge_config.metrics.cert_path.clone(),
+            key_path: tedge_config.metrics.key_path.clone(),
+            ca_path: tedge_config.metrics.ca_path.clone(),
+            mqtt_schema: MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+        }))
+    }
+}
+
+/// Find the port of a service, given a list of `<service>=<port>` settings
+fn port_of(service_name: &str, settings: &[String]) -> Result<Option<u16>, MetricsError> {
+    for setting in settings {
+        let invalid_setting = || MetricsError::InvalidPortSetting {
+            setting: setting.to_string(),
+        };
+        let (service, port) = setting.split_once('=').ok_or_else(invalid_setting)?;
+        let port = port.trim().parse().map_err(|_| invalid_setting())?;
+        if service.trim() == service_name {
+            return Ok(Some(port));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_the_port_of_a_service() {
+        let settings = vec![
+            "tedge-agent=9100".to_string(),
+            "tedge-mapper-c8y = 9101".to_string(),
+        ];
+
+        assert_eq!(port_of("tedge-agent", &settings).unwrap(), Some(9100));
+        assert_eq!(port_of("tedge-mapper-c8y", &settings).unwrap(), Some(9101));
+        assert_eq!(port_of("tedge-mapper-az", &settings).unwrap(), None);
+        assert!(port_of("tedge-agent", &["tedge-agent:9100".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use std::net::SocketAddr;
+use tedge_actors::RuntimeError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MetricsError {
+    #[error("Invalid `metrics.ports` setting {setting:?}: expected `<service>=<port>`")]
+    InvalidPortSetting { setting: String },
+
+    #[error("Fail to bind the metrics endpoint to {address}")]
+    BindError {
+        address: SocketAddr,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<MetricsError> for RuntimeError {
+    fn from(error: MetricsError) -> Self {
+        RuntimeError::ActorError(Box::new(error))
+    }
+}
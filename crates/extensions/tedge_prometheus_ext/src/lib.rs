@@ -0,0 +1,107 @@
+//! Export device telemetry and thin-edge internal metrics to Prometheus.
+//!
+//! Each thin-edge service configured with a port in `metrics.ports`
+//! serves its metrics on `/metrics`, using the Prometheus text exposition format:
+//! - the internal counters of the service, as registered with [tedge_metrics],
+//! - and, if connected to MQTT, the latest value of each measurement series
+//!   and the number of commands per operation and status.
+mod actor;
+mod collector;
+mod config;
+mod error;
+mod server;
+
+#[cfg(test)]
+mod tests;
+
+pub use actor::*;
+pub use collector::TelemetryCollector;
+pub use config::*;
+pub use error::*;
+use futures::channel::mpsc;
+use rustls::ServerConfig;
+use std::convert::Infallible;
+use std::net::TcpListener;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSource;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+
+pub struct PrometheusExporterBuilder {
+    listener: TcpListener,
+    rustls_config: Option<ServerConfig>,
+    mqtt_schema: MqttSchema,
+    signal_sender: mpsc::Sender<RuntimeRequest>,
+    signal_receiver: mpsc::Receiver<RuntimeRequest>,
+    telemetry: Option<(TelemetryCollector, mpsc::Receiver<MqttMessage>)>,
+}
+
+impl PrometheusExporterBuilder {
+    pub fn try_bind(config: PrometheusExporterConfig) -> Result<Self, MetricsError> {
+        let listener =
+            TcpListener::bind(config.bind_address).map_err(|source| MetricsError::BindError {
+                address: config.bind_address,
+                source,
+            })?;
+        listener.set_nonblocking(true)?;
+        let rustls_config = axum_tls::config::load_ssl_config(
+            config.cert_path,
+            config.key_path,
+            config.ca_path,
+            "Prometheus metrics endpoint",
+        )?;
+        let (signal_sender, signal_receiver) = mpsc::channel(10);
+
+        Ok(PrometheusExporterBuilder {
+            listener,
+            rustls_config,
+            mqtt_schema: config.mqtt_schema,
+            signal_sender,
+            signal_receiver,
+            telemetry: None,
+        })
+    }
+
+    /// Also export the measurements and command counts published over MQTT
+    pub fn with_telemetry(
+        mut self,
+        mqtt: &mut impl MessageSource<MqttMessage, TopicFilter>,
+    ) -> Self {
+        let (input_sender, mqtt_input) = mpsc::channel(16);
+        mqtt.register_peer(
+            TelemetryCollector::subscriptions(&self.mqtt_schema),
+            input_sender.into(),
+        );
+        let collector = TelemetryCollector::new(self.mqtt_schema.clone());
+        self.telemetry = Some((collector, mqtt_input));
+        self
+    }
+
+    /// The address the metrics endpoint is bound to
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl RuntimeRequestSink for PrometheusExporterBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        Box::new(self.signal_sender.clone())
+    }
+}
+
+impl Builder<PrometheusExporterActor> for PrometheusExporterBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<PrometheusExporterActor, Self::Error> {
+        Ok(PrometheusExporterActor {
+            listener: self.listener,
+            rustls_config: self.rustls_config,
+            signal_receiver: self.signal_receiver,
+            telemetry: self.telemetry,
+        })
+    }
+}
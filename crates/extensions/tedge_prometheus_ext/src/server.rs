@@ -0,0 +1,32 @@
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rustls::ServerConfig;
+use std::io;
+use std::net::TcpListener;
+
+/// The content type of the Prometheus text exposition format
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serve the metrics of the process on `/metrics`
+pub(crate) fn metrics_server(
+    listener: TcpListener,
+    rustls_config: Option<ServerConfig>,
+) -> BoxFuture<'static, io::Result<()>> {
+    let router = Router::new().route("/metrics", get(render_metrics));
+
+    if let Some(rustls_config) = rustls_config {
+        axum_tls::start_tls_server(listener, rustls_config, router).boxed()
+    } else {
+        axum_server::from_tcp(listener)
+            .serve(router.into_make_service())
+            .boxed()
+    }
+}
+
+async fn render_metrics() -> impl IntoResponse {
+    ([(CONTENT_TYPE, TEXT_FORMAT)], tedge_metrics::render())
+}
@@ -0,0 +1,82 @@
+use crate::PrometheusExporterBuilder;
+use crate::PrometheusExporterConfig;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::NoMessage;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::OptionalConfig;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+
+/// Spawn an exporter and return its address along a box to send it MQTT messages
+fn spawn_prometheus_exporter() -> (SocketAddr, SimpleMessageBox<NoMessage, MqttMessage>) {
+    let config = PrometheusExporterConfig {
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        cert_path: OptionalConfig::empty("metrics.cert_path"),
+        key_path: OptionalConfig::empty("metrics.key_path"),
+        ca_path: OptionalConfig::empty("metrics.ca_path"),
+        mqtt_schema: MqttSchema::default(),
+    };
+    let mut mqtt_builder: SimpleMessageBoxBuilder<NoMessage, MqttMessage> =
+        SimpleMessageBoxBuilder::new("MQTT", 5);
+
+    let exporter = PrometheusExporterBuilder::try_bind(config)
+        .unwrap()
+        .with_telemetry(&mut mqtt_builder);
+    let address = exporter.local_addr().unwrap();
+    let actor = exporter.build();
+    tokio::spawn(async move { actor.run().await });
+
+    (address, mqtt_builder.build())
+}
+
+async fn get_metrics(address: SocketAddr) -> reqwest::Response {
+    reqwest::get(format!("http://{address}/metrics"))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn serve_the_measurements_published_over_mqtt() -> Result<(), anyhow::Error> {
+    let (address, mut mqtt) = spawn_prometheus_exporter();
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/environment"),
+        r#"{"humidity": 45.5}"#,
+    ))
+    .await?;
+
+    let expected =
+        r#"tedge_measurement{entity="device/main//",type="environment",series="humidity"} 45.5"#;
+    for _ in 0..50 {
+        let response = get_metrics(address).await;
+        assert!(response.status().is_success());
+        if response.text().await?.contains(expected) {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The measurement is not exported");
+}
+
+#[tokio::test]
+async fn serve_the_internal_metrics() {
+    let (address, _mqtt) = spawn_prometheus_exporter();
+    tedge_metrics::uploaded_bytes().inc_by(1024);
+
+    let response = get_metrics(address).await;
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/plain; version=0.0.4; charset=utf-8"
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("# TYPE tedge_uploaded_bytes_total counter"));
+}
@@ -6,17 +6,29 @@ use tedge_actors::Concurrent;
 use tedge_actors::Server;
 use tedge_actors::ServerActorBuilder;
 use tedge_actors::ServerConfig;
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Clone)]
 pub struct ScriptActor;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug)]
 pub struct Execute {
     pub command: String,
     pub args: Vec<String>,
     pub timeouts: Option<(Duration, Duration)>,
+    pub cancellation: Option<CancellationToken>,
 }
 
+/// Two command executions are equal if they run the same command with the same timeouts,
+/// the cancellation token being ignored.
+impl PartialEq for Execute {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command && self.args == other.args && self.timeouts == other.timeouts
+    }
+}
+
+impl Eq for Execute {}
+
 impl Execute {
     /// A new command with its arguments
     pub fn new(command: String, args: Vec<String>) -> Self {
@@ -24,6 +36,7 @@ impl Execute {
             command,
             args,
             timeouts: None,
+            cancellation: None,
         }
     }
 
@@ -61,6 +74,17 @@ impl Execute {
             ..self
         }
     }
+
+    /// Kill the process as soon as the given token is cancelled
+    ///
+    /// On cancellation, a SIGTERM is sent to the process,
+    /// followed by a SIGKILL if the process is still running after the forceful timeout.
+    pub fn with_cancellation(self, cancellation: CancellationToken) -> Self {
+        Self {
+            cancellation: Some(cancellation),
+            ..self
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -80,23 +104,46 @@ impl Server for ScriptActor {
             .stderr(Stdio::piped())
             .spawn()?;
 
-        match (child.id(), message.timeouts) {
-            (_, None) | (None, _) => child.wait_with_output().await,
-            (Some(pid), Some((graceful_timeout, forceful_timeout))) => {
-                tokio::select! {
-                    response = child.wait_with_output() => response,
-                    not_killed = kill_on_timeout(pid, graceful_timeout, forceful_timeout) => Err(not_killed),
-                }
-            }
+        let Some(pid) = child.id() else {
+            return child.wait_with_output().await;
+        };
+        let forceful_timeout = message
+            .timeouts
+            .map(|(_, forceful_timeout)| forceful_timeout)
+            .unwrap_or(Duration::from_secs(5));
+
+        tokio::select! {
+            response = child.wait_with_output() => response,
+            not_killed = kill_on_timeout(pid, message.timeouts) => Err(not_killed),
+            not_killed = kill_on_cancel(pid, message.cancellation, forceful_timeout) => Err(not_killed),
+        }
+    }
+}
+
+async fn kill_on_timeout(pid: u32, timeouts: Option<(Duration, Duration)>) -> std::io::Error {
+    match timeouts {
+        None => std::future::pending().await,
+        Some((graceful_timeout, forceful_timeout)) => {
+            kill(pid, graceful_timeout, forceful_timeout).await
         }
     }
 }
 
-async fn kill_on_timeout(
+async fn kill_on_cancel(
     pid: u32,
-    graceful_timeout: Duration,
+    cancellation: Option<CancellationToken>,
     forceful_timeout: Duration,
 ) -> std::io::Error {
+    match cancellation {
+        None => std::future::pending().await,
+        Some(cancellation) => {
+            cancellation.cancelled().await;
+            kill(pid, Duration::ZERO, forceful_timeout).await
+        }
+    }
+}
+
+async fn kill(pid: u32, graceful_timeout: Duration, forceful_timeout: Duration) -> std::io::Error {
     let pid = nix::unistd::Pid::from_raw(pid as nix::libc::pid_t);
 
     tokio::time::sleep(graceful_timeout).await;
@@ -106,10 +153,7 @@ async fn kill_on_timeout(
     let _ = nix::sys::signal::kill(pid, nix::sys::signal::SIGKILL);
 
     tokio::time::sleep(Duration::from_secs(1)).await;
-    std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "failed to kill the process after timeout",
-    )
+    std::io::Error::new(std::io::ErrorKind::Other, "failed to kill the process")
 }
 
 impl ScriptActor {
@@ -134,6 +178,7 @@ mod tests {
                 command: "python".to_string(),
                 args: vec!["-c".to_string(), "print('Hello world!')".to_string()],
                 timeouts: None,
+                cancellation: None,
             })
         )
     }
@@ -146,6 +191,7 @@ mod tests {
                 command: "echo".to_owned(),
                 args: vec!["A message".to_owned()],
                 timeouts: None,
+                cancellation: None,
             })
             .await
             .unwrap()
@@ -225,6 +271,29 @@ mod tests {
         assert_eq!(output.status.signal(), Some(9));
     }
 
+    #[tokio::test]
+    async fn script_is_killed_on_cancellation() {
+        let mut actor = spawn_script_actor();
+        let cancellation = CancellationToken::new();
+        let command = Execute::try_new("sleep 10")
+            .unwrap()
+            .with_cancellation(cancellation.clone());
+        let output = tokio::spawn(async move {
+            tokio::time::timeout(Duration::from_secs(5), actor.await_response(command)).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancellation.cancel();
+
+        let output = output
+            .await
+            .unwrap()
+            .expect("execution timeout")
+            .expect("result send error")
+            .expect("execution error");
+        assert!(!output.status.success());
+        assert_eq!(output.status.signal(), Some(15));
+    }
+
     fn spawn_script_actor() -> ClientMessageBox<Execute, std::io::Result<Output>> {
         let mut actor = ScriptActor::builder();
         let handle = ClientMessageBox::new("Tester", &mut actor);
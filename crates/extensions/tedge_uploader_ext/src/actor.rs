@@ -19,6 +19,7 @@ pub struct UploadRequest {
     pub file_path: Utf8PathBuf,
     pub auth: Option<Auth>,
     pub content_type: ContentType,
+    pub chunk_size: Option<u64>,
 }
 
 impl UploadRequest {
@@ -28,6 +29,7 @@ impl UploadRequest {
             file_path: file_path.to_owned(),
             auth: None,
             content_type: ContentType::ApplicationOctetStream,
+            chunk_size: None,
         }
     }
 
@@ -44,6 +46,16 @@ impl UploadRequest {
             ..self
         }
     }
+
+    /// Upload the file in chunks of the given size, resuming an interrupted upload when possible
+    ///
+    /// The target server must support resumable uploads, as the file-transfer service does.
+    pub fn with_chunk_size(self, chunk_size: u64) -> Self {
+        Self {
+            chunk_size: Some(chunk_size),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -105,6 +117,9 @@ impl Server for UploaderActor {
         if let Some(auth) = request.auth {
             upload_info = upload_info.with_auth(auth);
         }
+        if let Some(chunk_size) = request.chunk_size {
+            upload_info = upload_info.with_chunk_size(chunk_size);
+        }
 
         let uploader = Uploader::new(request.file_path.clone(), self.identity.clone());
 
@@ -137,6 +137,7 @@ async fn run_with(
         tmp_path: Arc::from(tedge_config.tmp.path.as_path()),
         is_sudo_enabled: tedge_config.sudo.enable,
         config_update_enabled: true,
+        scheduled_by_agent: false,
     })?;
 
     let config_actor = ConfigManagerBuilder::try_new(
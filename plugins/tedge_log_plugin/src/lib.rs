@@ -129,6 +129,7 @@ async fn run_with(
         tmp_dir,
         mqtt_schema: MqttSchema::with_root(mqtt_topic_root.to_string()),
         mqtt_device_topic_id: mqtt_device_topic_id.to_string().parse()?,
+        scheduled_by_agent: false,
     })?;
     let log_actor = LogManagerBuilder::try_new(
         log_manager_config,